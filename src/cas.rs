@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::marker::PhantomData;
+
+use strict_encoding::{StreamWriter, StrictEncode, StrictWriter};
+
+use crate::hasher::HashWriter;
+use crate::{AoraHasher, AoraKey, AoraMap, Sha256Hasher};
+
+/// Content-addressed append-only map, where the key of each item is computed as a hash of its
+/// strict-encoded value.
+///
+/// The hash function is provided by the `H` type parameter, defaulting to SHA-256.
+///
+/// Since the key is derived from the value, two different values can't be stored under the same
+/// key, guaranteeing the append-only no-conflict invariant by construction.
+#[derive(Debug)]
+pub struct CasMap<K, V, M, H = Sha256Hasher, const KEY_LEN: usize = 32>
+where
+    K: AoraKey<KEY_LEN>,
+    M: AoraMap<K, V, KEY_LEN>,
+    H: AoraHasher<KEY_LEN>,
+{
+    map: M,
+    _phantom: PhantomData<(K, V, H)>,
+}
+
+impl<K, V, M, H, const KEY_LEN: usize> CasMap<K, V, M, H, KEY_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    V: StrictEncode,
+    M: AoraMap<K, V, KEY_LEN>,
+    H: AoraHasher<KEY_LEN>,
+{
+    /// Wraps an append-only map, which must contain only content-addressed items.
+    pub fn new(map: M) -> Self { Self { map, _phantom: PhantomData } }
+
+    /// Computes the key under which the value is (or will be) stored.
+    pub fn key_for(value: &V) -> K {
+        let writer =
+            StrictWriter::with(StreamWriter::new::<{ usize::MAX }>(HashWriter(H::default())));
+        let hasher = value
+            .strict_encode(writer)
+            .expect("unable to encode item")
+            .unbox()
+            .unconfine();
+        K::from(hasher.0.finalize())
+    }
+
+    /// Returns a number of the items in the map.
+    pub fn len(&self) -> usize { self.map.len() }
+
+    /// Checks whether the map is empty.
+    pub fn is_empty(&self) -> bool { self.map.is_empty() }
+
+    /// Checks whether an item with a given key is present in the map.
+    pub fn contains_key(&self, key: K) -> bool { self.map.contains_key(key) }
+
+    /// Checks whether a given value is present in the map.
+    pub fn contains(&self, value: &V) -> bool { self.map.contains_key(Self::key_for(value)) }
+
+    /// Retrieves value from the map.
+    pub fn get(&self, key: K) -> Option<V> { self.map.get(key) }
+
+    /// Inserts (appends) the value to the map, returning the key it is stored under. If the value
+    /// is already in the map, does nothing.
+    pub fn insert(&mut self, value: &V) -> K
+    where K: Copy {
+        let key = Self::key_for(value);
+        self.map.insert(key, value);
+        key
+    }
+
+    /// Returns an iterator over the key and value pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)> + '_ { self.map.iter() }
+
+    /// Returns a reference to the underlying map.
+    pub fn as_map(&self) -> &M { &self.map }
+
+    /// Releases the underlying map.
+    pub fn into_map(self) -> M { self.map }
+}
+
+#[cfg(all(test, feature = "file-strict"))]
+mod tests {
+    use amplify::confinement::SmallVec;
+
+    use super::*;
+    use crate::file::FileAoraMap;
+
+    type Db = FileAoraMap<[u8; 32], SmallVec<u8>, { u64::from_be_bytes(*b"DUMBTEST") }>;
+
+    #[test]
+    fn content_addressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db: CasMap<_, _, Db> = CasMap::new(Db::create_new(dir.path(), "cas").unwrap());
+
+        let val = SmallVec::from_checked(b"hello".to_vec());
+        let key = db.insert(&val);
+        assert_eq!(key, CasMap::<_, _, Db>::key_for(&val));
+        assert_eq!(db.insert(&val), key);
+        assert_eq!(db.len(), 1);
+        assert_eq!(db.get(key), Some(val.clone()));
+        assert!(db.contains(&val));
+        assert!(!db.contains(&SmallVec::from_checked(b"world".to_vec())));
+    }
+
+    #[test]
+    #[cfg(feature = "blake3")]
+    fn blake3() {
+        use crate::Blake3Hasher;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Db::create_new(dir.path(), "blake3").unwrap();
+        let mut db: CasMap<_, _, _, Blake3Hasher> = CasMap::new(db);
+
+        let val = SmallVec::from_checked(b"hello".to_vec());
+        let key = db.insert(&val);
+        assert_ne!(key, CasMap::<_, _, Db>::key_for(&val));
+        assert_eq!(key, *blake3::hash(b"\x05\x00hello").as_bytes());
+    }
+}
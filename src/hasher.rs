@@ -0,0 +1,118 @@
+// SPDX-License-Identifier: Apache-2.0
+
+#[cfg(feature = "sha2")]
+use core::marker::PhantomData;
+
+/// Hash function used for content addressing and verification of the stored data.
+///
+/// Allows using the digest standardized in a specific ecosystem.
+pub trait AoraHasher<const LEN: usize = 32>: Default {
+    /// Adds data to the hashed message.
+    fn update(&mut self, data: &[u8]);
+
+    /// Completes hashing, returning the digest.
+    fn finalize(self) -> [u8; LEN];
+
+    /// Computes digest of a single piece of data.
+    fn digest(data: &[u8]) -> [u8; LEN] {
+        let mut hasher = Self::default();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+/// Adaptor allowing to stream encoded data into a hasher.
+#[cfg(feature = "cas")]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HashWriter<H: AoraHasher<LEN>, const LEN: usize>(pub H);
+
+#[cfg(feature = "cas")]
+impl<H: AoraHasher<LEN>, const LEN: usize> std::io::Write for HashWriter<H, LEN> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+}
+
+/// SHA-256 hasher.
+#[cfg(feature = "sha2")]
+#[derive(Clone, Debug, Default)]
+pub struct Sha256Hasher(sha2::Sha256);
+
+#[cfg(feature = "sha2")]
+impl AoraHasher for Sha256Hasher {
+    fn update(&mut self, data: &[u8]) { sha2::Digest::update(&mut self.0, data) }
+
+    fn finalize(self) -> [u8; 32] { sha2::Digest::finalize(self.0).into() }
+}
+
+/// Tag used to domain-separate [`TaggedSha256`] hashes.
+pub trait HashTag {
+    /// Tag string.
+    const TAG: &'static str;
+}
+
+/// SHA-256 tagged hash, computed as `SHA256(SHA256(tag) || SHA256(tag) || data)` (as in BIP-340).
+#[cfg(feature = "sha2")]
+#[derive(Clone, Debug)]
+pub struct TaggedSha256<T: HashTag>(sha2::Sha256, PhantomData<T>);
+
+#[cfg(feature = "sha2")]
+impl<T: HashTag> Default for TaggedSha256<T> {
+    fn default() -> Self {
+        use sha2::Digest;
+
+        let tag = sha2::Sha256::digest(T::TAG.as_bytes());
+        let mut engine = sha2::Sha256::new();
+        engine.update(tag);
+        engine.update(tag);
+        Self(engine, PhantomData)
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl<T: HashTag> AoraHasher for TaggedSha256<T> {
+    fn update(&mut self, data: &[u8]) { sha2::Digest::update(&mut self.0, data) }
+
+    fn finalize(self) -> [u8; 32] { sha2::Digest::finalize(self.0).into() }
+}
+
+/// BLAKE3 hasher.
+#[cfg(feature = "blake3")]
+#[derive(Clone, Debug, Default)]
+pub struct Blake3Hasher(blake3::Hasher);
+
+#[cfg(feature = "blake3")]
+impl AoraHasher for Blake3Hasher {
+    fn update(&mut self, data: &[u8]) { self.0.update(data); }
+
+    fn finalize(self) -> [u8; 32] { self.0.finalize().into() }
+}
+
+#[cfg(all(test, any(feature = "sha2", feature = "blake3")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn sha256() {
+        struct Tag;
+        impl HashTag for Tag {
+            const TAG: &'static str = "BIP0340/challenge";
+        }
+
+        assert_eq!(
+            Sha256Hasher::digest(b"abc"),
+            *b"\xba\x78\x16\xbf\x8f\x01\xcf\xea\x41\x41\x40\xde\x5d\xae\x22\x23\xb0\x03\x61\xa3\x96\x17\x7a\x9c\xb4\x10\xff\x61\xf2\x00\x15\xad"
+        );
+        assert_ne!(TaggedSha256::<Tag>::digest(b"abc"), Sha256Hasher::digest(b"abc"));
+    }
+
+    #[test]
+    #[cfg(feature = "blake3")]
+    fn blake3() {
+        assert_eq!(Blake3Hasher::digest(b"abc"), *blake3::hash(b"abc").as_bytes());
+    }
+}
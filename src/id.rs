@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use core::ops::Range;
+
+use crate::{AoraKey, AuraMap, TransactionalMap, U64Le};
+
+/// Allocator of strictly increasing `u64` ids, persisting the counter in a cell of an
+/// append-update map.
+///
+/// The counter is updated in the pending transaction of the map, so the allocated ids become
+/// durable together with the rest of the transaction: if the transaction is aborted with
+/// [`Self::abort`], the ids allocated in it are handed out again.
+///
+/// The allocator remembers the counter value it wrote to the pending transaction, so it doesn't
+/// hand out the same id twice even if the reads of the map don't see the pending writes (as with
+/// a `FileAuraMap` set to the committed-only visibility). If the transaction is aborted directly
+/// through the map, the allocator still counts from the remembered value until the next
+/// [`Self::commit`] or [`Self::abort`], leaving a gap in the ids.
+#[derive(Debug)]
+pub struct IdAllocator<K, M, const KEY_LEN: usize = 32>
+where
+    K: AoraKey<KEY_LEN> + Copy,
+    M: AuraMap<K, U64Le, KEY_LEN, 8>,
+{
+    map: M,
+    cell: K,
+    /// Counter value written to the pending transaction of the map.
+    pending: Option<u64>,
+}
+
+impl<K, M, const KEY_LEN: usize> IdAllocator<K, M, KEY_LEN>
+where
+    K: AoraKey<KEY_LEN> + Copy,
+    M: AuraMap<K, U64Le, KEY_LEN, 8>,
+{
+    /// Creates an allocator keeping its counter in the map under the `cell` key. If the cell is
+    /// not present yet, the ids start from zero.
+    pub fn new(map: M, cell: K) -> Self { Self { map, cell, pending: None } }
+
+    /// Returns the id which will be allocated next.
+    pub fn peek(&self) -> u64 {
+        let stored = self.map.get(self.cell).map_or(0, |next| next.0);
+        self.pending.map_or(stored, |pending| pending.max(stored))
+    }
+
+    /// Allocates the next id.
+    ///
+    /// # Panics
+    ///
+    /// If all the ids are already allocated.
+    pub fn next_id(&mut self) -> u64 { self.allocate(1).start }
+
+    /// Allocates a continuous range of `count` ids.
+    ///
+    /// # Panics
+    ///
+    /// If there are not enough unallocated ids left.
+    pub fn allocate(&mut self, count: u64) -> Range<u64> {
+        let start = self.peek();
+        let end = start
+            .checked_add(count)
+            .expect("unable to allocate ids since the id space is exhausted");
+        if count > 0 {
+            self.map.insert_or_update(self.cell, U64Le(end));
+            self.pending = Some(end);
+        }
+        start..end
+    }
+
+    /// Returns a reference to the underlying map.
+    pub fn as_map(&self) -> &M { &self.map }
+
+    /// Returns a mutable reference to the underlying map, allowing to update other cells in the
+    /// same transaction as the counter.
+    pub fn as_map_mut(&mut self) -> &mut M { &mut self.map }
+
+    /// Releases the underlying map.
+    pub fn into_map(self) -> M { self.map }
+}
+
+impl<K, M, const KEY_LEN: usize> IdAllocator<K, M, KEY_LEN>
+where
+    K: AoraKey<KEY_LEN> + Copy,
+    M: AuraMap<K, U64Le, KEY_LEN, 8> + TransactionalMap<K>,
+{
+    /// Commits the pending transaction of the map, making the allocated ids durable.
+    pub fn commit(&mut self) -> Option<u64> {
+        self.pending = None;
+        self.map.commit_transaction()
+    }
+
+    /// Aborts the pending transaction of the map, releasing the ids allocated in it.
+    pub fn abort(&mut self) {
+        self.pending = None;
+        self.map.abort_transaction()
+    }
+}
+
+#[cfg(all(test, feature = "file-strict"))]
+mod tests {
+    use super::*;
+    use crate::file::FileAuraMap;
+
+    type Db = FileAuraMap<U64Le, U64Le, { u64::from_be_bytes(*b"DUMBTEST") }, 1, 8, 8>;
+
+    #[test]
+    fn allocate() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Db::create_new(dir.path(), "ids").unwrap();
+        let mut ids = IdAllocator::new(db, U64Le(0));
+        assert_eq!(ids.next_id(), 0);
+        assert_eq!(ids.next_id(), 1);
+        assert_eq!(ids.allocate(10), 2..12);
+        assert_eq!(ids.allocate(0), 12..12);
+        assert_eq!(ids.commit(), Some(0));
+
+        // Ids allocated in an aborted transaction are handed out again
+        assert_eq!(ids.next_id(), 12);
+        ids.abort();
+        assert_eq!(ids.peek(), 12);
+        drop(ids);
+
+        let db = Db::open(dir.path(), "ids").unwrap();
+        let mut ids = IdAllocator::new(db, U64Le(0));
+        assert_eq!(ids.next_id(), 12);
+        ids.commit();
+    }
+
+    #[test]
+    fn allocate_committed_only() {
+        use crate::file::Visibility;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "ids").unwrap();
+        db.set_visibility(Visibility::CommittedOnly);
+        let mut ids = IdAllocator::new(db, U64Le(0));
+        assert_eq!(ids.next_id(), 0);
+        assert_eq!(ids.next_id(), 1);
+        assert_eq!(ids.allocate(2), 2..4);
+        assert_eq!(ids.commit(), Some(0));
+        assert_eq!(ids.next_id(), 4);
+        ids.abort();
+        assert_eq!(ids.peek(), 4);
+    }
+}
@@ -0,0 +1,153 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::any::Any;
+use std::marker::PhantomData;
+
+use crate::{AoraIndex, AoraKey, AoraMap, build_index_from};
+
+/// Index attached to an [`IndexedAoraMap`] together with its extractor function.
+trait AttachedIndex<K, V> {
+    fn update(&mut self, key: K, val: &V);
+    fn as_any(&self) -> &dyn Any;
+}
+
+struct Attached<X, F, I, const KEY_LEN: usize, const IDX_LEN: usize> {
+    index: X,
+    extract: F,
+    _phantom: PhantomData<fn() -> I>,
+}
+
+impl<K, V, X, F, I, E, const KEY_LEN: usize, const IDX_LEN: usize> AttachedIndex<K, V>
+    for Attached<X, F, I, KEY_LEN, IDX_LEN>
+where
+    K: AoraKey<KEY_LEN> + Copy,
+    I: AoraKey<IDX_LEN> + 'static,
+    X: AoraIndex<I, K, IDX_LEN, KEY_LEN> + 'static,
+    F: Fn(&V) -> E + 'static,
+    E: IntoIterator<Item = I>,
+{
+    fn update(&mut self, key: K, val: &V) {
+        self.index
+            .extend((self.extract)(val).into_iter().map(|i| (i, key)));
+    }
+
+    fn as_any(&self) -> &dyn Any { &self.index }
+}
+
+/// Append-only map with automatically maintained secondary indexes.
+///
+/// Each index is attached together with an extractor function, deriving index keys from an item
+/// value. Every item inserted into the map gets its key pushed into all attached indexes under the
+/// extracted index keys, keeping the map and the indexes consistent by construction.
+pub struct IndexedAoraMap<K, V, M, const KEY_LEN: usize = 32>
+where
+    K: AoraKey<KEY_LEN> + Copy,
+    M: AoraMap<K, V, KEY_LEN>,
+{
+    map: M,
+    indexes: Vec<Box<dyn AttachedIndex<K, V>>>,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V, M, const KEY_LEN: usize> IndexedAoraMap<K, V, M, KEY_LEN>
+where
+    K: AoraKey<KEY_LEN> + Copy,
+    M: AoraMap<K, V, KEY_LEN>,
+{
+    /// Wraps a map, which doesn't have any indexes attached yet.
+    pub fn new(map: M) -> Self { Self { map, indexes: Vec::new(), _phantom: PhantomData } }
+
+    /// Attaches a new index, populating it with all the items already present in the map.
+    ///
+    /// Returns the number of the index, which can be used to access it with [`Self::index`].
+    pub fn attach<I, X, E, const IDX_LEN: usize>(
+        &mut self,
+        mut index: X,
+        extract: impl Fn(&V) -> E + 'static,
+    ) -> usize
+    where
+        I: AoraKey<IDX_LEN> + 'static,
+        X: AoraIndex<I, K, IDX_LEN, KEY_LEN> + 'static,
+        E: IntoIterator<Item = I>,
+    {
+        build_index_from(&self.map, &mut index, &extract);
+        let attached =
+            Attached::<_, _, I, KEY_LEN, IDX_LEN> { index, extract, _phantom: PhantomData };
+        self.indexes.push(Box::new(attached));
+        self.indexes.len() - 1
+    }
+
+    /// Returns a reference to the attached index with the given number, if it has the type `X`.
+    pub fn index<X: 'static>(&self, no: usize) -> Option<&X> {
+        self.indexes.get(no)?.as_any().downcast_ref()
+    }
+
+    /// Returns a reference to the underlying map.
+    pub fn as_map(&self) -> &M { &self.map }
+
+    /// Releases the underlying map, detaching all the indexes.
+    pub fn into_map(self) -> M { self.map }
+}
+
+impl<K, V, M, const KEY_LEN: usize> AoraMap<K, V, KEY_LEN> for IndexedAoraMap<K, V, M, KEY_LEN>
+where
+    K: AoraKey<KEY_LEN> + Copy,
+    M: AoraMap<K, V, KEY_LEN>,
+{
+    fn len(&self) -> usize { self.map.len() }
+
+    fn contains_key(&self, key: K) -> bool { self.map.contains_key(key) }
+
+    fn get(&self, key: K) -> Option<V> { self.map.get(key) }
+
+    fn insert(&mut self, key: K, item: &V) {
+        self.map.insert(key, item);
+        for index in &mut self.indexes {
+            index.update(key, item);
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (K, V)> { self.map.iter() }
+
+    fn iter_rev(&self) -> impl Iterator<Item = (K, V)> { self.map.iter_rev() }
+}
+
+#[cfg(all(test, feature = "file-strict"))]
+mod tests {
+    use amplify::confinement::SmallVec;
+
+    use super::*;
+    use crate::file::{FileAoraIndex, FileAoraMap};
+
+    const MAGIC: u64 = u64::from_be_bytes(*b"DUMBTEST");
+    type Db = FileAoraMap<[u8; 8], SmallVec<u8>, MAGIC, 1, 8>;
+    type Index = FileAoraIndex<[u8; 1], [u8; 8], MAGIC, 1, 1, 8>;
+
+    fn val(no: u64) -> SmallVec<u8> { SmallVec::from_checked(no.to_le_bytes().to_vec()) }
+
+    #[test]
+    fn secondary_indexes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "indexed").unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+
+        let mut db = IndexedAoraMap::new(db);
+        let parity = Index::create_new(dir.path(), "parity").unwrap();
+        let parity = db.attach(parity, |val: &SmallVec<u8>| [[val[0] % 2]]);
+        let bytes = Index::create_new(dir.path(), "bytes").unwrap();
+        let bytes =
+            db.attach(bytes, |val: &SmallVec<u8>| val.iter().map(|b| [*b]).collect::<Vec<_>>());
+
+        for no in 1..10u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+
+        let parity = db.index::<Index>(parity).unwrap();
+        assert_eq!(parity.value_len([0]), 5);
+        assert_eq!(parity.value_len([1]), 5);
+        let bytes = db.index::<Index>(bytes).unwrap();
+        assert_eq!(bytes.value_len([0]), 10);
+        assert_eq!(bytes.value_len([9]), 1);
+        assert!(db.index::<Db>(0).is_none());
+    }
+}
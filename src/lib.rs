@@ -7,21 +7,81 @@
 
 #[macro_use]
 extern crate amplify;
-
+extern crate alloc;
+
+#[cfg(feature = "cas")]
+mod cas;
+mod hasher;
+mod id;
+#[cfg(feature = "std")]
+mod indexed;
+#[cfg(feature = "std")]
+mod merkle;
 mod providers;
+#[cfg(feature = "std")]
+mod sync;
 mod types;
 
-use core::fmt::Display;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::{self, Display, Formatter};
+use core::ops::Range;
 
 use amplify::hex::ToHex;
 
+#[cfg(feature = "cas")]
+pub use crate::cas::CasMap;
+#[cfg(feature = "blake3")]
+pub use crate::hasher::Blake3Hasher;
+pub use crate::hasher::{AoraHasher, HashTag};
+#[cfg(feature = "sha2")]
+pub use crate::hasher::{Sha256Hasher, TaggedSha256};
+pub use crate::id::IdAllocator;
+#[cfg(feature = "std")]
+pub use crate::indexed::IndexedAoraMap;
+#[cfg(feature = "std")]
+pub use crate::merkle::{InclusionProof, MerkleBuilder, merkle_leaf, merkle_node};
 #[allow(unused_imports)]
 pub use crate::providers::*;
+#[cfg(feature = "std")]
+pub use crate::sync::{SyncAoraMap, SyncAuraMap};
 pub use crate::types::*;
 
+/// Key (or fixed-size value) of the AORA providers, which is stored as a byte array of the given
+/// length.
+///
+/// The trait is implemented for all the types convertible into and from such byte arrays, so it
+/// doesn't need to be implemented manually.
+pub trait AoraKey<const LEN: usize>: Into<[u8; LEN]> + From<[u8; LEN]> {
+    /// Returns the byte representation of the key.
+    fn key_bytes(self) -> [u8; LEN] { self.into() }
+
+    /// Constructs the key from its byte representation.
+    fn from_key_bytes(bytes: [u8; LEN]) -> Self { Self::from(bytes) }
+
+    /// Returns a value displaying the byte representation of the key in hex.
+    fn key_hex(self) -> KeyHex<LEN> { KeyHex(self.into()) }
+
+    /// Compares the keys by their byte representation, which is the order of the keys in the
+    /// sorted providers.
+    fn cmp_key(self, other: Self) -> Ordering { self.key_bytes().cmp(&other.key_bytes()) }
+}
+
+impl<T, const LEN: usize> AoraKey<LEN> for T where T: Into<[u8; LEN]> + From<[u8; LEN]> {}
+
+/// Byte representation of a key, displayed in hex; returned by [`AoraKey::key_hex`].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct KeyHex<const LEN: usize>(pub [u8; LEN]);
+
+impl<const LEN: usize> Display for KeyHex<LEN> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.to_hex())
+    }
+}
+
 /// Trait for providers of append-only key-value maps.
 pub trait AoraMap<K, V, const KEY_LEN: usize = 32>
-where K: Into<[u8; KEY_LEN]> + From<[u8; KEY_LEN]>
+where K: AoraKey<KEY_LEN>
 {
     /// Returns a number of the items in the log.
     fn len(&self) -> usize;
@@ -70,14 +130,47 @@ where K: Into<[u8; KEY_LEN]> + From<[u8; KEY_LEN]>
 
     /// Returns an iterator over the key and value pairs.
     fn iter(&self) -> impl Iterator<Item = (K, V)>;
+
+    /// Returns an iterator over the key and value pairs, yielding the most recently appended items
+    /// first.
+    ///
+    /// The default implementation collects all the items of [`Self::iter`] and reverses them.
+    fn iter_rev(&self) -> impl Iterator<Item = (K, V)> {
+        self.iter().collect::<Vec<_>>().into_iter().rev()
+    }
+}
+
+/// Append-only log of values addressed by their sequence numbers, which are assigned on append
+/// starting from zero. Useful for sequential event streams, which don't have natural keys.
+pub trait AoraLog<V> {
+    /// Returns a number of the values in the log, which is also the sequence number the next
+    /// appended value will get.
+    fn len(&self) -> u64;
+
+    /// Checks whether the log is empty.
+    fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Appends a value to the log, returning its sequence number.
+    fn append(&mut self, value: &V) -> u64;
+
+    /// Retrieves the value with the given sequence number, if it is present in the log.
+    fn get(&self, seq: u64) -> Option<V>;
+
+    /// Returns an iterator over the sequence numbers and values, starting from the given sequence
+    /// number (inclusive).
+    fn iter_from(&self, seq: u64) -> impl Iterator<Item = (u64, V)>;
+
+    /// Returns an iterator over all the sequence numbers and values.
+    fn iter(&self) -> impl Iterator<Item = (u64, V)> { self.iter_from(0) }
 }
 
 /// Append-only log mapping keys to value sets, which is useful for building one-to-many key
-/// indexes. The values in the index are not necessarily kept in the order they were added.
+/// indexes. The values in the index are not necessarily kept in the order they were added, unless
+/// the provider is ordered (see [`AoraIndex::is_ordered`]).
 pub trait AoraIndex<K, V, const KEY_LEN: usize = 32, const VAL_LEN: usize = 32>
 where
-    K: Into<[u8; KEY_LEN]> + From<[u8; KEY_LEN]>,
-    V: Into<[u8; VAL_LEN]> + From<[u8; VAL_LEN]>,
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
 {
     /// Returns a number of the items in the log.
     fn len(&self) -> usize;
@@ -88,6 +181,31 @@ where
     /// Returns iterator over all known keys.
     fn keys(&self) -> impl Iterator<Item = K>;
 
+    /// Returns iterator over all known keys which byte representation starts with the given
+    /// prefix.
+    fn keys_with_prefix(&self, prefix: &[u8]) -> impl Iterator<Item = K> {
+        self.keys()
+            .map(K::into)
+            .filter(move |key| key.starts_with(prefix))
+            .map(K::from)
+    }
+
+    /// Returns iterator over the keys having at least `n` values, which allows finding the hot
+    /// keys of the index.
+    fn keys_with_min_values(&self, n: usize) -> impl Iterator<Item = K> {
+        self.filter_keys(move |_, len| len >= n)
+    }
+
+    /// Returns iterator over the keys for which the predicate, given the key and the number of its
+    /// values, returns `true`.
+    fn filter_keys(&self, mut predicate: impl FnMut(&K, usize) -> bool) -> impl Iterator<Item = K> {
+        self.keys().map(K::into).filter_map(move |key| {
+            let len = self.value_len(K::from(key));
+            let key = K::from(key);
+            predicate(&key, len).then_some(key)
+        })
+    }
+
     /// Checks whether a given value is present in the log.
     fn contains_key(&self, key: K) -> bool { self.value_len(key) > 0 }
 
@@ -95,10 +213,120 @@ where
     fn value_len(&self, key: K) -> usize;
 
     /// Retrieves value vector from the log. If the key is not present, returns an empty iterator.
+    ///
+    /// The iterator may borrow from the provider, so no copy of the value vector is made.
     fn get(&self, key: K) -> impl ExactSizeIterator<Item = V>;
 
+    /// Iterates over the values stored under the given key. If the key is not present, returns an
+    /// empty iterator.
+    fn iter_values(&self, key: K) -> impl Iterator<Item = V> { self.get(key) }
+
+    /// Checks whether the provider guarantees that the values under each key are kept in the
+    /// order they were first pushed, in memory and across reopening the index, so the value
+    /// vector can be treated as a sequence of events.
+    fn is_ordered(&self) -> bool { false }
+
+    /// Retrieves value vector in the order the values were first pushed, or `None` if the provider
+    /// is not ordered. If the key is not present, returns an empty iterator.
+    fn get_ordered(&self, key: K) -> Option<impl ExactSizeIterator<Item = V>> {
+        let _ = key;
+        None::<core::iter::Empty<V>>
+    }
+
+    /// Returns an iterator over the values stored under each of the given keys, yielding each value
+    /// once. If no keys are given, returns an empty iterator. The order of the values is
+    /// unspecified.
+    ///
+    /// The default implementation re-reads the value vectors instead of materializing them;
+    /// providers should override it with a lookup in their value sets.
+    fn intersect(&self, keys: &[K]) -> impl Iterator<Item = V>
+    where K: Copy {
+        let (first, rest) = match keys.split_first() {
+            Some((first, rest)) => (Some(*first), rest),
+            None => (None, keys),
+        };
+        first
+            .into_iter()
+            .flat_map(move |key| self.get(key))
+            .map(V::into)
+            .filter(move |val| {
+                rest.iter().all(|key| {
+                    self.get(*key)
+                        .any(|other| Into::<[u8; VAL_LEN]>::into(other) == *val)
+                })
+            })
+            .map(V::from)
+    }
+
+    /// Returns an iterator over the values stored under any of the given keys, yielding each value
+    /// once. The order of the values is unspecified.
+    ///
+    /// The default implementation re-reads the value vectors instead of materializing them;
+    /// providers should override it with a lookup in their value sets.
+    fn union(&self, keys: &[K]) -> impl Iterator<Item = V>
+    where K: Copy {
+        keys.iter()
+            .enumerate()
+            .flat_map(move |(no, key)| {
+                self.get(*key).map(V::into).filter(move |val| {
+                    !keys[..no].iter().any(|key| {
+                        self.get(*key)
+                            .any(|other| Into::<[u8; VAL_LEN]>::into(other) == *val)
+                    })
+                })
+            })
+            .map(V::from)
+    }
+
+    /// Returns an iterator over all key-value pairs in the index, yielding a pair for each of the
+    /// values stored under a key.
+    fn iter(&self) -> impl Iterator<Item = (K, V)> {
+        self.keys()
+            .map(K::into)
+            .flat_map(move |key| self.get(K::from(key)).map(move |val| (K::from(key), val)))
+    }
+
     /// Pushes a new value into the value array for the given key.
     fn push(&mut self, key: K, val: V);
+
+    /// Pushes all values from an iterator into the value array for the given key.
+    ///
+    /// Providers persisting the index should do that once, after all values are added.
+    fn push_all(&mut self, key: K, vals: impl IntoIterator<Item = V>)
+    where K: Copy {
+        for val in vals {
+            self.push(key, val);
+        }
+    }
+
+    /// Pushes all key-value pairs from an iterator into the index.
+    ///
+    /// Providers persisting the index should do that once, after all values are added.
+    fn extend(&mut self, iter: impl IntoIterator<Item = (K, V)>) {
+        for (key, val) in iter {
+            self.push(key, val);
+        }
+    }
+}
+
+/// Scans all items of an append-only map and pushes into the index the keys of the items under
+/// each of the index keys extracted from the item value, producing a one-to-many reverse index.
+///
+/// Useful for (re)constructing secondary indexes after schema changes. The index is populated
+/// with a single [`AoraIndex::extend`] call.
+pub fn build_index_from<K, V, I, E, const KEY_LEN: usize, const IDX_LEN: usize>(
+    map: &impl AoraMap<K, V, KEY_LEN>,
+    index: &mut impl AoraIndex<I, K, IDX_LEN, KEY_LEN>,
+    extract: impl Fn(&V) -> E,
+) where
+    K: AoraKey<KEY_LEN> + Copy,
+    I: AoraKey<IDX_LEN>,
+    E: IntoIterator<Item = I>,
+{
+    index.extend(
+        map.iter()
+            .flat_map(|(key, val)| extract(&val).into_iter().map(move |i| (i, key))),
+    );
 }
 
 /// Append-update key-value map.
@@ -106,15 +334,41 @@ where
 /// Requires value to be encodable as a fixed-size array.
 pub trait AuraMap<K, V, const KEY_LEN: usize = 32, const VAL_LEN: usize = 32>
 where
-    K: Into<[u8; KEY_LEN]> + From<[u8; KEY_LEN]>,
-    V: Into<[u8; VAL_LEN]> + From<[u8; VAL_LEN]>,
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
 {
     /// Returns human-readable table identifier
     fn display(&self) -> impl Display;
 
-    /// Returns iterator over all known keys.
+    /// Returns iterator over all known keys, yielding each key once.
     fn keys(&self) -> impl Iterator<Item = K>;
 
+    /// Returns iterator over all known keys, yielding the most recently inserted or updated keys
+    /// first.
+    ///
+    /// The default implementation collects all the keys of [`Self::keys`] and reverses them, so it
+    /// yields them in the reverse order of the first insertion unless the provider overrides it.
+    fn keys_rev(&self) -> impl Iterator<Item = K> {
+        self.keys().collect::<Vec<_>>().into_iter().rev()
+    }
+
+    /// Returns the number of the known keys.
+    fn len(&self) -> usize { self.keys().count() }
+
+    /// Checks whether the map has no keys.
+    fn is_empty(&self) -> bool { self.keys().next().is_none() }
+
+    /// Returns iterator over all known keys with their latest values, in the order of
+    /// [`Self::keys`].
+    fn iter(&self) -> impl Iterator<Item = (K, V)> {
+        self.keys()
+            .map(K::into)
+            .filter_map(move |key| self.get(K::from(key)).map(|val| (K::from(key), val)))
+    }
+
+    /// Returns iterator over the latest values of all known keys, in the order of [`Self::keys`].
+    fn values(&self) -> impl Iterator<Item = V> { self.iter().map(|(_, val)| val) }
+
     /// Checks whether a given value is present in the log.
     fn contains_key(&self, key: K) -> bool;
 
@@ -140,24 +394,41 @@ where
     /// Panics if the item under the given id is different from another item under the same id
     /// already present in the log.
     fn insert_only(&mut self, key: K, val: V)
+    where K: Copy {
+        if let Err(AuraMapError::KeyExistsWithDifferentValue { key, old, new }) =
+            self.try_insert_only(key, val)
+        {
+            panic!(
+                "failed to insert-only key {} which is already present in the table '{}' (old \
+                 value={}, attempted new value={})",
+                key.to_hex(),
+                self.display(),
+                old.to_hex(),
+                new.to_hex()
+            )
+        }
+    }
+
+    /// Inserts item to the append-only log if the key is not yet present, or does nothing if
+    /// the key is present with the same value.
+    ///
+    /// # Errors
+    ///
+    /// If the item under the given id is different from another item under the same id already
+    /// present in the log.
+    fn try_insert_only(&mut self, key: K, val: V) -> Result<(), AuraMapError<KEY_LEN, VAL_LEN>>
     where K: Copy {
         let bytes = key.into();
         if let Some(v) = self.get(bytes.into()) {
             let old = v.into();
             let new = val.into();
             if old != new {
-                panic!(
-                    "failed to insert-only key {} which is already present in the table '{}' (old \
-                     value={}, attempted new value={})",
-                    bytes.to_hex(),
-                    self.display(),
-                    old.to_hex(),
-                    new.to_hex()
-                )
+                return Err(AuraMapError::KeyExistsWithDifferentValue { key: bytes, old, new });
             }
-            return;
+            return Ok(());
         }
         self.insert_or_update(key, val);
+        Ok(())
     }
 
     /// Inserts an item to the append-only log or updates its value.
@@ -170,18 +441,65 @@ where
     /// If the key is not present in the log.
     fn update_only(&mut self, key: K, val: V)
     where K: Copy {
-        let bytes = key.into();
-        if !self.contains_key(bytes.into()) {
+        if let Err(AuraMapError::KeyNotFound { key }) = self.try_update_only(key, val) {
             panic!(
                 "failed to update non-existing key {} in the table '{}'",
                 self.display(),
-                bytes.to_hex()
+                key.to_hex()
             );
         }
+    }
+
+    /// Updates the value for a given key.
+    ///
+    /// # Errors
+    ///
+    /// If the key is not present in the log.
+    fn try_update_only(&mut self, key: K, val: V) -> Result<(), AuraMapError<KEY_LEN, VAL_LEN>>
+    where K: Copy {
+        let bytes = key.into();
+        if !self.contains_key(bytes.into()) {
+            return Err(AuraMapError::KeyNotFound { key: bytes });
+        }
         self.insert_or_update(key, val);
+        Ok(())
+    }
+}
+
+/// Errors of the fallible [`AuraMap`] mutations.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum AuraMapError<const KEY_LEN: usize = 32, const VAL_LEN: usize = 32> {
+    /// The key is already present with a value different from the new one.
+    KeyExistsWithDifferentValue {
+        key: [u8; KEY_LEN],
+        old: [u8; VAL_LEN],
+        new: [u8; VAL_LEN],
+    },
+
+    /// The key is not present.
+    KeyNotFound { key: [u8; KEY_LEN] },
+}
+
+impl<const KEY_LEN: usize, const VAL_LEN: usize> Display for AuraMapError<KEY_LEN, VAL_LEN> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::KeyExistsWithDifferentValue { key, old, new } => write!(
+                f,
+                "key {} is already present with value {}, different from the new value {}",
+                key.to_hex(),
+                old.to_hex(),
+                new.to_hex()
+            ),
+            Self::KeyNotFound { key } => write!(f, "key {} is not found", key.to_hex()),
+        }
     }
 }
 
+impl<const KEY_LEN: usize, const VAL_LEN: usize> core::error::Error
+    for AuraMapError<KEY_LEN, VAL_LEN>
+{
+}
+
 /// Transaction interface for append-only logs.
 ///
 /// If an AORA log supports transactions, it should start a transaction on database open - and panic
@@ -209,6 +527,33 @@ pub trait TransactionalMap<K> {
     /// If the transaction number is not known.
     fn transaction_keys(&self, txno: u64) -> impl ExactSizeIterator<Item = K>;
 
-    /// Returns number of transactions.
+    /// Iterates over keys added to the log as a part of the transactions with the numbers in the
+    /// range, in the order of the transactions. Keys updated by several transactions of the range
+    /// are yielded once for each of them.
+    ///
+    /// # Panics
+    ///
+    /// If the range includes unknown transaction numbers.
+    fn keys_in_range(&self, range: Range<u64>) -> impl Iterator<Item = K> {
+        range.flat_map(move |txno| self.transaction_keys(txno))
+    }
+
+    /// Returns number of the committed transactions.
     fn transaction_count(&self) -> u64;
+
+    /// Returns number of the writes in the pending transaction, which is not committed yet.
+    ///
+    /// The default implementation, kept for the maps implemented before the method was added,
+    /// reports no pending writes.
+    fn pending_len(&self) -> usize { 0 }
+
+    /// Checks whether there is a pending transaction with some writes, which would be lost
+    /// unless committed.
+    fn has_pending(&self) -> bool { self.pending_len() > 0 }
+
+    /// Iterates over keys written by the pending transaction, yielding each key once.
+    ///
+    /// The default implementation, matching the default of [`Self::pending_len`], yields no
+    /// keys.
+    fn pending_keys(&self) -> impl Iterator<Item = K> { core::iter::empty() }
 }
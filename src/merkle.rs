@@ -0,0 +1,205 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::marker::PhantomData;
+use std::vec::Vec;
+
+use crate::AoraHasher;
+
+/// Computes hash of a Merkle tree leaf, domain-separated from the internal nodes as in RFC 6962.
+pub fn merkle_leaf<H: AoraHasher>(data: &[u8]) -> [u8; 32] {
+    let mut hasher = H::default();
+    hasher.update(&[0x00]);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Computes hash of an internal Merkle tree node, domain-separated from the leaves as in RFC 6962.
+pub fn merkle_node<H: AoraHasher>(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = H::default();
+    hasher.update(&[0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize()
+}
+
+/// Streaming builder of an RFC 6962 Merkle tree root, using memory logarithmic to the number of
+/// leaves.
+#[derive(Clone, Debug)]
+pub struct MerkleBuilder<H: AoraHasher> {
+    /// Roots of the complete subtrees with their heights, from the largest to the smallest.
+    stack: Vec<(u32, [u8; 32])>,
+    count: u64,
+    _phantom: PhantomData<H>,
+}
+
+impl<H: AoraHasher> Default for MerkleBuilder<H> {
+    fn default() -> Self { Self { stack: Vec::new(), count: 0, _phantom: PhantomData } }
+}
+
+impl<H: AoraHasher> MerkleBuilder<H> {
+    /// Creates builder for an empty tree.
+    pub fn new() -> Self { Self::default() }
+
+    /// Returns number of the leaves added to the tree.
+    pub fn count(&self) -> u64 { self.count }
+
+    /// Adds a leaf with the given data to the tree.
+    pub fn push(&mut self, data: &[u8]) { self.push_leaf(merkle_leaf::<H>(data)) }
+
+    /// Adds a leaf with an already computed leaf hash to the tree.
+    pub fn push_leaf(&mut self, leaf: [u8; 32]) {
+        let mut node = (0, leaf);
+        while let Some((height, left)) = self.stack.last() {
+            if *height != node.0 {
+                break;
+            }
+            node = (height + 1, merkle_node::<H>(left, &node.1));
+            self.stack.pop();
+        }
+        self.stack.push(node);
+        self.count += 1;
+    }
+
+    /// Computes the root of the tree. The root of an empty tree is the hash of an empty string.
+    pub fn root(&self) -> [u8; 32] {
+        let mut iter = self.stack.iter().rev();
+        let Some((_, mut root)) = iter.next().copied() else {
+            return H::digest(&[]);
+        };
+        for (_, left) in iter {
+            root = merkle_node::<H>(left, &root);
+        }
+        root
+    }
+}
+
+/// Computes root of a Merkle tree from the leaf hashes.
+fn subtree_root<H: AoraHasher>(leaves: &[[u8; 32]]) -> [u8; 32] {
+    let mut builder = MerkleBuilder::<H>::new();
+    for leaf in leaves {
+        builder.push_leaf(*leaf);
+    }
+    builder.root()
+}
+
+/// Proof of inclusion of a leaf into an RFC 6962 Merkle tree, allowing light clients to check
+/// membership of a record without downloading the whole log.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct InclusionProof {
+    /// Position of the leaf in the tree.
+    pub index: u64,
+    /// Number of the leaves in the tree.
+    pub size: u64,
+    /// Hashes of the sibling subtrees, from the leaf up to the root.
+    pub path: Vec<[u8; 32]>,
+}
+
+impl InclusionProof {
+    /// Builds the proof for a leaf with a given position from the hashes of all tree leaves.
+    ///
+    /// # Panics
+    ///
+    /// If the position is outside of the tree.
+    pub fn build<H: AoraHasher>(leaves: &[[u8; 32]], index: usize) -> Self {
+        assert!(index < leaves.len(), "leaf {index} is outside of the tree");
+        let size = leaves.len() as u64;
+        let mut path = Vec::new();
+        let (mut leaves, mut pos) = (leaves, index);
+        while leaves.len() > 1 {
+            let split = 1 << (usize::BITS - (leaves.len() - 1).leading_zeros() - 1);
+            if pos < split {
+                path.push(subtree_root::<H>(&leaves[split..]));
+                leaves = &leaves[..split];
+            } else {
+                path.push(subtree_root::<H>(&leaves[..split]));
+                leaves = &leaves[split..];
+                pos -= split;
+            }
+        }
+        path.reverse();
+        Self { index: index as u64, size, path }
+    }
+
+    /// Verifies that the leaf with the given hash is included in the tree with the given root.
+    pub fn verify_leaf<H: AoraHasher>(&self, root: [u8; 32], leaf: [u8; 32]) -> bool {
+        if self.index >= self.size {
+            return false;
+        }
+        let (mut fnode, mut snode) = (self.index, self.size - 1);
+        let mut hash = leaf;
+        for sibling in &self.path {
+            if snode == 0 {
+                return false;
+            }
+            if fnode & 1 == 1 || fnode == snode {
+                hash = merkle_node::<H>(sibling, &hash);
+                while fnode & 1 == 0 && fnode != 0 {
+                    fnode >>= 1;
+                    snode >>= 1;
+                }
+            } else {
+                hash = merkle_node::<H>(&hash, sibling);
+            }
+            fnode >>= 1;
+            snode >>= 1;
+        }
+        snode == 0 && hash == root
+    }
+
+    /// Verifies that a log record with the given key and value hash is included in the log with
+    /// the given Merkle root.
+    pub fn verify<H: AoraHasher>(&self, root: [u8; 32], key: &[u8], value_hash: [u8; 32]) -> bool {
+        let mut data = Vec::with_capacity(key.len() + 32);
+        data.extend_from_slice(key);
+        data.extend_from_slice(&value_hash);
+        self.verify_leaf::<H>(root, merkle_leaf::<H>(&data))
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::*;
+    use crate::Sha256Hasher;
+
+    /// Reference implementation following the recursive RFC 6962 definition.
+    fn mth(leaves: &[&[u8]]) -> [u8; 32] {
+        match leaves.len() {
+            0 => Sha256Hasher::digest(&[]),
+            1 => merkle_leaf::<Sha256Hasher>(leaves[0]),
+            n => {
+                let k = 1 << (usize::BITS - (n - 1).leading_zeros() - 1);
+                merkle_node::<Sha256Hasher>(&mth(&leaves[..k]), &mth(&leaves[k..]))
+            }
+        }
+    }
+
+    #[test]
+    fn streaming_root() {
+        let data = (0..20u8).map(|i| vec![i; i as usize]).collect::<Vec<_>>();
+        let leaves = data.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        let mut builder = MerkleBuilder::<Sha256Hasher>::new();
+        assert_eq!(builder.root(), mth(&[]));
+        for n in 1..=leaves.len() {
+            builder.push(leaves[n - 1]);
+            assert_eq!(builder.root(), mth(&leaves[..n]), "root for {n} leaves");
+        }
+    }
+
+    #[test]
+    fn inclusion_proofs() {
+        for size in 1..20u8 {
+            let leaves = (0..size)
+                .map(|i| merkle_leaf::<Sha256Hasher>(&[i]))
+                .collect::<Vec<_>>();
+            let root = subtree_root::<Sha256Hasher>(&leaves);
+            for index in 0..size as usize {
+                let proof = InclusionProof::build::<Sha256Hasher>(&leaves, index);
+                assert!(proof.verify_leaf::<Sha256Hasher>(root, leaves[index]));
+                let other = (index + 1) % size as usize;
+                if other != index {
+                    assert!(!proof.verify_leaf::<Sha256Hasher>(root, leaves[other]));
+                }
+            }
+        }
+    }
+}
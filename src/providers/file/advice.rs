@@ -0,0 +1,40 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::File;
+
+/// Expected access pattern of a file, which allows the kernel to tune the readahead and the
+/// page cache use.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub(crate) enum Access {
+    /// Reads at random positions, for which the readahead is disabled.
+    #[default]
+    Random,
+    /// Reads from the beginning to the end, for which the readahead is made more aggressive.
+    Sequential,
+    /// The cached data are not going to be accessed again and can be dropped from the page cache.
+    Done,
+}
+
+/// Advises the kernel about the expected access pattern of the whole file.
+///
+/// The advice is just a hint, so the failures are ignored; on the platforms other than Linux
+/// this is a no-op.
+pub(crate) fn advise(file: &File, access: Access) {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::fd::AsRawFd;
+
+        let advice = match access {
+            Access::Random => libc::POSIX_FADV_RANDOM,
+            Access::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+            Access::Done => libc::POSIX_FADV_DONTNEED,
+        };
+        // SAFETY: the file descriptor stays valid for the duration of the call since the file is
+        // borrowed, and `posix_fadvise` doesn't access any memory owned by the process.
+        unsafe {
+            libc::posix_fadvise(file.as_raw_fd(), 0, 0, advice);
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (file, access);
+}
@@ -1,18 +1,45 @@
 // SPDX-License-Identifier: Apache-2.0
 
-use std::cell::{RefCell, RefMut};
-use std::fs;
+use std::cell::{Ref, RefCell, RefMut};
+use std::fs::{self, File};
 use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::marker::PhantomData;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use amplify::hex::ToHex;
 use binfile::BinFile;
 use indexmap::IndexMap;
-use strict_encoding::{
-    StreamReader, StreamWriter, StrictDecode, StrictEncode, StrictReader, StrictWriter,
-};
+use strict_encoding::{StreamReader, StrictDecode, StrictEncode, StrictReader, StrictWriter};
 
-use crate::AoraMap;
+use super::advice::{Access, advise};
+use super::bloom::BloomFilter;
+use super::budget::{Charge, MemoryBudget, RecordCache};
+use super::check::{CheckIssue, CheckReport, DuplicateKey, IndexAudit, IndexRepair};
+#[cfg(target_os = "linux")]
+use super::direct::DirectAppender;
+use super::format::{Codec, HashFn, LogFormat, RecordFlags, RecordHeader, WRAPPED_KEY_LEN};
+use super::handle::open_write_through;
+use super::latency::Latencies;
+use super::layout::{TableKind, TableLayout};
+use super::observer::{AoraObserver, Observer};
+use super::prealloc::preallocate;
+use super::raw::FileAoraMapRaw;
+#[cfg(any(unix, windows))]
+use super::reader::{Reader, Snapshot};
+use super::report::DebugReport;
+use super::segment::{LogFile, SegmentedLog};
+use super::sparse::{FullIndex, KeyIndex, SparseIndex};
+use super::tomb::Tombstones;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use super::uring::Ring;
+#[cfg(feature = "notify")]
+use super::watch::ChangeWatcher;
+use super::{AoraError, LogOptions, StorageStats, telemetry};
+use crate::{
+    AoraCursor, AoraHasher, AoraKey, AoraMap, Checked, InclusionProof, MerkleBuilder, merkle_leaf,
+};
 
 #[derive(Clone, Debug, Display, Error)]
 #[display(doc_comments)]
@@ -34,17 +61,175 @@ pub enum AoraMapError {
 //       through a channel
 #[derive(Debug)]
 pub struct FileAoraMap<K, V, const MAGIC: u64, const VER: u16 = 1, const KEY_LEN: usize = 32>
-where K: Into<[u8; KEY_LEN]> + From<[u8; KEY_LEN]>
+where K: AoraKey<KEY_LEN>
 {
-    log: RefCell<BinFile<MAGIC, VER>>,
+    /// Name of the log, used to label the metrics.
+    name: String,
+    /// Directory of the log files, where the auxiliary files are created on demand.
+    dir: PathBuf,
+    log: RefCell<LogFile<MAGIC, VER>>,
     idx: RefCell<BinFile<MAGIC, VER>>,
-    index: RefCell<IndexMap<[u8; KEY_LEN], u64>>,
+    index: RefCell<KeyIndex<MAGIC, VER, KEY_LEN>>,
+    /// Optional file with value hashes (following the order of the idx entries) and the hash
+    /// function used to verify the values on read.
+    sums: Option<(RefCell<BinFile<MAGIC, VER>>, HashFn)>,
+    /// Optional file with the wrapped data keys of the records (following the order of the idx
+    /// entries), present if the log is shreddable.
+    deks: Option<RefCell<BinFile<MAGIC, VER>>>,
+    /// Optional Bloom filter of the keys, answering most of the negative lookups.
+    bloom: Option<BloomFilter<MAGIC, VER>>,
+    /// Tombstones of the superseded records, present once any record was superseded.
+    tombstones: Option<Tombstones<MAGIC, VER>>,
+    format: LogFormat,
+    /// Hash function for the hash-chained logs.
+    hasher: Option<HashFn>,
+    /// Hash of the last record in a hash-chained log.
+    tip: [u8; 32],
+    observer: Option<Observer>,
+    latencies: Option<Latencies>,
+    /// Maximal total size of the files of the map, in bytes.
+    quota: Option<u64>,
+    /// Memory charged for the in-memory index and the cache of the records read from the log,
+    /// present if a memory budget is set.
+    memory: Option<(Charge, RefCell<RecordCache>)>,
+    /// Optional handles of the files writing down to the storage device, used for appending.
+    write_through: Option<WriteThrough>,
+    /// Optional buffer of the index entries not written to the index file yet, and the size in
+    /// bytes at which the buffer is written out.
+    idx_buf: Option<(RefCell<Vec<u8>>, usize)>,
+    /// Optional io_uring instance performing the log I/O in batches.
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    ring: Option<Ring>,
+    /// Optional watcher of the changes made to the files by other processes.
+    #[cfg(feature = "notify")]
+    watcher: Option<ChangeWatcher>,
     _phantom: PhantomData<(K, V)>,
 }
 
+/// Reads a value from the log record starting at the current reader position.
+pub(super) fn read_value<V: StrictDecode>(
+    format: &LogFormat,
+    reader: &mut impl Read,
+    key: &[u8],
+    pos: u64,
+) -> Result<V, AoraError> {
+    read_timed_value(format, reader, key, None, pos).map(|(value, _)| value)
+}
+
+/// Reads a value from the log record starting at the current reader position together with the
+/// time the record was appended, if the log is timestamped. The wrapped data key of the record
+/// must be provided if the log is shreddable.
+fn read_timed_value<V: StrictDecode>(
+    format: &LogFormat,
+    reader: &mut impl Read,
+    key: &[u8],
+    wrapped: Option<&[u8; WRAPPED_KEY_LEN]>,
+    pos: u64,
+) -> Result<(V, Option<SystemTime>), AoraError> {
+    let map_err = |error| AoraError::Decode { key: key.to_hex(), pos, error };
+    if !format.is_framed() {
+        let mut reader = StrictReader::with(StreamReader::new::<{ usize::MAX }>(reader));
+        return V::strict_decode(&mut reader)
+            .map(|value| (value, None))
+            .map_err(map_err);
+    }
+    let (header, payload) = read_record(format, reader)?;
+    if header.flags.is_redacted() {
+        return Err(AoraError::Redacted { key: key.to_hex(), pos });
+    }
+    let time = format
+        .timestamped
+        .then(|| UNIX_EPOCH + Duration::from_nanos(header.time));
+    let payload = format
+        .unseal(key, payload, wrapped)
+        .ok_or_else(|| AoraError::Decrypt { key: key.to_hex(), pos })?;
+    let payload = format
+        .decompress(header.codec, payload)
+        .ok_or_else(|| AoraError::Decompress { key: key.to_hex(), pos })?;
+    let mut reader = StrictReader::with(StreamReader::in_memory::<{ usize::MAX }>(payload));
+    V::strict_decode(&mut reader)
+        .map(|value| (value, time))
+        .map_err(map_err)
+}
+
+/// Reads the wrapped data key of the record with the given `.idx` file entry number from the data
+/// key file, if the log is shreddable. Fails with [`AoraError::Shredded`] if the data key was
+/// destroyed.
+fn read_data_key<const MAGIC: u64, const VER: u16>(
+    deks: Option<&RefCell<BinFile<MAGIC, VER>>>,
+    entry: usize,
+    key: &[u8],
+    pos: u64,
+) -> Result<Option<[u8; WRAPPED_KEY_LEN]>, AoraError> {
+    let Some(deks) = deks else {
+        return Ok(None);
+    };
+    let mut deks = deks.borrow_mut();
+    let mut wrapped = [0u8; WRAPPED_KEY_LEN];
+    deks.seek(SeekFrom::Start(10 + (entry * WRAPPED_KEY_LEN) as u64))?;
+    deks.read_exact(&mut wrapped)?;
+    if wrapped == [0u8; WRAPPED_KEY_LEN] {
+        return Err(AoraError::Shredded { key: key.to_hex(), pos });
+    }
+    Ok(Some(wrapped))
+}
+
+/// Reads header and payload of a record in the framed log format.
+pub(super) fn read_record(
+    format: &LogFormat,
+    reader: &mut impl Read,
+) -> io::Result<(RecordHeader, Vec<u8>)> {
+    let header = format.read_header(reader)?;
+    let mut payload = vec![0u8; header.len as usize];
+    reader.read_exact(&mut payload)?;
+    Ok((header, payload))
+}
+
+/// Reader keeping a copy of the bytes read from the inner one.
+///
+/// The log doesn't store the record lengths, so a record is cached as it is read while being
+/// decoded.
+struct Recording<'a, R: Read> {
+    inner: &'a mut R,
+    record: Vec<u8>,
+}
+
+impl<R: Read> Read for Recording<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.record.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+}
+
+/// Record to be appended to the log, the hash of its value and its wrapped data key.
+type Record = (Vec<u8>, Option<[u8; 32]>, Option<[u8; WRAPPED_KEY_LEN]>);
+
+/// Handles of the files of the map writing down to the storage device, used for appending.
+#[derive(Debug)]
+struct WriteThrough {
+    log: File,
+    idx: File,
+    /// Handle of the data key file, if the log is shreddable.
+    deks: Option<File>,
+}
+
+/// Replaces the content of the file following the header with `data`, syncing it to the disk.
+fn replace_content<const MAGIC: u64, const VER: u16>(
+    file: &mut BinFile<MAGIC, VER>,
+    data: &[u8],
+) -> io::Result<()> {
+    file.seek(SeekFrom::Start(10))?;
+    file.write_all(data)?;
+    file.set_len(10 + data.len() as u64)?;
+    file.sync_data()?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(())
+}
+
 impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize>
     FileAoraMap<K, V, MAGIC, VER, KEY_LEN>
-where K: Into<[u8; KEY_LEN]> + From<[u8; KEY_LEN]>
+where K: AoraKey<KEY_LEN>
 {
     fn prepare(path: impl AsRef<Path>, name: &str) -> (PathBuf, PathBuf) {
         let path = path.as_ref();
@@ -53,10 +238,20 @@ where K: Into<[u8; KEY_LEN]> + From<[u8; KEY_LEN]>
         (log, idx)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(path), err))]
     pub fn create_new(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        Self::create_files(path, name, None)
+    }
+
+    fn create_files(
+        path: impl AsRef<Path>,
+        name: &str,
+        segment_size: Option<u64>,
+    ) -> io::Result<Self> {
         let path = path.as_ref();
         let (log, idx) = Self::prepare(path, name);
-        let log_exists = fs::exists(&log)?;
+        let log_exists =
+            fs::exists(&log)? || fs::exists(SegmentedLog::<MAGIC, VER>::manifest_path(path, name))?;
         let idx_exists = fs::exists(&idx)?;
         if log_exists && idx_exists {
             return Err(io::Error::other(AoraMapError::Exists {
@@ -70,14 +265,40 @@ where K: Into<[u8; KEY_LEN]> + From<[u8; KEY_LEN]>
                 path: path.display().to_string(),
             }));
         }
-        let log = BinFile::create_new(&log)
-            .map_err(|err| io::Error::new(err.kind(), format!("log file '{}'", log.display())))?;
+        let mut log = match segment_size {
+            None => BinFile::create_new(&log)
+                .map_err(|err| io::Error::new(err.kind(), format!("log file '{}'", log.display())))?
+                .into(),
+            Some(size) => LogFile::Segmented(SegmentedLog::create_new(path, name, size)?),
+        };
+        log.advise(Access::Random);
         let idx = BinFile::create_new(&idx)
             .map_err(|err| io::Error::new(err.kind(), format!("index file '{}'", idx.display())))?;
+        TableLayout { kind: TableKind::AppendOnly, key_len: KEY_LEN, val_len: None }
+            .save::<MAGIC, VER>(path, name)?;
         Ok(Self {
+            name: name.to_string(),
+            dir: path.to_path_buf(),
             log: RefCell::new(log),
             idx: RefCell::new(idx),
-            index: RefCell::new(IndexMap::new()),
+            index: RefCell::default(),
+            sums: None,
+            deks: None,
+            bloom: None,
+            tombstones: None,
+            format: LogFormat::default(),
+            hasher: None,
+            tip: [0u8; 32],
+            observer: None,
+            latencies: None,
+            quota: None,
+            memory: None,
+            write_through: None,
+            idx_buf: None,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            ring: None,
+            #[cfg(feature = "notify")]
+            watcher: None,
             _phantom: PhantomData,
         })
     }
@@ -117,17 +338,48 @@ where K: Into<[u8; KEY_LEN]> + From<[u8; KEY_LEN]>
         };
 
         Ok(Self {
-            log: RefCell::new(log),
+            name: name.to_string(),
+            dir: path.to_path_buf(),
+            log: RefCell::new(log.into()),
             idx: RefCell::new(idx),
-            index: RefCell::new(IndexMap::new()),
+            index: RefCell::default(),
+            sums: None,
+            deks: None,
+            bloom: None,
+            tombstones: None,
+            format: LogFormat::default(),
+            hasher: None,
+            tip: [0u8; 32],
+            observer: None,
+            latencies: None,
+            quota: None,
+            memory: None,
+            write_through: None,
+            idx_buf: None,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            ring: None,
+            #[cfg(feature = "notify")]
+            watcher: None,
             _phantom: PhantomData,
         })
     }
 
     pub fn open(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        Self::open_with(path, name, LogOptions::default())
+    }
+
+    /// Opens the log and index files, keeping either all the index entries in memory, or (if the
+    /// sparse index step is provided) only a sparse index over the sorted index file.
+    fn open_files(
+        path: impl AsRef<Path>,
+        name: &str,
+        sparse_step: Option<u64>,
+    ) -> io::Result<Self> {
         let path = path.as_ref();
         let (log, idx) = Self::prepare(path, name);
-        let log_exists = fs::exists(&log)?;
+        let manifest = SegmentedLog::<MAGIC, VER>::manifest_path(path, name);
+        let segmented = fs::exists(&manifest)?;
+        let log_exists = segmented || fs::exists(&log)?;
         let idx_exists = fs::exists(&idx)?;
         if !log_exists && !idx_exists {
             return Err(io::Error::other(AoraMapError::NotExists {
@@ -142,137 +394,3928 @@ where K: Into<[u8; KEY_LEN]> + From<[u8; KEY_LEN]>
             }));
         }
 
-        let mut log = BinFile::open_rw(&log)
-            .map_err(|err| io::Error::new(err.kind(), format!("log file '{}'", log.display())))?;
-        let mut idx = BinFile::open_rw(&idx)
-            .map_err(|err| io::Error::new(err.kind(), format!("index file '{}'", idx.display())))?;
+        TableLayout { kind: TableKind::AppendOnly, key_len: KEY_LEN, val_len: None }
+            .check::<MAGIC, VER>(path, name)?;
 
-        let mut index = IndexMap::new();
-        loop {
-            let mut key_buf = [0u8; KEY_LEN];
-            let res = idx.read_exact(&mut key_buf);
-            if matches!(res, Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof) {
-                break;
-            } else {
-                res.expect("unable to read item ID");
-            }
+        let mut log = if segmented {
+            LogFile::Segmented(SegmentedLog::open(path, name)?)
+        } else {
+            BinFile::open_rw(&log)
+                .map_err(|err| io::Error::new(err.kind(), format!("log file '{}'", log.display())))?
+                .into()
+        };
+        let idx_path = idx;
+        let mut idx = BinFile::open_rw(&idx_path).map_err(|err| {
+            io::Error::new(err.kind(), format!("index file '{}'", idx_path.display()))
+        })?;
 
-            let mut buf = [0u8; 8];
-            idx.read_exact(&mut buf)
-                .expect("unable to read index entry");
-            let pos = u64::from_le_bytes(buf);
+        let index = match sparse_step {
+            None => {
+                advise(&idx, Access::Sequential);
+                let entries = core::iter::from_fn(|| {
+                    let mut key_buf = [0u8; KEY_LEN];
+                    let res = idx.read_exact(&mut key_buf);
+                    if matches!(res, Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof) {
+                        return None;
+                    } else {
+                        res.expect("unable to read item ID");
+                    }
 
-            index.insert(key_buf, pos);
-        }
+                    let mut buf = [0u8; 8];
+                    idx.read_exact(&mut buf)
+                        .expect("unable to read index entry");
+                    let pos = u64::from_le_bytes(buf);
+
+                    Some((key_buf, pos))
+                });
+                let index = FullIndex::load(entries);
+                // All the entries are in memory now, and the file is used only for appending
+                advise(&idx, Access::Done);
+                KeyIndex::Full(index)
+            }
+            Some(step) => KeyIndex::Sparse(SparseIndex::open(
+                &idx_path,
+                &Self::sparse_path(path, name),
+                step,
+            )?),
+        };
 
+        log.advise(Access::Random);
         log.seek(SeekFrom::End(0))
             .expect("unable to seek to the end of the log");
         idx.seek(SeekFrom::End(0))
             .expect("unable to seek to the end of the index");
 
         Ok(Self {
+            name: name.to_string(),
+            dir: path.to_path_buf(),
             log: RefCell::new(log),
             idx: RefCell::new(idx),
             index: RefCell::new(index),
+            sums: None,
+            deks: None,
+            bloom: None,
+            tombstones: None,
+            format: LogFormat::default(),
+            hasher: None,
+            tip: [0u8; 32],
+            observer: None,
+            latencies: None,
+            quota: None,
+            memory: None,
+            write_through: None,
+            idx_buf: None,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            ring: None,
+            #[cfg(feature = "notify")]
+            watcher: None,
             _phantom: PhantomData,
         })
     }
-}
 
-impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize> AoraMap<K, V, KEY_LEN>
-    for FileAoraMap<K, V, MAGIC, VER, KEY_LEN>
-where
-    K: Into<[u8; KEY_LEN]> + From<[u8; KEY_LEN]>,
-    V: Eq + StrictEncode + StrictDecode,
-{
-    fn len(&self) -> usize { self.index.borrow().len() }
+    fn meta_path(path: impl AsRef<Path>, name: &str) -> PathBuf {
+        path.as_ref().join(name).with_extension("meta")
+    }
 
-    fn contains_key(&self, key: K) -> bool { self.index.borrow().contains_key(&key.into()) }
+    fn bloom_path(path: impl AsRef<Path>, name: &str) -> PathBuf {
+        path.as_ref().join(name).with_extension("bloom")
+    }
 
-    fn get(&self, key: K) -> Option<V> {
+    fn deks_path(path: impl AsRef<Path>, name: &str) -> PathBuf {
+        path.as_ref().join(name).with_extension("dek")
+    }
+
+    fn tomb_path(path: impl AsRef<Path>, name: &str) -> PathBuf {
+        path.as_ref().join(name).with_extension("tomb")
+    }
+
+    fn sparse_path(path: impl AsRef<Path>, name: &str) -> PathBuf {
+        path.as_ref().join(name).with_extension("sidx")
+    }
+
+    /// Creates a new log with the records format and behaviour defined by the options.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(path, opts), err))]
+    pub fn create_with(path: impl AsRef<Path>, name: &str, opts: LogOptions) -> io::Result<Self> {
+        let path = path.as_ref();
+        let meta = Self::meta_path(path, name);
+        if fs::exists(&meta)? {
+            return Err(io::Error::other(AoraMapError::PartiallyExists {
+                name: name.to_string(),
+                path: path.display().to_string(),
+            }));
+        }
+        if opts.format.sealed_keys && !opts.format.encrypted {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "index keys can't be encrypted without encrypting the values",
+            ));
+        }
+        if opts.format.sealed_keys && opts.sparse_step.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sparse index can't be used with encrypted index keys",
+            ));
+        }
+        if opts.format.shreddable && !opts.format.encrypted {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "values can't be shreddable without being encrypted",
+            ));
+        }
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if opts.io_uring.is_some() && opts.segment_size.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "io_uring can't be used with segmented logs",
+            ));
+        }
+        if opts.write_through && opts.segment_size.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "write-through can't be used with segmented logs",
+            ));
+        }
+        #[cfg(target_os = "linux")]
+        if opts.direct_io && opts.segment_size.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "direct I/O can't be used with segmented logs",
+            ));
+        }
+        let mut me = Self::create_files(path, name, opts.segment_size)?;
+        opts.format.save::<MAGIC, VER>(&meta)?;
+        if let Some(step) = opts.sparse_step {
+            let (_, idx) = Self::prepare(path, name);
+            let index = SparseIndex::open(&idx, &Self::sparse_path(path, name), step)?;
+            *me.index.get_mut() = KeyIndex::Sparse(index);
+        }
+        if let Some((keys, bytes)) = opts.capacity {
+            me.reserve(keys, bytes)?;
+        }
+        if let Some((expected, fp_rate)) = opts.bloom {
+            let bloom = Self::bloom_path(path, name);
+            me.bloom = Some(BloomFilter::create_new(&bloom, expected, fp_rate)?);
+        }
+        if opts.format.shreddable {
+            let deks = Self::deks_path(path, name);
+            let file = BinFile::create_new(&deks).map_err(|err| {
+                io::Error::new(err.kind(), format!("data key file '{}'", deks.display()))
+            })?;
+            me.deks = Some(RefCell::new(file));
+        }
+        if opts.write_through {
+            me.open_write_through(path, name)?;
+        }
+        #[cfg(target_os = "linux")]
+        if opts.direct_io {
+            me.open_direct(path, name)?;
+        }
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some(entries) = opts.io_uring {
+            me.setup_ring(entries)?;
+        }
+        if let Some(chunk) = opts.idx_chunk {
+            me.coalesce_index(chunk)?;
+        }
+        #[cfg(feature = "notify")]
+        if opts.watch {
+            me.open_watcher(path, name)?;
+        }
+        me.format = opts.format;
+        me.hasher = opts.hasher;
+        Ok(me)
+    }
+
+    /// Opens an existing log, checking that the options match the format the log was created
+    /// with.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(path, opts), err))]
+    pub fn open_with(path: impl AsRef<Path>, name: &str, opts: LogOptions) -> io::Result<Self> {
+        let path = path.as_ref();
+        let format = LogFormat::load::<MAGIC, VER>(&Self::meta_path(path, name))?;
+        if let (Some(schema), Some(expected)) = (format.schema, opts.format.schema) {
+            if schema != expected {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "AORA log database '{name}' at '{}' stores values with schema {}, while \
+                         {} is expected",
+                        path.display(),
+                        schema.to_hex(),
+                        expected.to_hex()
+                    ),
+                ));
+            }
+        }
+        if format.flags() != opts.format.flags() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "AORA log database '{name}' at '{}' has format {format:?}, which doesn't \
+                     match the provided options",
+                    path.display()
+                ),
+            ));
+        }
+        let dict = opts.format.dict.as_ref();
+        if format.codec != opts.format.codec
+            || dict.is_some_and(|dict| Some(dict) != format.dict.as_ref())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "AORA log database '{name}' at '{}' is compressed with another codec or \
+                     dictionary than the provided ones",
+                    path.display()
+                ),
+            ));
+        }
+        if format.sealed_keys && opts.sparse_step.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sparse index can't be used with encrypted index keys",
+            ));
+        }
+        let format = LogFormat {
+            nonce: format.nonce,
+            codec: format.codec,
+            dict: format.dict,
+            ..opts.format
+        };
+        let mut me = Self::open_files(path, name, opts.sparse_step)?;
+        me.format = format.clone();
+        me.hasher = opts.hasher;
+        if format.shreddable {
+            me.open_deks(path, name)?;
+        }
+        if let Some((keys, bytes)) = opts.capacity {
+            me.reserve(keys, bytes)?;
+        }
+        if opts.write_through {
+            me.open_write_through(path, name)?;
+        }
+        #[cfg(target_os = "linux")]
+        if opts.direct_io {
+            me.open_direct(path, name)?;
+        }
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some(entries) = opts.io_uring {
+            me.setup_ring(entries)?;
+        }
+        if let Some(chunk) = opts.idx_chunk {
+            me.coalesce_index(chunk)?;
+        }
+        #[cfg(feature = "notify")]
+        if opts.watch {
+            me.open_watcher(path, name)?;
+        }
+        if let (true, KeyIndex::Full(index)) = (format.sealed_keys, me.index.get_mut()) {
+            // The sealed keys of the duplicate entries differ, so each entry got its own place
+            *index = FullIndex::load(index.iter().enumerate().map(|(no, (key, pos))| {
+                let mut key = *key;
+                format.seal_key(no, &mut key);
+                (key, *pos)
+            }));
+        }
+        let tomb = Self::tomb_path(path, name);
+        if fs::exists(&tomb)? {
+            me.tombstones = Some(Tombstones::open(&tomb)?);
+        }
+        let bloom = Self::bloom_path(path, name);
+        if fs::exists(&bloom)? {
+            me.bloom = Some(BloomFilter::open(&bloom)?);
+        } else if let Some((expected, fp_rate)) = opts.bloom {
+            let index = me.index.get_mut();
+            let mut filter =
+                BloomFilter::create_new(&bloom, expected.max(index.len() as u64), fp_rate)?;
+            for no in 0..index.len() {
+                if let Some((key, _)) = index.get_index(no)? {
+                    filter.insert(&key)?;
+                }
+            }
+            me.bloom = Some(filter);
+        }
+        if let (true, Some(hasher)) = (format.chained, opts.hasher) {
+            let last = me.index.borrow().last()?;
+            if let Some((key, pos)) = last {
+                let mut log = me.log.borrow_mut();
+                log.seek(SeekFrom::Start(pos))?;
+                let (header, payload) = read_record(&format, &mut *log)?;
+                me.tip = format.link(&header, hasher, &key, &payload);
+            }
+        }
+        Ok(me)
+    }
+
+    /// Preallocates the index and log files and reserves the in-memory index capacity for the
+    /// given number of keys and bytes of values to be appended.
+    fn reserve(&mut self, keys: u64, bytes: u64) -> io::Result<()> {
+        preallocate(self.idx.get_mut(), keys * (KEY_LEN as u64 + 8))?;
+        self.log.get_mut().preallocate(bytes)?;
+        self.index.get_mut().reserve(keys as usize);
+        Ok(())
+    }
+
+    /// Opens the log and index files for the write-through appends.
+    fn open_write_through(&mut self, path: &Path, name: &str) -> io::Result<()> {
+        if matches!(self.log.get_mut(), LogFile::Segmented(_)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "write-through can't be used with segmented logs",
+            ));
+        }
+        let (log, idx) = Self::prepare(path, name);
+        let log = open_write_through(&log)
+            .map_err(|err| io::Error::new(err.kind(), format!("log file '{}'", log.display())))?;
+        let idx = open_write_through(&idx)
+            .map_err(|err| io::Error::new(err.kind(), format!("index file '{}'", idx.display())))?;
+        let deks = if self.deks.is_some() {
+            let deks = Self::deks_path(path, name);
+            let file = open_write_through(&deks).map_err(|err| {
+                io::Error::new(err.kind(), format!("data key file '{}'", deks.display()))
+            })?;
+            Some(file)
+        } else {
+            None
+        };
+        self.write_through = Some(WriteThrough { log, idx, deks });
+        Ok(())
+    }
+
+    /// Starts buffering the index entries, writing them to the index file in chunks.
+    fn coalesce_index(&mut self, chunk: usize) -> io::Result<()> {
+        if matches!(self.index.get_mut(), KeyIndex::Sparse(_)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "index write coalescing can't be used with sparse index",
+            ));
+        }
+        if self.write_through.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "index write coalescing can't be used with write-through",
+            ));
+        }
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if self.ring.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "index write coalescing can't be used with io_uring",
+            ));
+        }
+        // The data keys are read by the record numbers, so they can't be buffered together with
+        // the index entries
+        if self.deks.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "index write coalescing can't be used with shreddable logs",
+            ));
+        }
+        self.idx_buf = Some((RefCell::new(Vec::with_capacity(chunk)), chunk));
+        Ok(())
+    }
+
+    /// Writes the index entries buffered by the index write coalescing to the index file.
+    fn write_idx_buf(&self) -> io::Result<()> {
+        let Some((buf, _)) = &self.idx_buf else {
+            return Ok(());
+        };
+        let mut buf = buf.borrow_mut();
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let mut idx = self.idx.borrow_mut();
+        idx.seek(SeekFrom::End(0))?;
+        idx.write_all(&buf)?;
+        buf.clear();
+        Ok(())
+    }
+
+    /// Opens the log for the appends bypassing the page cache.
+    #[cfg(target_os = "linux")]
+    fn open_direct(&mut self, path: &Path, name: &str) -> io::Result<()> {
+        if matches!(self.log.get_mut(), LogFile::Segmented(_)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "direct I/O can't be used with segmented logs",
+            ));
+        }
+        if self.write_through.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "direct I/O can't be used with write-through",
+            ));
+        }
+        // The padding of an interrupted tail write is trimmed down to the end of the last record
+        let end = match self.index.get_mut().last()? {
+            Some((_, pos)) => {
+                let log = self.log.get_mut();
+                log.seek(SeekFrom::Start(pos))?;
+                read_record(&self.format, log)?;
+                log.stream_position()?
+            }
+            None => 10,
+        };
+        let (log, _) = Self::prepare(path, name);
+        let file = BinFile::open_rw(&log).map_err(|err| {
+            io::Error::new(err.kind(), format!("log file '{}'", log.display()))
+        })?;
+        *self.log.get_mut() = LogFile::Direct(file, DirectAppender::open(&log, end)?);
+        Ok(())
+    }
+
+    /// Starts watching the log and index files for the changes made by other processes.
+    #[cfg(feature = "notify")]
+    fn open_watcher(&mut self, path: &Path, name: &str) -> io::Result<()> {
+        if matches!(self.log.get_mut(), LogFile::Segmented(_)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "change watching can't be used with segmented logs",
+            ));
+        }
+        let (log, idx) = Self::prepare(path, name);
+        self.watcher = Some(ChangeWatcher::new(&[&log, &idx])?);
+        Ok(())
+    }
+
+    /// Sets up the io_uring instance used for the log I/O.
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    fn setup_ring(&mut self, entries: u32) -> io::Result<()> {
+        if matches!(self.log.get_mut(), LogFile::Segmented(_)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "io_uring can't be used with segmented logs",
+            ));
+        }
+        if matches!(self.log.get_mut(), LogFile::Direct(..)) || self.write_through.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "io_uring can't be used with direct I/O or write-through",
+            ));
+        }
+        self.ring = Some(Ring::new(entries)?);
+        Ok(())
+    }
+
+    /// Reports the sizes of the log and index files and the number of the records.
+    pub fn stats(&self) -> io::Result<StorageStats> {
+        Ok(StorageStats {
+            log_size: self.log.borrow().disk_size()?,
+            idx_size: self.idx_size()?,
+            records: self.index.borrow().len(),
+            pages: 0,
+            pending: 0,
+            latency: self.latencies.as_ref().map(Latencies::stats),
+        })
+    }
+
+    /// Returns the total size of the index file and the auxiliary files, in bytes.
+    fn idx_size(&self) -> io::Result<u64> {
+        let mut idx_size = self.idx.borrow().metadata()?.len() + self.index.borrow().disk_size()?;
+        if let Some((buf, _)) = &self.idx_buf {
+            idx_size += buf.borrow().len() as u64;
+        }
+        if let Some((sums, _)) = &self.sums {
+            idx_size += sums.borrow().metadata()?.len();
+        }
+        if let Some(deks) = &self.deks {
+            idx_size += deks.borrow().metadata()?.len();
+        }
+        if let Some(bloom) = &self.bloom {
+            idx_size += bloom.disk_size();
+        }
+        Ok(idx_size)
+    }
+
+    /// Limits the total size of the log, index and auxiliary files of the map to the given
+    /// number of bytes. Once appending an item would make the files larger, [`Self::try_insert`]
+    /// fails with [`AoraError::QuotaExceeded`] without writing anything, while
+    /// [`AoraMap::insert`] and [`Self::insert_batch`] panic.
+    ///
+    /// The quota is not persisted and applies only to the appends made through this map.
+    pub fn set_quota(&mut self, limit: u64) { self.quota = Some(limit); }
+
+    /// Checks that appending the given number of bytes doesn't make the files of the map exceed
+    /// the quota.
+    fn check_quota(&self, bytes: u64) -> Result<(), AoraError> {
+        let Some(limit) = self.quota else {
+            return Ok(());
+        };
+        let size = self.log.borrow().disk_size()? + self.idx_size()? + bytes;
+        if size > limit {
+            return Err(AoraError::QuotaExceeded { table: self.name.clone(), size, limit });
+        }
+        Ok(())
+    }
+
+    /// Enables tracking of the operation latencies, which are reported by [`Self::stats`].
+    pub fn track_latencies(&mut self) { self.latencies = Some(Latencies::default()); }
+
+    /// Returns the keys in the append order together with the log positions and the lengths of
+    /// their records.
+    pub fn to_dump(&self) -> io::Result<FileAoraMapDump<KEY_LEN>> {
         let index = self.index.borrow();
-        let pos = index.get(&key.into())?;
+        let entries = (0..index.len())
+            .map(|no| {
+                let entry = index.get_index(no)?;
+                entry.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("index of the log '{}' misses entry #{no}", self.name),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        // The records follow each other, so each one ends where the next one in the log starts,
+        // which may be not the next one in the index once a key got a duplicate entry
+        let end = self.log.borrow_mut().seek(SeekFrom::End(0))?;
+        let mut bounds = entries.iter().map(|(_, pos)| *pos).collect::<Vec<_>>();
+        bounds.sort_unstable();
+        let records = entries
+            .iter()
+            .map(|(key, pos)| {
+                let next = bounds
+                    .get(bounds.partition_point(|bound| bound <= pos))
+                    .map_or(end, |next| *next);
+                RecordDump { key: *key, pos: *pos, len: next.saturating_sub(*pos) }
+            })
+            .collect();
+        Ok(FileAoraMapDump { records })
+    }
 
-        let mut log = self.log.borrow_mut();
-        log.seek(SeekFrom::Start(*pos))
-            .expect("unable to seek to the item");
-        let mut reader = StrictReader::with(StreamReader::new::<{ usize::MAX }>(&mut *log));
-        let value = V::strict_decode(&mut reader).expect("unable to read item");
-        Some(value)
+    /// Reads all the entries of the index file in the order they were appended, including the
+    /// duplicated keys, which are not present in the in-memory index.
+    fn read_idx_entries(&self) -> io::Result<Vec<([u8; KEY_LEN], u64)>> {
+        self.write_idx_buf()?;
+        let mut idx = self.idx.borrow_mut();
+        idx.seek(SeekFrom::Start(10))?;
+        let mut data = Vec::new();
+        let res = idx.read_to_end(&mut data);
+        idx.seek(SeekFrom::End(0))?;
+        res?;
+        Ok(data
+            .chunks_exact(KEY_LEN + 8)
+            .enumerate()
+            .map(|(no, entry)| {
+                let mut key = [0u8; KEY_LEN];
+                key.copy_from_slice(&entry[..KEY_LEN]);
+                self.format.seal_key(no, &mut key);
+                let mut pos = [0u8; 8];
+                pos.copy_from_slice(&entry[KEY_LEN..]);
+                (key, u64::from_le_bytes(pos))
+            })
+            .collect())
     }
 
-    fn insert(&mut self, key: K, value: &V) {
-        let key = key.into();
-        if self.index.borrow().contains_key(&key) {
-            let old = self.get(key.into());
-            if old.as_ref() != Some(value) {
-                panic!(
-                    "item under the given id is different from another item under the same id \
-                     already present in the log"
-                );
+    /// Validates the whole database: re-reads and decodes every record, verifying its hash (if
+    /// the log stores value hashes) and the hash chain (for the hash-chained logs), and re-derives
+    /// the record positions from the log, comparing them with the entries of the index file.
+    ///
+    /// The check is slow for large databases, but makes it possible to trust the backups.
+    pub fn check_all(&self) -> io::Result<CheckReport>
+    where V: StrictEncode + StrictDecode {
+        let entries = self.read_idx_entries()?;
+        let mut issues = Vec::new();
+        let keys = self.index.borrow().len();
+        if entries.len() != keys {
+            issues.push(CheckIssue::EntryCount { idx_entries: entries.len(), keys });
+        }
+
+        let mut log = self.log.borrow_mut();
+        log.advise(Access::Sequential);
+        let end = log.seek(SeekFrom::End(0))?;
+        // Position where the next record starts, according to the log
+        let mut next = 10;
+        for (no, (key, pos)) in entries.iter().enumerate() {
+            let (key, pos) = (key.to_hex(), *pos);
+            if pos != next {
+                issues.push(CheckIssue::PositionMismatch {
+                    no,
+                    key: key.clone(),
+                    idx_pos: pos,
+                    log_pos: next,
+                });
+            }
+            // The next record is looked for after the one pointed by the index entry, so a single
+            // damaged entry doesn't make all the following ones mismatch
+            next = entries.get(no + 1).map_or(end, |(_, pos)| *pos);
+            if pos >= end {
+                issues.push(CheckIssue::DanglingEntry { no, key, pos });
+                continue;
+            }
+            log.seek(SeekFrom::Start(pos))?;
+            let id = &entries[no].0;
+            let res = read_data_key(self.deks.as_ref(), no, id, pos).and_then(|wrapped| {
+                read_timed_value::<V>(&self.format, &mut *log, id, wrapped.as_ref(), pos)
+            });
+            let value = match res {
+                Ok((value, _)) => value,
+                // The destroyed values are not a sign of corruption
+                Err(AoraError::Redacted { .. } | AoraError::Shredded { .. }) => continue,
+                Err(err) => {
+                    issues.push(CheckIssue::BadRecord { key, pos, error: err.to_string() });
+                    continue;
+                }
+            };
+            next = log.stream_position()?;
+            if let Some((sums, hasher)) = &self.sums {
+                let mut sums = sums.borrow_mut();
+                let mut expected = [0u8; 32];
+                sums.seek(SeekFrom::Start(10 + no as u64 * 32))?;
+                let valid = sums.read_exact(&mut expected).is_ok()
+                    && hasher(&Self::encode(&value)) == expected;
+                if !valid {
+                    let error = AoraError::HashMismatch { key: key.clone(), pos }.to_string();
+                    issues.push(CheckIssue::BadRecord { key, pos, error });
+                }
             }
-            return;
         }
-        let log = self.log.get_mut();
+        if next < end {
+            issues.push(CheckIssue::UnindexedTail { pos: next, len: end - next });
+        }
+        log.advise(Access::Random);
+        drop(log);
+
+        if self.format.chained && self.hasher.is_some() {
+            if let Err(err) = self.verify_chain() {
+                let (key, pos) = match &err {
+                    AoraError::ChainBroken { key, pos } => (key.clone(), *pos),
+                    _ => (s!("~"), 0),
+                };
+                issues.push(CheckIssue::BadRecord { key, pos, error: err.to_string() });
+            }
+        }
+        Ok(CheckReport { entries: entries.len(), issues })
+    }
+
+    /// Closes the map, syncing its files to the storage device and reporting the failures, which
+    /// are lost when the map is just dropped.
+    pub fn close(mut self) -> Result<(), AoraError> {
+        self.sync()?;
+        Ok(())
+    }
+
+    /// Flushes the buffered writes to the log and index files to the operating system.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_idx_buf()?;
+        self.log.get_mut().flush()?;
+        self.idx.get_mut().flush()?;
+        if let Some((sums, _)) = &mut self.sums {
+            sums.get_mut().flush()?;
+        }
+        if let Some(deks) = &mut self.deks {
+            deks.get_mut().flush()?;
+        }
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the buffered writes and syncs all the files of the map to the storage device,
+    /// making the inserted items durable. The other handles used for appending (write-through,
+    /// direct I/O) refer to the same files, so their writes get synced too.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.flush()?;
+        // The filter gets durable first, so it doesn't miss the keys of a durable index
+        if let Some(bloom) = &self.bloom {
+            bloom.sync()?;
+        }
+        self.log.get_mut().sync_all()?;
+        self.idx.get_mut().sync_all()?;
+        if let Some((sums, _)) = &mut self.sums {
+            sums.get_mut().sync_all()?;
+        }
+        if let Some(deks) = &mut self.deks {
+            deks.get_mut().sync_all()?;
+        }
+        if let Some(tombstones) = &self.tombstones {
+            tombstones.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Loads the records and the tombstones appended to the log by another process since the map
+    /// was opened or last refreshed, returning the number of the new keys.
+    ///
+    /// Only the complete index entries are loaded, so the map may be refreshed while the other
+    /// process is appending.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`io::ErrorKind::Unsupported`] for the segmented logs, and with I/O errors if
+    /// the files can't be read.
+    pub fn refresh(&mut self) -> io::Result<usize> {
+        if matches!(self.log.get_mut(), LogFile::Segmented(_)) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("segmented log '{}' can't be refreshed", self.name),
+            ));
+        }
+        self.write_idx_buf()?;
+        self.reload_tombstones()?;
+        let entry_len = KEY_LEN as u64 + 8;
+        let known = self.index.get_mut().len();
+        let start = 10 + self.index.get_mut().entries() as u64 * entry_len;
         let idx = self.idx.get_mut();
+        let count = idx.seek(SeekFrom::End(0))?.saturating_sub(start) / entry_len;
+        if count == 0 {
+            return Ok(0);
+        }
+        let mut data = vec![0u8; (count * entry_len) as usize];
+        idx.seek(SeekFrom::Start(start))?;
+        let res = idx.read_exact(&mut data);
+        idx.seek(SeekFrom::End(0))?;
+        res?;
 
-        log.seek(SeekFrom::End(0))
-            .expect("unable to seek to the end of the log");
-        let pos = log.stream_position().expect("unable to get log position");
-        let writer = StrictWriter::with(StreamWriter::new::<{ usize::MAX }>(log));
-        value.strict_encode(writer).unwrap();
+        let index = self.index.get_mut();
+        for entry in data.chunks_exact(entry_len as usize) {
+            let mut key = [0u8; KEY_LEN];
+            key.copy_from_slice(&entry[..KEY_LEN]);
+            self.format.seal_key(index.entries(), &mut key);
+            let mut pos = [0u8; 8];
+            pos.copy_from_slice(&entry[KEY_LEN..]);
+            index.insert(key, u64::from_le_bytes(pos))?;
+        }
+        if let Some(bloom) = &mut self.bloom {
+            bloom.reload()?;
+        }
+        if let (true, Some(hasher)) = (self.format.chained, self.hasher) {
+            if let Some((key, pos)) = self.index.get_mut().last()? {
+                let log = self.log.get_mut();
+                log.seek(SeekFrom::Start(pos))?;
+                let (header, payload) = read_record(&self.format, log)?;
+                self.tip = self.format.link(&header, hasher, &key, &payload);
+            }
+        }
+        if let Some((charge, _)) = &mut self.memory {
+            charge.set(self.index.get_mut().memory_size());
+        }
+        Ok(self.index.get_mut().len() - known)
+    }
 
-        idx.seek(SeekFrom::End(0))
-            .expect("unable to seek to the end of the index");
-        idx.write_all(&key).expect("unable to write to index");
-        idx.write_all(&pos.to_le_bytes())
-            .expect("unable to write to index");
+    /// Reads the tombstones appended by another process, opening the tombstone file if it was
+    /// created after the map was opened.
+    fn reload_tombstones(&mut self) -> io::Result<()> {
+        if let Some(tombstones) = &mut self.tombstones {
+            return tombstones.reload();
+        }
+        let path = Self::tomb_path(&self.dir, &self.name);
+        if fs::exists(&path)? {
+            self.tombstones = Some(Tombstones::open(&path)?);
+        }
+        Ok(())
+    }
 
-        self.index.borrow_mut().insert(key, pos);
+    /// Refreshes the map like [`Self::refresh`] if the files may have changed since the last
+    /// refresh.
+    ///
+    /// If the map watches the changes (see [`LogOptions::watch_changes`]), the files are not
+    /// touched unless the watcher has noticed their modification, making the call cheap enough
+    /// to be done before each read. Otherwise, the map is always refreshed.
+    pub fn refresh_if_changed(&mut self) -> io::Result<usize> {
+        #[cfg(feature = "notify")]
+        if let Some(watcher) = &self.watcher {
+            if !watcher.take_changes() {
+                return Ok(0);
+            }
+        }
+        self.refresh()
     }
 
-    fn iter(&self) -> impl Iterator<Item = (K, V)> {
-        let index = self.index.borrow().clone();
-        Iter {
-            log: self.log.borrow_mut(),
-            index: index.into_iter(),
-            _phantom: PhantomData,
+    /// Audits the index file for the keys present in it more than once, which may happen after
+    /// a crash in the middle of an append, reporting the log position the map actually uses for
+    /// each of such keys.
+    pub fn audit_index(&self) -> io::Result<IndexAudit> {
+        let entries = self.read_idx_entries()?;
+        let mut keys = IndexMap::<_, Vec<usize>>::with_capacity(entries.len());
+        for (no, (key, _)) in entries.iter().enumerate() {
+            keys.entry(*key).or_default().push(no);
+        }
+        let index = self.index.borrow();
+        let mut duplicates = Vec::new();
+        for (key, nos) in keys {
+            if nos.len() < 2 {
+                continue;
+            }
+            duplicates.push(DuplicateKey {
+                key: key.to_hex(),
+                positions: nos.iter().map(|no| entries[*no].1).collect(),
+                entries: nos,
+                winner: index.get_full(&key)?.map(|(_, pos)| pos),
+            });
         }
+        Ok(IndexAudit { entries: entries.len(), duplicates })
     }
-}
 
-pub struct Iter<
-    'file,
-    K: From<[u8; KEY_LEN]>,
-    V: StrictDecode,
-    const MAGIC: u64,
-    const VER: u16,
-    const KEY_LEN: usize,
-> {
-    log: RefMut<'file, BinFile<MAGIC, VER>>,
-    index: indexmap::map::IntoIter<[u8; KEY_LEN], u64>,
-    _phantom: PhantomData<(K, V)>,
-}
+    /// Rewrites the index file (and the files with value hashes, data keys and tombstones, if
+    /// present), leaving a single entry per key with the log position the map uses for it. Returns
+    /// the number of the dropped entries.
+    ///
+    /// The files are rewritten in place, so an interrupted rewrite leaves the index damaged;
+    /// back up the `.idx` file before calling the method.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`io::ErrorKind::Unsupported`] if the map uses a sparse index, and with I/O
+    /// errors if the files can't be rewritten.
+    pub fn rewrite_index(&mut self) -> io::Result<usize> {
+        let entries = self.read_idx_entries()?;
+        self.write_index(entries, |_| true)
+    }
 
-impl<
-    K: From<[u8; KEY_LEN]>,
-    V: StrictDecode,
-    const MAGIC: u64,
-    const VER: u16,
-    const KEY_LEN: usize,
-> Iterator for Iter<'_, K, V, MAGIC, VER, KEY_LEN>
-{
-    type Item = (K, V);
+    /// Repairs a damaged index file in place, dropping the entries pointing past the end of the
+    /// log (left by a crash in the middle of an append) and leaving a single entry per key, as
+    /// [`Self::rewrite_index`] does.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`io::ErrorKind::Unsupported`] if the map uses a sparse index, and with I/O
+    /// errors if the files can't be rewritten.
+    pub fn repair_index(&mut self) -> io::Result<IndexRepair> {
+        let entries = self.read_idx_entries()?;
+        let end = self.log.get_mut().seek(SeekFrom::End(0))?;
+        let dangling = entries.iter().filter(|(_, pos)| *pos >= end).count();
+        let dropped = self.write_index(entries, |pos| pos < end)?;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let (id, pos) = self.index.next()?;
-        self.log
-            .seek(SeekFrom::Start(pos))
-            .expect("unable to seek to the iterator position");
+        // The chain tip could be never computed if the last entry was dangling
+        if let (true, Some(hasher)) = (self.format.chained, self.hasher) {
+            let last = self.index.get_mut().last()?;
+            if let Some((key, pos)) = last {
+                let log = self.log.get_mut();
+                log.seek(SeekFrom::Start(pos))?;
+                let (header, payload) = read_record(&self.format, log)?;
+                self.tip = self.format.link(&header, hasher, &key, &payload);
+            }
+        }
+        Ok(IndexRepair { dangling, duplicates: dropped - dangling })
+    }
+
+    /// Rewrites the index file with the `entries` for which `keep` returns true, leaving a
+    /// single entry per key. Returns the number of the dropped entries.
+    fn write_index(
+        &mut self,
+        entries: Vec<([u8; KEY_LEN], u64)>,
+        keep: impl Fn(u64) -> bool,
+    ) -> io::Result<usize> {
+        if !matches!(self.index.get_mut(), KeyIndex::Full(_)) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("index of '{}' can be rewritten only if it is fully in memory", self.name),
+            ));
+        }
+        // As when the index is loaded on open, the later entries override the position of the
+        // earlier ones, but not their place in the append order
+        let mut clean = IndexMap::<_, (u64, usize)>::with_capacity(entries.len());
+        for (no, (key, pos)) in entries.iter().enumerate() {
+            if keep(*pos) {
+                clean.insert(*key, (*pos, no));
+            }
+        }
+
+        let mut data = Vec::with_capacity(clean.len() * (KEY_LEN + 8));
+        for (no, (key, (pos, _))) in clean.iter().enumerate() {
+            let mut key = *key;
+            self.format.seal_key(no, &mut key);
+            data.extend_from_slice(&key);
+            data.extend_from_slice(&pos.to_le_bytes());
+        }
+        replace_content(self.idx.get_mut(), &data)?;
+        // The sorted file of a sparse index no longer matches the index, so it is rebuilt when the
+        // map is opened with a sparse index next time
+        let sparse = Self::sparse_path(&self.dir, &self.name);
+        if fs::exists(&sparse)? {
+            fs::remove_file(&sparse)?;
+        }
+        if let Some((sums, _)) = &mut self.sums {
+            let sums = sums.get_mut();
+            let mut data = Vec::with_capacity(clean.len() * 32);
+            let mut sum = [0u8; 32];
+            for (_, no) in clean.values() {
+                sums.seek(SeekFrom::Start(10 + *no as u64 * 32))?;
+                sums.read_exact(&mut sum)?;
+                data.extend_from_slice(&sum);
+            }
+            replace_content(sums, &data)?;
+        }
+        if let Some(deks) = &mut self.deks {
+            let deks = deks.get_mut();
+            let mut data = Vec::with_capacity(clean.len() * WRAPPED_KEY_LEN);
+            let mut wrapped = [0u8; WRAPPED_KEY_LEN];
+            for (_, no) in clean.values() {
+                deks.seek(SeekFrom::Start(10 + (*no * WRAPPED_KEY_LEN) as u64))?;
+                deks.read_exact(&mut wrapped)?;
+                data.extend_from_slice(&wrapped);
+            }
+            replace_content(deks, &data)?;
+        }
+        if let Some(tombstones) = &mut self.tombstones {
+            // The tombstones refer to the records by their numbers in the current index
+            let index = self.index.get_mut();
+            let mut superseded = Vec::new();
+            for (no, key) in clean.keys().enumerate() {
+                if index
+                    .get_full(key)?
+                    .is_some_and(|(old, _)| tombstones.contains(old))
+                {
+                    superseded.push(no);
+                }
+            }
+            tombstones.replace(&superseded)?;
+        }
+
+        let dropped = entries.len() - clean.len();
+        let index = clean
+            .into_iter()
+            .map(|(key, (pos, _))| (key, pos))
+            .collect::<IndexMap<_, _>>();
+        *self.index.get_mut() = KeyIndex::Full(index.into());
+        if let Some((charge, _)) = &mut self.memory {
+            charge.set(self.index.get_mut().memory_size());
+        }
+        Ok(dropped)
+    }
+
+    /// Produces a human-readable summary of the map in YAML, which can be attached to bug
+    /// reports.
+    ///
+    /// Reads the whole log to find undecodable records and, for the hash-chained logs, checks the
+    /// hash chain.
+    pub fn debug_report(&self) -> io::Result<String>
+    where V: StrictDecode {
+        let stats = self.stats()?;
+        let index = self.index.borrow();
+        let first = index.get_index(0)?.map(|(key, _)| key);
+        let last = index.last()?.map(|(key, _)| key);
+        drop(index);
+
+        let mut anomalies = self
+            .try_iter()
+            .filter_map(Result::err)
+            .map(|err| err.to_string())
+            .collect::<Vec<_>>();
+        if self.format.chained {
+            if let Err(err) = self.verify_chain() {
+                anomalies.push(err.to_string());
+            }
+        }
+
+        let mut report = DebugReport::new::<MAGIC, VER>("FileAoraMap", &self.name);
+        report
+            .field("key_len", KEY_LEN)
+            .field("records", stats.records)
+            .field("log_size", stats.log_size)
+            .field("idx_size", stats.idx_size)
+            .field("segments", self.closed_segments().len())
+            .key("first_key", first.as_ref().map(<[u8; KEY_LEN]>::as_slice))
+            .key("last_key", last.as_ref().map(<[u8; KEY_LEN]>::as_slice))
+            .anomalies(anomalies);
+        Ok(report.to_string())
+    }
+
+    pub(crate) fn latencies(&self) -> Option<&Latencies> { self.latencies.as_ref() }
+
+    /// Charges the memory used by the in-memory index and a cache of the records read from the
+    /// log to the budget. The cache keeps the recently read records until the budget is exceeded,
+    /// evicting the least recently used ones.
+    pub fn set_memory_budget(&mut self, budget: MemoryBudget) {
+        let mut charge = Charge::new(budget.clone());
+        charge.set(self.index.get_mut().memory_size());
+        self.memory = Some((charge, RefCell::new(RecordCache::new(budget))));
+    }
+
+    /// Sets the observer notified about each inserted key.
+    pub fn set_observer(&mut self, observer: impl AoraObserver + Send + Sync + 'static) {
+        self.observer = Some(Observer::new(observer));
+    }
+
+    pub(crate) fn set_shared_observer(&mut self, observer: Observer) {
+        self.observer = Some(observer);
+    }
+
+    /// Returns paths of the closed segments of a segmented log, which are never modified again.
+    /// For the logs which are not segmented returns an empty list.
+    pub fn closed_segments(&self) -> Vec<PathBuf> { self.log.borrow().closed_segments() }
+
+    /// Compresses the closed segments of a segmented log with zstd using the given compression
+    /// level, returning the number of the newly compressed segments. The active segment stays
+    /// uncompressed; for the logs which are not segmented does nothing.
+    ///
+    /// The segment data are compressed in independent frames, so reading a record from a
+    /// compressed segment decompresses just the frame containing it, keeping the memory use
+    /// bounded.
+    #[cfg(feature = "zstd")]
+    pub fn compress_segments(&mut self, level: i32) -> io::Result<usize> {
+        self.log.get_mut().compress_segments(level)
+    }
+
+    /// Returns the hash of the last record in a hash-chained log, which commits to the whole
+    /// log history. Returns `None` if the log is not hash-chained.
+    pub fn chain_tip(&self) -> Option<[u8; 32]> { self.format.chained.then_some(self.tip) }
+
+    /// Returns the identifier of the schema of the values embedded into the log, if any.
+    pub fn schema(&self) -> Option<[u8; 32]> { self.format.schema }
+
+    /// Validates the whole hash chain of a tamper-evident log, checking that each record commits
+    /// to the previous one. Returns the hash of the last record.
+    ///
+    /// # Panics
+    ///
+    /// If the log is not hash-chained.
+    pub fn verify_chain(&self) -> Result<[u8; 32], AoraError> {
+        let hasher = self
+            .hasher
+            .filter(|_| self.format.chained)
+            .expect("the log is not hash-chained");
+        let index = self.index.borrow();
+        let mut log = self.log.borrow_mut();
+        let mut expected = [0u8; 32];
+        for no in 0..index.len() {
+            let Some((key, pos)) = index.get_index(no)? else {
+                break;
+            };
+            log.seek(SeekFrom::Start(pos))?;
+            let (header, payload) = read_record(&self.format, &mut *log)?;
+            if header.prev != expected {
+                return Err(AoraError::ChainBroken { key: key.to_hex(), pos });
+            }
+            expected = self.format.link(&header, hasher, &key, &payload);
+        }
+        Ok(expected)
+    }
+
+    /// Computes Merkle tree leaf hashes for the records in the given range of log positions.
+    fn merkle_leaves<H: AoraHasher>(
+        &self,
+        range: Range<usize>,
+    ) -> impl Iterator<Item = Result<[u8; 32], AoraError>> + '_
+    where
+        V: StrictEncode + StrictDecode,
+    {
+        TryIter(self.iter_range(range, false)).map(|item| {
+            let (key, value) = item?;
+            let mut leaf = key.into().to_vec();
+            leaf.extend(H::digest(&Self::encode(&value)));
+            Ok(merkle_leaf::<H>(&leaf))
+        })
+    }
+
+    /// Computes the root of an RFC 6962 Merkle tree over the first `count` records of the log (in
+    /// the order they were appended), providing a compact commitment to the log state after the
+    /// given number of insertions.
+    ///
+    /// Each leaf is the record key followed by the hash of the encoded value.
+    pub fn merkle_root_at<H: AoraHasher>(&self, count: usize) -> Result<[u8; 32], AoraError>
+    where V: StrictEncode + StrictDecode {
+        let mut builder = MerkleBuilder::<H>::new();
+        for leaf in self.merkle_leaves::<H>(0..count) {
+            builder.push_leaf(leaf?);
+        }
+        Ok(builder.root())
+    }
+
+    /// Computes the root of an RFC 6962 Merkle tree over all records of the log, providing a
+    /// compact commitment to the full log state. See [`Self::merkle_root_at`] for the details.
+    pub fn merkle_root<H: AoraHasher>(&self) -> Result<[u8; 32], AoraError>
+    where V: StrictEncode + StrictDecode {
+        self.merkle_root_at::<H>(usize::MAX)
+    }
+
+    /// Generates a proof of inclusion of the record with the given key into the log with the
+    /// current [`Self::merkle_root`]. Returns `None` if the key is not present.
+    ///
+    /// The proof can be verified with [`InclusionProof::verify`] against the hash of the encoded
+    /// value.
+    pub fn prove_inclusion<H: AoraHasher>(
+        &self,
+        key: K,
+    ) -> Result<Option<InclusionProof>, AoraError>
+    where
+        V: StrictEncode + StrictDecode,
+    {
+        let Some((pos, _)) = self.index.borrow().get_full(&key.into())? else {
+            return Ok(None);
+        };
+        let leaves = self
+            .merkle_leaves::<H>(0..usize::MAX)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(InclusionProof::build::<H>(&leaves, pos)))
+    }
+
+    fn sums_path(path: impl AsRef<Path>, name: &str) -> PathBuf {
+        path.as_ref().join(name).with_extension("sum")
+    }
+
+    /// Creates a new log which stores a hash of each value next to its index entry, and verifies
+    /// it on each read.
+    pub fn create_verified<H: AoraHasher>(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut me = Self::create_new(path, name)?;
+        let sums_path = Self::sums_path(path, name);
+        let sums = BinFile::create_new(&sums_path).map_err(|err| {
+            io::Error::new(err.kind(), format!("hash file '{}'", sums_path.display()))
+        })?;
+        me.sums = Some((RefCell::new(sums), H::digest));
+        Ok(me)
+    }
+
+    /// Opens an existing log verifying hashes of the values on each read.
+    ///
+    /// If the log was created without hashes, they are computed from the current log data, and
+    /// stored from now on. Likewise, the hashes missing at the end of a hash file shorter than the
+    /// index (as after a crash of a map with [`LogOptions::write_through`], whose hashes are
+    /// buffered) are computed from the log.
+    pub fn open_verified<H: AoraHasher>(path: impl AsRef<Path>, name: &str) -> io::Result<Self>
+    where V: StrictEncode + StrictDecode {
+        let path = path.as_ref();
+        let mut me = Self::open(path, name)?;
+        let sums_path = Self::sums_path(path, name);
+        // The hashes are stored per entry of the index file, including the duplicate ones
+        let count = me.index.borrow().entries() as u64;
+        let (mut sums, known) = if fs::exists(&sums_path)? {
+            let mut sums = BinFile::open_rw(&sums_path).map_err(|err| {
+                io::Error::new(err.kind(), format!("hash file '{}'", sums_path.display()))
+            })?;
+            // The hashes written before the index entries of an interrupted append are dropped,
+            // while the ones lost after the index entries were written are re-computed
+            let end = sums.seek(SeekFrom::End(0))?;
+            let known = (end.saturating_sub(10) / 32).min(count);
+            if end != 10 + known * 32 {
+                sums.set_len(10 + known * 32)?;
+                sums.seek(SeekFrom::End(0))?;
+            }
+            (sums, known)
+        } else {
+            let sums = BinFile::create_new(&sums_path).map_err(|err| {
+                io::Error::new(err.kind(), format!("hash file '{}'", sums_path.display()))
+            })?;
+            (sums, 0)
+        };
+        if known < count {
+            let entries = me.read_idx_entries()?;
+            for (entry, (key, pos)) in entries.into_iter().enumerate().skip(known as usize) {
+                let value = me.read_entry(&key, entry, pos).map_err(io::Error::other)?;
+                sums.write_all(&H::digest(&Self::encode(&value)))?;
+            }
+        }
+        me.sums = Some((RefCell::new(sums), H::digest));
+        Ok(me)
+    }
+
+    /// Upgrades the log, whose files have the older version `from_ver`, to the version `VER`,
+    /// rewriting it with each value produced by the migration closure from the strict-encoded
+    /// bytes of the old value. Returns the upgraded log.
+    ///
+    /// The upgraded log has the original (unframed) format, so the logs with a format file,
+    /// value hashes, a Bloom filter or a sparse index are not supported. The log is rewritten in
+    /// a temporary directory, whose files replace the old ones at the end; since the replacement
+    /// is not atomic, the log should be backed up before the upgrade.
+    pub fn upgrade(
+        path: impl AsRef<Path>,
+        name: &str,
+        from_ver: u16,
+        mut migrate: impl FnMut(&[u8]) -> V,
+    ) -> io::Result<Self>
+    where V: Eq + StrictEncode + StrictDecode {
+        let path = path.as_ref();
+        if from_ver == VER {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("AORA log database '{name}' already has version {VER}"),
+            ));
+        }
+        for aux in [
+            Self::meta_path(path, name),
+            Self::sums_path(path, name),
+            Self::bloom_path(path, name),
+            Self::sparse_path(path, name),
+            Self::tomb_path(path, name),
+        ] {
+            if fs::exists(&aux)? {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("file '{}' can't be upgraded", aux.display()),
+                ));
+            }
+        }
+        let layout = TableLayout { kind: TableKind::AppendOnly, key_len: KEY_LEN, val_len: None };
+        let old = FileAoraMapRaw::open_with_layout(path, name, MAGIC, from_ver, layout)?;
+
+        let tmp = path.join(format!("{name}.upgrade"));
+        fs::create_dir(&tmp)?;
+        let mut new = Self::create_new(&tmp, name)?;
+        for item in old.iter() {
+            let (key, value) = item.map_err(io::Error::other)?;
+            let key = <[u8; KEY_LEN]>::try_from(key).expect("fixed length");
+            new.insert(key.into(), &migrate(&value));
+        }
+        new.sync()?;
+        drop(new);
+        drop(old);
+
+        let (log, idx) = Self::prepare(path, name);
+        let layout = TableLayout::path(path, name);
+        for file in [idx, log, layout] {
+            fs::rename(tmp.join(file.file_name().expect("file path")), file)?;
+        }
+        fs::remove_dir(&tmp)?;
+        Self::open(path, name)
+    }
+
+    /// Trains a zstd dictionary of at most `max_size` bytes from the sample values, which can be
+    /// used with [`LogOptions::zstd_dictionary`] to efficiently compress logs of similar values.
+    #[cfg(feature = "zstd")]
+    pub fn train_zstd_dictionary<'v>(
+        samples: impl IntoIterator<Item = &'v V>,
+        max_size: usize,
+    ) -> io::Result<Vec<u8>>
+    where
+        V: StrictEncode + 'v,
+    {
+        let samples = samples.into_iter().map(Self::encode).collect::<Vec<_>>();
+        zstd::dict::from_samples(&samples, max_size)
+    }
+
+    fn encode(value: &V) -> Vec<u8>
+    where V: StrictEncode {
+        value
+            .strict_encode(StrictWriter::in_memory::<{ usize::MAX }>())
+            .expect("unable to encode item")
+            .unbox()
+            .unconfine()
+    }
+
+    /// Retrieves value from the log, reporting I/O failures, undecodable values and (if the log
+    /// stores value hashes) hash mismatches as errors.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all, fields(table = %self.name), err))]
+    pub fn try_get(&self, key: K) -> Result<Option<V>, AoraError>
+    where V: StrictEncode + StrictDecode {
+        let start = Instant::now();
+        let res = self.read(key.into());
+        if let Some(latencies) = &self.latencies {
+            latencies.get.record(start);
+        }
+        res
+    }
+
+    fn read(&self, key: [u8; KEY_LEN]) -> Result<Option<V>, AoraError>
+    where V: StrictEncode + StrictDecode {
+        telemetry::got(&self.name);
+        if self
+            .bloom
+            .as_ref()
+            .is_some_and(|bloom| !bloom.contains(&key))
+        {
+            telemetry::bloom_negative(&self.name);
+            return Ok(None);
+        }
+        let index = self.index.borrow();
+        let Some((no, pos)) = index.get_full(&key)? else {
+            return Ok(None);
+        };
+        self.read_entry(&key, index.entry_no(no), pos).map(Some)
+    }
+
+    /// Reads the value of the `.idx` file entry with the given number and log position.
+    fn read_entry(&self, key: &[u8; KEY_LEN], entry: usize, pos: u64) -> Result<V, AoraError>
+    where V: StrictEncode + StrictDecode {
+        let wrapped = read_data_key(self.deks.as_ref(), entry, key, pos)?;
+        let (value, _) = match &self.memory {
+            Some((_, cache)) => self.read_cached(cache, pos, |mut reader| {
+                read_timed_value(&self.format, &mut reader, key, wrapped.as_ref(), pos)
+            })?,
+            None => {
+                let mut log = self.log.borrow_mut();
+                log.seek(SeekFrom::Start(pos))?;
+                read_timed_value(&self.format, &mut *log, key, wrapped.as_ref(), pos)?
+            }
+        };
+
+        if let Some((sums, hasher)) = &self.sums {
+            let mut sums = sums.borrow_mut();
+            let mut expected = [0u8; 32];
+            sums.seek(SeekFrom::Start(10 + entry as u64 * 32))?;
+            sums.read_exact(&mut expected)?;
+            if hasher(&Self::encode(&value)) != expected {
+                return Err(AoraError::HashMismatch { key: key.to_hex(), pos });
+            }
+        }
+        Ok(value)
+    }
+
+    /// Decodes the record at the given position with `decode`, looking it up in the cache first.
+    ///
+    /// The record following this one in the index isn't necessarily the next one in the log once
+    /// a key got a duplicate entry, so the bytes of a missed record are taken from its decoding.
+    fn read_cached<T>(
+        &self,
+        cache: &RefCell<RecordCache>,
+        pos: u64,
+        decode: impl FnOnce(&mut dyn Read) -> Result<T, AoraError>,
+    ) -> Result<T, AoraError> {
+        let record = cache.borrow_mut().get(pos).map(<[u8]>::to_vec);
+        if let Some(record) = record {
+            telemetry::cache(&self.name, true);
+            return decode(&mut record.as_slice());
+        }
+        telemetry::cache(&self.name, false);
+        let mut log = self.log.borrow_mut();
+        log.seek(SeekFrom::Start(pos))?;
+        let mut reader = Recording { inner: &mut *log, record: vec![] };
+        let value = decode(&mut reader)?;
+        cache.borrow_mut().insert(pos, reader.record);
+        Ok(value)
+    }
+
+    /// Retrieves the values for multiple keys at once, returning `None` for the absent keys.
+    ///
+    /// If the log is opened with [`LogOptions::io_uring`] and is not shreddable, all the records
+    /// (and their hashes, if the log stores them) are read with a single batch of requests;
+    /// otherwise the values are read one by one. A record which a duplicate index entry placed
+    /// after the one of the next key is read separately, after the batch.
+    pub fn get_many(&self, keys: impl IntoIterator<Item = K>) -> Result<Vec<Option<V>>, AoraError>
+    where V: StrictEncode + StrictDecode {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let (Some(ring), None) = (&self.ring, &self.deks) {
+            return self.read_many(ring, keys.into_iter().map(K::into).collect());
+        }
+        keys.into_iter().map(|key| self.try_get(key)).collect()
+    }
+
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    fn read_many(
+        &self,
+        ring: &Ring,
+        keys: Vec<[u8; KEY_LEN]>,
+    ) -> Result<Vec<Option<V>>, AoraError>
+    where
+        V: StrictEncode + StrictDecode,
+    {
+        let index = self.index.borrow();
+        let log = self.log.borrow();
+        let LogFile::Single(file) = &*log else {
+            unreachable!("io_uring is used with a segmented log")
+        };
+        let end = file.metadata()?.len();
+
+        // Index of the key, the entry number and position, and buffers for the record and hash
+        let mut found = Vec::with_capacity(keys.len());
+        let mut separate = Vec::new();
+        for (slot, key) in keys.iter().enumerate() {
+            telemetry::got(&self.name);
+            if self
+                .bloom
+                .as_ref()
+                .is_some_and(|bloom| !bloom.contains(key))
+            {
+                telemetry::bloom_negative(&self.name);
+                continue;
+            }
+            let Some((no, pos)) = index.get_full(key)? else {
+                continue;
+            };
+            // No record starts inside another one, so the record ends not after the start of the
+            // next indexed one. A duplicate entry may place that one before this record, and then
+            // the record is read separately.
+            let entry = index.entry_no(no);
+            match index.get_index(no + 1)? {
+                Some((_, next)) if next <= pos => separate.push((slot, entry, pos)),
+                next => {
+                    let next = next.map_or(end, |(_, next)| next);
+                    found.push((slot, entry, pos, vec![0u8; (next - pos) as usize], [0u8; 32]));
+                }
+            }
+        }
+
+        let sums = self
+            .sums
+            .as_ref()
+            .map(|(sums, hasher)| (sums.borrow(), hasher));
+        let mut reads = Vec::with_capacity(found.len() * 2);
+        for (_, entry, pos, record, sum) in &mut found {
+            reads.push((&**file, *pos, record.as_mut_slice()));
+            if let Some((sums, _)) = &sums {
+                reads.push((&***sums, 10 + *entry as u64 * 32, sum.as_mut_slice()));
+            }
+        }
+        ring.read_at(&mut reads)?;
+        drop(reads);
+
+        let mut values = keys.iter().map(|_| None).collect::<Vec<_>>();
+        for (slot, _, pos, record, sum) in found {
+            let key = &keys[slot];
+            let value = read_value(&self.format, &mut record.as_slice(), key, pos)?;
+            if let Some((_, hasher)) = &sums {
+                if hasher(&Self::encode(&value)) != sum {
+                    return Err(AoraError::HashMismatch { key: key.to_hex(), pos });
+                }
+            }
+            values[slot] = Some(value);
+        }
+        drop(sums);
+        drop(log);
+        for (slot, entry, pos) in separate {
+            values[slot] = Some(self.read_entry(&keys[slot], entry, pos)?);
+        }
+        Ok(values)
+    }
+
+    /// Appends the item to the log like [`AoraMap::insert`], reporting the quota set with
+    /// [`Self::set_quota`] being exceeded as [`AoraError::QuotaExceeded`] instead of panicking.
+    /// Nothing is written if the quota would be exceeded.
+    ///
+    /// If the key is present, but the value of its record was destroyed with [`Self::redact`]
+    /// or [`Self::shred`], it can't be compared with the new one, and the method fails with
+    /// [`AoraError::Redacted`] or [`AoraError::Shredded`] without writing anything.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the item is different from another item under the same key already present in
+    /// the log, or if the files can't be written.
+    pub fn try_insert(&mut self, key: K, value: &V) -> Result<(), AoraError>
+    where V: Eq + StrictEncode + StrictDecode {
+        let key = key.into();
+        if self
+            .index
+            .borrow()
+            .contains_key(&key)
+            .expect("unable to read the index")
+        {
+            let old = match self.try_get(key.into()) {
+                Ok(old) => old,
+                Err(err @ (AoraError::Redacted { .. } | AoraError::Shredded { .. })) => {
+                    return Err(err);
+                }
+                Err(err) => panic!("unable to read item: {err}"),
+            };
+            if old.as_ref() != Some(value) {
+                panic!(
+                    "item under the given id is different from another item under the same id \
+                     already present in the log"
+                );
+            }
+            return Ok(());
+        }
+        let start = Instant::now();
+        let log = self.log.get_mut();
+        log.prepare_append()
+            .expect("unable to start a new log segment");
+        log.seek(SeekFrom::End(0))
+            .expect("unable to seek to the end of the log");
+        let pos = log.stream_position().expect("unable to get log position");
+        let tip = self.tip;
+        let (record, sum, wrapped) = self.make_record(&key, value);
+
+        let mut entry = key.to_vec();
+        // The keys are sealed with the numbers of their entries in the index file
+        self.format.seal_key(self.index.borrow().entries(), &mut entry);
+        entry.extend_from_slice(&pos.to_le_bytes());
+        let sum = sum.as_ref().map_or(&[][..], |sum| sum.as_slice());
+        let wrapped = wrapped.as_ref().map_or(&[][..], |wrapped| wrapped.as_slice());
+        let written = (record.len() + entry.len() + sum.len() + wrapped.len()) as u64;
+        if let Err(err) = self.check_quota(written) {
+            self.tip = tip;
+            return Err(err);
+        }
+        // The filter must not miss a key present in the index, even if the append gets
+        // interrupted
+        if let Some(bloom) = &mut self.bloom {
+            bloom.insert(&key).expect("unable to write to bloom filter");
+        }
+        self.append_data_keys(wrapped);
+        self.append(pos, &record, sum, &entry);
+
+        self.index
+            .get_mut()
+            .insert(key, pos)
+            .expect("unable to update the index");
+        if let Some((charge, cache)) = &mut self.memory {
+            charge.set(self.index.get_mut().memory_size());
+            cache.get_mut().evict();
+        }
+        telemetry::inserted(&self.name, 1);
+        telemetry::written(&self.name, written);
+        if let Some(latencies) = &self.latencies {
+            latencies.insert.record(start);
+        }
+        if let Some(observer) = &self.observer {
+            observer.on_insert(&key);
+        }
+        Ok(())
+    }
+
+    /// Inserts the items, appending all their records to the log with a single write and all
+    /// their index entries with another one. Returns the number of the inserted items.
+    ///
+    /// Items already present in the log with the same values are skipped, as are the repeated
+    /// keys of the batch and the keys whose values were redacted or shredded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an item is different from another item under the same key already present in
+    /// the log or in the batch, or if the batch would exceed the quota set with
+    /// [`Self::set_quota`].
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all, fields(table = %self.name)))]
+    pub fn insert_batch<'a>(&mut self, items: impl IntoIterator<Item = (K, &'a V)>) -> usize
+    where V: 'a + Eq + StrictEncode + StrictDecode {
+        let start = Instant::now();
+        let log = self.log.get_mut();
+        log.prepare_append()
+            .expect("unable to start a new log segment");
+        let pos = log
+            .seek(SeekFrom::End(0))
+            .expect("unable to seek to the end of the log");
+
+        let tip = self.tip;
+        let mut batch = IndexMap::<[u8; KEY_LEN], (u64, Vec<u8>)>::new();
+        let mut records = Vec::new();
+        let mut sums = Vec::new();
+        let mut deks = Vec::new();
+        let mut entries = Vec::new();
+        for (key, value) in items {
+            let key = key.into();
+            let exists = self
+                .index
+                .borrow()
+                .contains_key(&key)
+                .expect("unable to read the index");
+            let known = if exists {
+                match self.try_get(key.into()) {
+                    Ok(old) => old.as_ref() == Some(value),
+                    Err(AoraError::Redacted { .. } | AoraError::Shredded { .. }) => true,
+                    Err(err) => panic!("unable to read item: {err}"),
+                }
+            } else if let Some((_, encoded)) = batch.get(&key) {
+                *encoded == Self::encode(value)
+            } else {
+                let (record, sum, wrapped) = self.make_record(&key, value);
+                let mut entry = key.to_vec();
+                self.format
+                    .seal_key(self.index.borrow().entries() + batch.len(), &mut entry);
+                entry.extend_from_slice(&(pos + records.len() as u64).to_le_bytes());
+                batch.insert(key, (pos + records.len() as u64, Self::encode(value)));
+                records.extend(record);
+                sums.extend(sum.iter().flatten());
+                deks.extend(wrapped.iter().flatten());
+                entries.extend(entry);
+                continue;
+            };
+            if !known {
+                panic!(
+                    "item under the given id is different from another item under the same id \
+                     already present in the log"
+                );
+            }
+        }
+        if batch.is_empty() {
+            return 0;
+        }
+        let written = records.len() + sums.len() + deks.len() + entries.len();
+        if let Err(err) = self.check_quota(written as u64) {
+            self.tip = tip;
+            panic!("unable to insert items: {err}");
+        }
+        if let Some(bloom) = &mut self.bloom {
+            for key in batch.keys() {
+                bloom.insert(key).expect("unable to write to bloom filter");
+            }
+        }
+        self.append_data_keys(&deks);
+        self.append(pos, &records, &sums, &entries);
+
+        let index = self.index.get_mut();
+        for (key, (pos, _)) in &batch {
+            index.insert(*key, *pos).expect("unable to update the index");
+        }
+        if let Some((charge, cache)) = &mut self.memory {
+            charge.set(self.index.get_mut().memory_size());
+            cache.get_mut().evict();
+        }
+        telemetry::inserted(&self.name, batch.len() as u64);
+        telemetry::written(&self.name, (records.len() + sums.len() + entries.len()) as u64);
+        if let Some(latencies) = &self.latencies {
+            latencies.insert.record(start);
+        }
+        if let Some(observer) = &self.observer {
+            for key in batch.keys() {
+                observer.on_insert(key);
+            }
+        }
+        batch.len()
+    }
+
+    /// Encodes the value into the record to be appended to the log, returning it together with
+    /// the hash of the value, if the log stores the value hashes, and the wrapped data key, if the
+    /// log is shreddable. Advances the hash chain.
+    fn make_record(&mut self, key: &[u8; KEY_LEN], value: &V) -> Record
+    where V: StrictEncode {
+        let data = Self::encode(value);
+        let sum = self.sums.as_ref().map(|(_, hasher)| hasher(&data));
+        let (codec, data) = self.format.compress(data);
+        let (data, wrapped) = self.format.seal(key, data);
+        if !self.format.is_framed() {
+            return (data, sum, wrapped);
+        }
+        let time = match self.format.timestamped {
+            true => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("system time is before the Unix epoch")
+                .as_nanos() as u64,
+            false => 0,
+        };
+        let mut flags = RecordFlags::default();
+        if codec != Codec::Raw {
+            flags.0 |= RecordFlags::COMPRESSED;
+        }
+        if self.format.encrypted {
+            flags.0 |= RecordFlags::ENCRYPTED;
+        }
+        let header = RecordHeader { len: data.len() as u32, flags, codec, time, prev: self.tip };
+        let mut record = Vec::with_capacity(data.len() + 46);
+        self.format
+            .write_header(&mut record, &header)
+            .expect("unable to write to log");
+        if let Some(hasher) = self.hasher {
+            self.tip = self.format.link(&header, hasher, key, &data);
+        }
+        record.extend_from_slice(&data);
+        (record, sum, wrapped)
+    }
+
+    /// Appends the wrapped data keys of the records to be appended to the log. They are written
+    /// before the index entries, so the keys left by an interrupted append are dropped on open.
+    fn append_data_keys(&mut self, wrapped: &[u8]) {
+        let Some(deks) = &mut self.deks else {
+            return;
+        };
+        let deks: &mut dyn Write = match &mut self.write_through {
+            Some(WriteThrough { deks: Some(through), .. }) => through,
+            _ => {
+                let deks = deks.get_mut();
+                deks.seek(SeekFrom::End(0))
+                    .expect("unable to seek to the end of the data key file");
+                deks
+            }
+        };
+        deks.write_all(wrapped)
+            .expect("unable to write to data key file");
+    }
+
+    /// Opens the file with the wrapped data keys of a shreddable log, dropping the keys left by
+    /// an interrupted append.
+    fn open_deks(&mut self, path: &Path, name: &str) -> io::Result<()> {
+        let path = Self::deks_path(path, name);
+        let mut deks = BinFile::open_rw(&path).map_err(|err| {
+            io::Error::new(err.kind(), format!("data key file '{}'", path.display()))
+        })?;
+        let len = 10 + (self.index.get_mut().entries() * WRAPPED_KEY_LEN) as u64;
+        let end = deks.seek(SeekFrom::End(0))?;
+        if end < len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("data key file '{}' doesn't match the index", path.display()),
+            ));
+        }
+        if end > len {
+            deks.set_len(len)?;
+        }
+        self.deks = Some(RefCell::new(deks));
+        Ok(())
+    }
+
+    /// Writes the records starting at the given log position, followed by the hashes of their
+    /// values and their index entries.
+    fn append(&mut self, pos: u64, records: &[u8], sums: &[u8], entries: &[u8]) {
+        let sums = match &mut self.sums {
+            Some((file, _)) if !sums.is_empty() => Some((file.get_mut(), sums)),
+            _ => None,
+        };
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some(ring) = &self.ring {
+            let LogFile::Single(log) = self.log.get_mut() else {
+                unreachable!("io_uring is used with a segmented log")
+            };
+            let idx = self.idx.get_mut();
+            let idx_end = idx.metadata().expect("unable to read the index").len();
+            let mut writes = vec![(&**log, pos, records)];
+            if let Some((sums, sum)) = &sums {
+                let sums_end = sums.metadata().expect("unable to read the hash file").len();
+                writes.push((&***sums, sums_end, *sum));
+            }
+            ring.write_at(&writes).expect("unable to write to log");
+            // The requests of a batch may complete in any order, so the index entries are
+            // submitted only after the records are written, as with the plain writes
+            ring.write_at(&[(&**idx, idx_end, entries)])
+                .expect("unable to write to index");
+            return;
+        }
+
+        let log = self.log.get_mut();
+        // The reader handles may share the file cursor with the log handle (as on Windows)
+        log.seek(SeekFrom::Start(pos))
+            .expect("unable to seek to the end of the log");
+        let mut log: &mut dyn Write = log;
+        if let Some(through) = &mut self.write_through {
+            log = &mut through.log;
+        }
+        log.write_all(records).expect("unable to write to log");
+        if let Some((sums, sum)) = sums {
+            sums.seek(SeekFrom::End(0))
+                .expect("unable to seek to the end of the hash file");
+            sums.write_all(sum).expect("unable to write to hash file");
+        }
+        if let Some((buf, chunk)) = &mut self.idx_buf {
+            let buf = buf.get_mut();
+            buf.extend_from_slice(entries);
+            if buf.len() >= *chunk {
+                self.write_idx_buf().expect("unable to write to index");
+            }
+            return;
+        }
+        let idx: &mut dyn Write = match &mut self.write_through {
+            Some(through) => &mut through.idx,
+            None => {
+                let idx = self.idx.get_mut();
+                idx.seek(SeekFrom::End(0))
+                    .expect("unable to seek to the end of the index");
+                idx
+            }
+        };
+        idx.write_all(entries).expect("unable to write to index");
+    }
+
+    pub(super) fn iter_range(
+        &self,
+        range: Range<usize>,
+        rev: bool,
+    ) -> Iter<'_, K, V, MAGIC, VER, KEY_LEN>
+    where
+        V: StrictDecode,
+    {
+        let index = self.index.borrow();
+        let range = range.start.min(index.len())..range.end.min(index.len());
+        let mut log = self.log.borrow_mut();
+        if !rev {
+            log.advise(Access::Sequential);
+        }
+        Iter {
+            log,
+            index,
+            range,
+            rev,
+            format: &self.format,
+            tombstones: None,
+            deks: self.deks.as_ref(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the key and value pairs in the order they were appended to the
+    /// log, starting from the given key (inclusive). If the key is not present, the iterator is
+    /// empty.
+    pub fn iter_from(&self, key: K) -> impl Iterator<Item = (K, V)> + '_
+    where V: StrictDecode {
+        let from = self
+            .index
+            .borrow()
+            .get_full(&key.into())
+            .expect("unable to read the index")
+            .map_or(usize::MAX, |(no, _)| no);
+        self.iter_range(from..usize::MAX, false)
+    }
+
+    /// Returns the time the record with the given key was appended to the log, or `None` if the
+    /// key is absent or the log was created without [`LogOptions::timestamps`].
+    pub fn inserted_at(&self, key: K) -> Option<SystemTime> {
+        if !self.format.timestamped {
+            return None;
+        }
+        let (_, pos) = self
+            .index
+            .borrow()
+            .get_full(&key.into())
+            .expect("unable to read the index")?;
+        let mut log = self.log.borrow_mut();
+        log.seek(SeekFrom::Start(pos)).expect("unable to read the log");
+        let header = self
+            .format
+            .read_header(&mut *log)
+            .expect("unable to read the log");
+        Some(UNIX_EPOCH + Duration::from_nanos(header.time))
+    }
+
+    /// Returns the flags of the record with the given key, or `None` if the key is absent or the
+    /// log was created without [`LogOptions::record_flags`].
+    pub fn record_flags(&self, key: K) -> Option<RecordFlags> {
+        if !self.format.flagged {
+            return None;
+        }
+        let (no, pos) = self
+            .index
+            .borrow()
+            .get_full(&key.into())
+            .expect("unable to read the index")?;
+        let mut log = self.log.borrow_mut();
+        log.seek(SeekFrom::Start(pos)).expect("unable to read the log");
+        let mut flags = self
+            .format
+            .read_header(&mut *log)
+            .expect("unable to read the log")
+            .flags;
+        if self.is_tombstoned(no) {
+            flags.0 |= RecordFlags::SUPERSEDED;
+        }
+        Some(flags)
+    }
+
+    fn is_tombstoned(&self, no: usize) -> bool {
+        self.tombstones
+            .as_ref()
+            .is_some_and(|tombstones| tombstones.contains(no))
+    }
+
+    /// Marks the record with the given key as superseded by appending a tombstone to the `.tomb`
+    /// file next to the index, which is created on the first call. The record itself stays in
+    /// the log and is still returned by [`AoraMap::get`] and [`AoraMap::iter`], but is skipped
+    /// by [`Self::iter_live`].
+    ///
+    /// The tombstones are kept in a side file rather than appended to the log as marker records:
+    /// a marker record would take an index entry of the key, which the readers without tombstone
+    /// support would take for its value. Thus, the log format doesn't change, but the `.tomb`
+    /// file must be copied together with the log and the index to keep the markers.
+    ///
+    /// Returns `false` if the record is already superseded.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`io::ErrorKind::NotFound`] if the key is absent, and with I/O errors if the
+    /// tombstone can't be written.
+    pub fn mark_superseded(&mut self, key: K) -> io::Result<bool> {
+        let key = key.into();
+        let Some((no, _)) = self.index.get_mut().get_full(&key)? else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("AORA log database '{}' has no key {}", self.name, key.to_hex()),
+            ));
+        };
+        if self.tombstones.is_none() {
+            let path = Self::tomb_path(&self.dir, &self.name);
+            self.tombstones = Some(Tombstones::create_new(&path)?);
+        }
+        self.tombstones
+            .as_mut()
+            .expect("tombstone file is created")
+            .insert(no)
+    }
+
+    /// Checks whether the record with the given key was marked as superseded with
+    /// [`Self::mark_superseded`].
+    pub fn is_superseded(&self, key: K) -> bool {
+        self.index
+            .borrow()
+            .get_full(&key.into())
+            .expect("unable to read the index")
+            .is_some_and(|(no, _)| self.is_tombstoned(no))
+    }
+
+    /// Retrieves the value together with the flag telling whether the record was marked as
+    /// superseded.
+    pub fn get_with_status(&self, key: K) -> Option<(V, bool)>
+    where V: StrictEncode + StrictDecode {
+        let key = key.into();
+        let value = match self.try_get(key.into()) {
+            Ok(value) => value?,
+            Err(AoraError::Redacted { .. } | AoraError::Shredded { .. }) => return None,
+            Err(err) => panic!("unable to read item: {err}"),
+        };
+        Some((value, self.is_superseded(key.into())))
+    }
+
+    /// Returns an iterator over the key and value pairs in the order they were appended to the
+    /// log, skipping the records marked as superseded.
+    pub fn iter_live(&self) -> impl Iterator<Item = (K, V)> + '_
+    where V: StrictDecode {
+        let mut iter = self.iter_range(0..usize::MAX, false);
+        iter.tombstones = self.tombstones.as_ref();
+        iter
+    }
+
+    /// Replaces the user bits of the flags of the record with the given key, overwriting the
+    /// flags byte of the record header in place. The other flags and the record value are not
+    /// changed.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if the bits don't fit into the four user bits,
+    /// with [`io::ErrorKind::NotFound`] if the key is absent, and with
+    /// [`io::ErrorKind::Unsupported`] if the log was created without
+    /// [`LogOptions::record_flags`] or is segmented or opened with direct I/O, since the closed
+    /// segments and the blocks owned by the direct appender are never re-written.
+    pub fn set_user_flags(&mut self, key: K, bits: u8) -> io::Result<()> {
+        if bits > RecordFlags::USER >> 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("user flags {bits:#04x} exceed the four user bits"),
+            ));
+        }
+        let pos = self.locate_in_place(key)?;
+        self.update_flags(pos, |flags| (flags & !RecordFlags::USER) | (bits << 4))?;
+        self.log.get_mut().flush()
+    }
+
+    /// Destroys the value of the record with the given key, overwriting its payload bytes in the
+    /// log with zeros and marking the record as redacted in its flags. The key, the index entry
+    /// and the record length are kept, so the structure of the log is preserved.
+    ///
+    /// The redacted records are skipped by the iterators and reported as absent by
+    /// [`AoraMap::get`], while [`Self::try_get`] and [`Self::try_iter`] report them as
+    /// [`AoraError::Redacted`]. Their keys, however, stay in the map: they are still counted by
+    /// [`AoraMap::len`] and [`AoraMap::contains_key`], and can't be inserted again, so
+    /// [`AoraMap::insert`] leaves them as is, while [`Self::try_insert`] fails with
+    /// [`AoraError::Redacted`]. Since the payload is destroyed, the hash chain and the value
+    /// hashes of a log with redacted records can't be verified anymore.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`io::ErrorKind::NotFound`] if the key is absent, and with
+    /// [`io::ErrorKind::Unsupported`] under the same conditions as [`Self::set_user_flags`].
+    pub fn redact(&mut self, key: K) -> io::Result<()> {
+        let pos = self.locate_in_place(key)?;
+        // Mark the record first, so an interrupted redaction never exposes a partially zeroed
+        // payload
+        self.update_flags(pos, |flags| flags | RecordFlags::REDACTED)?;
+        let log = self.log.get_mut();
+        log.seek(SeekFrom::Start(pos))?;
+        let header = self.format.read_header(log)?;
+        log.write_all(&vec![0u8; header.len as usize])?;
+        log.flush()?;
+        log.sync_all()
+    }
+
+    /// Makes the value of the record with the given key unrecoverable by destroying its data key,
+    /// which is overwritten with zeros in the `.dek` file. The log itself is not modified.
+    ///
+    /// The shredded records are skipped by the iterators and reported as absent by
+    /// [`AoraMap::get`], while [`Self::try_get`] and [`Self::try_iter`] report them as
+    /// [`AoraError::Shredded`].
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`io::ErrorKind::Unsupported`] if the log was created without
+    /// [`LogOptions::shreddable`], with [`io::ErrorKind::NotFound`] if the key is absent, and
+    /// with I/O errors if the data key file can't be written.
+    pub fn shred(&mut self, key: K) -> io::Result<()> {
+        let Some(deks) = &mut self.deks else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("values of the AORA log database '{}' are not shreddable", self.name),
+            ));
+        };
+        let key = key.into();
+        let Some((no, pos)) = self.index.get_mut().get_full(&key)? else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("AORA log database '{}' has no key {}", self.name, key.to_hex()),
+            ));
+        };
+        let entry = self.index.get_mut().entry_no(no);
+        let deks = deks.get_mut();
+        deks.seek(SeekFrom::Start(10 + (entry * WRAPPED_KEY_LEN) as u64))?;
+        deks.write_all(&[0u8; WRAPPED_KEY_LEN])?;
+        deks.sync_data()?;
+        if let Some((_, cache)) = &self.memory {
+            cache.borrow_mut().remove(pos);
+        }
+        Ok(())
+    }
+
+    /// Finds the log position of the record with the given key, checking that the record can be
+    /// modified in place.
+    fn locate_in_place(&mut self, key: K) -> io::Result<u64> {
+        #[cfg(target_os = "linux")]
+        let direct = matches!(self.log.get_mut(), LogFile::Direct(..));
+        #[cfg(not(target_os = "linux"))]
+        let direct = false;
+        if !self.format.flagged || direct || matches!(self.log.get_mut(), LogFile::Segmented(_)) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "records of the AORA log database '{}' can't be modified in place",
+                    self.name
+                ),
+            ));
+        }
+        let key = key.into();
+        let Some((_, pos)) = self.index.get_mut().get_full(&key)? else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("AORA log database '{}' has no key {}", self.name, key.to_hex()),
+            ));
+        };
+        Ok(pos)
+    }
+
+    /// Overwrites the flags byte of the record at the given log position with the updated one,
+    /// dropping the record from the cache.
+    fn update_flags(&mut self, pos: u64, update: impl FnOnce(u8) -> u8) -> io::Result<()> {
+        let log = self.log.get_mut();
+        // The flags byte directly follows the 4-byte record length
+        log.seek(SeekFrom::Start(pos + 4))?;
+        let mut flags = [0u8; 1];
+        log.read_exact(&mut flags)?;
+        log.seek(SeekFrom::Start(pos + 4))?;
+        log.write_all(&[update(flags[0])])?;
+        if let Some((_, cache)) = &self.memory {
+            cache.borrow_mut().remove(pos);
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over the key and value pairs in the order they were appended to the
+    /// log, together with the time each record was appended, which is `None` if the log was
+    /// created without [`LogOptions::timestamps`].
+    pub fn iter_timed(&self) -> impl Iterator<Item = (K, V, Option<SystemTime>)> + '_
+    where V: StrictDecode {
+        TimedIter(self.iter_range(0..usize::MAX, false))
+    }
+
+    /// Returns an iterator over the key and value pairs, which reports I/O failures and
+    /// undecodable items as errors instead of panicking or stopping silently.
+    pub fn try_iter(&self) -> impl Iterator<Item = Result<(K, V), AoraError>> + '_
+    where V: StrictDecode {
+        TryIter(self.iter_range(0..usize::MAX, false))
+    }
+
+    /// Returns a parallel iterator over the key and value pairs, decoding the values on multiple
+    /// threads using positioned reads from the log. The order of the items is not preserved.
+    #[cfg(all(feature = "rayon", any(unix, windows)))]
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (K, V)>
+    where
+        K: Send,
+        V: StrictDecode + Send,
+    {
+        use rayon::prelude::*;
+
+        let log = self
+            .log
+            .borrow_mut()
+            .shared()
+            .expect("unable to clone the log file handles");
+        let index = self.index.borrow();
+        let index = (0..index.len())
+            .filter_map(|no| {
+                let (key, pos) = index.get_index(no).expect("unable to read the index")?;
+                let entry = index.entry_no(no);
+                Some((key, pos, read_data_key(self.deks.as_ref(), entry, &key, pos)))
+            })
+            .collect::<Vec<_>>();
+        let format = self.format.clone();
+        index.into_par_iter().filter_map(move |(key, pos, wrapped)| {
+            let mut reader = io::BufReader::new(log.reader(pos));
+            let res = wrapped.and_then(|wrapped| {
+                read_timed_value(&format, &mut reader, &key, wrapped.as_ref(), pos)
+            });
+            match res {
+                Ok((value, _)) => Some((K::from(key), value)),
+                Err(AoraError::Redacted { .. } | AoraError::Shredded { .. }) => None,
+                Err(err) => panic!("unable to read item: {err}"),
+            }
+        })
+    }
+
+    /// Returns a read-only handle serving the items present in the map at the moment of the call,
+    /// which can be cloned and sent to other threads without locking the map.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`io::ErrorKind::Unsupported`] if the map uses a sparse index, and with I/O
+    /// errors if the file handles can't be cloned.
+    #[cfg(any(unix, windows))]
+    pub fn reader(&self) -> io::Result<Reader<K, V, KEY_LEN>>
+    where V: StrictEncode + StrictDecode {
+        let KeyIndex::Full(index) = &*self.index.borrow() else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("reader handles of '{}' require a full in-memory index", self.name),
+            ));
+        };
+        if self.deks.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("reader handles of '{}' can't read shreddable values", self.name),
+            ));
+        }
+        let sums = match &self.sums {
+            Some((sums, hasher)) => Some((sums.borrow().try_clone()?, *hasher)),
+            None => None,
+        };
+        Ok(Reader::new(Snapshot {
+            name: self.name.clone(),
+            log: self.log.borrow_mut().shared()?,
+            index: index.clone(),
+            sums,
+            format: self.format.clone(),
+        }))
+    }
+
+    /// Reads a page of at most `limit` items starting from the `cursor` position, in the order
+    /// they were appended to the log.
+    ///
+    /// Returns the cursor for reading the next page, or `None` if the end of the log is reached.
+    /// The cursor counts the items in the append order, so it stays valid across database
+    /// re-opens, but not across [`Self::rewrite_index`] and [`Self::repair_index`], which
+    /// renumber the items if the index had duplicate or dangling entries.
+    pub fn page(&self, cursor: AoraCursor, limit: usize) -> (Vec<(K, V)>, Option<AoraCursor>)
+    where V: StrictDecode {
+        let from = cursor.0 as usize;
+        let to = from.saturating_add(limit);
+        let items = self.iter_range(from..to, false).collect::<Vec<_>>();
+        let next = if to < self.index.borrow().len() { Some(AoraCursor(to as u64)) } else { None };
+        (items, next)
+    }
+}
+
+impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize>
+    FileAoraMap<Checked<K, KEY_LEN>, V, MAGIC, VER, KEY_LEN>
+where K: Into<[u8; KEY_LEN]> + TryFrom<[u8; KEY_LEN]>
+{
+    /// Retrieves the value from the log, reporting failures as errors like [`Self::try_get`].
+    pub fn try_get_checked(&self, key: K) -> Result<Option<V>, AoraError>
+    where V: StrictEncode + StrictDecode {
+        self.try_get(Checked::new(key))
+    }
+
+    /// Returns an iterator over the key and value pairs like [`Self::try_iter`], which also
+    /// validates the keys, reporting invalid ones as [`AoraError::InvalidKey`].
+    pub fn try_iter_checked(&self) -> impl Iterator<Item = Result<(K, V), AoraError>> + '_
+    where V: StrictDecode {
+        self.try_iter().map(|item| {
+            let (key, val) = item?;
+            Ok((key.check()?, val))
+        })
+    }
+}
+
+impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize> AoraMap<K, V, KEY_LEN>
+    for FileAoraMap<K, V, MAGIC, VER, KEY_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    V: Eq + StrictEncode + StrictDecode,
+{
+    fn len(&self) -> usize { self.index.borrow().len() }
+
+    fn contains_key(&self, key: K) -> bool {
+        let key = key.into();
+        if self
+            .bloom
+            .as_ref()
+            .is_some_and(|bloom| !bloom.contains(&key))
+        {
+            return false;
+        }
+        self.index
+            .borrow()
+            .contains_key(&key)
+            .expect("unable to read the index")
+    }
+
+    fn get(&self, key: K) -> Option<V> {
+        match self.try_get(key) {
+            Ok(value) => value,
+            Err(AoraError::Redacted { .. } | AoraError::Shredded { .. }) => None,
+            Err(err) => panic!("unable to read item: {err}"),
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all, fields(table = %self.name)))]
+    fn insert(&mut self, key: K, value: &V) {
+        match self.try_insert(key, value) {
+            // The key of a redacted or shredded record stays in the map unchanged
+            Ok(()) | Err(AoraError::Redacted { .. } | AoraError::Shredded { .. }) => {}
+            Err(err) => panic!("unable to insert item: {err}"),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (K, V)> { self.iter_range(0..usize::MAX, false) }
+
+    fn iter_rev(&self) -> impl Iterator<Item = (K, V)> { self.iter_range(0..usize::MAX, true) }
+}
+
+pub struct Iter<
+    'file,
+    K: From<[u8; KEY_LEN]>,
+    V: StrictDecode,
+    const MAGIC: u64,
+    const VER: u16,
+    const KEY_LEN: usize,
+> {
+    log: RefMut<'file, LogFile<MAGIC, VER>>,
+    index: Ref<'file, KeyIndex<MAGIC, VER, KEY_LEN>>,
+    /// Range of positions in the index which are not iterated yet.
+    range: Range<usize>,
+    rev: bool,
+    format: &'file LogFormat,
+    /// Tombstones of the records to skip.
+    tombstones: Option<&'file Tombstones<MAGIC, VER>>,
+    /// File with the wrapped data keys of the records, if the log is shreddable.
+    deks: Option<&'file RefCell<BinFile<MAGIC, VER>>>,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<
+    K: From<[u8; KEY_LEN]>,
+    V: StrictDecode,
+    const MAGIC: u64,
+    const VER: u16,
+    const KEY_LEN: usize,
+> Iterator for Iter<'_, K, V, MAGIC, VER, KEY_LEN>
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.try_next()? {
+                Ok(item) => return Some(item),
+                Err(AoraError::Redacted { .. } | AoraError::Shredded { .. }) => continue,
+                Err(AoraError::Io(err)) => panic!("unable to read the log: {err}"),
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+impl<
+    K: From<[u8; KEY_LEN]>,
+    V: StrictDecode,
+    const MAGIC: u64,
+    const VER: u16,
+    const KEY_LEN: usize,
+> Iter<'_, K, V, MAGIC, VER, KEY_LEN>
+{
+    fn try_next(&mut self) -> Option<Result<(K, V), AoraError>> {
+        let res = self.try_next_timed()?;
+        Some(res.map(|(key, value, _)| (key, value)))
+    }
+
+    fn try_next_timed(&mut self) -> Option<Result<(K, V, Option<SystemTime>), AoraError>> {
+        let mut no = if self.rev { self.range.next_back()? } else { self.range.next()? };
+        while self
+            .tombstones
+            .is_some_and(|tombstones| tombstones.contains(no))
+        {
+            no = if self.rev { self.range.next_back()? } else { self.range.next()? };
+        }
+        let (id, pos) = match self.index.get_index(no) {
+            Ok(entry) => entry?,
+            Err(err) => return Some(Err(err.into())),
+        };
+        if let Err(err) = self.log.seek(SeekFrom::Start(pos)) {
+            return Some(Err(err.into()));
+        }
+
+        let wrapped = match read_data_key(self.deks, self.index.entry_no(no), &id, pos) {
+            Ok(wrapped) => wrapped,
+            Err(err) => return Some(Err(err)),
+        };
+        let res = read_timed_value(self.format, &mut *self.log, &id, wrapped.as_ref(), pos)
+            .map(|(item, time)| (id.into(), item, time));
+        Some(res)
+    }
+}
+
+impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize> Drop
+    for FileAoraMap<K, V, MAGIC, VER, KEY_LEN>
+where K: AoraKey<KEY_LEN>
+{
+    fn drop(&mut self) {
+        // The errors can't be reported here; `close` or `flush` must be used to handle them
+        let _ = self.write_idx_buf();
+    }
+}
+
+impl<
+    K: From<[u8; KEY_LEN]>,
+    V: StrictDecode,
+    const MAGIC: u64,
+    const VER: u16,
+    const KEY_LEN: usize,
+> Drop for Iter<'_, K, V, MAGIC, VER, KEY_LEN>
+{
+    fn drop(&mut self) {
+        if !self.rev {
+            self.log.advise(Access::Random);
+        }
+    }
+}
+
+/// Iterator over the log items reporting errors, returned by [`FileAoraMap::try_iter`].
+pub struct TryIter<
+    'file,
+    K: From<[u8; KEY_LEN]>,
+    V: StrictDecode,
+    const MAGIC: u64,
+    const VER: u16,
+    const KEY_LEN: usize,
+>(Iter<'file, K, V, MAGIC, VER, KEY_LEN>);
+
+impl<
+    K: From<[u8; KEY_LEN]>,
+    V: StrictDecode,
+    const MAGIC: u64,
+    const VER: u16,
+    const KEY_LEN: usize,
+> Iterator for TryIter<'_, K, V, MAGIC, VER, KEY_LEN>
+{
+    type Item = Result<(K, V), AoraError>;
+
+    fn next(&mut self) -> Option<Self::Item> { self.0.try_next() }
+}
+
+/// Iterator over the log items with their append times, returned by
+/// [`FileAoraMap::iter_timed`].
+pub struct TimedIter<
+    'file,
+    K: From<[u8; KEY_LEN]>,
+    V: StrictDecode,
+    const MAGIC: u64,
+    const VER: u16,
+    const KEY_LEN: usize,
+>(Iter<'file, K, V, MAGIC, VER, KEY_LEN>);
+
+impl<
+    K: From<[u8; KEY_LEN]>,
+    V: StrictDecode,
+    const MAGIC: u64,
+    const VER: u16,
+    const KEY_LEN: usize,
+> Iterator for TimedIter<'_, K, V, MAGIC, VER, KEY_LEN>
+{
+    type Item = (K, V, Option<SystemTime>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.try_next_timed()? {
+                Ok(item) => return Some(item),
+                Err(AoraError::Redacted { .. } | AoraError::Shredded { .. }) => continue,
+                Err(AoraError::Io(err)) => panic!("unable to read the log: {err}"),
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+/// Structured dump of a [`FileAoraMap`], returned by [`FileAoraMap::to_dump`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct FileAoraMapDump<const KEY_LEN: usize> {
+    /// Records in the order they were appended to the log.
+    pub records: Vec<RecordDump<KEY_LEN>>,
+}
+
+/// Log record listed in a [`FileAoraMapDump`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct RecordDump<const KEY_LEN: usize> {
+    pub key: [u8; KEY_LEN],
+    /// Position of the record in the log.
+    pub pos: u64,
+    /// Length of the record in the log, including the record header if the log format has one.
+    pub len: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU64;
+
+    use amplify::confinement::SmallVec;
+
+    use super::*;
+
+    type Db = FileAoraMap<[u8; 8], SmallVec<u8>, { u64::from_be_bytes(*b"DUMBTEST") }, 1, 8>;
+
+    fn val(no: u64) -> SmallVec<u8> { SmallVec::from_checked(no.to_le_bytes().to_vec()) }
+
+    #[test]
+    fn pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "pages").unwrap();
+        for no in 0..25u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+
+        let (items, cursor) = db.page(AoraCursor::START, 10);
+        assert_eq!(items.len(), 10);
+        assert_eq!(items[0], (0u64.to_be_bytes(), val(0)));
+        let cursor = cursor.unwrap();
+
+        // Cursor can be passed around as a string
+        let cursor = cursor.to_string().parse::<AoraCursor>().unwrap();
+        let (items, cursor) = db.page(cursor, 10);
+        assert_eq!(items[0], (10u64.to_be_bytes(), val(10)));
+        let (items, cursor) = db.page(cursor.unwrap(), 10);
+        assert_eq!(items.len(), 5);
+        assert_eq!(cursor, None);
+
+        assert_eq!(db.iter_from(20u64.to_be_bytes()).count(), 5);
+        assert_eq!(db.iter_from(30u64.to_be_bytes()).count(), 0);
+        assert_eq!(db.iter_rev().next(), Some((24u64.to_be_bytes(), val(24))));
+    }
+
+    #[test]
+    fn checked_keys() {
+        #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+        struct NonZeroKey(NonZeroU64);
+        impl From<NonZeroKey> for [u8; 8] {
+            fn from(key: NonZeroKey) -> Self { key.0.get().to_be_bytes() }
+        }
+        impl TryFrom<[u8; 8]> for NonZeroKey {
+            type Error = ();
+
+            fn try_from(bytes: [u8; 8]) -> Result<Self, ()> {
+                NonZeroU64::new(u64::from_be_bytes(bytes)).map(Self).ok_or(())
+            }
+        }
+        const MAGIC: u64 = u64::from_be_bytes(*b"DUMBTEST");
+        type CheckedDb = FileAoraMap<Checked<NonZeroKey, 8>, SmallVec<u8>, MAGIC, 1, 8>;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = CheckedDb::create_new(dir.path(), "checked").unwrap();
+        let key = NonZeroKey(NonZeroU64::new(1).unwrap());
+        db.insert(Checked::new(key), &val(1));
+        // Simulates a corrupted key
+        db.insert(Checked::from([0u8; 8]), &val(0));
+
+        assert_eq!(db.try_get_checked(key).unwrap(), Some(val(1)));
+        let mut iter = db.try_iter_checked();
+        assert_eq!(iter.next().unwrap().unwrap(), (key, val(1)));
+        assert!(matches!(iter.next(), Some(Err(AoraError::InvalidKey { .. }))));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn dump() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "dump").unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        db.insert(1u64.to_be_bytes(), &SmallVec::from_checked(vec![]));
+
+        let dump = db.to_dump().unwrap();
+        assert_eq!(dump.records, vec![
+            RecordDump { key: 0u64.to_be_bytes(), pos: 10, len: 10 },
+            RecordDump { key: 1u64.to_be_bytes(), pos: 20, len: 2 },
+        ]);
+        drop(db);
+
+        // Record for the key 0 is appended once again, so it follows the one of the next key
+        let log = dir.path().join("dump.log");
+        let data = fs::read(&log).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&log)
+            .unwrap()
+            .write_all(&data[10..20])
+            .unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("dump.idx"))
+            .unwrap()
+            .write_all(&[0u64.to_be_bytes(), 22u64.to_le_bytes()].concat())
+            .unwrap();
+        let db = Db::open(dir.path(), "dump").unwrap();
+        let dump = db.to_dump().unwrap();
+        assert_eq!(dump.records, vec![
+            RecordDump { key: 0u64.to_be_bytes(), pos: 22, len: 10 },
+            RecordDump { key: 1u64.to_be_bytes(), pos: 20, len: 2 },
+        ]);
+    }
+
+    #[test]
+    fn check_all() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "check").unwrap();
+        for no in 0..3u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        let report = db.check_all().unwrap();
+        assert_eq!(report.entries, 3);
+        assert!(report.is_ok());
+        drop(db);
+
+        let mut log = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("check.log"))
+            .unwrap();
+        log.write_all(&[1, 2, 3]).unwrap();
+        let db = Db::open(dir.path(), "check").unwrap();
+        assert_eq!(db.check_all().unwrap().issues, vec![CheckIssue::UnindexedTail {
+            pos: 40,
+            len: 3
+        }]);
+        drop(db);
+
+        let mut idx = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("check.idx"))
+            .unwrap();
+        idx.write_all(&[7u64.to_be_bytes(), 1000u64.to_le_bytes()].concat())
+            .unwrap();
+        let db = Db::open(dir.path(), "check").unwrap();
+        let report = db.check_all().unwrap();
+        assert_eq!(report.entries, 4);
+        assert_eq!(report.issues, vec![
+            CheckIssue::PositionMismatch {
+                no: 3,
+                key: s!("0000000000000007"),
+                idx_pos: 1000,
+                log_pos: 40
+            },
+            CheckIssue::DanglingEntry { no: 3, key: s!("0000000000000007"), pos: 1000 },
+        ]);
+    }
+
+    #[test]
+    fn audit_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "audit").unwrap();
+        for no in 0..3u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        assert!(db.audit_index().unwrap().is_clean());
+        drop(db);
+
+        // Record for the key 1 is appended once again, as if the first append was not noticed
+        let log = dir.path().join("audit.log");
+        let data = fs::read(&log).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&log)
+            .unwrap()
+            .write_all(&data[20..30])
+            .unwrap();
+        let idx = dir.path().join("audit.idx");
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&idx)
+            .unwrap()
+            .write_all(&[1u64.to_be_bytes(), 40u64.to_le_bytes()].concat())
+            .unwrap();
+
+        let mut db = Db::open(dir.path(), "audit").unwrap();
+        let audit = db.audit_index().unwrap();
+        assert_eq!(audit.entries, 4);
+        assert_eq!(audit.duplicates, vec![DuplicateKey {
+            key: s!("0000000000000001"),
+            entries: vec![1, 3],
+            positions: vec![20, 40],
+            winner: Some(40),
+        }]);
+
+        assert_eq!(db.rewrite_index().unwrap(), 1);
+        assert!(db.audit_index().unwrap().is_clean());
+        assert_eq!(fs::metadata(&idx).unwrap().len(), 10 + 3 * 16);
+        db.insert(3u64.to_be_bytes(), &val(3));
+        drop(db);
+
+        let db = Db::open(dir.path(), "audit").unwrap();
+        assert_eq!(db.audit_index().unwrap().entries, 4);
+        assert_eq!(db.get(1u64.to_be_bytes()), Some(val(1)));
+        assert_eq!(db.get(3u64.to_be_bytes()), Some(val(3)));
+    }
+
+    #[test]
+    fn repair_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "repair").unwrap();
+        for no in 0..3u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+
+        let entries = [
+            (2u64, 30u64), // entry of the key 2 written twice
+            (4, 40),       // entry of the key 4, whose record was never written
+            (5, 1000),
+        ];
+        let mut idx = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("repair.idx"))
+            .unwrap();
+        for (key, pos) in entries {
+            idx.write_all(&[key.to_be_bytes(), pos.to_le_bytes()].concat())
+                .unwrap();
+        }
+        drop(idx);
+
+        let mut db = Db::open(dir.path(), "repair").unwrap();
+        assert!(!db.check_all().unwrap().is_ok());
+        assert_eq!(db.repair_index().unwrap(), IndexRepair { dangling: 2, duplicates: 1 });
+        let report = db.check_all().unwrap();
+        assert_eq!(report.entries, 3);
+        assert!(report.is_ok());
+        assert_eq!(db.get(2u64.to_be_bytes()), Some(val(2)));
+        assert!(!db.contains_key(4u64.to_be_bytes()));
+    }
+
+    #[test]
+    fn repair_index_tombstones() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "repair").unwrap();
+        for no in 0..3u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+        let mut idx = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("repair.idx"))
+            .unwrap();
+        idx.write_all(&[7u64.to_be_bytes(), 1000u64.to_le_bytes()].concat())
+            .unwrap();
+        drop(idx);
+
+        // The dangling entry of the key 7 precedes the one of the key 3, which gets a lower
+        // record number after the repair
+        let mut db = Db::open(dir.path(), "repair").unwrap();
+        db.insert(3u64.to_be_bytes(), &val(3));
+        assert!(db.mark_superseded(1u64.to_be_bytes()).unwrap());
+        assert!(db.mark_superseded(3u64.to_be_bytes()).unwrap());
+        assert_eq!(db.repair_index().unwrap(), IndexRepair { dangling: 1, duplicates: 0 });
+        let superseded = |db: &Db| {
+            (0..4u64)
+                .filter(|no| db.is_superseded(no.to_be_bytes()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(superseded(&db), vec![1, 3]);
+        drop(db);
+
+        let db = Db::open(dir.path(), "repair").unwrap();
+        assert_eq!(superseded(&db), vec![1, 3]);
+        assert_eq!(db.iter_live().count(), 2);
+    }
+
+    #[test]
+    fn close() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = || LogOptions::new().bloom_filter(10, 0.01);
+        let mut db = Db::create_with(dir.path(), "close", opts()).unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        db.flush().unwrap();
+        db.sync().unwrap();
+        db.insert(1u64.to_be_bytes(), &val(1));
+        db.close().unwrap();
+
+        let db = Db::open_with(dir.path(), "close", opts()).unwrap();
+        assert_eq!(db.get(0u64.to_be_bytes()), Some(val(0)));
+        assert_eq!(db.get(1u64.to_be_bytes()), Some(val(1)));
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn refresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "refresh").unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        let mut reader = Db::open(dir.path(), "refresh").unwrap();
+        assert_eq!(reader.refresh_if_changed().unwrap(), 0);
+
+        for no in 1..4u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        assert!(!reader.contains_key(3u64.to_be_bytes()));
+        assert_eq!(reader.refresh().unwrap(), 3);
+        assert_eq!(reader.get(3u64.to_be_bytes()), Some(val(3)));
+        assert_eq!(reader.iter().count(), 4);
+
+        // Entry being appended is not loaded until complete
+        let mut idx = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("refresh.idx"))
+            .unwrap();
+        idx.write_all(&4u64.to_be_bytes()).unwrap();
+        assert_eq!(reader.refresh().unwrap(), 0);
+    }
+
+    #[test]
+    fn schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = || LogOptions::new().schema([1u8; 32]);
+        let mut db = Db::create_with(dir.path(), "schema", opts()).unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        assert_eq!(db.schema(), Some([1u8; 32]));
+        drop(db);
+        // The records stay unframed
+        let log = fs::read(dir.path().join("schema.log")).unwrap();
+        assert_eq!(log.len(), 20);
+
+        let db = Db::open_with(dir.path(), "schema", opts()).unwrap();
+        assert_eq!(db.get(0u64.to_be_bytes()), Some(val(0)));
+        drop(db);
+        let err = Db::open_with(dir.path(), "schema", LogOptions::new().schema([2u8; 32]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(Db::open(dir.path(), "schema").is_err());
+    }
+
+    #[test]
+    fn upgrade() {
+        type Db2 = FileAoraMap<[u8; 8], u64, { u64::from_be_bytes(*b"DUMBTEST") }, 2, 8>;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "upgrade").unwrap();
+        for no in 0..5u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+
+        assert!(Db2::open(dir.path(), "upgrade").is_err());
+        // The old values are prefixed with their 2-byte length
+        let db = Db2::upgrade(dir.path(), "upgrade", 1, |bytes| {
+            u64::from_le_bytes(bytes[2..].try_into().unwrap()) * 10
+        })
+        .unwrap();
+        assert_eq!(db.len(), 5);
+        assert_eq!(db.get(3u64.to_be_bytes()), Some(30));
+        drop(db);
+        assert!(!fs::exists(dir.path().join("upgrade.upgrade")).unwrap());
+        assert!(Db::open(dir.path(), "upgrade").is_err());
+        let db = Db2::open(dir.path(), "upgrade").unwrap();
+        assert_eq!(db.iter().map(|(_, v)| v).collect::<Vec<_>>(), vec![0, 10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn insert_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "batch").unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        let vals = (0..4u64).map(val).collect::<Vec<_>>();
+        let items = [0, 1, 2, 1, 3].map(|no: u64| (no.to_be_bytes(), &vals[no as usize]));
+        assert_eq!(db.insert_batch(items), 3);
+        assert_eq!(db.insert_batch([(3u64.to_be_bytes(), &vals[3])]), 0);
+        assert_eq!(db.len(), 4);
+        drop(db);
+
+        let log = fs::read(dir.path().join("batch.log")).unwrap();
+        assert_eq!(log.len(), 10 + 4 * 10);
+        let db = Db::open(dir.path(), "batch").unwrap();
+        assert_eq!(db.iter().map(|(_, v)| v).collect::<Vec<_>>(), vals);
+    }
+
+    #[test]
+    #[should_panic(expected = "item under the given id is different")]
+    fn insert_batch_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "batch").unwrap();
+        db.insert_batch([(0u64.to_be_bytes(), &val(0)), (0u64.to_be_bytes(), &val(1))]);
+    }
+
+    #[test]
+    fn try_iter() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "try_iter").unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        db.insert(1u64.to_be_bytes(), &val(1));
+        drop(db);
+
+        // Truncate the last record
+        let path = dir.path().join("try_iter.log");
+        let len = fs::metadata(&path).unwrap().len();
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 2)
+            .unwrap();
+
+        let db = Db::open(dir.path(), "try_iter").unwrap();
+        let mut iter = db.try_iter();
+        assert_eq!(iter.next().unwrap().unwrap(), (0u64.to_be_bytes(), val(0)));
+        assert!(matches!(iter.next(), Some(Err(AoraError::Decode { pos: 20, .. }))));
+        assert!(iter.next().is_none());
+        drop(iter);
+
+        let report = db.debug_report().unwrap();
+        assert!(report.contains("records: 2\n"));
+        assert!(report.contains("last_key: 0000000000000001\n"));
+        assert!(report.contains("anomalies:\n  - \"Item under the key 0000000000000001"));
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = || LogOptions::new().encrypt([7u8; 32]).encrypt_keys();
+        let mut db = Db::create_with(dir.path(), "encrypted", opts()).unwrap();
+        for no in 0..3u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+
+        let log = fs::read(dir.path().join("encrypted.log")).unwrap();
+        let idx = fs::read(dir.path().join("encrypted.idx")).unwrap();
+        let plain = 2u64.to_le_bytes();
+        assert!(!log.windows(plain.len()).any(|w| w == plain));
+        assert!(!idx.windows(8).any(|w| w == 2u64.to_be_bytes()));
+
+        let db = Db::open_with(dir.path(), "encrypted", opts()).unwrap();
+        assert_eq!(db.get(1u64.to_be_bytes()), Some(val(1)));
+        assert_eq!(db.iter().count(), 3);
+        let mut key = 1u64.to_be_bytes();
+        db.format.seal_key(3, &mut key);
+        drop(db);
+
+        // Record for the key 1 is appended once again, so the keys appended later are sealed with
+        // the numbers of their entries, which follow the duplicate one
+        let pos = |no: usize| {
+            let start = 10 + no * 16 + 8;
+            u64::from_le_bytes(idx[start..start + 8].try_into().unwrap()) as usize
+        };
+        let append = |ext: &str, data: &[u8]| {
+            let path = dir.path().join("encrypted").with_extension(ext);
+            let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+            file.write_all(data).unwrap();
+        };
+        append("log", &log[pos(1)..pos(2)]);
+        append("idx", &[key, (log.len() as u64).to_le_bytes()].concat());
+        let mut db = Db::open_with(dir.path(), "encrypted", opts()).unwrap();
+        db.insert(3u64.to_be_bytes(), &val(3));
+        drop(db);
+        let db = Db::open_with(dir.path(), "encrypted", opts()).unwrap();
+        assert!(db.iter().map(|(_, value)| value).eq((0..4).map(val)));
+        drop(db);
+
+        let err = Db::open(dir.path(), "encrypted").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let opts = LogOptions::new().encrypt([8u8; 32]);
+        assert!(Db::open_with(dir.path(), "encrypted", opts).is_err());
+
+        let mut db =
+            Db::create_with(dir.path(), "wrong_key", LogOptions::new().encrypt([7u8; 32])).unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        drop(db);
+        let db =
+            Db::open_with(dir.path(), "wrong_key", LogOptions::new().encrypt([8u8; 32])).unwrap();
+        assert!(matches!(db.try_get(0u64.to_be_bytes()), Err(AoraError::Decrypt { pos: 10, .. })));
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn zstd() {
+        let json = |no: u64| {
+            let data = format!(
+                r#"{{"id":{no},"name":"user #{no}","kind":"account","active":true,"tags":[]}}"#
+            );
+            SmallVec::from_checked(data.into_bytes())
+        };
+        let samples = (0..1000).map(json).collect::<Vec<_>>();
+        let dict = Db::train_zstd_dictionary(&samples, 1024).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let opts = || LogOptions::new().zstd(3);
+        let mut db = Db::create_with(dir.path(), "plain", opts()).unwrap();
+        let mut dicted =
+            Db::create_with(dir.path(), "dict", opts().zstd_dictionary(dict.clone())).unwrap();
+        for no in 0..100u64 {
+            db.insert(no.to_be_bytes(), &json(no));
+            dicted.insert(no.to_be_bytes(), &json(no));
+        }
+        // Small values are not compressed without a dictionary
+        db.insert(100u64.to_be_bytes(), &val(100));
+        drop(db);
+        drop(dicted);
+
+        let plain_len = fs::metadata(dir.path().join("plain.log")).unwrap().len();
+        let dict_len = fs::metadata(dir.path().join("dict.log")).unwrap().len();
+        assert!(dict_len < plain_len / 2, "{dict_len} vs {plain_len}");
+
+        let db = Db::open_with(dir.path(), "plain", opts()).unwrap();
+        assert_eq!(db.get(100u64.to_be_bytes()), Some(val(100)));
+        // The dictionary is loaded from the log metadata
+        let dicted = Db::open_with(dir.path(), "dict", opts()).unwrap();
+        assert!(dicted.iter().eq(db.iter().take(100)));
+    }
+
+    #[test]
+    #[cfg(all(feature = "lz4", feature = "zstd"))]
+    fn lz4() {
+        let text = |no: u64| SmallVec::from_checked(format!("{no:0>64}").into_bytes());
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_with(dir.path(), "lz4", LogOptions::new().lz4()).unwrap();
+        db.insert(0u64.to_be_bytes(), &text(0));
+        drop(db);
+        assert!(fs::metadata(dir.path().join("lz4.log")).unwrap().len() < 10 + 64);
+
+        // The codec selected at the creation is persisted
+        let err = Db::open_with(dir.path(), "lz4", LogOptions::new().zstd(3)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let mut db = Db::open_with(dir.path(), "lz4", LogOptions::new().lz4()).unwrap();
+        db.insert(1u64.to_be_bytes(), &text(1));
+        drop(db);
+
+        let db = Db::open_with(dir.path(), "lz4", LogOptions::new().lz4()).unwrap();
+        assert_eq!(db.get(0u64.to_be_bytes()), Some(text(0)));
+        assert_eq!(db.get(1u64.to_be_bytes()), Some(text(1)));
+        assert!(Db::open(dir.path(), "lz4").is_err());
+    }
+
+    #[test]
+    fn segmented() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = LogOptions::new().segment_size(100);
+        let mut db = Db::create_with(dir.path(), "segmented", opts).unwrap();
+        for no in 0..20u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        // Each record takes 10 bytes, and a segment is closed once it reaches 100 bytes
+        assert_eq!(db.closed_segments().len(), 1);
+        assert!(!fs::exists(dir.path().join("segmented.log")).unwrap());
+        assert!(fs::exists(dir.path().join("segmented.0002.log")).unwrap());
+        assert_eq!(db.get(15u64.to_be_bytes()), Some(val(15)));
+        drop(db);
+
+        // Leftovers of a rotation interrupted before the segment was recorded in the manifest
+        fs::write(dir.path().join("segmented.0003.log"), b"interrupted").unwrap();
+        let mut manifest = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("segmented.segments"))
+            .unwrap();
+        manifest.write_all(&3u32.to_le_bytes()).unwrap();
+        drop(manifest);
+
+        let mut db = Db::open(dir.path(), "segmented").unwrap();
+        assert!(
+            db.iter()
+                .eq((0..20u64).map(|no| (no.to_be_bytes(), val(no))))
+        );
+        for no in 20..35u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        assert_eq!(db.closed_segments(), vec![
+            dir.path().join("segmented.0001.log"),
+            dir.path().join("segmented.0002.log"),
+            dir.path().join("segmented.0003.log"),
+        ]);
+        assert_eq!(db.iter_rev().next(), Some((34u64.to_be_bytes(), val(34))));
+        assert_eq!(db.get(9u64.to_be_bytes()), Some(val(9)));
+        assert_eq!(db.get(10u64.to_be_bytes()), Some(val(10)));
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::iter::ParallelIterator;
+            assert_eq!(db.par_iter().count(), 35);
+        }
+        assert!(Db::create_new(dir.path(), "segmented").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn compressed_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = LogOptions::new().segment_size(100);
+        let mut db = Db::create_with(dir.path(), "compressed", opts).unwrap();
+        for no in 0..35u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        assert_eq!(db.compress_segments(19).unwrap(), 3);
+        assert_eq!(db.compress_segments(19).unwrap(), 0);
+        assert_eq!(db.closed_segments(), vec![
+            dir.path().join("compressed.0001.log.zst"),
+            dir.path().join("compressed.0002.log.zst"),
+            dir.path().join("compressed.0003.log.zst"),
+        ]);
+        assert!(!fs::exists(dir.path().join("compressed.0001.log")).unwrap());
+        assert_eq!(db.get(15u64.to_be_bytes()), Some(val(15)));
+        for no in 35..45u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+
+        // Leftovers of an interrupted compression are removed on open
+        fs::write(dir.path().join("compressed.0004.log.zst.tmp"), b"partial").unwrap();
+        let db = Db::open(dir.path(), "compressed").unwrap();
+        assert!(!fs::exists(dir.path().join("compressed.0004.log.zst.tmp")).unwrap());
+        assert!(
+            db.iter()
+                .eq((0..45u64).map(|no| (no.to_be_bytes(), val(no))))
+        );
+        assert_eq!(db.iter_rev().count(), 45);
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::iter::ParallelIterator;
+            assert_eq!(db.par_iter().count(), 45);
+        }
+    }
+
+    #[test]
+    fn bloom_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = || LogOptions::new().bloom_filter(100, 0.01);
+        let mut db = Db::create_with(dir.path(), "bloom", opts()).unwrap();
+        for no in 0..50u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        assert!(db.contains_key(10u64.to_be_bytes()));
+        assert!(!db.contains_key(60u64.to_be_bytes()));
+        drop(db);
+        assert!(fs::exists(dir.path().join("bloom.bloom")).unwrap());
+
+        // The filter is loaded even if not requested by the options
+        let db = Db::open(dir.path(), "bloom").unwrap();
+        assert!(db.bloom.is_some());
+        assert!((0..50u64).all(|no| db.get(no.to_be_bytes()) == Some(val(no))));
+        assert_eq!(db.get(60u64.to_be_bytes()), None);
+
+        // The filter is built for the existing logs
+        let mut db = Db::create_new(dir.path(), "late").unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        drop(db);
+        let db = Db::open_with(dir.path(), "late", opts()).unwrap();
+        assert!(db.bloom.as_ref().unwrap().contains(&0u64.to_be_bytes()));
+        assert!(db.contains_key(0u64.to_be_bytes()));
+    }
+
+    #[test]
+    fn sparse_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = |no: u64| (no * 7919 % 1000).to_be_bytes();
+        let mut db = Db::create_new(dir.path(), "sparse").unwrap();
+        for no in 0..300u64 {
+            db.insert(key(no), &val(no));
+        }
+        drop(db);
+
+        let mut db =
+            Db::open_with(dir.path(), "sparse", LogOptions::new().sparse_index(8)).unwrap();
+        for no in 300..1000u64 {
+            db.insert(key(no), &val(no));
+        }
+        {
+            let KeyIndex::Sparse(index) = &*db.index.borrow() else {
+                panic!("index is not sparse")
+            };
+            assert!(fs::exists(dir.path().join("sparse.sidx")).unwrap());
+            assert!(index.get(&key(999)).unwrap().is_some());
+        }
+        drop(db);
+
+        let db = Db::open_with(dir.path(), "sparse", LogOptions::new().sparse_index(8)).unwrap();
+        assert_eq!(db.len(), 1000);
+        assert!((0..1000u64).all(|no| db.get(key(no)) == Some(val(no))));
+        assert!(!db.contains_key(1000u64.to_be_bytes()));
+        assert!(db.iter().map(|(_, v)| v).eq((0..1000).map(val)));
+        assert_eq!(db.iter_from(key(998)).count(), 2);
+
+        // The log is still readable with the full index
+        let mut db = Db::open(dir.path(), "sparse").unwrap();
+        assert_eq!(db.get(key(500)), Some(val(500)));
+
+        // Rewriting the index drops the sorted file, which is rebuilt on the next open
+        assert_eq!(db.rewrite_index().unwrap(), 0);
+        assert!(!fs::exists(dir.path().join("sparse.sidx")).unwrap());
+        drop(db);
+        let db = Db::open_with(dir.path(), "sparse", LogOptions::new().sparse_index(8)).unwrap();
+        assert!((0..1000u64).all(|no| db.get(key(no)) == Some(val(no))));
+    }
+
+    #[test]
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    fn io_uring() {
+        let dir = tempfile::tempdir().unwrap();
+        let val = |no: u64| SmallVec::from_checked(vec![no as u8; no as usize % 50]);
+        let opts = LogOptions::new().io_uring(8);
+        let mut db = Db::create_with(dir.path(), "uring", opts.clone()).unwrap();
+        for no in 0..100u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        let values = db
+            .get_many([99u64, 500, 3, 0].map(u64::to_be_bytes))
+            .unwrap();
+        assert_eq!(values, vec![Some(val(99)), None, Some(val(3)), Some(val(0))]);
+        drop(db);
+
+        // The log written through io_uring is readable without it, and vice versa
+        let mut db = Db::open_with(dir.path(), "uring", LogOptions::new()).unwrap();
+        assert!(db.iter().map(|(_, v)| v).eq((0..100).map(val)));
+        db.insert(100u64.to_be_bytes(), &val(100));
+        drop(db);
+        let db = Db::open_with(dir.path(), "uring", opts.clone()).unwrap();
+        let values = db.get_many((0..=100u64).map(u64::to_be_bytes)).unwrap();
+        assert!(values.into_iter().eq((0..=100).map(val).map(Some)));
+        drop(db);
+
+        // Record for the key 1 is appended once again, so it follows the one of the next key
+        let (log, idx) = (dir.path().join("uring.log"), dir.path().join("uring.idx"));
+        let data = fs::read(&log).unwrap();
+        let entries = fs::read(&idx).unwrap();
+        let pos = |no: usize| {
+            let start = 10 + no * 16 + 8;
+            u64::from_le_bytes(entries[start..start + 8].try_into().unwrap()) as usize
+        };
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&log)
+            .unwrap()
+            .write_all(&data[pos(1)..pos(2)])
+            .unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&idx)
+            .unwrap()
+            .write_all(&[1u64.to_be_bytes(), (data.len() as u64).to_le_bytes()].concat())
+            .unwrap();
+        let db = Db::open_with(dir.path(), "uring", opts.clone()).unwrap();
+        let values = db.get_many([1u64, 0, 2].map(u64::to_be_bytes)).unwrap();
+        assert_eq!(values, vec![Some(val(1)), Some(val(0)), Some(val(2))]);
+
+        let err = Db::create_with(dir.path(), "segmented", opts.segment_size(1000)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn direct_io() {
+        let dir = tempfile::tempdir().unwrap();
+        let val = |no: u64| SmallVec::from_checked(vec![no as u8; no as usize * 97 % 5000]);
+        let opts = LogOptions::new().direct_io();
+        let mut db = match Db::create_with(dir.path(), "direct", opts.clone()) {
+            Ok(db) => db,
+            // The file system doesn't support direct I/O (as tmpfs), failing with `EINVAL`
+            Err(err) if err.kind() == io::ErrorKind::InvalidInput => return,
+            Err(err) => panic!("{err}"),
+        };
+        for no in 0..50u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+            assert_eq!(db.get(no.to_be_bytes()), Some(val(no)));
+        }
+        drop(db);
+
+        let mut db = Db::open_with(dir.path(), "direct", opts.clone()).unwrap();
+        for no in 50..60u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+        let db = Db::open(dir.path(), "direct").unwrap();
+        assert!(db.iter().map(|(_, v)| v).eq((0..60).map(val)));
+
+        let err = Db::create_with(dir.path(), "segmented", opts.segment_size(1000)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn write_through() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = LogOptions::new().write_through();
+        let mut db = Db::create_with(dir.path(), "through", opts.clone()).unwrap();
+        for no in 0..10u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+
+        // A reader coexists with the writer, seeing the items present when it was opened
+        let reader = Db::open(dir.path(), "through").unwrap();
+        db.insert(10u64.to_be_bytes(), &val(10));
+        assert_eq!(reader.len(), 10);
+        assert!(reader.iter().map(|(_, v)| v).eq((0..10).map(val)));
+        assert_eq!(db.get(10u64.to_be_bytes()), Some(val(10)));
+        drop(db);
+
+        let db = Db::open_with(dir.path(), "through", opts.clone()).unwrap();
+        assert!(db.iter().map(|(_, v)| v).eq((0..11).map(val)));
+
+        let err = Db::create_with(dir.path(), "segmented", opts.segment_size(1000)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn coalesce_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = LogOptions::new().coalesce_index(3 * 16);
+        let idx = dir.path().join("coalesce.idx");
+        let mut db = Db::create_with(dir.path(), "coalesce", opts.clone()).unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        db.insert(1u64.to_be_bytes(), &val(1));
+        assert_eq!(fs::metadata(&idx).unwrap().len(), 10);
+        assert_eq!(db.get(1u64.to_be_bytes()), Some(val(1)));
+        db.insert(2u64.to_be_bytes(), &val(2));
+        assert_eq!(fs::metadata(&idx).unwrap().len(), 10 + 3 * 16);
+        db.insert(3u64.to_be_bytes(), &val(3));
+        db.sync().unwrap();
+        assert_eq!(fs::metadata(&idx).unwrap().len(), 10 + 4 * 16);
+        db.insert(4u64.to_be_bytes(), &val(4));
+        drop(db);
+
+        let db = Db::open_with(dir.path(), "coalesce", opts.clone()).unwrap();
+        assert!(db.iter().map(|(_, v)| v).eq((0..5).map(val)));
+        drop(db);
+
+        let err = Db::create_with(dir.path(), "through", opts.write_through()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn reader() {
+        use std::thread;
+
+        use crate::Sha256Hasher;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_verified::<Sha256Hasher>(dir.path(), "reader").unwrap();
+        for no in 0..10u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+
+        // The reader sees the items committed before it was created, while the writer goes on
+        let reader = db.reader().unwrap();
+        for no in 10..20u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        let threads = (0..4u64)
+            .map(|no| {
+                let reader = reader.clone();
+                thread::spawn(move || {
+                    assert_eq!(reader.len(), 10);
+                    assert_eq!(reader.get(no.to_be_bytes()), Some(val(no)));
+                    assert_eq!(reader.get(15u64.to_be_bytes()), None);
+                    assert!(reader.iter().map(|(_, v)| v).eq((0..10).map(val)));
+                    assert!(reader.iter_rev().map(|(_, v)| v).eq((0..10).rev().map(val)));
+                })
+            })
+            .collect::<Vec<_>>();
+        db.insert(20u64.to_be_bytes(), &val(20));
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(db.iter().map(|(_, v)| v).eq((0..21).map(val)));
+        assert_eq!(db.reader().unwrap().get(20u64.to_be_bytes()), Some(val(20)));
+
+        // Segmented logs are served as well, but the sparse index isn't kept in memory
+        let opts = LogOptions::new().segment_size(64);
+        let mut db = Db::create_with(dir.path(), "segmented", opts).unwrap();
+        for no in 0..20u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        assert!(
+            db.reader()
+                .unwrap()
+                .iter()
+                .map(|(_, v)| v)
+                .eq((0..20).map(val))
+        );
+        let opts = LogOptions::new().sparse_index(4);
+        let db = Db::create_with(dir.path(), "sparse", opts).unwrap();
+        assert_eq!(db.reader().unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn verbatim_long_path() {
+        let dir = tempfile::tempdir().unwrap();
+        // Canonical paths on Windows are verbatim (`\\?\`) paths, which are not limited to 260
+        // characters
+        let mut path = dir.path().canonicalize().unwrap();
+        assert!(path.to_string_lossy().starts_with(r"\\?\"));
+        for no in 0..10 {
+            path.push(format!("{no:040}"));
+        }
+        fs::create_dir_all(&path).unwrap();
+        assert!(path.as_os_str().len() > 400);
+
+        let opts = LogOptions::new().segment_size(64).sparse_index(4);
+        let mut db = Db::create_with(&path, "long", opts.clone()).unwrap();
+        for no in 0..100u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+        let db = Db::open_with(&path, "long", opts).unwrap();
+        assert_eq!(db.get(50u64.to_be_bytes()), Some(val(50)));
+        assert!(db.iter().map(|(_, v)| v).eq((0..100).map(val)));
+    }
+
+    #[test]
+    fn memory_budget() {
+        use super::super::budget::ENTRY_OVERHEAD;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "budget").unwrap();
+        for no in 0..100u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        // The index takes a key and a position per entry, and each record takes 10 bytes
+        let index = 100 * (8 + 8 + ENTRY_OVERHEAD);
+        let budget = MemoryBudget::new(index + 10 * (10 + ENTRY_OVERHEAD));
+        db.set_memory_budget(budget.clone());
+        assert_eq!(budget.used(), index);
+
+        for _ in 0..2 {
+            assert!((0..100u64).all(|no| db.get(no.to_be_bytes()) == Some(val(no))));
+        }
+        assert_eq!(budget.used(), budget.limit());
+
+        // The growing index evicts the cached records
+        db.insert(100u64.to_be_bytes(), &val(100));
+        assert_eq!(
+            budget.used(),
+            budget.limit() + (8 + 8 + ENTRY_OVERHEAD) - 2 * (10 + ENTRY_OVERHEAD)
+        );
+        assert_eq!(db.get(99u64.to_be_bytes()), Some(val(99)));
+
+        drop(db);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn memory_budget_duplicate() {
+        use super::super::budget::ENTRY_OVERHEAD;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "budget").unwrap();
+        for no in 0..3u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+
+        // Record for the key 1 is appended once again, so it follows the one of the next key
+        let log = dir.path().join("budget.log");
+        let data = fs::read(&log).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&log)
+            .unwrap()
+            .write_all(&data[20..30])
+            .unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("budget.idx"))
+            .unwrap()
+            .write_all(&[1u64.to_be_bytes(), 40u64.to_le_bytes()].concat())
+            .unwrap();
+
+        let mut db = Db::open(dir.path(), "budget").unwrap();
+        let budget = MemoryBudget::new(1 << 16);
+        db.set_memory_budget(budget.clone());
+        let index = budget.used();
+        for _ in 0..2 {
+            assert!((0..3u64).all(|no| db.get(no.to_be_bytes()) == Some(val(no))));
+        }
+        assert_eq!(budget.used(), index + 3 * (10 + ENTRY_OVERHEAD));
+    }
+
+    #[test]
+    fn stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = LogOptions::new().bloom_filter(100, 0.01);
+        let mut db = Db::create_with(dir.path(), "stats", opts).unwrap();
+        for no in 0..10u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        let stats = db.stats().unwrap();
+        let bloom = fs::metadata(dir.path().join("stats.bloom")).unwrap().len();
+        assert_eq!(stats.log_size, 10 + 10 * 10);
+        assert_eq!(stats.idx_size, 10 + 10 * 16 + bloom);
+        assert_eq!((stats.records, stats.pages, stats.pending), (10, 0, 0));
+    }
+
+    #[test]
+    fn capacity() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = LogOptions::new().with_capacity(10_000, 1 << 20);
+        let mut db = Db::create_with(dir.path(), "capacity", opts).unwrap();
+        let KeyIndex::Full(index) = db.index.get_mut() else {
+            panic!("index is not full")
+        };
+        assert!(index.capacity() >= 10_000);
+        let idx = fs::metadata(dir.path().join("capacity.idx")).unwrap();
+        assert_eq!(idx.len(), 10);
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let log = fs::metadata(dir.path().join("capacity.log")).unwrap();
+            assert!(log.blocks() == 0 || log.blocks() * 512 >= 1 << 20);
+        }
+
+        for no in 0..100u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+        let db =
+            Db::open_with(dir.path(), "capacity", LogOptions::new().with_capacity(1, 1)).unwrap();
+        assert_eq!(db.len(), 100);
+        assert!(db.iter().map(|(_, v)| v).eq((0..100).map(val)));
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn verified() {
+        use crate::Sha256Hasher;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "verified").unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        drop(db);
+
+        let mut db = Db::open_verified::<Sha256Hasher>(dir.path(), "verified").unwrap();
+        db.insert(1u64.to_be_bytes(), &val(1));
+        assert_eq!(db.try_get(0u64.to_be_bytes()).unwrap(), Some(val(0)));
+        assert_eq!(db.try_get(1u64.to_be_bytes()).unwrap(), Some(val(1)));
+        drop(db);
+
+        // The hash left by an interrupted append is dropped on open
+        let sums = dir.path().join("verified.sum");
+        let mut file = fs::OpenOptions::new().append(true).open(&sums).unwrap();
+        file.write_all(&[0u8; 32]).unwrap();
+        drop(file);
+        let db = Db::open_verified::<Sha256Hasher>(dir.path(), "verified").unwrap();
+        assert_eq!(fs::metadata(&sums).unwrap().len(), 10 + 2 * 32);
+        drop(db);
+
+        // The hashes lost after their index entries were written are re-computed on open
+        let hashes = fs::read(&sums).unwrap();
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&sums)
+            .unwrap()
+            .set_len(10 + 20)
+            .unwrap();
+        let db = Db::open_verified::<Sha256Hasher>(dir.path(), "verified").unwrap();
+        assert_eq!(db.try_get(1u64.to_be_bytes()).unwrap(), Some(val(1)));
+        drop(db);
+        assert_eq!(fs::read(&sums).unwrap(), hashes);
+
+        // Corrupt the last byte of the second value
+        let path = dir.path().join("verified.log");
+        let mut data = fs::read(&path).unwrap();
+        *data.last_mut().unwrap() ^= 0xFF;
+        fs::write(&path, data).unwrap();
+
+        let db = Db::open_verified::<Sha256Hasher>(dir.path(), "verified").unwrap();
+        assert_eq!(db.try_get(0u64.to_be_bytes()).unwrap(), Some(val(0)));
+        assert!(matches!(
+            db.try_get(1u64.to_be_bytes()),
+            Err(AoraError::HashMismatch { pos: 20, .. })
+        ));
+        // Without verification, the corrupted value is returned
+        let db = Db::open(dir.path(), "verified").unwrap();
+        assert_ne!(db.try_get(1u64.to_be_bytes()).unwrap(), Some(val(1)));
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn verified_duplicate() {
+        use crate::Sha256Hasher;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_verified::<Sha256Hasher>(dir.path(), "verified").unwrap();
+        for no in 0..3u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+
+        // Record for the key 1 is appended once again with its hash, which shifts the hashes of
+        // the keys appended later
+        let append = |ext: &str, data: &[u8]| {
+            let path = dir.path().join("verified").with_extension(ext);
+            let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+            file.write_all(data).unwrap();
+        };
+        let data = fs::read(dir.path().join("verified.log")).unwrap();
+        append("log", &data[20..30]);
+        append("idx", &[1u64.to_be_bytes(), 40u64.to_le_bytes()].concat());
+        let sums = dir.path().join("verified.sum");
+        let hashes = fs::read(&sums).unwrap();
+        append("sum", &hashes[10 + 32..10 + 64]);
+
+        let mut db = Db::open_verified::<Sha256Hasher>(dir.path(), "verified").unwrap();
+        db.insert(3u64.to_be_bytes(), &val(3));
+        assert!((0..4u64).all(|no| db.try_get(no.to_be_bytes()).unwrap() == Some(val(no))));
+        let reader = db.reader().unwrap();
+        assert_eq!(reader.try_get(3u64.to_be_bytes()).unwrap(), Some(val(3)));
+        drop(db);
+
+        // The lost hashes are re-computed for each index entry, including the duplicate one
+        let hashes = fs::read(&sums).unwrap();
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&sums)
+            .unwrap()
+            .set_len(10 + 32)
+            .unwrap();
+        let db = Db::open_verified::<Sha256Hasher>(dir.path(), "verified").unwrap();
+        assert!((0..4u64).all(|no| db.try_get(no.to_be_bytes()).unwrap() == Some(val(no))));
+        drop(db);
+        assert_eq!(fs::read(&sums).unwrap(), hashes);
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn hash_chain() {
+        use crate::Sha256Hasher;
+
+        let opts = || LogOptions::new().hash_chain::<Sha256Hasher>();
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_with(dir.path(), "chain", opts()).unwrap();
+        assert_eq!(db.chain_tip(), Some([0u8; 32]));
+        for no in 0..10u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        let tip = db.chain_tip().unwrap();
+        assert_eq!(db.verify_chain().unwrap(), tip);
+        assert_eq!(db.get(5u64.to_be_bytes()), Some(val(5)));
+        drop(db);
+
+        assert!(Db::open(dir.path(), "chain").is_err());
+        let mut db = Db::open_with(dir.path(), "chain", opts()).unwrap();
+        assert_eq!(db.chain_tip(), Some(tip));
+        db.insert(10u64.to_be_bytes(), &val(10));
+        assert_eq!(db.verify_chain().unwrap(), db.chain_tip().unwrap());
+        assert_eq!(db.iter().count(), 11);
+        drop(db);
+
+        // Tamper with the value of the third record: 4-byte length, 32-byte hash, 10-byte value
+        let path = dir.path().join("chain.log");
+        let mut data = fs::read(&path).unwrap();
+        data[10 + 2 * 46 + 36 + 2] ^= 0xFF;
+        fs::write(&path, data).unwrap();
+
+        let db = Db::open_with(dir.path(), "chain", opts()).unwrap();
+        assert!(matches!(db.verify_chain(), Err(AoraError::ChainBroken { pos: 148, .. })));
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn merkle_root() {
+        use crate::{AoraHasher, Sha256Hasher, merkle_leaf, merkle_node};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "merkle").unwrap();
+        let empty = db.merkle_root::<Sha256Hasher>().unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        db.insert(1u64.to_be_bytes(), &val(1));
+
+        let value_hash = |no: u64| {
+            let mut data = vec![8, 0];
+            data.extend(no.to_le_bytes());
+            Sha256Hasher::digest(&data)
+        };
+        let leaf = |no: u64| {
+            let mut data = no.to_be_bytes().to_vec();
+            data.extend(value_hash(no));
+            merkle_leaf::<Sha256Hasher>(&data)
+        };
+        assert_eq!(db.merkle_root_at::<Sha256Hasher>(0).unwrap(), empty);
+        assert_eq!(db.merkle_root_at::<Sha256Hasher>(1).unwrap(), leaf(0));
+        assert_eq!(
+            db.merkle_root::<Sha256Hasher>().unwrap(),
+            merkle_node::<Sha256Hasher>(&leaf(0), &leaf(1))
+        );
+
+        for no in 2..7u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        let root = db.merkle_root::<Sha256Hasher>().unwrap();
+        let proof = db
+            .prove_inclusion::<Sha256Hasher>(3u64.to_be_bytes())
+            .unwrap()
+            .unwrap();
+        assert_eq!(proof.size, 7);
+        assert!(proof.verify::<Sha256Hasher>(root, &3u64.to_be_bytes(), value_hash(3)));
+        assert!(!proof.verify::<Sha256Hasher>(root, &3u64.to_be_bytes(), value_hash(4)));
+        assert!(!proof.verify::<Sha256Hasher>(root, &4u64.to_be_bytes(), value_hash(3)));
+        assert_eq!(
+            db.prove_inclusion::<Sha256Hasher>(7u64.to_be_bytes())
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn reverse_index() {
+        use crate::file::FileAoraIndex;
+        use crate::{AoraIndex, build_index_from};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "reverse_index").unwrap();
+        for no in 0..10u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        let mut index = FileAoraIndex::<
+            [u8; 1],
+            [u8; 8],
+            { u64::from_be_bytes(*b"DUMBTEST") },
+            1,
+            1,
+            8,
+        >::create_new(dir.path(), "reverse_index")
+        .unwrap();
+        build_index_from(&db, &mut index, |val| [[val[0] % 2]]);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.value_len([0]), 5);
+        assert!(index.get([1]).all(|key| u64::from_be_bytes(key) % 2 == 1));
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn par_iter() {
+        use rayon::prelude::*;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "par_iter").unwrap();
+        for no in 0..1000u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        let mut items = db.par_iter().collect::<Vec<_>>();
+        items.sort_by_key(|(key, _)| *key);
+        assert_eq!(items, db.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn timestamps() {
+        let opts = || LogOptions::new().timestamps();
+        let dir = tempfile::tempdir().unwrap();
+        let start = SystemTime::now();
+        let mut db = Db::create_with(dir.path(), "timed", opts()).unwrap();
+        for no in 0..5u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        let end = SystemTime::now();
+        let first = db.inserted_at(0u64.to_be_bytes()).unwrap();
+        assert!(start <= first && first <= end);
+        assert_eq!(db.inserted_at(5u64.to_be_bytes()), None);
+        drop(db);
+
+        assert!(Db::open(dir.path(), "timed").is_err());
+        let mut db = Db::open_with(dir.path(), "timed", opts()).unwrap();
+        db.insert(5u64.to_be_bytes(), &val(5));
+        assert_eq!(db.get(3u64.to_be_bytes()), Some(val(3)));
+        let items = db.iter_timed().collect::<Vec<_>>();
+        assert_eq!(items.len(), 6);
+        assert_eq!(items[0].2, Some(first));
+        assert!(items.windows(2).all(|pair| pair[0].2 <= pair[1].2));
+        assert!(items[5].2.unwrap() >= end);
+        assert_eq!(
+            items.iter().map(|(key, value, _)| (*key, value.clone())).collect::<Vec<_>>(),
+            db.iter().collect::<Vec<_>>()
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "plain").unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        assert_eq!(db.inserted_at(0u64.to_be_bytes()), None);
+        assert_eq!(db.iter_timed().next(), Some((0u64.to_be_bytes(), val(0), None)));
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn record_flags() {
+        use crate::Sha256Hasher;
+
+        let opts = || LogOptions::new().record_flags().hash_chain::<Sha256Hasher>();
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_with(dir.path(), "flags", opts()).unwrap();
+        for no in 0..5u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        let flags = db.record_flags(2u64.to_be_bytes()).unwrap();
+        assert_eq!(flags, RecordFlags::default());
+        assert!(!flags.is_compressed() && !flags.is_encrypted() && !flags.is_superseded());
+
+        db.set_user_flags(2u64.to_be_bytes(), 0x0A).unwrap();
+        assert_eq!(db.record_flags(2u64.to_be_bytes()).unwrap().user(), 0x0A);
+        assert_eq!(db.record_flags(3u64.to_be_bytes()).unwrap().user(), 0);
+        let err = db.set_user_flags(2u64.to_be_bytes(), 0x10).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = db.set_user_flags(5u64.to_be_bytes(), 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        db.insert(5u64.to_be_bytes(), &val(5));
+        assert_eq!(db.get(2u64.to_be_bytes()), Some(val(2)));
+        assert_eq!(db.verify_chain().unwrap(), db.chain_tip().unwrap());
+        drop(db);
+
+        let db = Db::open_with(dir.path(), "flags", opts()).unwrap();
+        assert_eq!(db.record_flags(2u64.to_be_bytes()).unwrap().user(), 0x0A);
+        assert_eq!(db.iter().count(), 6);
+
+        let mut db = Db::create_new(dir.path(), "plain").unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        assert_eq!(db.record_flags(0u64.to_be_bytes()), None);
+        let err = db.set_user_flags(0u64.to_be_bytes(), 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn superseded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "tomb").unwrap();
+        for no in 0..5u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        assert!(!db.is_superseded(1u64.to_be_bytes()));
+        assert!(db.mark_superseded(1u64.to_be_bytes()).unwrap());
+        assert!(db.mark_superseded(3u64.to_be_bytes()).unwrap());
+        assert!(!db.mark_superseded(3u64.to_be_bytes()).unwrap());
+        let err = db.mark_superseded(5u64.to_be_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        assert_eq!(db.get(1u64.to_be_bytes()), Some(val(1)));
+        assert_eq!(db.get_with_status(1u64.to_be_bytes()), Some((val(1), true)));
+        assert_eq!(db.get_with_status(2u64.to_be_bytes()), Some((val(2), false)));
+        assert_eq!(db.get_with_status(5u64.to_be_bytes()), None);
+        assert_eq!(db.iter().count(), 5);
+        let live = db.iter_live().map(|(key, _)| key).collect::<Vec<_>>();
+        assert_eq!(live, [0u64, 2, 4].map(u64::to_be_bytes));
+
+        let mut other = Db::open(dir.path(), "tomb").unwrap();
+        assert!(other.is_superseded(3u64.to_be_bytes()));
+        db.mark_superseded(4u64.to_be_bytes()).unwrap();
+        db.flush().unwrap();
+        other.refresh().unwrap();
+        assert!(other.is_superseded(4u64.to_be_bytes()));
+        assert_eq!(other.iter_live().count(), 2);
+    }
+
+    #[test]
+    fn redact() {
+        let opts = || LogOptions::new().record_flags();
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_with(dir.path(), "redact", opts()).unwrap();
+        for no in 0..5u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        db.redact(2u64.to_be_bytes()).unwrap();
+        let err = db.redact(5u64.to_be_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        assert!(db.contains_key(2u64.to_be_bytes()));
+        assert_eq!(db.get(2u64.to_be_bytes()), None);
+        assert!(matches!(db.try_get(2u64.to_be_bytes()), Err(AoraError::Redacted { pos: 40, .. })));
+        assert!(db.record_flags(2u64.to_be_bytes()).unwrap().is_redacted());
+        assert_eq!(db.get(3u64.to_be_bytes()), Some(val(3)));
+        assert_eq!(db.iter().count(), 4);
+        assert_eq!(db.try_iter().filter(Result::is_err).count(), 1);
+        drop(db);
+
+        // Each record has a 4-byte length, a flags byte and a 10-byte value
+        let data = fs::read(dir.path().join("redact.log")).unwrap();
+        assert_eq!(data[10 + 2 * 15 + 4], RecordFlags::REDACTED);
+        assert!(data[10 + 2 * 15 + 5..10 + 3 * 15].iter().all(|byte| *byte == 0));
+
+        let mut db = Db::open_with(dir.path(), "redact", opts()).unwrap();
+        assert_eq!(db.get(2u64.to_be_bytes()), None);
+        assert_eq!(db.iter_rev().count(), 4);
+
+        // The key of the redacted record stays in the map and can't be inserted again
+        assert!(matches!(
+            db.try_insert(2u64.to_be_bytes(), &val(2)),
+            Err(AoraError::Redacted { pos: 40, .. })
+        ));
+        db.insert(2u64.to_be_bytes(), &val(2));
+        let batch = [(2u64.to_be_bytes(), &val(2)), (5u64.to_be_bytes(), &val(5))];
+        assert_eq!(db.insert_batch(batch), 1);
+        assert_eq!(db.len(), 6);
+        assert_eq!(db.get(2u64.to_be_bytes()), None);
+
+        let mut db = Db::create_new(dir.path(), "plain").unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        let err = db.redact(0u64.to_be_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "quota").unwrap();
+        db.insert(0u64.to_be_bytes(), &val(0));
+        let stats = db.stats().unwrap();
+        let size = stats.log_size + stats.idx_size;
+        // Each item takes a 10-byte record and a 16-byte index entry
+        db.set_quota(size + 2 * 26);
+        db.try_insert(1u64.to_be_bytes(), &val(1)).unwrap();
+        db.try_insert(2u64.to_be_bytes(), &val(2)).unwrap();
+        let err = db.try_insert(3u64.to_be_bytes(), &val(3)).unwrap_err();
+        assert!(matches!(err, AoraError::QuotaExceeded { size: s, limit, .. }
+            if s == size + 3 * 26 && limit == size + 2 * 26));
+        // Re-inserting an item writes nothing
+        db.try_insert(2u64.to_be_bytes(), &val(2)).unwrap();
+        assert_eq!(db.get(3u64.to_be_bytes()), None);
+        let stats = db.stats().unwrap();
+        assert_eq!(stats.log_size + stats.idx_size, size + 2 * 26);
+        drop(db);
+
+        let mut db = Db::open(dir.path(), "quota").unwrap();
+        assert_eq!(db.iter().count(), 3);
+        db.insert(3u64.to_be_bytes(), &val(3));
+        assert_eq!(db.get(3u64.to_be_bytes()), Some(val(3)));
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn shred() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = Db::create_with(dir.path(), "plain", LogOptions::new().shreddable()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let coalesced = LogOptions::new()
+            .encrypt([7u8; 32])
+            .shreddable()
+            .coalesce_index(64);
+        let err = Db::create_with(dir.path(), "coalesced", coalesced).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let opts = || LogOptions::new().encrypt([7u8; 32]).shreddable();
+        let mut db = Db::create_with(dir.path(), "shred", opts()).unwrap();
+        for no in 0..3u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        db.insert_batch((3..5u64).map(|no| no.to_be_bytes()).zip([val(3), val(4)].iter()));
+        let log = fs::read(dir.path().join("shred.log")).unwrap();
+
+        db.shred(1u64.to_be_bytes()).unwrap();
+        let err = db.shred(5u64.to_be_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(db.contains_key(1u64.to_be_bytes()));
+        assert_eq!(db.get(1u64.to_be_bytes()), None);
+        assert!(matches!(db.try_get(1u64.to_be_bytes()), Err(AoraError::Shredded { .. })));
+        assert_eq!(db.get(4u64.to_be_bytes()), Some(val(4)));
+        assert_eq!(db.iter().count(), 4);
+        assert!(db.check_all().unwrap().issues.is_empty());
+        assert!(matches!(
+            db.try_insert(1u64.to_be_bytes(), &val(1)),
+            Err(AoraError::Shredded { .. })
+        ));
+        db.insert(1u64.to_be_bytes(), &val(1));
+        assert_eq!(db.len(), 5);
+        drop(db);
+
+        // Shredding doesn't touch the log
+        assert_eq!(fs::read(dir.path().join("shred.log")).unwrap(), log);
+        let deks = fs::read(dir.path().join("shred.dek")).unwrap();
+        assert_eq!(deks.len(), 10 + 5 * WRAPPED_KEY_LEN);
+
+        let db = Db::open_with(dir.path(), "shred", opts()).unwrap();
+        assert_eq!(db.get(1u64.to_be_bytes()), None);
+        assert_eq!(db.get(2u64.to_be_bytes()), Some(val(2)));
+        assert_eq!(db.iter_rev().count(), 4);
+        drop(db);
+
+        // The data keys can't be used with another secret key
+        let opts = LogOptions::new().encrypt([8u8; 32]).shreddable();
+        let db = Db::open_with(dir.path(), "shred", opts).unwrap();
+        assert!(matches!(db.try_get(2u64.to_be_bytes()), Err(AoraError::Decrypt { .. })));
+
+        // The data keys are appended through a write-through handle too
+        let opts = || {
+            LogOptions::new()
+                .encrypt([7u8; 32])
+                .shreddable()
+                .write_through()
+        };
+        let mut db = Db::create_with(dir.path(), "through", opts()).unwrap();
+        for no in 0..3u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        let deks = fs::read(dir.path().join("through.dek")).unwrap();
+        assert_eq!(deks.len(), 10 + 3 * WRAPPED_KEY_LEN);
+        db.shred(1u64.to_be_bytes()).unwrap();
+        db.insert(3u64.to_be_bytes(), &val(3));
+        drop(db);
+        let db = Db::open_with(dir.path(), "through", opts()).unwrap();
+        assert_eq!(db.get(1u64.to_be_bytes()), None);
+        assert_eq!(db.get(3u64.to_be_bytes()), Some(val(3)));
+        assert_eq!(db.iter().count(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn shred_duplicate() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = || LogOptions::new().encrypt([7u8; 32]).shreddable();
+        let mut db = Db::create_with(dir.path(), "shred", opts()).unwrap();
+        for no in 0..3u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+
+        // Record for the key 1 is appended once again with its data key, which shifts the data
+        // keys of the keys appended later
+        let file = |ext: &str| dir.path().join("shred").with_extension(ext);
+        let append = |ext: &str, data: &[u8]| {
+            let mut file = fs::OpenOptions::new().append(true).open(file(ext)).unwrap();
+            file.write_all(data).unwrap();
+        };
+        let (log, idx) = (fs::read(file("log")).unwrap(), fs::read(file("idx")).unwrap());
+        let pos = |no: usize| {
+            let start = 10 + no * 16 + 8;
+            u64::from_le_bytes(idx[start..start + 8].try_into().unwrap()) as usize
+        };
+        append("log", &log[pos(1)..pos(2)]);
+        append("idx", &[1u64.to_be_bytes(), (log.len() as u64).to_le_bytes()].concat());
+        let deks = fs::read(file("dek")).unwrap();
+        append("dek", &deks[10 + WRAPPED_KEY_LEN..10 + 2 * WRAPPED_KEY_LEN]);
 
-        let mut reader = StrictReader::with(StreamReader::new::<{ usize::MAX }>(&mut *self.log));
-        let item = V::strict_decode(&mut reader).ok()?;
+        let mut db = Db::open_with(dir.path(), "shred", opts()).unwrap();
+        db.insert(3u64.to_be_bytes(), &val(3));
+        assert_eq!(db.get(3u64.to_be_bytes()), Some(val(3)));
+        db.shred(3u64.to_be_bytes()).unwrap();
+        assert_eq!(db.get(3u64.to_be_bytes()), None);
+        assert!((0..3u64).all(|no| db.get(no.to_be_bytes()) == Some(val(no))));
+        drop(db);
 
-        Some((id.into(), item))
+        let db = Db::open_with(dir.path(), "shred", opts()).unwrap();
+        assert_eq!(fs::read(file("dek")).unwrap().len(), 10 + 5 * WRAPPED_KEY_LEN);
+        assert!(db.iter().map(|(_, value)| value).eq((0..3).map(val)));
     }
 }
@@ -1,19 +1,174 @@
 // SPDX-License-Identifier: Apache-2.0
 
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::ffi::OsStr;
-use std::fmt::Display;
+use std::fmt::{self, Debug, Display, Formatter};
 use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::marker::PhantomData;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{fs, mem};
 
 use amplify::hex::ToHex;
 use binfile::BinFile;
 use indexmap::IndexMap;
 
-use crate::{AuraMap, TransactionalMap};
+use super::budget::{Charge, ENTRY_OVERHEAD, MemoryBudget};
+use super::latency::Latencies;
+use super::layout::{TableKind, TableLayout};
+use super::observer::{AoraObserver, Observer};
+use super::report::DebugReport;
+use super::spill::Spill;
+#[cfg(feature = "notify")]
+use super::watch::ChangeWatcher;
+use super::{AoraError, StorageStats, telemetry};
+use crate::{AoraKey, AuraMap, TransactionalMap};
+
+/// Signer producing detached signatures for the committed [`FileAuraMap`] pages.
+pub trait PageSigner {
+    /// Signs the message, returning the signature bytes.
+    fn sign(&self, msg: &[u8]) -> Vec<u8>;
+}
+
+impl<F: Fn(&[u8]) -> Vec<u8>> PageSigner for F {
+    fn sign(&self, msg: &[u8]) -> Vec<u8> { self(msg) }
+}
+
+/// Public key verifying the detached signatures of the [`FileAuraMap`] pages.
+pub trait PageVerifier {
+    /// Checks that the signature is valid for the message.
+    fn verify(&self, msg: &[u8], sig: &[u8]) -> bool;
+}
+
+impl<F: Fn(&[u8], &[u8]) -> bool> PageVerifier for F {
+    fn verify(&self, msg: &[u8], sig: &[u8]) -> bool { self(msg, sig) }
+}
+
+struct Signer(Box<dyn PageSigner + Send + Sync>);
+
+impl Debug for Signer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str("Signer(..)") }
+}
+
+/// Flag set in the key count of a page chunk continued by the next chunk of the same page, so a
+/// large transaction can be written to the log in several chunks.
+pub(super) const CONTINUED: u64 = 1 << 63;
+/// Flag set in the key count of the first chunk of a page, which is followed by the commit time of
+/// the page.
+pub(super) const TIMESTAMPED: u64 = 1 << 62;
+/// Flag set in the key count of the first chunk of the base page, which replaces the last archived
+/// transaction and holds the latest values of the keys updated by the archived transactions.
+pub(super) const BASE: u64 = 1 << 61;
+/// Flags which may be set in the key count of a page chunk.
+pub(super) const PAGE_FLAGS: u64 = CONTINUED | TIMESTAMPED | BASE;
+
+/// Returns the current time as the number of nanoseconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("unable to get time since the Unix epoch")
+        .as_nanos() as u64
+}
+
+/// Filters out the keys already yielded by the iterator, so each key is yielded once.
+fn unique<const KEY_LEN: usize>(
+    keys: impl Iterator<Item = [u8; KEY_LEN]>,
+) -> impl Iterator<Item = [u8; KEY_LEN]> {
+    let mut seen = HashSet::new();
+    keys.filter(move |key| seen.insert(*key))
+}
+
+/// Adds the range to the list, merging it with the last one if they are adjacent.
+fn push_range(ranges: &mut Vec<Range<u64>>, range: Range<u64>) {
+    match ranges.last_mut() {
+        Some(last) if last.end == range.start => last.end = range.end,
+        _ => ranges.push(range),
+    }
+}
+
+/// Visibility of the pending transaction writes to the reads of a [`FileAuraMap`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum Visibility {
+    /// Reads see the writes of the pending transaction (read-your-writes).
+    #[default]
+    IncludePending,
+    /// Reads see only the committed transactions.
+    CommittedOnly,
+}
+
+/// Handling of the pending transaction when a [`FileAuraMap`] is dropped.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum DropPolicy {
+    /// Panic if the pending transaction is not empty.
+    #[default]
+    PanicOnUncommitted,
+    /// Discard the pending transaction.
+    AbortTransaction,
+    /// Commit the pending transaction.
+    CommitOnDrop,
+}
+
+/// Page of a committed transaction, shared between the map and its snapshots.
+type Page<const KEY_LEN: usize, const VAL_LEN: usize> = Arc<IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>>;
+
+/// Pages read from a log file.
+struct LogPages<const KEY_LEN: usize, const VAL_LEN: usize> {
+    pages: Vec<Page<KEY_LEN, VAL_LEN>>,
+    /// Commit times of the pages.
+    times: Vec<Option<u64>>,
+    /// Positions of the pages in the log file.
+    offsets: Vec<u64>,
+    /// Number of the archived transactions.
+    archived: usize,
+}
+
+/// Index of the latest values of the keys from the saved pages evicted from memory, shared
+/// between the map and its snapshots.
+#[derive(Clone, Debug, Default)]
+struct LatestValues<const KEY_LEN: usize, const VAL_LEN: usize> {
+    /// Latest values of the keys in the order the keys were first inserted, with the sequence
+    /// numbers of the writes which last updated them.
+    values: IndexMap<[u8; KEY_LEN], ([u8; VAL_LEN], u64)>,
+    /// Number of the writes merged into the index.
+    writes: u64,
+}
+
+impl<const KEY_LEN: usize, const VAL_LEN: usize> LatestValues<KEY_LEN, VAL_LEN> {
+    /// Merges the writes of the page into the index.
+    fn merge(&mut self, page: &IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>) {
+        for (key, value) in page {
+            self.values.insert(*key, (*value, self.writes));
+            self.writes += 1;
+        }
+    }
+
+    fn get(&self, key: &[u8; KEY_LEN]) -> Option<[u8; VAL_LEN]> {
+        self.values.get(key).map(|(value, _)| *value)
+    }
+
+    /// Returns an iterator over the keys in the order they were first inserted.
+    fn keys(&self) -> impl Iterator<Item = [u8; KEY_LEN]> + '_ { self.values.keys().copied() }
+
+    /// Returns the keys sorted by their last update, the most recently updated first.
+    fn keys_rev(&self) -> impl Iterator<Item = [u8; KEY_LEN]> {
+        let mut keys = self
+            .values
+            .iter()
+            .map(|(key, (_, seq))| (*seq, *key))
+            .collect::<Vec<_>>();
+        keys.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+        keys.into_iter().map(|(_, key)| key)
+    }
+}
 
 // For now, this is just an in-memory read BTree. In the next releases we need to change this.
+/// File-based [`AuraMap`] provider.
+///
+/// The pages are stored unencrypted, even with the `encryption` feature, which covers only the
+/// [`super::FileAoraMap`] logs and the [`super::FileAoraIndex`] indexes.
 #[derive(Debug)]
 pub struct FileAuraMap<
     K,
@@ -23,29 +178,72 @@ pub struct FileAuraMap<
     const KEY_LEN: usize = 32,
     const VAL_LEN: usize = 32,
 > where
-    K: From<[u8; KEY_LEN]> + Into<[u8; KEY_LEN]>,
-    V: From<[u8; VAL_LEN]> + Into<[u8; VAL_LEN]>,
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
 {
     path: PathBuf,
-    on_disk: Vec<IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>>,
-    dirty: Vec<IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>>,
+    /// Committed pages, shared with the snapshots. Once the saved pages are evicted, they are
+    /// replaced with empty ones.
+    on_disk: Vec<Page<KEY_LEN, VAL_LEN>>,
+    /// Positions of the saved pages in the log file.
+    offsets: Vec<u64>,
+    /// Index of the latest values of the keys from the evicted pages, if the saved pages are
+    /// evicted from memory.
+    latest: Option<Arc<LatestValues<KEY_LEN, VAL_LEN>>>,
+    dirty: Vec<Page<KEY_LEN, VAL_LEN>>,
+    /// Commit times of the committed pages, in nanoseconds since the Unix epoch, or `None` for
+    /// the pages committed before the times were recorded.
+    times: Vec<Option<u64>>,
+    /// Number of the leading transactions moved to archive logs, the last of which is replaced
+    /// with the base page.
+    archived: usize,
     pending: IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>,
+    signer: Option<Signer>,
+    observer: Option<Observer>,
+    latencies: Option<Latencies>,
+    /// Memory charged for the pages, if a memory budget is set.
+    memory: Option<Charge>,
+    visibility: Visibility,
+    /// Maximal number of keys and size in bytes of the pending page, reaching which commits the
+    /// pending transaction.
+    auto_commit: Option<(usize, usize)>,
+    /// Pending writes spilled to a file, since they didn't fit into memory.
+    spill: Option<Spill<KEY_LEN, VAL_LEN>>,
+    /// Size in bytes of the pending writes kept in memory, reaching which spills them to a file.
+    spill_limit: Option<usize>,
+    /// Maximal number of keys in a chunk of the page written to the log on commit.
+    commit_chunk: Option<usize>,
+    /// Length of the log file up to the end of the committed pages.
+    log_len: u64,
+    /// Maximal size of the log file, in bytes.
+    quota: Option<u64>,
+    drop_policy: DropPolicy,
+    /// Whether a save has failed, leaving the log file out of sync with the committed pages.
+    poisoned: bool,
+    /// Optional watcher of the changes made to the log file by other processes.
+    #[cfg(feature = "notify")]
+    watcher: Option<ChangeWatcher>,
     _phantom: PhantomData<(K, V)>,
 }
 
 impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize, const VAL_LEN: usize>
     FileAuraMap<K, V, MAGIC, VER, KEY_LEN, VAL_LEN>
 where
-    K: From<[u8; KEY_LEN]> + Into<[u8; KEY_LEN]>,
-    V: From<[u8; VAL_LEN]> + Into<[u8; VAL_LEN]>,
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
 {
+    /// Estimated memory used by a page entry, in bytes.
+    const ENTRY_SIZE: usize = KEY_LEN + VAL_LEN + ENTRY_OVERHEAD;
+
     fn prepare(path: impl AsRef<Path>, name: &str) -> PathBuf {
         let path = path.as_ref();
         path.join(name).with_extension("log")
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(path), err))]
     pub fn create_new(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
-        let path = Self::prepare(path, name);
+        let dir = path.as_ref();
+        let path = Self::prepare(dir, name);
         if fs::exists(&path)? {
             return Err(io::Error::new(
                 io::ErrorKind::AlreadyExists,
@@ -55,10 +253,31 @@ where
         let mut file = BinFile::<MAGIC, VER>::create_new(&path)
             .map_err(|e| io::Error::new(e.kind(), format!("at path '{}'", path.display())))?;
         file.write_all(&[0u8; 8])?;
+        TableLayout { kind: TableKind::AppendUpdate, key_len: KEY_LEN, val_len: Some(VAL_LEN) }
+            .save::<MAGIC, VER>(dir, name)?;
         Ok(Self {
             on_disk: Vec::new(),
+            offsets: Vec::new(),
+            latest: None,
             dirty: Vec::new(),
+            times: Vec::new(),
+            archived: 0,
             pending: default!(),
+            signer: None,
+            observer: None,
+            latencies: None,
+            memory: None,
+            visibility: Visibility::default(),
+            auto_commit: None,
+            spill: None,
+            spill_limit: None,
+            commit_chunk: None,
+            log_len: 18,
+            quota: None,
+            drop_policy: DropPolicy::default(),
+            poisoned: false,
+            #[cfg(feature = "notify")]
+            watcher: None,
             path,
             _phantom: PhantomData,
         })
@@ -69,8 +288,10 @@ where
         if !fs::exists(&path)? { Self::create_new(path, name) } else { Self::open(path, name) }
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(path), err))]
     pub fn open(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
-        let path = Self::prepare(path, name);
+        let dir = path.as_ref();
+        let path = Self::prepare(dir, name);
 
         if !fs::exists(&path)? {
             return Err(io::Error::new(
@@ -78,44 +299,673 @@ where
                 format!("append-update log file '{}' does not exist", path.display()),
             ));
         }
+        TableLayout { kind: TableKind::AppendUpdate, key_len: KEY_LEN, val_len: Some(VAL_LEN) }
+            .check::<MAGIC, VER>(dir, name)?;
         let mut file = BinFile::<MAGIC, VER>::open(&path)?;
+        let LogPages { pages: cache, times, offsets, archived } = Self::read_pages(&mut file)?;
+        let log_len = file.stream_position()?;
+
+        if log_len != file.metadata()?.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("append-update log file '{}' is corrupted", path.display()),
+            ));
+        }
+
+        Ok(Self {
+            path,
+            on_disk: cache,
+            offsets,
+            latest: None,
+            dirty: Vec::new(),
+            times,
+            archived,
+            pending: default!(),
+            signer: None,
+            observer: None,
+            latencies: None,
+            memory: None,
+            visibility: Visibility::default(),
+            auto_commit: None,
+            spill: None,
+            spill_limit: None,
+            commit_chunk: None,
+            log_len,
+            quota: None,
+            drop_policy: DropPolicy::default(),
+            poisoned: false,
+            #[cfg(feature = "notify")]
+            watcher: None,
+            _phantom: PhantomData,
+        })
+    }
 
+    /// Reads the pages counted in the file header, together with their commit times, their
+    /// positions in the file and the number of the archived transactions.
+    fn read_pages(file: &mut BinFile<MAGIC, VER>) -> io::Result<LogPages<KEY_LEN, VAL_LEN>> {
         let mut buf = [0u8; 8];
         file.read_exact(&mut buf)?;
         let num_pages = u64::from_le_bytes(buf);
 
+        let mut pages = Vec::with_capacity(num_pages as usize);
+        let mut times = Vec::with_capacity(num_pages as usize);
+        let mut offsets = Vec::with_capacity(num_pages as usize);
+        let mut archived = 0;
+        for no in 0..num_pages as usize {
+            offsets.push(file.stream_position()?);
+            let (page, time, base) = Self::read_page(&mut **file)?;
+            pages.push(page);
+            times.push(time);
+            if base {
+                archived = no + 1;
+            }
+        }
+        Ok(LogPages { pages, times, offsets, archived })
+    }
+
+    /// Reads the number of keys followed by the key-value pairs of a page, joining the chunks the
+    /// page was written in, the commit time of the page, if it is recorded, and whether it is the
+    /// base page.
+    fn read_page(
+        reader: &mut impl Read,
+    ) -> io::Result<(Page<KEY_LEN, VAL_LEN>, Option<u64>, bool)> {
+        let mut buf = [0u8; 8];
         let mut key_buf = [0u8; KEY_LEN];
         let mut val_buf = [0u8; VAL_LEN];
-        let mut cache = Vec::with_capacity(num_pages as usize);
-        for _ in 0..num_pages {
-            file.read_exact(&mut buf)?;
+        let mut page = IndexMap::new();
+        let mut time = None;
+        let mut base = false;
+        loop {
+            reader.read_exact(&mut buf)?;
             let num_keys = u64::from_le_bytes(buf);
-            let mut page = IndexMap::with_capacity(num_keys as usize);
-            for _ in 0..num_keys {
-                file.read_exact(&mut key_buf)?;
-                file.read_exact(&mut val_buf)?;
+            base |= num_keys & BASE != 0;
+            if num_keys & TIMESTAMPED != 0 {
+                reader.read_exact(&mut buf)?;
+                time = Some(u64::from_le_bytes(buf));
+            }
+            page.reserve((num_keys & !PAGE_FLAGS) as usize);
+            for _ in 0..num_keys & !PAGE_FLAGS {
+                reader.read_exact(&mut key_buf)?;
+                reader.read_exact(&mut val_buf)?;
                 page.insert(key_buf, val_buf);
             }
-            cache.push(page);
+            if num_keys & CONTINUED == 0 {
+                break;
+            }
         }
+        Ok((Arc::new(page), time, base))
+    }
 
-        if file.stream_position()? != file.metadata()?.len() {
+    /// Opens the log, checking that all its pages are signed with the key matching the given
+    /// public key.
+    pub fn open_verified(
+        path: impl AsRef<Path>,
+        name: &str,
+        pubkey: &impl PageVerifier,
+    ) -> Result<Self, AoraError> {
+        let me = Self::open(path, name)?;
+        me.verify_signatures(pubkey)?;
+        Ok(me)
+    }
+
+    fn sigs_path(&self) -> PathBuf { self.path.with_extension("sig") }
+
+    /// Serializes the page with the given number into the message which gets signed.
+    fn page_msg(no: u64, page: &IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(16 + page.len() * (KEY_LEN + VAL_LEN));
+        msg.extend(no.to_le_bytes());
+        msg.extend((page.len() as u64).to_le_bytes());
+        for (key, value) in page {
+            msg.extend(key);
+            msg.extend(value);
+        }
+        msg
+    }
+
+    /// Registers a signer, which will produce a detached signature for each committed page. The
+    /// signatures are stored in a `.sig` file next to the log.
+    ///
+    /// Signing must be enabled before the first page is committed, or the log must have been
+    /// signed from its start.
+    pub fn set_signer(
+        &mut self,
+        signer: impl PageSigner + Send + Sync + 'static,
+    ) -> io::Result<()> {
+        let path = self.sigs_path();
+        if !fs::exists(&path)? {
+            if !self.on_disk.is_empty() || !self.dirty.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("append-update log '{}' has unsigned pages", self.path.display()),
+                ));
+            }
+            let mut file = BinFile::<MAGIC, VER>::create_new(&path).map_err(|e| {
+                io::Error::new(e.kind(), format!("signature file '{}'", path.display()))
+            })?;
+            file.write_all(&[0u8; 8])?;
+        } else if self.read_signatures()?.len() != self.on_disk.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("append-update log '{}' has unsigned pages", self.path.display()),
+            ));
+        }
+        self.signer = Some(Signer(Box::new(signer)));
+        Ok(())
+    }
+
+    /// Enables tracking of the operation latencies, which are reported by [`Self::stats`].
+    pub fn track_latencies(&mut self) { self.latencies = Some(Latencies::default()); }
+
+    /// Charges the memory used by the pages to the budget.
+    ///
+    /// The charge covers the pages kept in memory and the index of the latest values replacing
+    /// the evicted ones (see [`Self::evict_saved_pages`]). It is not reclaimed by the budget, so it
+    /// just leaves less memory to the caches of the other tables sharing the budget.
+    pub fn set_memory_budget(&mut self, budget: MemoryBudget) {
+        let records = self
+            .on_disk
+            .iter()
+            .chain(&self.dirty)
+            .map(|page| page.len())
+            .sum::<usize>()
+            + self.latest.as_ref().map_or(0, |latest| latest.values.len())
+            + self.pending.len();
+        let mut charge = Charge::new(budget);
+        charge.set(records * Self::ENTRY_SIZE);
+        self.memory = Some(charge);
+    }
+
+    /// Evicts the saved pages from memory, keeping only their positions in the log file and an
+    /// index of the latest values of their keys, which serves the reads and the key iterators.
+    /// The pages saved afterwards are evicted as soon as they are written to the log.
+    ///
+    /// The memory used by the map becomes proportional to the number of its keys rather than to
+    /// the number of the writes. The evicted pages are read back from the log file by the
+    /// operations which need the transaction contents: [`TransactionalMap::transaction_keys`],
+    /// the archiving, the exports and backups, and the signature checks. [`AuraMap::keys_rev`]
+    /// yields the keys from the index ordered by their last update.
+    pub fn evict_saved_pages(&mut self) {
+        if self.latest.is_none() {
+            self.latest = Some(default!());
+            self.evict_from(0);
+        }
+    }
+
+    /// Merges the saved pages starting from the given one into the index of the latest values
+    /// and evicts them from memory.
+    fn evict_from(&mut self, first: usize) {
+        let Some(latest) = &mut self.latest else {
+            return;
+        };
+        // The snapshots sharing the index keep their copy
+        let latest = Arc::make_mut(latest);
+        let known = latest.values.len();
+        let mut evicted = 0;
+        let empty = Arc::new(IndexMap::new());
+        for page in &mut self.on_disk[first..] {
+            latest.merge(page);
+            evicted += page.len();
+            *page = empty.clone();
+        }
+        if let Some(charge) = &mut self.memory {
+            charge.sub(evicted * Self::ENTRY_SIZE);
+            charge.add((latest.values.len() - known) * Self::ENTRY_SIZE);
+        }
+    }
+
+    /// Returns the saved pages, reading them back from the log file if they are evicted.
+    fn saved_pages(&self) -> io::Result<Cow<'_, [Page<KEY_LEN, VAL_LEN>]>> {
+        if self.latest.is_none() {
+            return Ok(Cow::Borrowed(&self.on_disk));
+        }
+        let mut file = BinFile::<MAGIC, VER>::open(&self.path)
+            .map_err(|e| io::Error::new(e.kind(), format!("at path '{}'", self.path.display())))?;
+        let mut pages = Self::read_pages(&mut file)?.pages;
+        if pages.len() < self.on_disk.len() {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
-                format!("append-update log file '{}' is corrupted", path.display()),
+                format!(
+                    "append-update log file '{}' doesn't match the committed pages",
+                    self.path.display()
+                ),
             ));
         }
+        pages.truncate(self.on_disk.len());
+        Ok(Cow::Owned(pages))
+    }
+
+    /// Returns the saved page with the given number, reading it back from the log file if it is
+    /// evicted.
+    fn saved_page(&self, no: usize) -> io::Result<Page<KEY_LEN, VAL_LEN>> {
+        if self.latest.is_none() {
+            return Ok(self.on_disk[no].clone());
+        }
+        let mut file = BinFile::<MAGIC, VER>::open(&self.path)
+            .map_err(|e| io::Error::new(e.kind(), format!("at path '{}'", self.path.display())))?;
+        file.seek(SeekFrom::Start(self.offsets[no]))?;
+        Self::read_page(&mut *file).map(|(page, ..)| page)
+    }
 
-        Ok(Self {
-            path,
-            on_disk: cache,
-            dirty: Vec::new(),
-            pending: default!(),
-            _phantom: PhantomData,
+    /// Makes the pending transaction commit automatically once its page reaches the given number
+    /// of keys or size in bytes, bounding the memory used by long-running imports and the size of
+    /// the pages.
+    ///
+    /// The automatic commits are made by the writes, which can't report errors, so a write panics
+    /// if its commit fails, as [`TransactionalMap::commit_transaction`] does, including the case
+    /// of the quota set with [`Self::set_quota`] being exceeded.
+    pub fn set_auto_commit(&mut self, max_keys: usize, max_bytes: usize) {
+        self.auto_commit = Some((max_keys, max_bytes));
+    }
+
+    /// Spills the pending transaction to a `.spill` file next to the log each time its writes
+    /// kept in memory reach the given size in bytes (or the memory budget of the map is
+    /// exceeded), so a transaction with more updates than fit into memory can be assembled. On
+    /// commit, the spilled writes are read back into the page of the transaction.
+    ///
+    /// While the transaction is spilled, the reads of its keys scan the spill file, and writing the
+    /// committed value under a key adds the key to the transaction.
+    pub fn set_spill_limit(&mut self, max_bytes: usize) { self.spill_limit = Some(max_bytes); }
+
+    /// Makes the commits write the page of a transaction to the log in chunks of at most the given
+    /// number of keys, streamed through a fixed-size buffer, instead of a single page. The chunks
+    /// form one logical transaction: the page counter in the log header is updated only once all
+    /// of them are written, and they are read back as a single page.
+    ///
+    /// The committed page stays cached in memory as the other pages of the map, unless they are
+    /// evicted; combined with [`Self::set_spill_limit`], this keeps the memory used by the commit
+    /// of a large transaction to its page alone.
+    pub fn set_commit_chunk(&mut self, max_keys: usize) {
+        self.commit_chunk = Some(max_keys.max(1));
+    }
+
+    /// Limits the size of the log file to the given number of bytes. Once committing the pending
+    /// transaction would make the log larger, [`Self::try_commit_transaction`] fails with
+    /// [`AoraError::QuotaExceeded`], leaving the transaction pending, while
+    /// [`TransactionalMap::commit_transaction`] panics.
+    ///
+    /// The size of a spilled transaction is estimated counting the repeated writes of its keys.
+    /// The quota is not persisted and applies only to the commits made through this map.
+    pub fn set_quota(&mut self, limit: u64) { self.quota = Some(limit); }
+
+    /// Returns the number of bytes taken in the log by a page with the given number of keys.
+    fn page_size(&self, keys: usize) -> u64 {
+        let chunks = keys.div_ceil(self.commit_chunk.unwrap_or(usize::MAX)).max(1);
+        (chunks * 8 + 8 + keys * (KEY_LEN + VAL_LEN)) as u64
+    }
+
+    /// Moves the pending writes kept in memory to the spill file.
+    fn spill_pending(&mut self) -> io::Result<()> {
+        let spill = match &mut self.spill {
+            Some(spill) => spill,
+            None => self.spill.insert(Spill::create(self.path.with_extension("spill"))?),
+        };
+        spill.append(&self.pending)?;
+        if let Some(charge) = &mut self.memory {
+            charge.sub(self.pending.len() * Self::ENTRY_SIZE);
+        }
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Commits the pending transaction like [`TransactionalMap::commit_transaction`], reporting
+    /// the failures as errors instead of panicking.
+    ///
+    /// If the log file can't be written, the transaction stays committed in memory, but the map
+    /// gets poisoned and refuses further commits until [`Self::recover`] is called. If the commit
+    /// would exceed the quota set with [`Self::set_quota`], nothing is written and the
+    /// transaction stays pending.
+    pub fn try_commit_transaction(&mut self) -> Result<Option<u64>, AoraError> {
+        if self.poisoned {
+            return Err(AoraError::Poisoned { table: self.name().to_owned() });
+        }
+        if !self.has_pending() {
+            return Ok(None);
+        }
+        if let Some(limit) = self.quota {
+            let size = self.log_len + self.page_size(self.pending_len());
+            if size > limit {
+                return Err(AoraError::QuotaExceeded { table: self.name().to_owned(), size, limit });
+            }
+        }
+        let start = Instant::now();
+        let page = match &self.spill {
+            None => mem::take(&mut self.pending),
+            Some(spill) => {
+                // The later writes of a key update its value, but not its place in the page
+                let mut page = IndexMap::new();
+                for entry in spill.entries(false) {
+                    let (key, value) = entry?;
+                    page.insert(key, value);
+                }
+                let in_memory = self.pending.len();
+                page.extend(mem::take(&mut self.pending));
+                if let Some(charge) = &mut self.memory {
+                    charge.add((page.len() - in_memory) * Self::ENTRY_SIZE);
+                }
+                self.spill = None;
+                page
+            }
+        };
+        // The saved page may get evicted, so the observer is notified from this copy
+        let page = Arc::new(page);
+        self.dirty.push(page.clone());
+        self.times.push(Some(now()));
+        self.save()?;
+        telemetry::committed(self.name(), start);
+        if let Some(latencies) = &self.latencies {
+            latencies.commit.record(start);
+        }
+        let txno = self.transaction_count() - 1;
+        if let Some(observer) = &self.observer {
+            let keys = page
+                .keys()
+                .map(<[u8; KEY_LEN]>::as_slice)
+                .collect::<Vec<_>>();
+            observer.on_commit(txno, &keys);
+        }
+        Ok(Some(txno))
+    }
+
+    /// Returns the wall-clock time the transaction with the given number was committed at, or
+    /// `None` if there is no such committed transaction or if it was committed by a version of
+    /// the library which didn't record the commit times.
+    ///
+    /// The transactions applied from the delta files and the incremental backups keep the times
+    /// they were originally committed at.
+    pub fn transaction_time(&self, txno: u64) -> Option<SystemTime> {
+        let time = self.times.get(txno as usize).copied().flatten()?;
+        Some(UNIX_EPOCH + Duration::from_nanos(time))
+    }
+
+    /// Returns the numbers of the transactions committed at or after `t0` and before `t1`,
+    /// skipping the ones without recorded commit times.
+    ///
+    /// The commit times follow the system clock, so they are not guaranteed to grow with the
+    /// transaction numbers; all the transactions are checked.
+    pub fn transactions_between(
+        &self,
+        t0: SystemTime,
+        t1: SystemTime,
+    ) -> impl Iterator<Item = u64> + '_ {
+        (0..self.times.len() as u64).filter(move |txno| {
+            self.transaction_time(*txno)
+                .is_some_and(|time| time >= t0 && time < t1)
         })
     }
 
+    /// Sets how the pending transaction is handled when the map is dropped; by default, dropping a
+    /// map with a pending transaction panics.
+    pub fn set_drop_policy(&mut self, policy: DropPolicy) { self.drop_policy = policy; }
+
+    /// Sets the observer notified about the inserted and updated keys, and the committed and
+    /// aborted transactions.
+    pub fn set_observer(&mut self, observer: impl AoraObserver + Send + Sync + 'static) {
+        self.observer = Some(Observer::new(observer));
+    }
+
+    fn read_signatures(&self) -> io::Result<Vec<Vec<u8>>> {
+        let path = self.sigs_path();
+        if !fs::exists(&path)? {
+            return Ok(Vec::new());
+        }
+        let mut file = BinFile::<MAGIC, VER>::open(&path).map_err(|e| {
+            io::Error::new(e.kind(), format!("signature file '{}'", path.display()))
+        })?;
+        let mut buf = [0u8; 8];
+        file.read_exact(&mut buf)?;
+        let count = u64::from_le_bytes(buf);
+        let mut sigs = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let mut len = [0u8; 2];
+            file.read_exact(&mut len)?;
+            let mut sig = vec![0u8; u16::from_le_bytes(len) as usize];
+            file.read_exact(&mut sig)?;
+            sigs.push(sig);
+        }
+        Ok(sigs)
+    }
+
+    /// Verifies that every page committed to the log has a valid detached signature produced by
+    /// the key matching the given public key.
+    pub fn verify_signatures(&self, pubkey: &impl PageVerifier) -> Result<(), AoraError> {
+        let sigs = self.read_signatures()?;
+        for (no, page) in self.saved_pages()?.iter().enumerate() {
+            let no = no as u64;
+            let valid = sigs
+                .get(no as usize)
+                .is_some_and(|sig| pubkey.verify(&Self::page_msg(no, page), sig));
+            if !valid {
+                return Err(AoraError::BadSignature { page: no });
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the committed pages to the log file.
+    ///
+    /// If the writing fails, the map gets poisoned: further saves and commits are refused until
+    /// the map is re-synchronized with the log file by [`Self::recover`].
+    ///
+    /// The saved pages stay cached in memory unless they are evicted with
+    /// [`Self::evict_saved_pages`], in which case the reads are served from an index of the
+    /// latest values.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(table = self.name()), err))]
     pub fn save(&mut self) -> io::Result<()> {
+        if self.poisoned {
+            return Err(io::Error::other(format!(
+                "append-update log '{}' is poisoned by a failed save",
+                self.path.display()
+            )));
+        }
+        let res = self.save_pages();
+        if res.is_err() {
+            self.poisoned = true;
+        }
+        res
+    }
+
+    /// Checks whether a failed save has left the log file out of sync with the committed pages.
+    pub fn is_poisoned(&self) -> bool { self.poisoned }
+
+    /// Re-synchronizes the poisoned map with the log file after a failed save.
+    ///
+    /// Truncates the partially written page (and its signature) at the end of the files, checks
+    /// that the pages present in the log file match the committed ones, and writes the committed
+    /// pages which didn't make it to the file.
+    pub fn recover(&mut self) -> Result<(), AoraError> {
+        let mut file = BinFile::<MAGIC, VER>::open_rw(&self.path)
+            .map_err(|e| io::Error::new(e.kind(), format!("at path '{}'", self.path.display())))?;
+        let LogPages { pages, offsets, .. } = Self::read_pages(&mut file)?;
+        let end = file.stream_position()?;
+        file.set_len(end)?;
+        self.log_len = end;
+
+        // The evicted pages can't be compared or written again
+        let evicted = if self.latest.is_some() { self.on_disk.len() } else { 0 };
+        let mut committed = mem::take(&mut self.on_disk);
+        let saved = committed.len();
+        committed.append(&mut self.dirty);
+        if pages.len() > committed.len()
+            || pages.len() < evicted
+            || pages.iter().zip(&committed).skip(evicted).any(|(a, b)| a != b)
+        {
+            self.dirty = committed.split_off(saved);
+            self.on_disk = committed;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "append-update log file '{}' doesn't match the committed pages",
+                    self.path.display()
+                ),
+            )
+            .into());
+        }
+        self.dirty = committed.split_off(pages.len());
+        self.on_disk = committed;
+        self.offsets = offsets;
+        self.recover_signatures()?;
+        self.evict_from(evicted);
+
+        self.poisoned = false;
+        self.save()?;
+        Ok(())
+    }
+
+    /// Truncates the signatures following the ones of the pages present in the log file, and
+    /// signs the pages which don't have signatures.
+    fn recover_signatures(&self) -> io::Result<()> {
+        let Some(Signer(signer)) = &self.signer else {
+            return Ok(());
+        };
+        let path = self.sigs_path();
+        let mut file = BinFile::<MAGIC, VER>::open_rw(&path).map_err(|e| {
+            io::Error::new(e.kind(), format!("signature file '{}'", path.display()))
+        })?;
+        let offset = file.stream_position()?;
+        let mut buf = [0u8; 8];
+        file.read_exact(&mut buf)?;
+        let count = u64::from_le_bytes(buf).min(self.on_disk.len() as u64);
+        for _ in 0..count {
+            let mut len = [0u8; 2];
+            file.read_exact(&mut len)?;
+            file.seek(SeekFrom::Current(u16::from_le_bytes(len) as i64))?;
+        }
+        let end = file.stream_position()?;
+        if end > file.metadata()?.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("signature file '{}' is corrupted", path.display()),
+            ));
+        }
+        file.set_len(end)?;
+        for (no, page) in self.saved_pages()?.iter().enumerate().skip(count as usize) {
+            let sig = signer.sign(&Self::page_msg(no as u64, page));
+            let len = u16::try_from(sig.len()).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "page signature is too long")
+            })?;
+            file.write_all(&len.to_le_bytes())?;
+            file.write_all(&sig)?;
+        }
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&(self.on_disk.len() as u64).to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Closes the map, saving the committed pages and syncing the log and signature files to the
+    /// storage device, and reporting the failures, which are lost (or cause a panic) when the
+    /// map is just dropped.
+    ///
+    /// The pending transaction is handled according to the drop policy (see
+    /// [`Self::set_drop_policy`]), except that with [`DropPolicy::PanicOnUncommitted`] it is
+    /// aborted, and [`AoraError::PendingTransaction`] is returned instead of panicking.
+    pub fn close(mut self) -> Result<(), AoraError> {
+        // Whatever fails below, dropping the map must not panic or retry the commit
+        let policy = mem::replace(&mut self.drop_policy, DropPolicy::AbortTransaction);
+        match policy {
+            DropPolicy::PanicOnUncommitted if self.has_pending() => {
+                return Err(AoraError::PendingTransaction { table: self.name().to_owned() });
+            }
+            DropPolicy::PanicOnUncommitted => {}
+            DropPolicy::AbortTransaction => self.abort_transaction(),
+            DropPolicy::CommitOnDrop => {
+                self.try_commit_transaction()?;
+            }
+        }
+        self.sync()?;
+        Ok(())
+    }
+
+    /// Writes the committed pages which are not yet in the log file, like [`Self::save`].
+    pub fn flush(&mut self) -> io::Result<()> { self.save() }
+
+    /// Writes the committed pages which are not yet in the log file and syncs the log and
+    /// signature files to the storage device, making the committed transactions durable.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.flush()?;
+        BinFile::<MAGIC, VER>::open_rw(&self.path)
+            .map_err(|e| io::Error::new(e.kind(), format!("at path '{}'", self.path.display())))?
+            .sync_all()?;
+        let path = self.sigs_path();
+        if fs::exists(&path)? {
+            BinFile::<MAGIC, VER>::open_rw(&path)
+                .map_err(|e| {
+                    io::Error::new(e.kind(), format!("signature file '{}'", path.display()))
+                })?
+                .sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Loads the transactions committed to the log file by another process since the map was
+    /// opened or last refreshed, returning the number of the new transactions.
+    ///
+    /// Since the page counter is updated after each page is written, the map may be refreshed
+    /// while the other process is saving. The transactions archived by another process are not
+    /// picked up; the map has to be reopened after [`Self::seal_and_archive`] is run elsewhere.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the map is poisoned, or if the log file can't be read.
+    pub fn refresh(&mut self) -> Result<usize, AoraError> {
+        if self.poisoned {
+            return Err(AoraError::Poisoned { table: self.name().to_owned() });
+        }
+        let mut file = BinFile::<MAGIC, VER>::open(&self.path)
+            .map_err(|e| io::Error::new(e.kind(), format!("at path '{}'", self.path.display())))?;
+        let mut buf = [0u8; 8];
+        file.read_exact(&mut buf)?;
+        let num_pages = u64::from_le_bytes(buf) as usize;
+        let known = self.on_disk.len();
+        if num_pages <= known {
+            return Ok(0);
+        }
+
+        file.seek(SeekFrom::Start(self.log_len))?;
+        let mut pages = Vec::with_capacity(num_pages - known);
+        let mut times = Vec::with_capacity(num_pages - known);
+        let mut offsets = Vec::with_capacity(num_pages - known);
+        for _ in known..num_pages {
+            offsets.push(file.stream_position()?);
+            let (page, time, _) = Self::read_page(&mut *file)?;
+            pages.push(page);
+            times.push(time);
+        }
+        self.log_len = file.stream_position()?;
+        self.times.extend(times);
+        self.offsets.extend(offsets);
+        if let Some(charge) = &mut self.memory {
+            charge.add(pages.iter().map(|page| page.len()).sum::<usize>() * Self::ENTRY_SIZE);
+        }
+        self.on_disk.extend(pages);
+        self.evict_from(known);
+        Ok(num_pages - known)
+    }
+
+    /// Refreshes the map like [`Self::refresh`] if the log file may have changed since the last
+    /// refresh.
+    ///
+    /// If the map watches the changes (see [`Self::watch_changes`]), the file is not touched
+    /// unless the watcher has noticed its modification, making the call cheap enough to be done
+    /// before each read. Otherwise, the map is always refreshed.
+    pub fn refresh_if_changed(&mut self) -> Result<usize, AoraError> {
+        #[cfg(feature = "notify")]
+        if let Some(watcher) = &self.watcher {
+            if !watcher.take_changes() {
+                return Ok(0);
+            }
+        }
+        self.refresh()
+    }
+
+    /// Starts watching the log file for the transactions committed by other processes, used by
+    /// [`Self::refresh_if_changed`].
+    #[cfg(feature = "notify")]
+    pub fn watch_changes(&mut self) -> io::Result<()> {
+        self.watcher = Some(ChangeWatcher::new(&[&self.path])?);
+        Ok(())
+    }
+
+    fn save_pages(&mut self) -> io::Result<()> {
         let mut index_file = BinFile::<MAGIC, VER>::open_rw(&self.path)
             .map_err(|e| io::Error::new(e.kind(), format!("at path '{}'", self.path.display())))?;
 
@@ -132,40 +982,789 @@ where
             debug_assert_eq!(prev_num_pages, num_pages);
         }
 
-        for page in &self.dirty {
-            index_file.seek(SeekFrom::End(0))?;
-
-            let num_keys = page.len() as u64;
-            index_file.write_all(&num_keys.to_le_bytes())?;
-            for (key, value) in page {
-                index_file.write_all(key)?;
-                index_file.write_all(value)?;
-            }
-
+        // Byte ranges written to the log and signature files, reported to the observer
+        let mut log_ranges = Vec::new();
+        let mut sig_ranges = Vec::new();
+        let mut offsets = Vec::with_capacity(self.dirty.len());
+        let times = &self.times[self.on_disk.len()..];
+        for (page, time) in self.dirty.iter().zip(times) {
+            let start = index_file.seek(SeekFrom::End(0))?;
+            offsets.push(start);
+
+            let len = match self.commit_chunk {
+                None => Self::write_page(&mut *index_file, page, *time)?,
+                Some(chunk) => {
+                    let mut writer = io::BufWriter::new(&mut *index_file);
+                    let len = Self::write_chunks(&mut writer, page, *time, false, chunk)?;
+                    writer.flush()?;
+                    len
+                }
+            };
+
+            telemetry::written(self.name(), len);
+            push_range(&mut log_ranges, start..start + len);
+            self.log_len = start + len;
             num_pages += 1;
             index_file.seek(SeekFrom::Start(offset))?;
             index_file.write_all(&num_pages.to_le_bytes())?;
+
+            if let Some(Signer(signer)) = &self.signer {
+                let sig = signer.sign(&Self::page_msg(num_pages - 1, page));
+                let len = u16::try_from(sig.len()).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "page signature is too long")
+                })?;
+                let path = self.sigs_path();
+                let mut sig_file = BinFile::<MAGIC, VER>::open_rw(&path).map_err(|e| {
+                    io::Error::new(e.kind(), format!("signature file '{}'", path.display()))
+                })?;
+                let start = sig_file.seek(SeekFrom::End(0))?;
+                sig_file.write_all(&len.to_le_bytes())?;
+                sig_file.write_all(&sig)?;
+                push_range(&mut sig_ranges, start..start + 2 + sig.len() as u64);
+                sig_file.seek(SeekFrom::Start(offset))?;
+                sig_file.write_all(&num_pages.to_le_bytes())?;
+            }
         }
         debug_assert_eq!(num_pages as usize, self.on_disk.len() + self.dirty.len());
 
+        if let (Some(observer), false) = (&self.observer, self.dirty.is_empty()) {
+            // The page counter in the header is updated with each page
+            log_ranges.insert(0, offset..offset + 8);
+            observer.on_written(&self.path, &log_ranges);
+            if !sig_ranges.is_empty() {
+                sig_ranges.insert(0, offset..offset + 8);
+                observer.on_written(&self.sigs_path(), &sig_ranges);
+            }
+        }
+        let first = self.on_disk.len();
+        self.offsets.extend(offsets);
         self.on_disk.append(&mut self.dirty);
+        self.evict_from(first);
 
         Ok(())
     }
 
-    fn keys_internal(&self) -> impl Iterator<Item = &[u8; KEY_LEN]> {
-        self.on_disk
+    /// Returns an iterator over the keys in the order they were first inserted, yielding each key
+    /// once.
+    fn keys_internal(&self) -> impl Iterator<Item = [u8; KEY_LEN]> + '_ {
+        let pending = self.visible_pending();
+        let keys = self
+            .latest
             .iter()
-            .flat_map(|page| page.keys())
-            .chain(self.pending.keys())
+            .flat_map(|latest| latest.keys())
+            .chain(self.on_disk.iter().flat_map(|page| page.keys()).copied())
+            .chain(self.spilled_keys(pending.is_some(), false))
+            .chain(pending.into_iter().flat_map(IndexMap::keys).copied());
+        unique(keys)
+    }
+
+    /// Returns an iterator over the keys of the spilled pending writes, if they are requested.
+    fn spilled_keys(&self, pending: bool, rev: bool) -> impl Iterator<Item = [u8; KEY_LEN]> + '_ {
+        let spill = if pending { self.spill.as_ref() } else { None };
+        spill.into_iter().flat_map(move |spill| {
+            spill
+                .entries(rev)
+                .map(|entry| entry.expect("unable to read the spill file").0)
+        })
+    }
+
+    /// Returns the pending writes if they are visible to the reads.
+    fn visible_pending(&self) -> Option<&IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>> {
+        match self.visibility {
+            Visibility::IncludePending => Some(&self.pending),
+            Visibility::CommittedOnly => None,
+        }
+    }
+
+    /// Looks up the value in the given pending writes (and the spilled ones, if any) and then in
+    /// the committed pages.
+    fn lookup(
+        &self,
+        key: &[u8; KEY_LEN],
+        pending: Option<&IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>>,
+    ) -> Option<[u8; VAL_LEN]> {
+        pending
+            .and_then(|pending| pending.get(key).copied())
+            .or_else(|| {
+                let spill = self.spill.as_ref().filter(|_| pending.is_some())?;
+                spill.get(key).expect("unable to read the spill file")
+            })
+            .or_else(|| {
+                self.dirty
+                    .iter()
+                    .rev()
+                    .chain(self.on_disk.iter().rev())
+                    .find_map(|page| page.get(key))
+                    .copied()
+            })
+            .or_else(|| self.latest.as_ref()?.get(key))
     }
 
+    /// Sets whether the reads through this handle see the writes of the pending transaction.
+    ///
+    /// With [`Visibility::CommittedOnly`], [`AuraMap::get`], [`AuraMap::contains_key`] and the
+    /// key iterators (as well as the checks made by [`AuraMap::insert_only`] and
+    /// [`AuraMap::update_only`]) report the state as of the last commit, while a transaction is
+    /// being assembled.
+    pub fn set_visibility(&mut self, visibility: Visibility) { self.visibility = visibility; }
+
+    /// Returns whether the reads see the writes of the pending transaction.
+    pub fn visibility(&self) -> Visibility { self.visibility }
+
     pub fn path(&self) -> &Path { &self.path }
 
+    fn name(&self) -> &str {
+        self.path
+            .file_stem()
+            .and_then(OsStr::to_str)
+            .unwrap_or("<unnamed>")
+    }
+
+    /// Reports the size of the log and signature files, the number of the committed pages and
+    /// records in them, and the number of the records pending in the current transaction.
+    pub fn stats(&self) -> io::Result<StorageStats> {
+        let sigs = self.sigs_path();
+        let idx_size = if fs::exists(&sigs)? { fs::metadata(&sigs)?.len() } else { 0 };
+        Ok(StorageStats {
+            log_size: fs::metadata(&self.path)?.len(),
+            idx_size,
+            records: self
+                .on_disk
+                .iter()
+                .chain(&self.dirty)
+                .map(|page| page.len())
+                .sum::<usize>()
+                + self.latest.as_ref().map_or(0, |latest| latest.writes as usize),
+            pages: self.on_disk.len() + self.dirty.len(),
+            pending: self.pending_len(),
+            latency: self.latencies.as_ref().map(Latencies::stats),
+        })
+    }
+
+    /// Returns an immutable view of the committed state of the map, which is not affected by the
+    /// pending writes and the transactions committed after the snapshot was taken.
+    ///
+    /// The snapshot shares the pages with the map, so taking it is cheap, and allows consistent
+    /// reads of multiple keys while the map keeps being updated. If the saved pages are evicted,
+    /// the snapshot shares the index of their latest values as well, so the next save while the
+    /// snapshot is alive copies the index.
+    pub fn snapshot(&self) -> FileAuraSnapshot<K, V, KEY_LEN, VAL_LEN> {
+        FileAuraSnapshot {
+            name: self.name().to_owned(),
+            pages: self.on_disk.iter().chain(&self.dirty).cloned().collect(),
+            latest: self.latest.clone(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns an in-memory overlay on top of the committed state of the map, which can be
+    /// updated without affecting the map, and then either dropped or merged back into the map
+    /// with [`Self::commit_fork`].
+    pub fn fork(&self) -> FileAuraFork<K, V, KEY_LEN, VAL_LEN> {
+        FileAuraFork { base: self.snapshot(), overlay: default!() }
+    }
+
+    /// Commits the changes made in the fork as a single transaction, returning its number, or
+    /// `None` if the fork has no changes. The automatic commits set with
+    /// [`Self::set_auto_commit`] don't split the transaction.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the map has a pending transaction, or if new transactions were committed after
+    /// the fork was taken. Fails as [`Self::try_commit_transaction`] does if the transaction
+    /// can't be committed; then the changes of the fork are dropped instead of being left
+    /// pending in the map.
+    pub fn commit_fork(
+        &mut self,
+        fork: FileAuraFork<K, V, KEY_LEN, VAL_LEN>,
+    ) -> Result<Option<u64>, AoraError> {
+        if self.has_pending() {
+            return Err(AoraError::PendingTransaction { table: self.name().to_owned() });
+        }
+        let current = (self.on_disk.len() + self.dirty.len()) as u64;
+        if fork.base.transaction_count() != current {
+            return Err(AoraError::ForkOutdated {
+                table: self.name().to_owned(),
+                base: fork.base.transaction_count(),
+                current,
+            });
+        }
+        let auto_commit = self.auto_commit.take();
+        for (key, val) in fork.overlay {
+            self.insert_or_update(K::from(key), V::from(val));
+        }
+        self.auto_commit = auto_commit;
+        let res = self.try_commit_transaction();
+        if res.is_err() {
+            self.abort_transaction();
+        }
+        res
+    }
+
+    /// Moves the transactions preceding `before_txno` to an archive log in `dest_dir`, keeping
+    /// only the recent transactions in the live map. Returns the path of the archive log, or
+    /// `None` if all these transactions are already archived.
+    ///
+    /// The archive log has the same format as the live one and is named
+    /// `<name>-<first txno>-<before_txno>.log`, so it can be opened as a [`FileAuraMap`]. The
+    /// archived transactions keep their numbers both in the archive and in the live map, where
+    /// they become empty, except for the last one, which is replaced with the base page holding
+    /// the latest values of all the keys updated by the archived transactions. Thus, the live map
+    /// keeps all its keys and values, while [`TransactionalMap::transaction_keys`] of the last
+    /// archived transaction returns the keys of the base page. The archive log starts with the
+    /// base page of the previous archiving, if any, so it has the complete state of the map as of
+    /// `before_txno`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the map has a pending transaction, is poisoned, has signed pages, or if there is
+    /// no transaction with the number preceding `before_txno`.
+    pub fn seal_and_archive(
+        &mut self,
+        before_txno: u64,
+        dest_dir: impl AsRef<Path>,
+    ) -> Result<Option<PathBuf>, AoraError> {
+        self.archive(before_txno, dest_dir.as_ref(), None)
+    }
+
+    /// Archives the transactions like [`Self::seal_and_archive`], compressing the archive log
+    /// with zstd using the given compression level.
+    ///
+    /// The archive is saved to `<name>-<first txno>-<before_txno>.log.zst`, which contains the
+    /// file header followed by the compressed archive log (including its own header).
+    #[cfg(feature = "zstd")]
+    pub fn seal_and_archive_compressed(
+        &mut self,
+        before_txno: u64,
+        dest_dir: impl AsRef<Path>,
+        level: i32,
+    ) -> Result<Option<PathBuf>, AoraError> {
+        self.archive(before_txno, dest_dir.as_ref(), Some(level))
+    }
+
+    fn archive(
+        &mut self,
+        before_txno: u64,
+        dest_dir: &Path,
+        level: Option<i32>,
+    ) -> Result<Option<PathBuf>, AoraError> {
+        if self.poisoned {
+            return Err(AoraError::Poisoned { table: self.name().to_owned() });
+        }
+        if self.has_pending() {
+            return Err(AoraError::PendingTransaction { table: self.name().to_owned() });
+        }
+        if self.signer.is_some() || fs::exists(self.sigs_path())? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("append-update log '{}' has signed pages", self.path.display()),
+            )
+            .into());
+        }
+        let to = before_txno as usize;
+        if to > self.on_disk.len() + self.dirty.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "append-update log '{}' has no transaction #{}",
+                    self.path.display(),
+                    before_txno.saturating_sub(1)
+                ),
+            )
+            .into());
+        }
+        self.save()?;
+        let from = self.archived;
+        if from >= to {
+            return Ok(None);
+        }
+
+        let saved = self.saved_pages()?;
+        let path = dest_dir.join(format!("{}-{from}-{to}.log", self.name()));
+        #[cfg(feature = "zstd")]
+        let path = match level {
+            None => path,
+            Some(_) => {
+                let mut path = path.into_os_string();
+                path.push(".zst");
+                PathBuf::from(path)
+            }
+        };
+        let mut file = BinFile::<MAGIC, VER>::create_new(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("archive log '{}'", path.display())))?;
+        // The archive is streamed to the file, starting with the base page of the previous
+        // archiving, if any
+        let pages = saved[..to].iter().zip(self.times.iter().copied());
+        let res = match level {
+            None => {
+                let mut writer = io::BufWriter::new(&mut *file);
+                Self::write_pages(&mut writer, pages, from).and_then(|_| writer.flush())
+            }
+            #[cfg(feature = "zstd")]
+            Some(level) => {
+                let writer = io::BufWriter::new(&mut *file);
+                zstd::stream::write::Encoder::new(writer, level).and_then(|mut encoder| {
+                    encoder.write_all(&MAGIC.to_be_bytes())?;
+                    encoder.write_all(&VER.to_be_bytes())?;
+                    Self::write_pages(&mut encoder, pages, from)?;
+                    encoder.finish()?.flush()
+                })
+            }
+            #[cfg(not(feature = "zstd"))]
+            Some(_) => unreachable!("compression requires zstd"),
+        };
+        let res = res.and_then(|_| file.sync_all());
+        drop(file);
+        if let Err(err) = res {
+            let _ = fs::remove_file(&path);
+            return Err(err.into());
+        }
+
+        // The latest values of the archived transactions are carried forward into the base page,
+        // keeping the keys in the order they were first inserted
+        let mut base = IndexMap::new();
+        for page in &saved[from.saturating_sub(1)..to] {
+            base.extend(page.iter().map(|(key, value)| (*key, *value)));
+        }
+        let base = Arc::new(base);
+        let empty = Arc::new(IndexMap::new());
+
+        // The live log is rewritten in a temporary file, which then replaces it atomically
+        let tmp = self.path.with_extension("tmp");
+        let mut file = BinFile::<MAGIC, VER>::create_new(&tmp)
+            .map_err(|e| io::Error::new(e.kind(), format!("at path '{}'", tmp.display())))?;
+        let pages = saved.iter().enumerate().map(|(no, page)| match no {
+            no if no + 1 < to => &empty,
+            no if no + 1 == to => &base,
+            _ => page,
+        });
+        let mut writer = io::BufWriter::new(&mut *file);
+        let res = Self::write_pages(&mut writer, pages.zip(self.times.iter().copied()), to)
+            .and_then(|offsets| writer.flush().map(|_| offsets));
+        drop(writer);
+        let res = res
+            .and_then(|offsets| file.sync_all().map(|_| offsets))
+            .and_then(|offsets| Ok((file.stream_position()?, offsets)));
+        drop(file);
+        let replaced = saved[from.saturating_sub(1)..to]
+            .iter()
+            .map(|page| page.len())
+            .sum::<usize>();
+        drop(saved);
+        match res.and_then(|res| fs::rename(&tmp, &self.path).map(|_| res)) {
+            Ok((len, offsets)) => {
+                self.log_len = len;
+                self.offsets = offsets;
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&tmp);
+                return Err(err.into());
+            }
+        }
+
+        // The evicted pages are already replaced with the index of the latest values, which the
+        // archiving doesn't change
+        if self.latest.is_none() {
+            if let Some(charge) = &mut self.memory {
+                charge.sub(replaced * Self::ENTRY_SIZE);
+                charge.add(base.len() * Self::ENTRY_SIZE);
+            }
+            for page in &mut self.on_disk[from.saturating_sub(1)..to - 1] {
+                *page = empty.clone();
+            }
+            self.on_disk[to - 1] = base;
+        }
+        self.archived = to;
+        Ok(Some(path))
+    }
+
+    /// Writes the number of pages followed by the pages with their commit times in the log file
+    /// format, flagging the page preceding the first `archived` ones as the base page. Returns the
+    /// positions of the pages in a file starting with the file header.
+    fn write_pages<'p>(
+        writer: &mut impl Write,
+        pages: impl ExactSizeIterator<Item = (&'p Page<KEY_LEN, VAL_LEN>, Option<u64>)>,
+        archived: usize,
+    ) -> io::Result<Vec<u64>> {
+        writer.write_all(&(pages.len() as u64).to_le_bytes())?;
+        let mut offsets = Vec::with_capacity(pages.len());
+        let mut pos = 18;
+        for (no, (page, time)) in pages.enumerate() {
+            offsets.push(pos);
+            pos += Self::write_chunks(writer, page, time, no + 1 == archived, usize::MAX)?;
+        }
+        Ok(offsets)
+    }
+
+    /// Writes the number of keys, the commit time (if known) and the key-value pairs of the page,
+    /// returning the number of the written bytes.
+    fn write_page(
+        writer: &mut impl Write,
+        page: &Page<KEY_LEN, VAL_LEN>,
+        time: Option<u64>,
+    ) -> io::Result<u64> {
+        Self::write_chunks(writer, page, time, false, usize::MAX)
+    }
+
+    /// Writes the page as a sequence of chunks of at most `chunk` keys, each but the last one
+    /// flagged as continued, with the commit time (if known) following the key count of the first
+    /// chunk, which is also flagged if the page is the base one. Returns the number of the written
+    /// bytes.
+    fn write_chunks(
+        writer: &mut impl Write,
+        page: &Page<KEY_LEN, VAL_LEN>,
+        mut time: Option<u64>,
+        mut base: bool,
+        chunk: usize,
+    ) -> io::Result<u64> {
+        let mut len = 0u64;
+        let mut entries = page.iter();
+        let mut left = page.len();
+        loop {
+            let count = left.min(chunk);
+            left -= count;
+            let mut flags = if left > 0 { CONTINUED } else { 0 };
+            if time.is_some() {
+                flags |= TIMESTAMPED;
+            }
+            if mem::take(&mut base) {
+                flags |= BASE;
+            }
+            writer.write_all(&(count as u64 | flags).to_le_bytes())?;
+            len += 8;
+            if let Some(time) = time.take() {
+                writer.write_all(&time.to_le_bytes())?;
+                len += 8;
+            }
+            for (key, value) in entries.by_ref().take(count) {
+                writer.write_all(key)?;
+                writer.write_all(value)?;
+            }
+            len += (count * (KEY_LEN + VAL_LEN)) as u64;
+            if left == 0 {
+                return Ok(len);
+            }
+        }
+    }
+
+    /// Exports each committed transaction from the `range` into a self-contained delta file in
+    /// `dir`, named `<name>-<txno>.delta`, returning the paths of the written files.
+    ///
+    /// A delta file contains the file header, the transaction number and the page of the
+    /// transaction in the log file format. Applying the delta files with
+    /// [`Self::apply_delta_file`] to a copy of the map (for instance, restored from a backup)
+    /// brings it up to date.
+    ///
+    /// # Errors
+    ///
+    /// Fails if some transactions from the range are not committed, or if a delta file already
+    /// exists.
+    pub fn export_transactions(
+        &self,
+        range: Range<u64>,
+        dir: impl AsRef<Path>,
+    ) -> io::Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        let committed = (self.on_disk.len() + self.dirty.len()) as u64;
+        if range.end > committed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "append-update log '{}' has only {committed} committed transactions",
+                    self.path.display()
+                ),
+            ));
+        }
+        let saved = self.saved_pages()?;
+        let pages = saved.iter().chain(&self.dirty).zip(&self.times);
+        let mut paths = Vec::new();
+        for ((page, time), txno) in pages.skip(range.start as usize).zip(range) {
+            let path = dir.join(format!("{}-{txno}.delta", self.name()));
+            let mut file = BinFile::<MAGIC, VER>::create_new(&path).map_err(|e| {
+                io::Error::new(e.kind(), format!("delta file '{}'", path.display()))
+            })?;
+            let mut data = Vec::from(txno.to_le_bytes());
+            Self::write_page(&mut data, page, *time)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// Commits the transaction from a delta file written by [`Self::export_transactions`],
+    /// returning its number, or `None` if the map already has the same transaction.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the map has a pending transaction, is poisoned, if the delta file is damaged or
+    /// was exported from a log with a different magic number or version, and if the transaction
+    /// doesn't follow the last committed one.
+    pub fn apply_delta_file(&mut self, path: impl AsRef<Path>) -> Result<Option<u64>, AoraError> {
+        let path = path.as_ref();
+        if self.poisoned {
+            return Err(AoraError::Poisoned { table: self.name().to_owned() });
+        }
+        if self.has_pending() {
+            return Err(AoraError::PendingTransaction { table: self.name().to_owned() });
+        }
+        let corrupted = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("delta file '{}' is corrupted", path.display()),
+            )
+        };
+        let mut file = BinFile::<MAGIC, VER>::open(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("delta file '{}'", path.display())))?;
+        let mut buf = [0u8; 8];
+        file.read_exact(&mut buf).map_err(|_| corrupted())?;
+        let txno = u64::from_le_bytes(buf);
+        let (page, time, _) = Self::read_page(&mut *file).map_err(|_| corrupted())?;
+        if file.stream_position()? != file.metadata()?.len() {
+            return Err(corrupted().into());
+        }
+
+        let current = (self.on_disk.len() + self.dirty.len()) as u64;
+        let existing = match (txno as usize).checked_sub(self.on_disk.len()) {
+            None => Some(self.saved_page(txno as usize)?),
+            Some(no) => self.dirty.get(no).cloned(),
+        };
+        match existing {
+            Some(existing) if existing == page => return Ok(None),
+            None if txno == current => {}
+            _ => {
+                return Err(AoraError::DeltaOutOfOrder {
+                    table: self.name().to_owned(),
+                    txno,
+                    current,
+                });
+            }
+        }
+
+        self.commit_pages(vec![page], vec![time])?;
+        Ok(Some(txno))
+    }
+
+    /// Writes an incremental backup of the transactions committed since `since_txno` to the
+    /// `dest` file, returning the number of the committed transactions, which is the
+    /// `since_txno` for the next incremental backup.
+    ///
+    /// The backup is a log file of the same format as the live one, where the transactions
+    /// preceding `since_txno` are empty. It can be opened as a [`FileAuraMap`], and restored on
+    /// top of an older copy of the map with [`Self::restore_incremental`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is no committed transaction preceding `since_txno`, or if the `dest` file
+    /// already exists.
+    pub fn backup_incremental(&self, since_txno: u64, dest: impl AsRef<Path>) -> io::Result<u64> {
+        let dest = dest.as_ref();
+        let committed = self.on_disk.len() + self.dirty.len();
+        if since_txno > committed as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "append-update log '{}' has only {committed} committed transactions",
+                    self.path.display()
+                ),
+            ));
+        }
+        let empty = Arc::new(IndexMap::new());
+        let saved = self.saved_pages()?;
+        let pages = saved.iter().chain(&self.dirty).enumerate();
+        let pages = pages
+            .map(|(no, page)| if (no as u64) < since_txno { &empty } else { page })
+            .zip(self.times.iter().copied());
+        // The base page stays flagged only if it is backed up
+        let archived = if since_txno < self.archived as u64 { self.archived } else { 0 };
+        let mut data = Vec::new();
+        Self::write_pages(&mut data, pages.collect::<Vec<_>>().into_iter(), archived)?;
+
+        let mut file = BinFile::<MAGIC, VER>::create_new(dest).map_err(|e| {
+            io::Error::new(e.kind(), format!("incremental backup '{}'", dest.display()))
+        })?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        Ok(committed as u64)
+    }
+
+    /// Commits the transactions from an incremental backup written by
+    /// [`Self::backup_incremental`] which the map doesn't have yet, returning the number of the
+    /// committed transactions.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the map has a pending transaction, is poisoned, if the backup is damaged, if it
+    /// starts after the last committed transaction of the map, or if it has a transaction
+    /// different from the one the map has under the same number.
+    pub fn restore_incremental(&mut self, path: impl AsRef<Path>) -> Result<u64, AoraError> {
+        let path = path.as_ref();
+        if self.poisoned {
+            return Err(AoraError::Poisoned { table: self.name().to_owned() });
+        }
+        if self.has_pending() {
+            return Err(AoraError::PendingTransaction { table: self.name().to_owned() });
+        }
+        let corrupted = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("incremental backup '{}' is corrupted", path.display()),
+            )
+        };
+        let mut file = BinFile::<MAGIC, VER>::open(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("incremental backup '{}'", path.display()))
+        })?;
+        let LogPages { pages, times, .. } = Self::read_pages(&mut file).map_err(|_| corrupted())?;
+        if file.stream_position()? != file.metadata()?.len() {
+            return Err(corrupted().into());
+        }
+
+        // The transactions preceding the backed up ones are empty
+        let first = pages
+            .iter()
+            .position(|page| !page.is_empty())
+            .unwrap_or(pages.len());
+        let current = self.on_disk.len() + self.dirty.len();
+        let saved = self.saved_pages()?;
+        let existing = saved.iter().chain(&self.dirty);
+        let mismatch = existing
+            .zip(&pages)
+            .skip(first)
+            .position(|(existing, page)| existing != page)
+            .map(|pos| first + pos);
+        if let Some(txno) = mismatch.or((first > current).then_some(first)) {
+            return Err(AoraError::DeltaOutOfOrder {
+                table: self.name().to_owned(),
+                txno: txno as u64,
+                current: current as u64,
+            });
+        }
+        drop(saved);
+        let pages = pages.into_iter().skip(current).collect();
+        self.commit_pages(pages, times.into_iter().skip(current).collect())?;
+        Ok((self.on_disk.len() + self.dirty.len()) as u64)
+    }
+
+    /// Commits the pages of the transactions exported from another copy of the map, which follow
+    /// the last committed transaction, keeping their original commit times.
+    fn commit_pages(
+        &mut self,
+        pages: Vec<Page<KEY_LEN, VAL_LEN>>,
+        times: Vec<Option<u64>>,
+    ) -> Result<(), AoraError> {
+        if pages.is_empty() {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let first = self.on_disk.len() + self.dirty.len();
+        if let Some(charge) = &mut self.memory {
+            charge.add(pages.iter().map(|page| page.len()).sum::<usize>() * Self::ENTRY_SIZE);
+        }
+        // The saved pages may get evicted, so the observer is notified from these copies
+        self.dirty.extend(pages.iter().cloned());
+        self.times.extend(times);
+        self.save()?;
+        telemetry::committed(self.name(), start);
+        if let Some(observer) = &self.observer {
+            for (txno, page) in (first..).zip(&pages) {
+                let keys = page
+                    .keys()
+                    .map(<[u8; KEY_LEN]>::as_slice)
+                    .collect::<Vec<_>>();
+                observer.on_commit(txno as u64, &keys);
+            }
+        }
+        Ok(())
+    }
+
+    /// Produces a human-readable summary of the map in YAML, which can be attached to bug
+    /// reports.
+    ///
+    /// Lists the last few transactions and checks that the log and signature files match the
+    /// committed pages.
+    pub fn debug_report(&self) -> io::Result<String> {
+        const LAST_TRANSACTIONS: usize = 5;
+
+        let stats = self.stats()?;
+        let saved = self.saved_pages()?;
+        let pages = saved.iter().chain(&self.dirty).collect::<Vec<_>>();
+        let archived = pages.iter().take_while(|page| page.is_empty()).count();
+        let last_transactions = pages
+            .iter()
+            .enumerate()
+            .rev()
+            .take(LAST_TRANSACTIONS)
+            .map(|(txno, page)| format!("#{txno}: {} keys", page.len()))
+            .collect();
+
+        let mut anomalies = Vec::new();
+        if self.poisoned {
+            anomalies.push(s!("the map is poisoned by a failed save"));
+        }
+        let mut file = BinFile::<MAGIC, VER>::open(&self.path)
+            .map_err(|e| io::Error::new(e.kind(), format!("at path '{}'", self.path.display())))?;
+        match Self::read_pages(&mut file) {
+            Err(err) => anomalies.push(format!("log file can't be read: {err}")),
+            Ok(LogPages { pages: on_file, .. }) => {
+                if on_file.len() != self.on_disk.len() {
+                    anomalies.push(format!(
+                        "log file has {} pages, while {} are saved",
+                        on_file.len(),
+                        self.on_disk.len()
+                    ));
+                } else if on_file.iter().zip(saved.iter()).any(|(a, b)| a != b) {
+                    anomalies.push(s!("log file pages don't match the saved ones"));
+                }
+                if file.stream_position()? != file.metadata()?.len() {
+                    anomalies.push(s!("log file has trailing bytes after the last page"));
+                }
+            }
+        }
+        if fs::exists(self.sigs_path())? {
+            let sigs = self.read_signatures()?.len();
+            if sigs != self.on_disk.len() {
+                anomalies.push(format!(
+                    "signature file has {sigs} signatures for {} saved pages",
+                    self.on_disk.len()
+                ));
+            }
+        }
+
+        let first_key = self.keys_internal().next();
+        let mut report = DebugReport::new::<MAGIC, VER>("FileAuraMap", self.name());
+        report
+            .field("key_len", KEY_LEN)
+            .field("val_len", VAL_LEN)
+            .field("transactions", pages.len())
+            .field("archived_transactions", archived)
+            .field("records", stats.records)
+            .field("pending", stats.pending)
+            .field("log_size", stats.log_size)
+            .field("signed", self.signer.is_some())
+            .field("visibility", format!("{:?}", self.visibility))
+            .key("first_key", first_key.as_ref().map(<[u8; KEY_LEN]>::as_slice))
+            .key(
+                "last_updated_key",
+                self.visible_pending()
+                    .and_then(|pending| pending.keys().last())
+                    .or_else(|| pages.iter().rev().find_map(|page| page.keys().last()))
+                    .map(<[u8; KEY_LEN]>::as_slice),
+            )
+            .list("last_transactions", last_transactions)
+            .anomalies(anomalies);
+        Ok(report.to_string())
+    }
+
+    /// Dumps the pages of the map, reading the evicted pages back from the log file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the evicted pages can't be read from the log file.
     pub fn to_dump(&self) -> FileAuraMapDump<KEY_LEN, VAL_LEN> {
+        let saved = self.saved_pages().expect("unable to read the log file");
         FileAuraMapDump {
-            on_disk: self.on_disk.clone(),
-            dirty: self.dirty.clone(),
+            on_disk: saved.iter().map(|page| (**page).clone()).collect(),
+            dirty: self.dirty.iter().map(|page| (**page).clone()).collect(),
             pending: self.pending.clone(),
         }
     }
@@ -174,81 +1773,176 @@ where
 impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize, const VAL_LEN: usize>
     AuraMap<K, V, KEY_LEN, VAL_LEN> for FileAuraMap<K, V, MAGIC, VER, KEY_LEN, VAL_LEN>
 where
-    K: From<[u8; KEY_LEN]> + Into<[u8; KEY_LEN]>,
-    V: From<[u8; VAL_LEN]> + Into<[u8; VAL_LEN]>,
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
 {
-    fn display(&self) -> impl Display {
-        self.path
-            .file_stem()
-            .and_then(OsStr::to_str)
-            .unwrap_or("<unnamed>")
+    fn display(&self) -> impl Display { self.name() }
+
+    fn keys(&self) -> impl Iterator<Item = K> { self.keys_internal().map(K::from) }
+
+    fn keys_rev(&self) -> impl Iterator<Item = K> {
+        let pending = self.visible_pending();
+        let keys = pending
+            .into_iter()
+            .flat_map(|pending| pending.keys().rev())
+            .copied()
+            .chain(self.spilled_keys(pending.is_some(), true))
+            .chain(
+                self.on_disk
+                    .iter()
+                    .rev()
+                    .flat_map(|page| page.keys().rev())
+                    .copied(),
+            )
+            .chain(self.latest.iter().flat_map(|latest| latest.keys_rev()));
+        unique(keys).map(K::from)
     }
 
-    fn keys(&self) -> impl Iterator<Item = K> { self.keys_internal().copied().map(K::from) }
+    fn len(&self) -> usize { self.keys_internal().count() }
+
+    fn iter(&self) -> impl Iterator<Item = (K, V)> {
+        let pending = self.visible_pending();
+        self.keys_internal().filter_map(move |key| {
+            self.lookup(&key, pending)
+                .map(|val| (K::from(key), V::from(val)))
+        })
+    }
 
     fn contains_key(&self, key: K) -> bool {
-        let key = key.into();
-        self.keys_internal().any(|k| *k == key)
+        self.lookup(&key.into(), self.visible_pending())
+            .is_some()
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all, fields(table = self.name())))]
     fn get(&self, key: K) -> Option<V> {
         let key = key.into();
-        if let Some(val) = self.pending.get(&key) {
-            return Some(V::from(*val));
+        telemetry::got(self.name());
+        let start = Instant::now();
+        let val = self.lookup(&key, self.visible_pending());
+        if let Some(latencies) = &self.latencies {
+            latencies.get.record(start);
         }
-        self.dirty
-            .iter()
-            .rev()
-            .chain(self.on_disk.iter().rev())
-            .find_map(|page| page.get(&key))
-            .copied()
-            .map(V::from)
+        val.map(V::from)
     }
 
     fn insert_or_update(&mut self, key: K, val: V) {
         let key = key.into();
         let val = val.into();
-        // Check if the value already known
-        if self.get(key.into()).map(V::into) == Some(val) {
+        // Check if the value already known, including the pending writes regardless of the
+        // visibility
+        let known = match self.spill {
+            None => self.lookup(&key, Some(&self.pending)),
+            // Scanning the spill file on each write would make the writes quadratic
+            Some(_) => self.pending.get(&key).copied(),
+        };
+        if known == Some(val) {
             return;
         }
-        *self.pending.entry(key).or_insert(val) = val;
+        telemetry::inserted(self.name(), 1);
+        let start = Instant::now();
+        if self.pending.insert(key, val).is_none() {
+            if let Some(charge) = &mut self.memory {
+                charge.add(Self::ENTRY_SIZE);
+            }
+        }
+        if let Some(latencies) = &self.latencies {
+            latencies.insert.record(start);
+        }
+        if let Some(observer) = &self.observer {
+            observer.on_insert(&key);
+        }
+        if let Some((max_keys, max_bytes)) = self.auto_commit {
+            let len = self.pending_len();
+            if len >= max_keys || 8 + len * (KEY_LEN + VAL_LEN) >= max_bytes {
+                self.commit_transaction();
+                return;
+            }
+        }
+        if let Some(max_bytes) = self.spill_limit {
+            let exceeded = self
+                .memory
+                .as_ref()
+                .is_some_and(|charge| charge.budget().is_exceeded());
+            if exceeded || self.pending.len() * Self::ENTRY_SIZE >= max_bytes {
+                self.spill_pending()
+                    .expect("unable to spill the pending transaction");
+            }
+        }
     }
 }
 
 impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize, const VAL_LEN: usize>
     TransactionalMap<K> for FileAuraMap<K, V, MAGIC, VER, KEY_LEN, VAL_LEN>
 where
-    K: From<[u8; KEY_LEN]> + Into<[u8; KEY_LEN]>,
-    V: From<[u8; VAL_LEN]> + Into<[u8; VAL_LEN]>,
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
 {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(table = self.name())))]
     fn commit_transaction(&mut self) -> Option<u64> {
-        if self.pending.is_empty() {
-            return None;
-        }
-        self.dirty.push(mem::take(&mut self.pending));
-        self.save().expect("Cannot save the log file");
-        Some(self.transaction_count() - 1)
+        self.try_commit_transaction()
+            .unwrap_or_else(|err| panic!("Cannot save the log file: {err}"))
     }
 
-    fn abort_transaction(&mut self) { self.pending.clear(); }
+    fn abort_transaction(&mut self) {
+        if let Some(charge) = &mut self.memory {
+            charge.sub(self.pending.len() * Self::ENTRY_SIZE);
+        }
+        self.pending.clear();
+        self.spill = None;
+        if let Some(observer) = &self.observer {
+            observer.on_abort();
+        }
+    }
 
+    /// Yields the keys of the saved transaction, reading its page back from the log file if it is
+    /// evicted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the evicted page can't be read from the log file.
     fn transaction_keys(&self, txno: u64) -> impl ExactSizeIterator<Item = K> {
-        self.on_disk[txno as usize].keys().copied().map(K::from)
+        let page = self
+            .saved_page(txno as usize)
+            .expect("unable to read the log file");
+        page.keys().copied().map(K::from).collect::<Vec<_>>().into_iter()
+    }
+
+    fn transaction_count(&self) -> u64 { (self.on_disk.len() + self.dirty.len()) as u64 }
+
+    /// Returns the number of the writes in the pending transaction, including the repeated
+    /// writes of the spilled keys.
+    fn pending_len(&self) -> usize {
+        self.pending.len() + self.spill.as_ref().map_or(0, |spill| spill.len() as usize)
     }
 
-    fn transaction_count(&self) -> u64 { (self.on_disk.len() + self.pending.len()) as u64 }
+    /// Checks whether the pending transaction has any writes, in memory or spilled.
+    fn has_pending(&self) -> bool { !self.pending.is_empty() || self.spill.is_some() }
+
+    /// Yields the keys of the pending transaction regardless of the [`Visibility`] of the map.
+    fn pending_keys(&self) -> impl Iterator<Item = K> {
+        let keys = self
+            .spilled_keys(true, false)
+            .chain(self.pending.keys().copied());
+        unique(keys).map(K::from)
+    }
 }
 
 impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize, const VAL_LEN: usize> Drop
     for FileAuraMap<K, V, MAGIC, VER, KEY_LEN, VAL_LEN>
 where
-    K: From<[u8; KEY_LEN]> + Into<[u8; KEY_LEN]>,
-    V: From<[u8; VAL_LEN]> + Into<[u8; VAL_LEN]>,
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
 {
     fn drop(&mut self) {
+        match self.drop_policy {
+            DropPolicy::PanicOnUncommitted => {}
+            DropPolicy::AbortTransaction => self.abort_transaction(),
+            DropPolicy::CommitOnDrop => {
+                self.commit_transaction();
+            }
+        }
         assert!(
-            self.pending.is_empty(),
+            !self.has_pending(),
             "the latest transaction in the table '{}' must be committed before \
              dropping\nNon-commited page:\n\t{}",
             self.display(),
@@ -261,17 +1955,257 @@ where
     }
 }
 
+/// Immutable view of a [`FileAuraMap`] pinned to the transaction which was the latest committed
+/// one when the snapshot was taken, returned by [`FileAuraMap::snapshot`].
+pub struct FileAuraSnapshot<K, V, const KEY_LEN: usize = 32, const VAL_LEN: usize = 32> {
+    name: String,
+    pages: Vec<Page<KEY_LEN, VAL_LEN>>,
+    latest: Option<Arc<LatestValues<KEY_LEN, VAL_LEN>>>,
+    _phantom: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V, const KEY_LEN: usize, const VAL_LEN: usize> Clone
+    for FileAuraSnapshot<K, V, KEY_LEN, VAL_LEN>
+{
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            pages: self.pages.clone(),
+            latest: self.latest.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<K, V, const KEY_LEN: usize, const VAL_LEN: usize> Debug
+    for FileAuraSnapshot<K, V, KEY_LEN, VAL_LEN>
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileAuraSnapshot")
+            .field("name", &self.name)
+            .field("transaction_count", &self.pages.len())
+            .finish()
+    }
+}
+
+impl<K, V, const KEY_LEN: usize, const VAL_LEN: usize> FileAuraSnapshot<K, V, KEY_LEN, VAL_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
+{
+    /// Returns the number of the transactions visible through the snapshot.
+    pub fn transaction_count(&self) -> u64 { self.pages.len() as u64 }
+
+    /// Returns iterator over all the keys known at the snapshot, yielding each key once.
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        let keys = self
+            .latest
+            .iter()
+            .flat_map(|latest| latest.keys())
+            .chain(self.pages.iter().flat_map(|page| page.keys()).copied());
+        unique(keys).map(K::from)
+    }
+
+    /// Returns iterator over all the keys known at the snapshot, yielding the most recently
+    /// inserted or updated keys first.
+    pub fn keys_rev(&self) -> impl Iterator<Item = K> + '_ {
+        let keys = self
+            .pages
+            .iter()
+            .rev()
+            .flat_map(|page| page.keys().rev())
+            .copied()
+            .chain(self.latest.iter().flat_map(|latest| latest.keys_rev()));
+        unique(keys).map(K::from)
+    }
+
+    /// Returns the number of the keys known at the snapshot.
+    pub fn len(&self) -> usize { self.keys().count() }
+
+    /// Checks whether no keys were known at the snapshot.
+    pub fn is_empty(&self) -> bool {
+        self.pages.iter().all(|page| page.is_empty())
+            && self
+                .latest
+                .as_ref()
+                .map_or(true, |latest| latest.values.is_empty())
+    }
+
+    /// Checks whether a given key was present at the snapshot.
+    pub fn contains_key(&self, key: K) -> bool {
+        let key = key.into();
+        self.pages.iter().any(|page| page.contains_key(&key))
+            || self
+                .latest
+                .as_ref()
+                .is_some_and(|latest| latest.values.contains_key(&key))
+    }
+
+    /// Retrieves the value the key had at the snapshot.
+    pub fn get(&self, key: K) -> Option<V> {
+        let key = key.into();
+        telemetry::got(&self.name);
+        self.pages
+            .iter()
+            .rev()
+            .find_map(|page| page.get(&key))
+            .copied()
+            .or_else(|| self.latest.as_ref()?.get(&key))
+            .map(V::from)
+    }
+
+    /// Retrieves the value the key had at the snapshot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the item under the provided key is not present.
+    pub fn get_expect(&self, key: K) -> V {
+        let bytes = key.into();
+        self.get(bytes.into()).unwrap_or_else(|| {
+            panic!(
+                "key {} is not found in the snapshot of the table '{}'",
+                bytes.to_hex(),
+                self.name
+            )
+        })
+    }
+}
+
+/// In-memory overlay on top of a [`FileAuraMap`] snapshot, returned by [`FileAuraMap::fork`].
+///
+/// The fork can be updated like the map itself, without affecting it, and is discarded when
+/// dropped unless merged back with [`FileAuraMap::commit_fork`].
+#[derive(Clone, Debug)]
+pub struct FileAuraFork<K, V, const KEY_LEN: usize = 32, const VAL_LEN: usize = 32> {
+    base: FileAuraSnapshot<K, V, KEY_LEN, VAL_LEN>,
+    overlay: IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>,
+}
+
+impl<K, V, const KEY_LEN: usize, const VAL_LEN: usize> FileAuraFork<K, V, KEY_LEN, VAL_LEN> {
+    /// Returns the number of the keys inserted or updated in the fork.
+    pub fn changes(&self) -> usize { self.overlay.len() }
+}
+
+impl<K, V, const KEY_LEN: usize, const VAL_LEN: usize> AuraMap<K, V, KEY_LEN, VAL_LEN>
+    for FileAuraFork<K, V, KEY_LEN, VAL_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
+{
+    fn display(&self) -> impl Display { &self.base.name }
+
+    fn keys(&self) -> impl Iterator<Item = K> {
+        let added = self
+            .overlay
+            .keys()
+            .filter(|key| !self.base.contains_key(K::from(**key)));
+        self.base.keys().chain(added.copied().map(K::from))
+    }
+
+    fn keys_rev(&self) -> impl Iterator<Item = K> {
+        let base = self
+            .base
+            .keys_rev()
+            .map(K::into)
+            .filter(|key| !self.overlay.contains_key(key));
+        self.overlay
+            .keys()
+            .rev()
+            .copied()
+            .chain(base)
+            .map(K::from)
+    }
+
+    fn contains_key(&self, key: K) -> bool {
+        let key = key.into();
+        self.overlay.contains_key(&key) || self.base.contains_key(K::from(key))
+    }
+
+    fn get(&self, key: K) -> Option<V> {
+        let key = key.into();
+        match self.overlay.get(&key) {
+            Some(val) => Some(V::from(*val)),
+            None => self.base.get(K::from(key)),
+        }
+    }
+
+    fn insert_or_update(&mut self, key: K, val: V) {
+        let key = key.into();
+        let val = val.into();
+        if self.get(key.into()).map(V::into) == Some(val) {
+            return;
+        }
+        self.overlay.insert(key, val);
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "DumpRepr", try_from = "DumpRepr")
+)]
 pub struct FileAuraMapDump<const KEY_LEN: usize, const VAL_LEN: usize> {
     pub on_disk: Vec<IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>>,
     pub dirty: Vec<IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>>,
     pub pending: IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>,
 }
 
+/// Serialized form of [`FileAuraMapDump`], with the keys and values in hex.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DumpRepr {
+    on_disk: Vec<IndexMap<String, String>>,
+    dirty: Vec<IndexMap<String, String>>,
+    pending: IndexMap<String, String>,
+}
+
+#[cfg(feature = "serde")]
+impl<const KEY_LEN: usize, const VAL_LEN: usize> From<FileAuraMapDump<KEY_LEN, VAL_LEN>>
+    for DumpRepr
+{
+    fn from(dump: FileAuraMapDump<KEY_LEN, VAL_LEN>) -> Self {
+        let page = |page: IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>| -> IndexMap<String, String> {
+            page.into_iter()
+                .map(|(key, val)| (key.to_hex(), val.to_hex()))
+                .collect()
+        };
+        Self {
+            on_disk: dump.on_disk.into_iter().map(page).collect(),
+            dirty: dump.dirty.into_iter().map(page).collect(),
+            pending: page(dump.pending),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<const KEY_LEN: usize, const VAL_LEN: usize> TryFrom<DumpRepr>
+    for FileAuraMapDump<KEY_LEN, VAL_LEN>
+{
+    type Error = amplify::hex::Error;
+
+    fn try_from(repr: DumpRepr) -> Result<Self, Self::Error> {
+        use amplify::hex::{Error, FromHex};
+
+        fn from_hex<const LEN: usize>(s: &str) -> Result<[u8; LEN], Error> {
+            <[u8; LEN]>::try_from(Vec::<u8>::from_hex(s)?)
+                .map_err(|_| Error::InvalidLength(LEN * 2, s.len()))
+        }
+        let page = |page: IndexMap<String, String>| -> Result<IndexMap<_, _>, Error> {
+            page.iter()
+                .map(|(key, val)| Ok((from_hex(key)?, from_hex(val)?)))
+                .collect()
+        };
+        Ok(Self {
+            on_disk: repr.on_disk.into_iter().map(page).collect::<Result<_, _>>()?,
+            dirty: repr.dirty.into_iter().map(page).collect::<Result<_, _>>()?,
+            pending: page(repr.pending)?,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::collections::HashSet;
-
     use super::*;
     use crate::U64Le;
 
@@ -316,6 +2250,7 @@ mod tests {
 
         // We have two keys at the end
         assert_eq!(db.keys().count(), 2);
+        assert_eq!(db.keys_rev().collect::<Vec<_>>(), vec![1.into(), 0.into()]);
     }
 
     #[test]
@@ -368,6 +2303,7 @@ mod tests {
         assert_eq!(db.transaction_count(), 2);
         assert_eq!(db.transaction_keys(0).collect::<HashSet<_>>(), set![0.into(), 1.into()]);
         assert_eq!(db.transaction_keys(1).collect::<HashSet<_>>(), set![3.into()]);
+        assert_eq!(db.keys_rev().collect::<Vec<_>>(), vec![3.into(), 1.into(), 0.into()]);
 
         db.save().unwrap();
 
@@ -394,6 +2330,449 @@ mod tests {
         assert_eq!(db.transaction_keys(1).collect::<HashSet<_>>(), set![3.into()]);
     }
 
+    #[test]
+    fn observer() {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct Events(Mutex<Vec<String>>);
+        impl AoraObserver for Arc<Events> {
+            fn on_insert(&self, key: &[u8]) {
+                self.0.lock().unwrap().push(format!("insert {}", key[0]));
+            }
+            fn on_commit(&self, txno: u64, keys: &[&[u8]]) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("commit {txno} with {} keys", keys.len()));
+            }
+            fn on_abort(&self) { self.0.lock().unwrap().push(s!("abort")); }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "observer").unwrap();
+        let events = Arc::new(Events::default());
+        db.set_observer(events.clone());
+        db.insert_only(1.into(), 1.into());
+        db.insert_only(2.into(), 2.into());
+        db.commit_transaction();
+        db.insert_or_update(1.into(), 3.into());
+        db.abort_transaction();
+        assert_eq!(*events.0.lock().unwrap(), vec![
+            s!("insert 1"),
+            s!("insert 2"),
+            s!("commit 0 with 2 keys"),
+            s!("insert 1"),
+            s!("abort")
+        ]);
+    }
+
+    #[test]
+    fn stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "stats").unwrap();
+        db.insert_only(0.into(), 1.into());
+        db.insert_only(1.into(), 2.into());
+        db.commit_transaction();
+        db.insert_only(2.into(), 3.into());
+        assert_eq!(db.stats().unwrap(), StorageStats {
+            log_size: 18 + 2 * 8 + 2 * 16,
+            idx_size: 0,
+            records: 2,
+            pages: 1,
+            pending: 1,
+            latency: None,
+        });
+        db.commit_transaction();
+        db.save().unwrap();
+        let stats = db.stats().unwrap();
+        assert_eq!(stats.log_size, 18 + 4 * 8 + 3 * 16);
+        assert_eq!((stats.records, stats.pages, stats.pending), (3, 2, 0));
+    }
+
+    #[test]
+    fn written_ranges() {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct Written(Mutex<Vec<(PathBuf, Vec<Range<u64>>)>>);
+        impl AoraObserver for Arc<Written> {
+            fn on_written(&self, path: &Path, ranges: &[Range<u64>]) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((path.to_owned(), ranges.to_vec()));
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "written").unwrap();
+        let written = Arc::new(Written::default());
+        db.set_observer(written.clone());
+        db.set_signer(|msg: &[u8]| msg[..4].to_vec()).unwrap();
+        normal_ops(&mut db);
+        db.commit_transaction();
+        db.insert_only(3.into(), 5.into());
+        db.commit_transaction();
+
+        let log = dir.path().join("written.log");
+        let sig = dir.path().join("written.sig");
+        assert_eq!(*written.0.lock().unwrap(), vec![
+            (log.clone(), vec![10..18, 18..66]),
+            (sig.clone(), vec![10..18, 18..24]),
+            (log.clone(), vec![10..18, 66..98]),
+            (sig.clone(), vec![10..18, 24..30]),
+        ]);
+        assert_eq!(fs::metadata(&log).unwrap().len(), 98);
+        assert_eq!(fs::metadata(&sig).unwrap().len(), 30);
+    }
+
+    #[test]
+    fn signed_pages() {
+        // Toy signature scheme, sufficient to test the storage of the signatures
+        let sign = |msg: &[u8]| msg.iter().rev().copied().collect::<Vec<u8>>();
+        let verify = |msg: &[u8], sig: &[u8]| msg.iter().rev().eq(sig.iter());
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "signed").unwrap();
+        db.set_signer(sign).unwrap();
+        normal_ops(&mut db);
+        assert_eq!(db.commit_transaction(), Some(0));
+        db.insert_only(3.into(), 5.into());
+        assert_eq!(db.commit_transaction(), Some(1));
+        drop(db);
+
+        let mut db = Db::open_verified(dir.path(), "signed", &verify).unwrap();
+        assert!(matches!(
+            db.verify_signatures(&|_: &[u8], _: &[u8]| false),
+            Err(AoraError::BadSignature { page: 0 })
+        ));
+        db.set_signer(sign).unwrap();
+        db.insert_only(4.into(), 6.into());
+        assert_eq!(db.commit_transaction(), Some(2));
+        db.verify_signatures(&verify).unwrap();
+
+        let mut db = Db::create_new(dir.path(), "unsigned").unwrap();
+        db.insert_only(0.into(), 1.into());
+        assert_eq!(db.commit_transaction(), Some(0));
+        assert!(matches!(db.verify_signatures(&verify), Err(AoraError::BadSignature { page: 0 })));
+        assert_eq!(db.set_signer(sign).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "snapshot").unwrap();
+
+        normal_ops(&mut db);
+        assert_eq!(db.snapshot().transaction_count(), 0);
+        assert_eq!(db.snapshot().get(0.into()), None);
+        assert_eq!(db.commit_transaction(), Some(0));
+
+        let snapshot = db.snapshot();
+        db.update_only(0.into(), 10.into());
+        db.insert_only(3.into(), 5.into());
+        // Pending writes are not visible
+        assert_eq!(snapshot.get_expect(0.into()).0, 3);
+        assert!(!snapshot.contains_key(3.into()));
+        assert_eq!(db.commit_transaction(), Some(1));
+
+        // Later commits are not visible either
+        assert_eq!(snapshot.transaction_count(), 1);
+        assert_eq!(snapshot.get_expect(0.into()).0, 3);
+        assert_eq!(snapshot.get_expect(1.into()).0, 4);
+        assert_eq!(snapshot.keys().collect::<HashSet<_>>(), set![0.into(), 1.into()]);
+
+        let snapshot = db.snapshot();
+        assert_eq!(snapshot.transaction_count(), 2);
+        assert_eq!(snapshot.get_expect(0.into()).0, 10);
+        assert_eq!(snapshot.keys_rev().collect::<Vec<_>>(), vec![3.into(), 0.into(), 1.into()]);
+        assert_eq!(snapshot.len(), 3);
+    }
+
+    #[test]
+    fn fork() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "fork").unwrap();
+        normal_ops(&mut db);
+        assert_eq!(db.commit_transaction(), Some(0));
+
+        // Discarded fork doesn't affect the map
+        let mut fork = db.fork();
+        fork.update_only(0.into(), 10.into());
+        assert_eq!(fork.get_expect(0.into()).0, 10);
+        drop(fork);
+        assert_eq!(db.get_expect(0.into()).0, 3);
+
+        let mut fork = db.fork();
+        fork.update_only(0.into(), 10.into());
+        fork.insert_only(3.into(), 5.into());
+        fork.insert_only(1.into(), 4.into());
+        assert_eq!(fork.changes(), 2);
+        assert!(fork.contains_key(3.into()));
+        assert!(!db.contains_key(3.into()));
+        assert_eq!(fork.keys().collect::<Vec<_>>(), vec![0.into(), 1.into(), 3.into()]);
+        assert_eq!(fork.keys_rev().collect::<Vec<_>>(), vec![3.into(), 0.into(), 1.into()]);
+        assert_eq!(fork.len(), 3);
+        assert_eq!(db.commit_fork(fork.clone()).unwrap(), Some(1));
+        assert_eq!(db.get_expect(0.into()).0, 10);
+        assert_eq!(db.get_expect(3.into()).0, 5);
+        assert_eq!(db.transaction_keys(1).collect::<HashSet<_>>(), set![0.into(), 3.into()]);
+
+        // Forks taken before the last commit or merged into a pending transaction are rejected
+        assert!(matches!(
+            db.commit_fork(fork),
+            Err(AoraError::ForkOutdated { base: 1, current: 2, .. })
+        ));
+        let fork = db.fork();
+        db.insert_only(4.into(), 6.into());
+        assert!(matches!(db.commit_fork(fork), Err(AoraError::PendingTransaction { .. })));
+        db.abort_transaction();
+        assert_eq!(db.commit_fork(db.fork()).unwrap(), None);
+    }
+
+    #[test]
+    fn visibility() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "visibility").unwrap();
+        db.insert_only(0.into(), 1.into());
+        assert_eq!(db.commit_transaction(), Some(0));
+
+        db.set_visibility(Visibility::CommittedOnly);
+        db.update_only(0.into(), 2.into());
+        db.insert_only(1.into(), 3.into());
+        assert_eq!(db.get_expect(0.into()).0, 1);
+        assert_eq!(db.get(1.into()), None);
+        assert!(!db.contains_key(1.into()));
+        assert_eq!(db.keys_rev().collect::<Vec<_>>(), vec![0.into()]);
+
+        // Writing the pending value again is still a no-op
+        db.insert_or_update(0.into(), 2.into());
+        assert_eq!(db.to_dump().pending.len(), 2);
+
+        db.set_visibility(Visibility::IncludePending);
+        assert_eq!(db.get_expect(0.into()).0, 2);
+        assert_eq!(db.keys_rev().collect::<Vec<_>>(), vec![1.into(), 0.into()]);
+        assert_eq!(db.keys().collect::<Vec<_>>(), vec![0.into(), 1.into()]);
+        assert_eq!(db.len(), 2);
+
+        db.set_visibility(Visibility::CommittedOnly);
+        assert_eq!(db.commit_transaction(), Some(1));
+        assert_eq!(db.get_expect(0.into()).0, 2);
+        assert_eq!(db.get_expect(1.into()).0, 3);
+    }
+
+    #[test]
+    fn auto_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "auto_commit").unwrap();
+        db.set_auto_commit(3, usize::MAX);
+        for no in 0..7 {
+            db.insert_or_update(no.into(), no.into());
+        }
+        assert_eq!(db.transaction_count(), 3);
+        assert_eq!(db.transaction_keys(1).collect::<Vec<_>>(), vec![3.into(), 4.into(), 5.into()]);
+        assert_eq!(db.commit_transaction(), Some(2));
+
+        // A page with two entries takes 8 + 2 * 16 bytes
+        db.set_auto_commit(usize::MAX, 40);
+        for no in 10..14 {
+            db.insert_or_update(no.into(), no.into());
+        }
+        assert_eq!(db.stats().unwrap().pages, 5);
+        assert_eq!(db.stats().unwrap().pending, 0);
+    }
+
+    #[test]
+    fn spill() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "spill").unwrap();
+        db.insert_only(0.into(), 1.into());
+        db.commit_transaction();
+
+        // Each page entry is estimated to take 48 bytes
+        db.set_spill_limit(2 * 48);
+        for no in 1..6 {
+            db.insert_or_update(no.into(), no.into());
+        }
+        db.insert_or_update(1.into(), 10.into());
+        let spill = dir.path().join("spill.spill");
+        assert_eq!(fs::metadata(&spill).unwrap().len(), 6 * 16);
+        assert_eq!(db.stats().unwrap().pending, 6);
+        assert_eq!(db.get_expect(1.into()).0, 10);
+        assert_eq!(db.get_expect(4.into()).0, 4);
+        assert!(db.contains_key(5.into()));
+        assert_eq!(db.keys_rev().next(), Some(1.into()));
+
+        assert_eq!(db.commit_transaction(), Some(1));
+        assert!(!fs::exists(&spill).unwrap());
+        assert_eq!(
+            db.transaction_keys(1).collect::<Vec<_>>(),
+            (1..6u64).map(U64Le::from).collect::<Vec<_>>()
+        );
+        assert_eq!(db.get_expect(1.into()).0, 10);
+
+        db.insert_or_update(6.into(), 6.into());
+        db.insert_or_update(7.into(), 7.into());
+        assert!(fs::exists(&spill).unwrap());
+        db.abort_transaction();
+        assert!(!fs::exists(&spill).unwrap());
+        assert_eq!(db.get(6.into()), None);
+        drop(db);
+
+        let db = Db::open(dir.path(), "spill").unwrap();
+        assert_eq!(db.get_expect(1.into()).0, 10);
+        assert_eq!(db.transaction_count(), 2);
+    }
+
+    #[test]
+    fn iter() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "iter").unwrap();
+        db.insert_or_update(1.into(), 10.into());
+        db.insert_or_update(2.into(), 20.into());
+        db.commit_transaction();
+        db.insert_or_update(3.into(), 30.into());
+
+        let pairs = |db: &Db| db.iter().map(|(k, v)| (k.0, v.0)).collect::<Vec<_>>();
+        assert_eq!(pairs(&db), vec![(1, 10), (2, 20), (3, 30)]);
+        assert_eq!(db.values().map(|v| v.0).collect::<Vec<_>>(), vec![10, 20, 30]);
+        db.set_visibility(Visibility::CommittedOnly);
+        assert_eq!(pairs(&db), vec![(1, 10), (2, 20)]);
+        db.set_visibility(Visibility::default());
+        db.commit_transaction();
+    }
+
+    #[test]
+    fn transaction_times() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "times").unwrap();
+        let start = SystemTime::now();
+        db.insert_or_update(1.into(), 1.into());
+        db.commit_transaction();
+        let middle = SystemTime::now();
+        db.insert_or_update(2.into(), 2.into());
+        db.commit_transaction();
+        let end = SystemTime::now();
+
+        let time = db.transaction_time(0).unwrap();
+        assert!(time >= start && time <= middle);
+        assert_eq!(db.transaction_time(2), None);
+        assert_eq!(db.transactions_between(start, middle).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(db.transactions_between(middle, end).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(db.transactions_between(end, end).count(), 0);
+        drop(db);
+
+        let db = Db::open(dir.path(), "times").unwrap();
+        assert_eq!(db.transaction_time(0), Some(time));
+        assert_eq!(db.transactions_between(start, end).collect::<Vec<_>>(), vec![0, 1]);
+    }
+
+    #[test]
+    fn pending_introspection() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "pending").unwrap();
+        assert!(!db.has_pending());
+        db.insert_or_update(1.into(), 1.into());
+        db.insert_or_update(2.into(), 2.into());
+        db.set_visibility(Visibility::CommittedOnly);
+        assert!(db.has_pending());
+        assert_eq!(db.pending_len(), 2);
+        assert_eq!(db.pending_keys().collect::<Vec<_>>(), vec![1.into(), 2.into()]);
+        assert_eq!(db.transaction_count(), 0);
+
+        assert_eq!(db.commit_transaction(), Some(0));
+        assert!(!db.has_pending());
+        assert_eq!(db.pending_keys().count(), 0);
+        db.insert_or_update(3.into(), 3.into());
+        assert_eq!(db.transaction_count(), 1);
+        db.abort_transaction();
+    }
+
+    #[test]
+    fn keys_in_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "range").unwrap();
+        for keys in [&[1u64, 2][..], &[3], &[2, 4], &[5]] {
+            for key in keys {
+                db.insert_or_update((*key).into(), 0.into());
+            }
+            db.commit_transaction();
+        }
+        let keys = |range| db.keys_in_range(range).map(|k| k.0).collect::<Vec<_>>();
+        assert_eq!(keys(1..3), vec![3, 2, 4]);
+        assert_eq!(keys(0..4), vec![1, 2, 3, 2, 4, 5]);
+        assert_eq!(keys(2..2), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn commit_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "chunks").unwrap();
+        db.set_commit_chunk(2);
+        for no in 0..5u64 {
+            db.insert_or_update(no.into(), no.into());
+        }
+        assert_eq!(db.commit_transaction(), Some(0));
+        db.insert_or_update(5.into(), 5.into());
+        assert_eq!(db.commit_transaction(), Some(1));
+        // Three chunks of the first page and a single chunk of the second one, with the commit
+        // time of each page
+        let path = dir.path().join("chunks.log");
+        assert_eq!(fs::metadata(&path).unwrap().len(), 18 + 4 * 8 + 2 * 8 + 6 * 16);
+
+        let mut reader = Db::open(dir.path(), "chunks").unwrap();
+        assert_eq!(reader.transaction_count(), 2);
+        assert_eq!(
+            reader.transaction_keys(0).collect::<Vec<_>>(),
+            (0..5u64).map(U64Le::from).collect::<Vec<_>>()
+        );
+        assert_eq!(reader.get_expect(4.into()).0, 4);
+
+        for no in 6..9u64 {
+            db.insert_or_update(no.into(), no.into());
+        }
+        db.commit_transaction();
+        assert_eq!(reader.refresh().unwrap(), 1);
+        assert_eq!(reader.get_expect(8.into()).0, 8);
+    }
+
+    #[test]
+    fn quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "quota").unwrap();
+        // Each page takes an 8-byte key count, an 8-byte commit time and 16 bytes per key
+        db.set_quota(18 + 2 * (16 + 2 * 16));
+        for no in 0..2u64 {
+            db.insert_or_update(no.into(), no.into());
+        }
+        assert_eq!(db.try_commit_transaction().unwrap(), Some(0));
+        for no in 2..5u64 {
+            db.insert_or_update(no.into(), no.into());
+        }
+        let err = db.try_commit_transaction().unwrap_err();
+        assert!(matches!(err, AoraError::QuotaExceeded { size: 130, limit: 114, .. }));
+        assert_eq!(db.pending_len(), 3);
+        assert_eq!(db.transaction_count(), 1);
+        assert!(!db.is_poisoned());
+
+        db.abort_transaction();
+        for no in 2..4u64 {
+            db.insert_or_update(no.into(), no.into());
+        }
+        assert_eq!(db.try_commit_transaction().unwrap(), Some(1));
+        let path = dir.path().join("quota.log");
+        assert_eq!(fs::metadata(&path).unwrap().len(), 114);
+
+        // The changes of a fork exceeding the quota are not left pending
+        let mut fork = db.fork();
+        fork.insert_only(4.into(), 4.into());
+        let err = db.commit_fork(fork).unwrap_err();
+        assert!(matches!(err, AoraError::QuotaExceeded { .. }));
+        assert!(!db.has_pending());
+        assert_eq!(db.transaction_count(), 2);
+    }
+
     #[test]
     fn insert_same() {
         let dir = tempfile::tempdir().unwrap();
@@ -409,6 +2788,34 @@ mod tests {
         assert_eq!(db.transaction_count(), 1);
     }
 
+    #[test]
+    fn fallible_mutations() {
+        use crate::AuraMapError;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "fallible").unwrap();
+        assert_eq!(
+            db.try_update_only(0.into(), 1.into()),
+            Err(AuraMapError::KeyNotFound { key: [0; 8] })
+        );
+        assert_eq!(db.try_insert_only(0.into(), 1.into()), Ok(()));
+        assert_eq!(db.try_insert_only(0.into(), 1.into()), Ok(()));
+        let err = db.try_insert_only(0.into(), 2.into()).unwrap_err();
+        assert_eq!(err, AuraMapError::KeyExistsWithDifferentValue {
+            key: [0; 8],
+            old: [1, 0, 0, 0, 0, 0, 0, 0],
+            new: [2, 0, 0, 0, 0, 0, 0, 0]
+        });
+        assert_eq!(
+            err.to_string(),
+            "key 0000000000000000 is already present with value 0100000000000000, different from \
+             the new value 0200000000000000"
+        );
+        assert_eq!(db.try_update_only(0.into(), 2.into()), Ok(()));
+        assert_eq!(db.get_expect(0.into()).0, 2);
+        assert_eq!(db.commit_transaction(), Some(0));
+    }
+
     #[test]
     #[should_panic(expected = "failed to insert-only key 0000000000000000 which is already \
                                present in the table 'unique_keys' (old value=0100000000000000, \
@@ -439,4 +2846,335 @@ Non-commited page:
         }
         // we panic at the end of the scope
     }
+    #[test]
+    fn recover() {
+        let sign = |msg: &[u8]| msg.iter().rev().copied().collect::<Vec<u8>>();
+        let verify = |msg: &[u8], sig: &[u8]| msg.iter().rev().eq(sig.iter());
+
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("recover.log");
+        let sig = dir.path().join("recover.sig");
+        let mut db = Db::create_new(dir.path(), "recover").unwrap();
+        db.set_signer(sign).unwrap();
+        normal_ops(&mut db);
+        assert_eq!(db.commit_transaction(), Some(0));
+        let log_data = fs::read(&log).unwrap();
+        let sig_data = fs::read(&sig).unwrap();
+
+        // Make the log file impossible to write
+        fs::remove_file(&log).unwrap();
+        fs::create_dir(&log).unwrap();
+        db.insert_only(3.into(), 5.into());
+        assert!(matches!(db.try_commit_transaction(), Err(AoraError::Io(_))));
+        assert!(db.is_poisoned());
+        assert_eq!(db.get_expect(3.into()).0, 5);
+        db.insert_only(4.into(), 6.into());
+        assert!(matches!(db.try_commit_transaction(), Err(AoraError::Poisoned { .. })));
+        assert!(db.save().is_err());
+
+        // Restore the files with partially written pages at their ends
+        fs::remove_dir(&log).unwrap();
+        fs::write(&log, [log_data, vec![1, 0, 0, 0, 0, 0, 0, 0, 3]].concat()).unwrap();
+        fs::write(&sig, [sig_data, vec![40, 0, 1, 2]].concat()).unwrap();
+        db.recover().unwrap();
+        assert!(!db.is_poisoned());
+        assert_eq!(db.commit_transaction(), Some(2));
+        drop(db);
+
+        let db = Db::open_verified(dir.path(), "recover", &verify).unwrap();
+        assert_eq!(db.transaction_keys(1).collect::<Vec<_>>(), vec![3.into()]);
+        assert_eq!(db.transaction_keys(2).collect::<Vec<_>>(), vec![4.into()]);
+        assert_eq!(db.get_expect(0.into()).0, 3);
+    }
+
+    #[test]
+    fn drop_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "drop_policy").unwrap();
+        db.set_drop_policy(DropPolicy::CommitOnDrop);
+        normal_ops(&mut db);
+        drop(db);
+
+        let mut db = Db::open(dir.path(), "drop_policy").unwrap();
+        assert_eq!(db.transaction_count(), 1);
+        assert_eq!(db.get_expect(1.into()).0, 4);
+        db.set_drop_policy(DropPolicy::AbortTransaction);
+        db.insert_only(3.into(), 5.into());
+        drop(db);
+
+        let db = Db::open(dir.path(), "drop_policy").unwrap();
+        assert_eq!(db.transaction_count(), 1);
+        assert!(!db.contains_key(3.into()));
+    }
+
+    #[test]
+    fn close() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "close").unwrap();
+        normal_ops(&mut db);
+        assert!(matches!(db.close(), Err(AoraError::PendingTransaction { .. })));
+
+        let mut db = Db::open(dir.path(), "close").unwrap();
+        assert_eq!(db.transaction_count(), 0);
+        db.set_drop_policy(DropPolicy::CommitOnDrop);
+        normal_ops(&mut db);
+        db.close().unwrap();
+
+        let db = Db::open(dir.path(), "close").unwrap();
+        assert_eq!(db.transaction_count(), 1);
+        assert_eq!(db.get_expect(1.into()).0, 4);
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "archive").unwrap();
+        for no in 0..4u64 {
+            db.insert_or_update(no.into(), no.into());
+            db.insert_or_update(10.into(), no.into());
+            db.commit_transaction();
+        }
+
+        db.insert_only(5.into(), 5.into());
+        assert!(matches!(
+            db.seal_and_archive(2, archive.path()),
+            Err(AoraError::PendingTransaction { .. })
+        ));
+        db.commit_transaction();
+        assert!(db.seal_and_archive(6, archive.path()).is_err());
+
+        let path = db.seal_and_archive(2, archive.path()).unwrap().unwrap();
+        assert_eq!(path, archive.path().join("archive-0-2.log"));
+        assert_eq!(db.seal_and_archive(2, archive.path()).unwrap(), None);
+        assert_eq!(db.transaction_count(), 5);
+        assert_eq!(db.transaction_keys(0).count(), 0);
+        // The base page keeps the latest values of the archived transactions
+        assert_eq!(
+            db.transaction_keys(1).collect::<HashSet<_>>(),
+            set![0.into(), 1.into(), 10.into()]
+        );
+        assert_eq!(db.get_expect(0.into()).0, 0);
+        assert_eq!(db.get_expect(10.into()).0, 3);
+        assert_eq!(db.get_expect(2.into()).0, 2);
+        drop(db);
+
+        let mut db = Db::open(dir.path(), "archive").unwrap();
+        assert_eq!(db.transaction_count(), 5);
+        assert_eq!(
+            db.keys().collect::<HashSet<_>>(),
+            set![0.into(), 1.into(), 2.into(), 3.into(), 5.into(), 10.into()]
+        );
+        assert_eq!(db.seal_and_archive(2, archive.path()).unwrap(), None);
+        let path = db.seal_and_archive(3, archive.path()).unwrap().unwrap();
+        assert_eq!(path, archive.path().join("archive-2-3.log"));
+        assert_eq!(db.transaction_keys(1).count(), 0);
+        assert_eq!(db.get_expect(1.into()).0, 1);
+        assert_eq!(db.get_expect(10.into()).0, 3);
+        drop(db);
+        let db = Db::open(dir.path(), "archive").unwrap();
+        assert_eq!(db.keys().count(), 6);
+        assert_eq!(db.get_expect(0.into()).0, 0);
+
+        let old = Db::open(archive.path(), "archive-0-2").unwrap();
+        assert_eq!(old.transaction_count(), 2);
+        assert_eq!(old.get_expect(10.into()).0, 1);
+        assert_eq!(old.keys().collect::<HashSet<_>>(), set![0.into(), 1.into(), 10.into()]);
+        let old = Db::open(archive.path(), "archive-2-3").unwrap();
+        assert_eq!(old.transaction_count(), 3);
+        assert_eq!(old.transaction_keys(0).count(), 0);
+        assert_eq!(old.transaction_keys(2).collect::<HashSet<_>>(), set![2.into(), 10.into()]);
+        // The archive starts with the base page of the previous archiving
+        assert_eq!(old.keys().count(), 4);
+        assert_eq!(old.get_expect(0.into()).0, 0);
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn archive_compressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "archive").unwrap();
+        for no in 0..3u64 {
+            db.insert_or_update(no.into(), no.into());
+            db.commit_transaction();
+        }
+        let path = db
+            .seal_and_archive_compressed(2, archive.path(), 3)
+            .unwrap()
+            .unwrap();
+        assert_eq!(path, archive.path().join("archive-0-2.log.zst"));
+        assert_eq!(db.keys().count(), 3);
+
+        let data = fs::read(&path).unwrap();
+        let data = zstd::decode_all(&data[10..]).unwrap();
+        fs::write(archive.path().join("old.log"), data).unwrap();
+        let old = Db::open(archive.path(), "old").unwrap();
+        assert_eq!(old.transaction_count(), 2);
+        assert_eq!(old.keys().collect::<HashSet<_>>(), set![0.into(), 1.into()]);
+    }
+
+    #[test]
+    fn delta_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let deltas = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "source").unwrap();
+        for no in 0..3u64 {
+            db.insert_or_update(no.into(), no.into());
+            db.insert_or_update(10.into(), no.into());
+            db.commit_transaction();
+        }
+        assert!(db.export_transactions(2..4, deltas.path()).is_err());
+        let paths = db.export_transactions(0..3, deltas.path()).unwrap();
+        assert_eq!(paths[1], deltas.path().join("source-1.delta"));
+
+        let mut replica = Db::create_new(dir.path(), "replica").unwrap();
+        assert!(matches!(
+            replica.apply_delta_file(&paths[1]),
+            Err(AoraError::DeltaOutOfOrder { txno: 1, current: 0, .. })
+        ));
+        assert_eq!(replica.apply_delta_file(&paths[0]).unwrap(), Some(0));
+        assert_eq!(replica.apply_delta_file(&paths[1]).unwrap(), Some(1));
+        // Re-applying the same transaction does nothing
+        assert_eq!(replica.apply_delta_file(&paths[1]).unwrap(), None);
+        assert_eq!(replica.apply_delta_file(&paths[2]).unwrap(), Some(2));
+        drop(replica);
+
+        let replica = Db::open(dir.path(), "replica").unwrap();
+        assert_eq!(replica.to_dump(), db.to_dump());
+
+        let mut data = fs::read(&paths[2]).unwrap();
+        data.pop();
+        fs::write(&paths[2], data).unwrap();
+        let mut db = Db::create_new(dir.path(), "damaged").unwrap();
+        assert!(db.apply_delta_file(&paths[2]).is_err());
+    }
+
+    #[test]
+    fn incremental_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "source").unwrap();
+        let mut replica = Db::create_new(dir.path(), "replica").unwrap();
+        let mut since = 0;
+        for (day, keys) in [0..3u64, 3..4, 4..8].into_iter().enumerate() {
+            for no in keys {
+                db.insert_or_update(no.into(), no.into());
+                db.insert_or_update(10.into(), no.into());
+                db.commit_transaction();
+            }
+            let path = backups.path().join(format!("day{day}.log"));
+            since = db.backup_incremental(since, &path).unwrap();
+            assert_eq!(replica.restore_incremental(&path).unwrap(), since);
+        }
+        assert_eq!(since, 8);
+        assert_eq!(replica.to_dump(), db.to_dump());
+
+        // The backups can be opened as the logs, where only the backed up transactions are present
+        let day1 = Db::open(backups.path(), "day1").unwrap();
+        assert_eq!(day1.transaction_count(), 4);
+        assert_eq!(day1.keys().collect::<HashSet<_>>(), set![3.into(), 10.into()]);
+
+        // Restoring the same backup again does nothing, while the older ones can't be skipped
+        assert_eq!(replica.restore_incremental(backups.path().join("day1.log")).unwrap(), 8);
+        let mut other = Db::create_new(dir.path(), "other").unwrap();
+        assert!(matches!(
+            other.restore_incremental(backups.path().join("day1.log")),
+            Err(AoraError::DeltaOutOfOrder { txno: 3, current: 0, .. })
+        ));
+        assert!(db.backup_incremental(9, backups.path().join("day3.log")).is_err());
+    }
+
+    #[test]
+    fn evict_saved_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "evict").unwrap();
+        normal_ops(&mut db);
+        db.commit_transaction();
+        let dump = db.to_dump();
+
+        db.evict_saved_pages();
+        assert!(db.on_disk.iter().all(|page| page.is_empty()));
+        assert_eq!(db.to_dump(), dump);
+        let snapshot = db.snapshot();
+        db.update_only(0.into(), 10.into());
+        db.insert_only(3.into(), 5.into());
+        assert_eq!(db.commit_transaction(), Some(1));
+        // The newly saved pages are evicted as well
+        assert!(db.on_disk.iter().all(|page| page.is_empty()));
+        assert_eq!(db.get_expect(0.into()).0, 10);
+        assert_eq!(db.get_expect(1.into()).0, 4);
+        assert_eq!(db.keys().collect::<Vec<_>>(), vec![0.into(), 1.into(), 3.into()]);
+        assert_eq!(db.keys_rev().collect::<Vec<_>>(), vec![3.into(), 0.into(), 1.into()]);
+        assert_eq!(db.transaction_keys(0).collect::<HashSet<_>>(), set![0.into(), 1.into()]);
+        assert_eq!(db.transaction_keys(1).collect::<HashSet<_>>(), set![0.into(), 3.into()]);
+        assert_eq!(db.stats().unwrap().records, 4);
+        // The snapshot keeps the values it was taken at
+        assert_eq!(snapshot.transaction_count(), 1);
+        assert_eq!(snapshot.get_expect(0.into()).0, 3);
+        assert!(!snapshot.contains_key(3.into()));
+        let keys = db.keys_rev().collect::<Vec<_>>();
+        assert_eq!(db.snapshot().keys_rev().collect::<Vec<_>>(), keys);
+        let dump = db.to_dump();
+        drop(db);
+
+        let mut db = Db::open(dir.path(), "evict").unwrap();
+        assert_eq!(db.to_dump(), dump);
+        db.evict_saved_pages();
+        assert_eq!(db.to_dump(), dump);
+        assert!(db.seal_and_archive(1, archive.path()).unwrap().is_some());
+        assert_eq!(db.get_expect(0.into()).0, 10);
+        assert_eq!(db.transaction_keys(0).collect::<HashSet<_>>(), set![0.into(), 1.into()]);
+        db.insert_or_update(1.into(), 6.into());
+        assert_eq!(db.commit_transaction(), Some(2));
+        assert_eq!(db.transaction_keys(2).collect::<Vec<_>>(), vec![1.into()]);
+        drop(db);
+
+        let db = Db::open(dir.path(), "evict").unwrap();
+        assert_eq!(db.get_expect(0.into()).0, 10);
+        assert_eq!(db.get_expect(1.into()).0, 6);
+        assert_eq!(db.transaction_keys(1).collect::<HashSet<_>>(), set![0.into(), 3.into()]);
+    }
+
+    #[test]
+    fn refresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "refresh").unwrap();
+        normal_ops(&mut db);
+        db.commit_transaction();
+        let mut reader = Db::open(dir.path(), "refresh").unwrap();
+        assert_eq!(reader.refresh_if_changed().unwrap(), 0);
+
+        db.insert_or_update(0.into(), 5.into());
+        db.commit_transaction();
+        db.insert_or_update(2.into(), 6.into());
+        db.commit_transaction();
+        assert_eq!(reader.get_expect(0.into()).0, 3);
+        assert_eq!(reader.refresh().unwrap(), 2);
+        assert_eq!(reader.transaction_count(), 3);
+        assert_eq!(reader.get_expect(0.into()).0, 5);
+        assert_eq!(reader.get_expect(2.into()).0, 6);
+        assert_eq!(reader.to_dump(), db.to_dump());
+    }
+
+    #[test]
+    fn debug_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "report").unwrap();
+        normal_ops(&mut db);
+        db.commit_transaction();
+        db.insert_or_update(2.into(), 5.into());
+
+        let report = db.debug_report().unwrap();
+        assert!(report.contains("table: \"report\"\n"));
+        assert!(report.contains("magic: 0x44554d4254455354\n"));
+        assert!(report.contains("transactions: 1\n"));
+        assert!(report.contains("pending: 1\n"));
+        assert!(report.contains("last_updated_key: 0200000000000000\n"));
+        assert!(report.contains("last_transactions:\n  - \"#0: 2 keys\"\n"));
+        assert!(report.ends_with("anomalies: []\n"));
+        db.abort_transaction();
+    }
 }
@@ -0,0 +1,131 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use binfile::BinFile;
+
+/// Length of the filter file header: [`BinFile`] header, number of bits and number of hashes.
+const HEADER_LEN: u64 = 10 + 8 + 4;
+
+/// Bloom filter persisted in a file, allowing to answer most negative lookups without touching the
+/// index.
+#[derive(Debug)]
+pub(crate) struct BloomFilter<const MAGIC: u64, const VER: u16> {
+    file: BinFile<MAGIC, VER>,
+    bits: Vec<u8>,
+    hashes: u32,
+}
+
+impl<const MAGIC: u64, const VER: u16> BloomFilter<MAGIC, VER> {
+    /// Creates a new empty filter sized for the expected number of items and the target false
+    /// positive rate.
+    pub fn create_new(path: &Path, expected: u64, fp_rate: f64) -> io::Result<Self> {
+        let ln2 = core::f64::consts::LN_2;
+        let expected = expected.max(1) as f64;
+        let bits = (-expected * fp_rate.clamp(f64::MIN_POSITIVE, 0.5).ln() / (ln2 * ln2)).ceil();
+        let bits = (bits as u64).max(64).next_multiple_of(8);
+        let hashes = ((bits as f64 / expected) * ln2).round().clamp(1.0, 32.0) as u32;
+
+        let mut file = BinFile::<MAGIC, VER>::create_new(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("bloom filter '{}'", path.display())))?;
+        file.write_all(&bits.to_le_bytes())?;
+        file.write_all(&hashes.to_le_bytes())?;
+        let bits = vec![0u8; bits as usize / 8];
+        file.write_all(&bits)?;
+        Ok(Self { file, bits, hashes })
+    }
+
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = BinFile::<MAGIC, VER>::open_rw(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("bloom filter '{}'", path.display())))?;
+        let mut buf = [0u8; 8];
+        file.read_exact(&mut buf)?;
+        let len = u64::from_le_bytes(buf) / 8;
+        let mut buf = [0u8; 4];
+        file.read_exact(&mut buf)?;
+        let hashes = u32::from_le_bytes(buf);
+        if file.metadata()?.len() != HEADER_LEN + len || len == 0 || hashes == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bloom filter '{}' is corrupted", path.display()),
+            ));
+        }
+        let mut bits = vec![0u8; len as usize];
+        file.read_exact(&mut bits)?;
+        Ok(Self { file, bits, hashes })
+    }
+
+    /// Returns the size of the filter file, in bytes.
+    pub fn disk_size(&self) -> u64 { HEADER_LEN + self.bits.len() as u64 }
+
+    /// Computes positions of the bits for the item using double hashing over 64-bit FNV-1a.
+    fn positions(&self, item: &[u8]) -> impl Iterator<Item = usize> + '_ {
+        let fnv = |seed: u64| {
+            item.iter()
+                .fold(0xcbf2_9ce4_8422_2325 ^ seed, |hash, byte| {
+                    (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+                })
+        };
+        let (h1, h2) = (fnv(0), fnv(0x9e37_79b9_7f4a_7c15) | 1);
+        let len = self.bits.len() as u64 * 8;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    /// Checks whether the item may be present. If `false` is returned, the item is certainly
+    /// absent.
+    pub fn contains(&self, item: &[u8]) -> bool {
+        self.positions(item)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Re-reads the filter from the file, picking up the items added by other processes.
+    pub fn reload(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(HEADER_LEN))?;
+        self.file.read_exact(&mut self.bits)
+    }
+
+    /// Syncs the filter file to the storage device.
+    pub fn sync(&self) -> io::Result<()> { self.file.sync_all() }
+
+    /// Adds the item to the filter, persisting the modified bytes.
+    pub fn insert(&mut self, item: &[u8]) -> io::Result<()> {
+        let positions = self.positions(item).collect::<Vec<_>>();
+        for bit in positions {
+            let byte = &mut self.bits[bit / 8];
+            if *byte & (1 << (bit % 8)) != 0 {
+                continue;
+            }
+            *byte |= 1 << (bit % 8);
+            self.file
+                .seek(SeekFrom::Start(HEADER_LEN + bit as u64 / 8))?;
+            self.file.write_all(&[*byte])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn false_positives() {
+        const MAGIC: u64 = u64::from_be_bytes(*b"DUMBTEST");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.bloom");
+        let mut bloom = BloomFilter::<MAGIC, 1>::create_new(&path, 1000, 0.01).unwrap();
+        for no in 0..1000u64 {
+            bloom.insert(&no.to_be_bytes()).unwrap();
+        }
+        drop(bloom);
+
+        let bloom = BloomFilter::<MAGIC, 1>::open(&path).unwrap();
+        assert!((0..1000u64).all(|no| bloom.contains(&no.to_be_bytes())));
+        let false_positives = (1000..11000u64)
+            .filter(|no| bloom.contains(&no.to_be_bytes()))
+            .count();
+        assert!(false_positives < 200, "{false_positives} false positives");
+    }
+}
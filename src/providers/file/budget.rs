@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Estimated memory overhead of a cached or indexed entry besides its key and value.
+pub(crate) const ENTRY_OVERHEAD: usize = 32;
+
+/// Limit on the memory used by the caches and the in-memory data of the file providers.
+///
+/// Each table with the budget set charges it for the memory used by its in-memory index, record
+/// cache and the data pending a flush. Once the budget is exceeded, the tables evict the least
+/// recently used cached records and flush the pending data where they can. The clones of a budget
+/// share the same limit, so a budget set to several tables bounds their total memory use, while
+/// a separate budget for each table bounds it independently.
+///
+/// The data which can't be evicted (the full in-memory index of [`super::FileAoraMap`] or the
+/// pages of [`super::FileAuraMap`]) are still charged, leaving less memory to the caches; use
+/// [`super::LogOptions::sparse_index`] to bound the memory used by the index.
+#[derive(Clone, Debug)]
+pub struct MemoryBudget(Arc<Budget>);
+
+#[derive(Debug)]
+struct Budget {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl MemoryBudget {
+    /// Creates a budget limiting the memory use to the given number of bytes.
+    pub fn new(limit: usize) -> Self { Self(Arc::new(Budget { limit, used: AtomicUsize::new(0) })) }
+
+    /// Returns the memory limit, in bytes.
+    pub fn limit(&self) -> usize { self.0.limit }
+
+    /// Returns the number of bytes currently charged by the tables using the budget.
+    pub fn used(&self) -> usize { self.0.used.load(Ordering::Relaxed) }
+
+    /// Checks whether the charged memory exceeds the limit.
+    pub fn is_exceeded(&self) -> bool { self.used() > self.limit() }
+}
+
+/// Memory charged to a budget by a table, released when the charge is dropped.
+#[derive(Debug)]
+pub(crate) struct Charge {
+    budget: MemoryBudget,
+    bytes: usize,
+}
+
+impl Charge {
+    pub fn new(budget: MemoryBudget) -> Self { Self { budget, bytes: 0 } }
+
+    pub fn budget(&self) -> &MemoryBudget { &self.budget }
+
+    /// Updates the number of the charged bytes.
+    pub fn set(&mut self, bytes: usize) {
+        let used = &self.budget.0.used;
+        if bytes > self.bytes {
+            used.fetch_add(bytes - self.bytes, Ordering::Relaxed);
+        } else {
+            used.fetch_sub(self.bytes - bytes, Ordering::Relaxed);
+        }
+        self.bytes = bytes;
+    }
+
+    pub fn add(&mut self, bytes: usize) { self.set(self.bytes + bytes) }
+
+    pub fn sub(&mut self, bytes: usize) { self.set(self.bytes.saturating_sub(bytes)) }
+}
+
+impl Drop for Charge {
+    fn drop(&mut self) { self.set(0) }
+}
+
+/// Cache of the raw log records by their position, evicting the least recently used ones once
+/// the budget is exceeded.
+#[derive(Debug)]
+pub(crate) struct RecordCache {
+    /// Record data with the tick of their last use.
+    records: HashMap<u64, (u64, Vec<u8>)>,
+    /// Positions of the records ordered by the tick of their last use.
+    lru: BTreeMap<u64, u64>,
+    tick: u64,
+    charge: Charge,
+}
+
+impl RecordCache {
+    pub fn new(budget: MemoryBudget) -> Self {
+        Self {
+            records: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            charge: Charge::new(budget),
+        }
+    }
+
+    pub fn get(&mut self, pos: u64) -> Option<&[u8]> {
+        let (tick, data) = self.records.get_mut(&pos)?;
+        self.tick += 1;
+        self.lru.remove(tick);
+        self.lru.insert(self.tick, pos);
+        *tick = self.tick;
+        Some(data)
+    }
+
+    pub fn insert(&mut self, pos: u64, data: Vec<u8>) {
+        self.tick += 1;
+        self.charge.add(data.len() + ENTRY_OVERHEAD);
+        if let Some((tick, old)) = self.records.insert(pos, (self.tick, data)) {
+            self.lru.remove(&tick);
+            self.charge.sub(old.len() + ENTRY_OVERHEAD);
+        }
+        self.lru.insert(self.tick, pos);
+        self.evict();
+    }
+
+    /// Removes the record from the cache, if present.
+    pub fn remove(&mut self, pos: u64) {
+        if let Some((tick, data)) = self.records.remove(&pos) {
+            self.lru.remove(&tick);
+            self.charge.sub(data.len() + ENTRY_OVERHEAD);
+        }
+    }
+
+    /// Evicts the least recently used records until the budget is not exceeded, or the cache is
+    /// empty.
+    pub fn evict(&mut self) {
+        while self.charge.budget().is_exceeded() {
+            let Some((_, pos)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some((_, data)) = self.records.remove(&pos) {
+                self.charge.sub(data.len() + ENTRY_OVERHEAD);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lru() {
+        let budget = MemoryBudget::new(3 * (ENTRY_OVERHEAD + 8));
+        let mut other = Charge::new(budget.clone());
+        let mut cache = RecordCache::new(budget.clone());
+        for pos in 0..3 {
+            cache.insert(pos, vec![pos as u8; 8]);
+        }
+        assert_eq!(budget.used(), budget.limit());
+        assert!(cache.get(0).is_some());
+
+        cache.insert(3, vec![3; 8]);
+        assert_eq!(cache.get(1), None);
+        assert_eq!(cache.get(0), Some(&[0u8; 8][..]));
+        assert_eq!(cache.get(3), Some(&[3u8; 8][..]));
+
+        // Memory charged elsewhere evicts the cached records
+        other.set(2 * (ENTRY_OVERHEAD + 8));
+        cache.evict();
+        assert_eq!(cache.get(2), None);
+        assert_eq!(cache.get(0), None);
+        assert!(cache.get(3).is_some());
+        assert!(!budget.is_exceeded());
+
+        drop(cache);
+        drop(other);
+        assert_eq!(budget.used(), 0);
+    }
+}
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: Apache-2.0
+
+/// Problem found by a full self-check of a [`super::FileAoraMap`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[display(doc_comments)]
+pub enum CheckIssue {
+    /// Index file has {idx_entries} entries, while the index has {keys} distinct keys.
+    EntryCount { idx_entries: usize, keys: usize },
+
+    /// Index entry #{no} for the key {key} points to the log position {idx_pos}, while the record
+    /// derived from the log starts at {log_pos}.
+    PositionMismatch {
+        no: usize,
+        key: String,
+        idx_pos: u64,
+        log_pos: u64,
+    },
+
+    /// Index entry #{no} for the key {key} points to the log position {pos} past the end of the
+    /// log.
+    DanglingEntry { no: usize, key: String, pos: u64 },
+
+    /// Record for the key {key} at the log position {pos} is damaged: {error}
+    BadRecord { key: String, pos: u64, error: String },
+
+    /// Log has {len} bytes starting at the position {pos}, which are not referenced by the index.
+    UnindexedTail { pos: u64, len: u64 },
+}
+
+/// Report of a full self-check of a [`super::FileAoraMap`], returned by
+/// [`super::FileAoraMap::check_all`].
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct CheckReport {
+    /// Number of the index entries checked.
+    pub entries: usize,
+    /// Problems found, in the order of the index entries.
+    pub issues: Vec<CheckIssue>,
+}
+
+impl CheckReport {
+    /// Checks whether no problems were found.
+    pub fn is_ok(&self) -> bool { self.issues.is_empty() }
+}
+
+/// Key present in the index file more than once, reported by
+/// [`super::FileAoraMap::audit_index`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct DuplicateKey {
+    pub key: String,
+    /// Numbers of the index file entries for the key.
+    pub entries: Vec<usize>,
+    /// Log positions from the entries, in the same order.
+    pub positions: Vec<u64>,
+    /// Log position used by the map when reading the key, or `None` if the key is missing from
+    /// the in-memory index.
+    pub winner: Option<u64>,
+}
+
+/// Result of an audit of the index file of a [`super::FileAoraMap`], returned by
+/// [`super::FileAoraMap::audit_index`].
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct IndexAudit {
+    /// Number of the entries in the index file.
+    pub entries: usize,
+    /// Keys present in the index file more than once, in the order of their first entries.
+    pub duplicates: Vec<DuplicateKey>,
+}
+
+impl IndexAudit {
+    /// Checks whether each key is present in the index file only once.
+    pub fn is_clean(&self) -> bool { self.duplicates.is_empty() }
+}
+
+/// Entries dropped from the index file by [`super::FileAoraMap::repair_index`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct IndexRepair {
+    /// Number of the dropped entries pointing past the end of the log.
+    pub dangling: usize,
+    /// Number of the dropped entries duplicating a key of another entry.
+    pub duplicates: usize,
+}
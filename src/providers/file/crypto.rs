@@ -0,0 +1,61 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use chacha20::XChaCha20;
+use chacha20::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+use chacha20poly1305::aead::{Aead, OsRng, Payload};
+use chacha20poly1305::{AeadCore, KeyInit, XChaCha20Poly1305, XNonce};
+
+/// Length of the nonces used by the XChaCha20 ciphers.
+pub const NONCE_LEN: usize = 24;
+
+/// Secret key used to encrypt the data at rest.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SecretKey(pub [u8; 32]);
+
+impl core::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("SecretKey(..)")
+    }
+}
+
+impl SecretKey {
+    /// Generates a random key.
+    pub fn random() -> Self { Self(XChaCha20Poly1305::generate_key(&mut OsRng).into()) }
+
+    /// Generates a random nonce.
+    pub fn nonce() -> [u8; NONCE_LEN] { XChaCha20Poly1305::generate_nonce(&mut OsRng).into() }
+
+    /// Encrypts the data with XChaCha20-Poly1305, authenticating the additional data. Returns the
+    /// random nonce followed by the ciphertext and the authentication tag.
+    pub fn seal(&self, aad: &[u8], data: &[u8]) -> Vec<u8> {
+        let nonce = Self::nonce();
+        let cipher = XChaCha20Poly1305::new(&self.0.into());
+        let ciphertext = cipher
+            .encrypt(&XNonce::from(nonce), Payload { msg: data, aad })
+            .expect("unable to encrypt data");
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        sealed
+    }
+
+    /// Decrypts the data produced by [`Self::seal`], returning `None` if the key or the additional
+    /// data don't match, or if the data were modified.
+    pub fn open(&self, aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let cipher = XChaCha20Poly1305::new(&self.0.into());
+        cipher
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+            .ok()
+    }
+
+    /// Applies XChaCha20 keystream starting at the given offset to the data, encrypting or
+    /// decrypting it in place. Each offset of the stream must be used to encrypt data only once.
+    pub fn apply_keystream(&self, nonce: &[u8; NONCE_LEN], offset: u64, data: &mut [u8]) {
+        let mut cipher = XChaCha20::new(&self.0.into(), nonce.into());
+        cipher.seek(offset);
+        cipher.apply_keystream(data);
+    }
+}
@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{self, Debug, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::path::Path;
+
+/// Alignment of the buffers, offsets and lengths of the unbuffered writes, matching the logical
+/// block size of virtually all the storage devices.
+const BLOCK: usize = 4096;
+
+#[derive(Clone, Copy)]
+#[repr(C, align(4096))]
+struct Block([u8; BLOCK]);
+
+impl Debug for Block {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str("Block(..)") }
+}
+
+fn as_bytes(blocks: &mut [Block]) -> &mut [u8] {
+    // SAFETY: the blocks are plain byte arrays without any padding between them, and the slice
+    // borrows them mutably.
+    unsafe { std::slice::from_raw_parts_mut(blocks.as_mut_ptr().cast(), blocks.len() * BLOCK) }
+}
+
+/// Appender writing to a file opened with `O_DIRECT`, so the written data bypass the page cache.
+///
+/// Since the unbuffered writes must cover whole aligned blocks, the appends write only the
+/// filled blocks, keeping the last partially filled one in memory until [`Self::write_tail`]
+/// (called on flush and drop) writes it padded with zeros and truncates the file back to the
+/// length of the data. The padding left by an interruption before the truncation is removed
+/// when the file is opened again.
+#[derive(Debug)]
+pub(crate) struct DirectAppender {
+    file: File,
+    /// Aligned buffer starting with the last partially filled block of the file.
+    buf: Vec<Block>,
+    end: u64,
+    /// Whether the last partially filled block has data not yet written to the file.
+    pending: bool,
+}
+
+impl DirectAppender {
+    /// Opens the file for the appends, removing the trailing zeros left after `min_len` bytes by
+    /// an interrupted write of a padded tail block.
+    pub fn open(path: &Path, min_len: u64) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("log file '{}'", path.display())))?;
+        let end = file.metadata()?.len();
+        let mut me = Self { file, buf: vec![Block([0; BLOCK])], end, pending: false };
+        if end > min_len && end % BLOCK as u64 == 0 {
+            let start = end - BLOCK as u64;
+            me.file.read_exact_at(&mut me.buf[0].0, start)?;
+            let data = me.buf[0].0.iter().rposition(|byte| *byte != 0).map_or(0, |pos| pos + 1);
+            let len = min_len.max(start + data as u64);
+            if len < end {
+                me.file.set_len(len)?;
+                me.end = len;
+            }
+        }
+        let tail = me.tail();
+        if tail > 0 {
+            let start = end - tail as u64;
+            // Unbuffered reads must be aligned as well, but may stop at the end of the file
+            if me.file.read_at(&mut me.buf[0].0, start)? < tail {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        Ok(me)
+    }
+
+    /// Length of the last partially filled block.
+    fn tail(&self) -> usize { (self.end % BLOCK as u64) as usize }
+
+    /// Length of the data, including the part not yet written to the file.
+    pub fn end(&self) -> u64 { self.end }
+
+    /// Appends the data to the end of the file, writing the filled blocks and keeping the last
+    /// partially filled one in memory.
+    pub fn append(&mut self, data: &[u8]) -> io::Result<()> {
+        let tail = self.tail();
+        let start = self.end - tail as u64;
+        let len = tail + data.len();
+        self.buf.resize(len.div_ceil(BLOCK), Block([0; BLOCK]));
+        as_bytes(&mut self.buf)[tail..len].copy_from_slice(data);
+        let full = len / BLOCK;
+        if full > 0 {
+            self.file.write_all_at(as_bytes(&mut self.buf[..full]), start)?;
+        }
+        self.end += data.len() as u64;
+
+        if full > 0 && len % BLOCK != 0 {
+            self.buf[0] = self.buf[full];
+        }
+        self.buf.truncate(1);
+        self.pending = self.tail() > 0;
+        Ok(())
+    }
+
+    /// Writes the last partially filled block padded with zeros, truncating the file back to the
+    /// length of the data.
+    pub fn write_tail(&mut self) -> io::Result<()> {
+        if !self.pending {
+            return Ok(());
+        }
+        let tail = self.tail();
+        let block = &mut self.buf[0];
+        block.0[tail..].fill(0);
+        self.file.write_all_at(&block.0, self.end - tail as u64)?;
+        self.file.set_len(self.end)?;
+        self.pending = false;
+        Ok(())
+    }
+}
+
+impl Drop for DirectAppender {
+    fn drop(&mut self) { let _ = self.write_tail(); }
+}
+
+impl Write for DirectAppender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.append(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { self.write_tail() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn unaligned() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("direct");
+        fs::write(&path, [0xFF; 10]).unwrap();
+
+        let mut expected = vec![0xFF; 10];
+        let mut appender = DirectAppender::open(&path, 0).unwrap();
+        for no in 0..10u8 {
+            let data = vec![no; 1000 + no as usize * 700];
+            appender.append(&data).unwrap();
+            expected.extend(data);
+        }
+        assert_eq!(appender.end(), expected.len() as u64);
+        appender.flush().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), expected);
+
+        let mut appender = DirectAppender::open(&path, 0).unwrap();
+        appender.append(b"tail").unwrap();
+        expected.extend(b"tail");
+        drop(appender);
+        assert_eq!(fs::read(&path).unwrap(), expected);
+
+        // Padding of an interrupted tail write
+        let len = expected.len() as u64;
+        let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len.next_multiple_of(BLOCK as u64)).unwrap();
+        let appender = DirectAppender::open(&path, len - 4).unwrap();
+        assert_eq!(appender.end(), len);
+        drop(appender);
+        assert_eq!(fs::read(&path).unwrap(), expected);
+    }
+}
@@ -0,0 +1,418 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use strict_encoding::{StrictDecode, StrictEncode, StrictWriter};
+
+use super::AoraError;
+use super::aomap::{AoraMapError, read_value};
+use super::format::LogFormat;
+use super::layout::{TableKind, TableLayout};
+use crate::{AoraKey, AoraMap};
+
+/// Length of the header written by [`binfile::BinFile`] at the start of each file.
+const HEADER_LEN: usize = 10;
+
+/// Returns the path of the table file with the given extension.
+pub(super) fn file_path(path: &Path, name: &str, ext: &str) -> PathBuf {
+    path.join(name).with_extension(ext)
+}
+
+/// Creates a new file with the same header as [`binfile::BinFile`] writes, using the magic number
+/// and the version known only at runtime.
+pub(super) fn create_file(path: &Path, magic: u64, ver: u16, what: &str) -> io::Result<File> {
+    let map_err =
+        |err: io::Error| io::Error::new(err.kind(), format!("{what} '{}'", path.display()));
+    let mut file = File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(map_err)?;
+    file.write_all(&magic.to_be_bytes())?;
+    file.write_all(&ver.to_be_bytes())?;
+    Ok(file)
+}
+
+/// Opens a file written by [`binfile::BinFile`], checking its header against the magic number and
+/// the version known only at runtime.
+pub(super) fn open_file(
+    path: &Path,
+    magic: u64,
+    ver: u16,
+    write: bool,
+    what: &str,
+) -> io::Result<File> {
+    let map_err =
+        |err: io::Error| io::Error::new(err.kind(), format!("{what} '{}'", path.display()));
+    let mut file = File::options()
+        .read(true)
+        .write(write)
+        .open(path)
+        .map_err(map_err)?;
+    let mut header = [0u8; HEADER_LEN];
+    file.read_exact(&mut header).map_err(map_err)?;
+    let found_magic = u64::from_be_bytes(header[..8].try_into().expect("fixed length"));
+    let found_ver = u16::from_be_bytes([header[8], header[9]]);
+    if found_magic != magic || found_ver != ver {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{what} '{}' has magic number {found_magic:#018x} and version {found_ver}, while \
+                 {magic:#018x} and version {ver} were expected",
+                path.display()
+            ),
+        ));
+    }
+    Ok(file)
+}
+
+/// Checks that the files of the append-only table exist and have a supported format, returning
+/// the paths of the log and index files and the format of the log records.
+pub(super) fn check_files(
+    path: &Path,
+    name: &str,
+    magic: u64,
+    ver: u16,
+) -> io::Result<(PathBuf, PathBuf, LogFormat)> {
+    let log = file_path(path, name, "log");
+    let idx = file_path(path, name, "idx");
+    if fs::exists(file_path(path, name, "segments"))? {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("log '{name}' at '{}' is segmented", path.display()),
+        ));
+    }
+    let log_exists = fs::exists(&log)?;
+    let idx_exists = fs::exists(&idx)?;
+    if !log_exists && !idx_exists {
+        return Err(io::Error::other(AoraMapError::NotExists {
+            name: name.to_string(),
+            path: path.display().to_string(),
+        }));
+    }
+    if !log_exists || !idx_exists {
+        return Err(io::Error::other(AoraMapError::PartiallyExists {
+            name: name.to_string(),
+            path: path.display().to_string(),
+        }));
+    }
+
+    let meta = file_path(path, name, "meta");
+    let format = if fs::exists(&meta)? {
+        let mut file = open_file(&meta, magic, ver, false, "format file")?;
+        LogFormat::read(&mut file, &meta)?
+    } else {
+        LogFormat::default()
+    };
+    if format.encrypted || format.sealed_keys {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("log '{name}' at '{}' is encrypted", path.display()),
+        ));
+    }
+    Ok((log, idx, format))
+}
+
+/// Variant of [`super::FileAoraMap`] taking the magic number and the version of its files at
+/// runtime rather than as const generic parameters, for the tools opening tables unknown at the
+/// compile time.
+///
+/// The files have the same format as the ones of [`super::FileAoraMap`], so a table may be
+/// written by one provider and read by the other. The tables whose files were created with
+/// non-default [`super::LogOptions`] (framed records, value hashes, Bloom filters or sparse
+/// indexes) or having tombstones can only be read, and the encrypted and segmented tables are not
+/// supported.
+#[derive(Debug)]
+pub struct FileAoraMapDyn<K, V, const KEY_LEN: usize = 32>
+where K: AoraKey<KEY_LEN>
+{
+    name: String,
+    magic: u64,
+    ver: u16,
+    log: RefCell<File>,
+    idx: File,
+    index: IndexMap<[u8; KEY_LEN], u64>,
+    format: LogFormat,
+    /// Whether the table has files which are not maintained by this provider, so it can only be
+    /// read.
+    read_only: bool,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V, const KEY_LEN: usize> FileAoraMapDyn<K, V, KEY_LEN>
+where K: AoraKey<KEY_LEN>
+{
+    pub fn create_new(
+        path: impl AsRef<Path>,
+        name: &str,
+        magic: u64,
+        ver: u16,
+    ) -> io::Result<Self> {
+        let path = path.as_ref();
+        let log = file_path(path, name, "log");
+        let idx = file_path(path, name, "idx");
+        let log_exists = fs::exists(&log)? || fs::exists(file_path(path, name, "segments"))?;
+        let idx_exists = fs::exists(&idx)?;
+        if log_exists && idx_exists {
+            return Err(io::Error::other(AoraMapError::Exists {
+                name: name.to_string(),
+                path: path.display().to_string(),
+            }));
+        }
+        if log_exists || idx_exists {
+            return Err(io::Error::other(AoraMapError::PartiallyExists {
+                name: name.to_string(),
+                path: path.display().to_string(),
+            }));
+        }
+        let log = create_file(&log, magic, ver, "log file")?;
+        let idx = create_file(&idx, magic, ver, "index file")?;
+        TableLayout { kind: TableKind::AppendOnly, key_len: KEY_LEN, val_len: None }
+            .save_dyn(path, name, magic, ver)?;
+        Ok(Self {
+            name: name.to_string(),
+            magic,
+            ver,
+            log: RefCell::new(log),
+            idx,
+            index: IndexMap::new(),
+            format: LogFormat::default(),
+            read_only: false,
+            _phantom: PhantomData,
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>, name: &str, magic: u64, ver: u16) -> io::Result<Self> {
+        let path = path.as_ref();
+        let (log, idx, format) = check_files(path, name, magic, ver)?;
+        TableLayout { kind: TableKind::AppendOnly, key_len: KEY_LEN, val_len: None }
+            .check_dyn(path, name, magic, ver)?;
+        let mut read_only = format.is_framed();
+        for ext in ["sum", "bloom", "sidx", "tomb", "dek"] {
+            read_only |= fs::exists(file_path(path, name, ext))?;
+        }
+
+        let log = open_file(&log, magic, ver, !read_only, "log file")?;
+        let mut idx = open_file(&idx, magic, ver, !read_only, "index file")?;
+        let mut data = Vec::new();
+        idx.read_to_end(&mut data)?;
+        // A trailing incomplete entry is left by an interrupted append and is ignored
+        let index = data
+            .chunks_exact(KEY_LEN + 8)
+            .map(|entry| {
+                let (key, pos) = entry.split_at(KEY_LEN);
+                let key = <[u8; KEY_LEN]>::try_from(key).expect("fixed length");
+                (key, u64::from_le_bytes(pos.try_into().expect("fixed length")))
+            })
+            .collect();
+
+        Ok(Self {
+            name: name.to_string(),
+            magic,
+            ver,
+            log: RefCell::new(log),
+            idx,
+            index,
+            format,
+            read_only,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns the magic number of the table files.
+    pub fn magic(&self) -> u64 { self.magic }
+
+    /// Returns the version of the table files.
+    pub fn version(&self) -> u16 { self.ver }
+
+    /// Checks whether the table can only be read, since it has files not maintained by this
+    /// provider.
+    pub fn is_read_only(&self) -> bool { self.read_only }
+
+    /// Syncs the log and index files to the storage device.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.log.get_mut().sync_all()?;
+        self.idx.sync_all()
+    }
+
+    /// Retrieves value from the log, reporting I/O failures and undecodable values as errors.
+    pub fn try_get(&self, key: K) -> Result<Option<V>, AoraError>
+    where V: StrictDecode {
+        let key = key.into();
+        match self.index.get(&key) {
+            Some(&pos) => self.read(&key, pos).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn read(&self, key: &[u8; KEY_LEN], pos: u64) -> Result<V, AoraError>
+    where V: StrictDecode {
+        let mut log = self.log.borrow_mut();
+        log.seek(SeekFrom::Start(pos))?;
+        read_value(&self.format, &mut *log, key, pos)
+    }
+}
+
+impl<K, V, const KEY_LEN: usize> AoraMap<K, V, KEY_LEN> for FileAoraMapDyn<K, V, KEY_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    V: Eq + StrictEncode + StrictDecode,
+{
+    fn len(&self) -> usize { self.index.len() }
+
+    fn contains_key(&self, key: K) -> bool { self.index.contains_key(&key.into()) }
+
+    fn get(&self, key: K) -> Option<V> {
+        match self.try_get(key) {
+            Ok(value) => value,
+            Err(AoraError::Redacted { .. }) => None,
+            Err(err) => panic!("unable to read item: {err}"),
+        }
+    }
+
+    fn insert(&mut self, key: K, value: &V) {
+        let key = key.into();
+        if self.index.contains_key(&key) {
+            let old = match self.try_get(key.into()) {
+                Ok(old) => old,
+                // The value of a redacted record can't be compared, and the record is kept as is
+                Err(AoraError::Redacted { .. }) => return,
+                Err(err) => panic!("unable to read item: {err}"),
+            };
+            if old.as_ref() != Some(value) {
+                panic!(
+                    "item under the given id is different from another item under the same id \
+                     already present in the log"
+                );
+            }
+            return;
+        }
+        if self.read_only {
+            panic!(
+                "log '{}' has files not maintained by the provider and can't be written",
+                self.name
+            );
+        }
+        let data = value
+            .strict_encode(StrictWriter::in_memory::<{ usize::MAX }>())
+            .expect("unable to encode item")
+            .unbox()
+            .unconfine();
+        let log = self.log.get_mut();
+        let pos = log
+            .seek(SeekFrom::End(0))
+            .expect("unable to seek to the end of the log");
+        log.write_all(&data).expect("unable to write to log");
+        let mut entry = key.to_vec();
+        entry.extend_from_slice(&pos.to_le_bytes());
+        self.idx
+            .seek(SeekFrom::End(0))
+            .expect("unable to seek to the end of the index");
+        self.idx
+            .write_all(&entry)
+            .expect("unable to write to index");
+        self.index.insert(key, pos);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (K, V)> {
+        self.index
+            .iter()
+            .filter_map(|(key, &pos)| match self.read(key, pos) {
+                Ok(value) => Some((K::from(*key), value)),
+                Err(AoraError::Redacted { .. }) => None,
+                Err(err) => panic!("unable to read item: {err}"),
+            })
+    }
+
+    fn iter_rev(&self) -> impl Iterator<Item = (K, V)> {
+        self.index
+            .iter()
+            .rev()
+            .filter_map(|(key, &pos)| match self.read(key, pos) {
+                Ok(value) => Some((K::from(*key), value)),
+                Err(AoraError::Redacted { .. }) => None,
+                Err(err) => panic!("unable to read item: {err}"),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::confinement::SmallVec;
+
+    use super::*;
+    use crate::file::FileAoraMap;
+
+    const MAGIC: u64 = u64::from_be_bytes(*b"DUMBTEST");
+
+    type Db = FileAoraMap<[u8; 8], SmallVec<u8>, MAGIC, 1, 8>;
+    type DynDb = FileAoraMapDyn<[u8; 8], SmallVec<u8>, 8>;
+
+    fn val(no: u64) -> SmallVec<u8> { SmallVec::from_checked(no.to_le_bytes().to_vec()) }
+
+    #[test]
+    fn same_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "test").unwrap();
+        for no in 0..5u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+
+        let mut dyn_db = DynDb::open(dir.path(), "test", MAGIC, 1).unwrap();
+        assert!(!dyn_db.is_read_only());
+        assert_eq!(dyn_db.len(), 5);
+        assert_eq!(dyn_db.get(3u64.to_be_bytes()), Some(val(3)));
+        assert_eq!(dyn_db.get(5u64.to_be_bytes()), None);
+        dyn_db.insert(5u64.to_be_bytes(), &val(5));
+        assert_eq!(
+            dyn_db.iter().map(|(_, v)| v).collect::<Vec<_>>(),
+            (0..6).map(val).collect::<Vec<_>>()
+        );
+        assert_eq!(dyn_db.iter_rev().next(), Some((5u64.to_be_bytes(), val(5))));
+        drop(dyn_db);
+
+        let db = Db::open(dir.path(), "test").unwrap();
+        assert_eq!(db.len(), 6);
+        assert_eq!(db.get(5u64.to_be_bytes()), Some(val(5)));
+        drop(db);
+
+        let mut dyn_db = DynDb::create_new(dir.path(), "other", MAGIC, 1).unwrap();
+        dyn_db.insert(7u64.to_be_bytes(), &val(7));
+        drop(dyn_db);
+        let db = Db::open(dir.path(), "other").unwrap();
+        assert_eq!(db.get(7u64.to_be_bytes()), Some(val(7)));
+    }
+
+    #[test]
+    fn wrong_magic() {
+        let dir = tempfile::tempdir().unwrap();
+        Db::create_new(dir.path(), "test").unwrap();
+        let err = DynDb::open(dir.path(), "test", u64::from_be_bytes(*b"OTHERDB!"), 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = DynDb::open(dir.path(), "test", MAGIC, 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(DynDb::create_new(dir.path(), "test", MAGIC, 1).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn read_only() {
+        use crate::Sha256Hasher;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_verified::<Sha256Hasher>(dir.path(), "test").unwrap();
+        db.insert(1u64.to_be_bytes(), &val(1));
+        drop(db);
+
+        let dyn_db = DynDb::open(dir.path(), "test", MAGIC, 1).unwrap();
+        assert!(dyn_db.is_read_only());
+        assert_eq!(dyn_db.get(1u64.to_be_bytes()), Some(val(1)));
+    }
+}
@@ -0,0 +1,83 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io;
+
+use amplify::hex::ToHex;
+use strict_encoding::DecodeError;
+
+use crate::InvalidKey;
+
+/// Errors happening during the file provider operations, which allow distinguishing data
+/// corruption from other I/O failures.
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum AoraError {
+    /// I/O error: {0}
+    #[from]
+    Io(io::Error),
+
+    /// Item under the key {key} at the log position {pos} can't be decoded: {error}
+    Decode {
+        key: String,
+        pos: u64,
+        error: DecodeError,
+    },
+
+    /// Item under the key {key} at the log position {pos} doesn't match its hash; the log is
+    /// corrupted.
+    HashMismatch { key: String, pos: u64 },
+
+    /// Record under the key {key} at the log position {pos} doesn't commit to the previous record;
+    /// the hash chain is broken.
+    ChainBroken { key: String, pos: u64 },
+
+    /// Item under the key {key} at the log position {pos} can't be decrypted; either the
+    /// encryption key is wrong or the log is corrupted.
+    Decrypt { key: String, pos: u64 },
+
+    /// Item under the key {key} at the log position {pos} can't be decompressed; the log is
+    /// corrupted.
+    Decompress { key: String, pos: u64 },
+
+    /// Item under the key {key} at the log position {pos} was redacted.
+    Redacted { key: String, pos: u64 },
+
+    /// Data key of the item under the key {key} at the log position {pos} was destroyed, so the
+    /// item can't be decrypted anymore.
+    Shredded { key: String, pos: u64 },
+
+    /// Key {key} read from the storage is invalid; the data are corrupted.
+    InvalidKey { key: String },
+
+    /// Transaction page {page} doesn't have a valid signature.
+    BadSignature { page: u64 },
+
+    /// Fork of the table '{table}' was taken after {base} committed transactions, while the
+    /// table has {current} of them now; the fork is outdated.
+    ForkOutdated {
+        table: String,
+        base: u64,
+        current: u64,
+    },
+
+    /// Transaction {txno} from a delta file or a backup can't be applied to the table '{table}',
+    /// which has {current} committed transactions and doesn't have the same transaction.
+    DeltaOutOfOrder {
+        table: String,
+        txno: u64,
+        current: u64,
+    },
+
+    /// Table '{table}' has a pending transaction, which must be committed or aborted first.
+    PendingTransaction { table: String },
+
+    /// Table '{table}' is poisoned by a failed save and must be recovered before committing.
+    Poisoned { table: String },
+
+    /// Table '{table}' would grow to {size} bytes, exceeding its quota of {limit} bytes.
+    QuotaExceeded { table: String, size: u64, limit: u64 },
+}
+
+impl<const LEN: usize> From<InvalidKey<LEN>> for AoraError {
+    fn from(err: InvalidKey<LEN>) -> Self { Self::InvalidKey { key: err.0.to_hex() } }
+}
@@ -0,0 +1,688 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use binfile::BinFile;
+
+#[cfg(feature = "encryption")]
+use super::crypto::SecretKey;
+use crate::AoraHasher;
+
+/// Length of a wrapped data key of a record in a shreddable log: the nonce, the encrypted key and
+/// the authentication tag.
+pub(crate) const WRAPPED_KEY_LEN: usize = 24 + 32 + 16;
+
+/// Hash function used by the file providers.
+pub(crate) type HashFn = fn(&[u8]) -> [u8; 32];
+
+/// Options for creating and opening [`super::FileAoraMap`] logs.
+///
+/// Some of the options define the format of the log records; they are persisted when the log is
+/// created and must be matched by the options used to open it later.
+#[derive(Clone, Debug, Default)]
+pub struct LogOptions {
+    pub(crate) hasher: Option<HashFn>,
+    pub(crate) format: LogFormat,
+    pub(crate) segment_size: Option<u64>,
+    pub(crate) bloom: Option<(u64, f64)>,
+    pub(crate) sparse_step: Option<u64>,
+    pub(crate) capacity: Option<(u64, u64)>,
+    pub(crate) write_through: bool,
+    pub(crate) idx_chunk: Option<usize>,
+    #[cfg(target_os = "linux")]
+    pub(crate) direct_io: bool,
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub(crate) io_uring: Option<u32>,
+    #[cfg(feature = "notify")]
+    pub(crate) watch: bool,
+}
+
+impl LogOptions {
+    /// Creates default options, which produce logs in the original (unframed) format.
+    pub fn new() -> Self { Self::default() }
+
+    /// Makes each appended record commit to the hash of the previous record, producing a
+    /// tamper-evident log. The provided hasher is used to compute the record hashes.
+    pub fn hash_chain<H: AoraHasher>(mut self) -> Self {
+        self.hasher = Some(H::digest);
+        self.format.chained = true;
+        self
+    }
+
+    /// Splits the log into segment files, starting a new segment once the active one grows
+    /// above the given size in bytes. The closed segments are never modified again, so they can
+    /// be backed up, compressed or archived independently.
+    ///
+    /// The segment size is persisted in the segment manifest and is used when the log is opened.
+    pub fn segment_size(mut self, size: u64) -> Self {
+        self.segment_size = Some(size);
+        self
+    }
+
+    /// Maintains a Bloom filter of the keys in a `.bloom` file next to the index, which allows
+    /// answering most of the lookups for the absent keys without touching the index. The filter
+    /// is sized for the expected number of items and the target false positive rate.
+    ///
+    /// Once created, the filter is used whenever the log is opened. If the log doesn't have a
+    /// filter yet, it is built from the existing index on open. The keys are added to the filter
+    /// before their records are appended, so an interrupted append can only leave a false
+    /// positive in the filter, never a missed key.
+    pub fn bloom_filter(mut self, expected_items: u64, false_positive_rate: f64) -> Self {
+        self.bloom = Some((expected_items, false_positive_rate));
+        self
+    }
+
+    /// Keeps in memory only every `step`-th key of the index instead of all of them, looking up
+    /// the rest in a sorted copy of the index persisted in a `.sidx` file next to the index. This
+    /// trades a little lookup latency for a memory use reduced roughly `step` times.
+    ///
+    /// The option is not persisted: a log can be opened with or without the sparse index, and
+    /// the sorted index file is updated with the new keys on open. Can't be used together with
+    /// encrypted index keys.
+    pub fn sparse_index(mut self, step: u64) -> Self {
+        self.sparse_step = Some(step);
+        self
+    }
+
+    /// Hints that the given number of keys with the given total size of the values in bytes is
+    /// going to be appended. The index and log files get disk space preallocated for them (on
+    /// the platforms supporting it), and the in-memory index reserves the capacity for the keys,
+    /// avoiding file fragmentation and repeated index rehashing during large imports.
+    pub fn with_capacity(mut self, keys: u64, bytes: u64) -> Self {
+        self.capacity = Some((keys, bytes));
+        self
+    }
+
+    /// Appends to the log and index files (and the data key file of a shreddable log) through
+    /// handles writing down to the storage device (`O_DSYNC` on Unix, `FILE_FLAG_WRITE_THROUGH` on
+    /// Windows), so the record of each insert is durable once it returns, at the cost of the write
+    /// throughput.
+    ///
+    /// The value hash, Bloom filter and tombstone files are still written through the buffered
+    /// handles and get durable only with [`super::FileAoraMap::sync`], so a crash may leave them
+    /// behind the log and the index.
+    ///
+    /// The option is not persisted. Can't be used with segmented logs, direct I/O or io_uring.
+    pub fn write_through(mut self) -> Self {
+        self.write_through = true;
+        self
+    }
+
+    /// Buffers the index entries in memory, writing them to the index file in chunks of the given
+    /// size in bytes instead of one small write per insert. The buffer is also written out by
+    /// [`super::FileAoraMap::flush`], [`super::FileAoraMap::sync`] and when the map is closed or
+    /// dropped; the records whose entries were lost in a crash are reported as an unindexed tail
+    /// of the log by the consistency check.
+    ///
+    /// The option is not persisted. Can't be used with sparse index, write-through, io_uring or
+    /// shreddable logs, whose data keys are written with each insert.
+    pub fn coalesce_index(mut self, chunk: usize) -> Self {
+        self.idx_chunk = Some(chunk);
+        self
+    }
+
+    /// Appends to the log file opened with `O_DIRECT`, so large sequential appends don't pollute
+    /// the page cache, which is useful for the applications managing their own caches. The reads
+    /// are still buffered.
+    ///
+    /// Since the unbuffered writes must be block-aligned, the appends write only the filled
+    /// blocks, and the last partially filled block of the log is written on flush, sync, reads of
+    /// the log and closing of the map. The zero padding left by an interrupted write of that block
+    /// is trimmed when the map is opened. The option is not persisted. Can't be used with
+    /// segmented logs or write-through.
+    #[cfg(target_os = "linux")]
+    pub fn direct_io(mut self) -> Self {
+        self.direct_io = true;
+        self
+    }
+
+    /// Performs the log I/O through an io_uring instance with the submission queue of the given
+    /// depth: the writes of each appended record and its value hash are submitted together,
+    /// followed by the write of its index entry once they complete, and
+    /// [`super::FileAoraMap::get_many`] reads all the requested records with a single batch of
+    /// requests.
+    ///
+    /// The option is not persisted. Can't be used with segmented logs.
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub fn io_uring(mut self, entries: u32) -> Self {
+        self.io_uring = Some(entries);
+        self
+    }
+
+    /// Watches the log and index files for the records appended by other processes, so that
+    /// [`super::FileAoraMap::refresh_if_changed`] picks them up only when the files have changed.
+    ///
+    /// The option is not persisted. Can't be used with segmented logs.
+    #[cfg(feature = "notify")]
+    pub fn watch_changes(mut self) -> Self {
+        self.watch = true;
+        self
+    }
+
+    /// Encrypts the values at rest with XChaCha20-Poly1305 using the provided secret key. The
+    /// same key must be provided when the log is opened.
+    ///
+    /// The values are authenticated together with their keys, so a record can't be moved under
+    /// another key without being detected.
+    ///
+    /// The indexes are encrypted with [`super::FileAoraIndex::create_encrypted`] instead, while
+    /// the [`super::FileAuraMap`] logs, whose pages are appended and truncated in place, are
+    /// always stored unencrypted.
+    #[cfg(feature = "encryption")]
+    pub fn encrypt(mut self, key: [u8; 32]) -> Self {
+        self.format.key = Some(SecretKey(key));
+        self.format.encrypted = true;
+        self
+    }
+
+    /// Encrypts each value with its own random data key, which is stored encrypted with the
+    /// secret key in a `.dek` file following the order of the index entries. Destroying the data
+    /// key with [`super::FileAoraMap::shred`] makes the value unrecoverable without rewriting the
+    /// log. Requires [`Self::encrypt`].
+    ///
+    /// The data keys are kept in their own file rather than in the index entries, so the index
+    /// file keeps the same fixed-size entries as in the logs without shredding. The `.dek` file is
+    /// written before the index, synced with it and appended through a write-through handle
+    /// together with it (see [`Self::write_through`]).
+    #[cfg(feature = "encryption")]
+    pub fn shreddable(mut self) -> Self {
+        self.format.shreddable = true;
+        self
+    }
+
+    /// Additionally encrypts the keys stored in the index file. Requires [`Self::encrypt`].
+    ///
+    /// Since the keys must keep their fixed length, they are encrypted with XChaCha20 stream
+    /// cipher without authentication; their integrity is still protected by the authentication
+    /// of the values.
+    #[cfg(feature = "encryption")]
+    pub fn encrypt_keys(mut self) -> Self {
+        self.format.sealed_keys = true;
+        self.format.nonce = SecretKey::nonce();
+        self
+    }
+
+    /// Compresses the values with zstd using the given compression level. Records which don't
+    /// become smaller are stored uncompressed.
+    #[cfg(feature = "zstd")]
+    pub fn zstd(mut self, level: i32) -> Self {
+        self.format.compressed = true;
+        self.format.codec = Codec::Zstd;
+        self.format.level = level;
+        self
+    }
+
+    /// Compresses the values with LZ4, which is less efficient than zstd, but much faster to
+    /// decompress, making it suitable for latency-sensitive reads. Records which don't become
+    /// smaller are stored uncompressed.
+    ///
+    /// The codec selected at the log creation is persisted, so the log must be opened with the
+    /// same one.
+    #[cfg(feature = "lz4")]
+    pub fn lz4(mut self) -> Self {
+        self.format.compressed = true;
+        self.format.codec = Codec::Lz4;
+        self
+    }
+
+    /// Uses a dictionary for zstd compression, which significantly improves the compression of
+    /// small and similar values. The dictionary can be trained with
+    /// [`super::FileAoraMap::train_zstd_dictionary`] and is persisted together with the log, so it
+    /// can be omitted when the log is opened, but a different dictionary is rejected.
+    #[cfg(feature = "zstd")]
+    pub fn zstd_dictionary(mut self, dict: Vec<u8>) -> Self {
+        self.format.dict = Some(dict.into());
+        self
+    }
+
+    /// Embeds the identifier of the schema of the values, such as the strict-types semantic id
+    /// of the value type, into the log. The log can be opened only with the same identifier, so
+    /// a change of the value type is detected instead of producing wrong decodes.
+    ///
+    /// The values stay in the original format unless other format options are used.
+    pub fn schema(mut self, id: impl Into<[u8; 32]>) -> Self {
+        self.format.schema = Some(id.into());
+        self
+    }
+
+    /// Stores the time each record was appended to the log in the record header, so it can be
+    /// retrieved with [`super::FileAoraMap::inserted_at`] and
+    /// [`super::FileAoraMap::iter_timed`] without maintaining a separate map of the insertion
+    /// times.
+    pub fn timestamps(mut self) -> Self {
+        self.format.timestamped = true;
+        self
+    }
+
+    /// Reserves a flags byte in each record header, telling whether the record is compressed,
+    /// encrypted, superseded or redacted, and holding four user bits. The user bits can be
+    /// changed after the record is appended with [`super::FileAoraMap::set_user_flags`], while
+    /// the value itself stays immutable.
+    ///
+    /// The flags are not covered by the hash chain and the value authentication, so they must not
+    /// be used to store security-sensitive information.
+    pub fn record_flags(mut self) -> Self {
+        self.format.flagged = true;
+        self
+    }
+}
+
+/// Flags of a log record, stored in the record header if the log is created with
+/// [`LogOptions::record_flags`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct RecordFlags(pub(crate) u8);
+
+impl RecordFlags {
+    pub(crate) const COMPRESSED: u8 = 0x01;
+    pub(crate) const ENCRYPTED: u8 = 0x02;
+    pub(crate) const SUPERSEDED: u8 = 0x04;
+    pub(crate) const REDACTED: u8 = 0x08;
+    /// Bits which can be changed by the user after the record is appended.
+    pub(crate) const USER: u8 = 0xF0;
+
+    /// Returns the raw value of the flags byte.
+    pub fn bits(self) -> u8 { self.0 }
+
+    /// Checks whether the record payload is compressed.
+    pub fn is_compressed(self) -> bool { self.0 & Self::COMPRESSED != 0 }
+
+    /// Checks whether the record payload is encrypted.
+    pub fn is_encrypted(self) -> bool { self.0 & Self::ENCRYPTED != 0 }
+
+    /// Checks whether the record is superseded.
+    pub fn is_superseded(self) -> bool { self.0 & Self::SUPERSEDED != 0 }
+
+    /// Checks whether the record payload was redacted.
+    pub fn is_redacted(self) -> bool { self.0 & Self::REDACTED != 0 }
+
+    /// Returns the four user bits, in the range `0..=0x0F`.
+    pub fn user(self) -> u8 { self.0 >> 4 }
+}
+
+/// Compression codec of a record, stored in the record header.
+#[repr(u8)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub(crate) enum Codec {
+    /// The record is not compressed.
+    #[default]
+    Raw = 0,
+    /// The record is compressed with zstd.
+    Zstd = 1,
+    /// The record is compressed with LZ4.
+    Lz4 = 2,
+}
+
+impl TryFrom<u8> for Codec {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Raw),
+            1 => Ok(Self::Zstd),
+            2 => Ok(Self::Lz4),
+            _ => Err(value),
+        }
+    }
+}
+
+/// Format of the log records, persisted in a `.meta` file next to the log. Logs which don't have
+/// the file use the original format, where the records are stored without headers.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub(crate) struct LogFormat {
+    /// Each record header contains the hash of the previous record.
+    pub chained: bool,
+    /// Record payloads are encrypted.
+    pub encrypted: bool,
+    /// Keys in the index file are encrypted.
+    pub sealed_keys: bool,
+    /// Record payloads are encrypted with per-record data keys.
+    pub shreddable: bool,
+    /// Nonce of the keystream used to encrypt the index keys.
+    pub nonce: [u8; 24],
+    /// Encryption key, which is not persisted.
+    #[cfg(feature = "encryption")]
+    pub key: Option<SecretKey>,
+    /// Record headers contain the compression codec.
+    pub compressed: bool,
+    /// Codec used to compress new records. The codec selected at the log creation is persisted.
+    pub codec: Codec,
+    /// Compression level, which is not persisted.
+    pub level: i32,
+    /// Dictionary for zstd compression.
+    pub dict: Option<Arc<[u8]>>,
+    /// Identifier of the schema of the values, verified when the log is opened.
+    pub schema: Option<[u8; 32]>,
+    /// Record headers contain the time the record was appended.
+    pub timestamped: bool,
+    /// Record headers contain the record flags.
+    pub flagged: bool,
+}
+
+impl LogFormat {
+    const CHAINED: u16 = 0x0001;
+    const ENCRYPTED: u16 = 0x0002;
+    const SEALED_KEYS: u16 = 0x0004;
+    const COMPRESSED: u16 = 0x0008;
+    const SCHEMA: u16 = 0x0010;
+    const TIMESTAMPED: u16 = 0x0020;
+    const FLAGGED: u16 = 0x0040;
+    const SHREDDABLE: u16 = 0x0080;
+
+    const KNOWN: u16 = Self::CHAINED
+        | Self::SCHEMA
+        | Self::TIMESTAMPED
+        | Self::FLAGGED
+        | if cfg!(feature = "encryption") {
+            Self::ENCRYPTED | Self::SEALED_KEYS | Self::SHREDDABLE
+        } else {
+            0
+        }
+        | if cfg!(any(feature = "zstd", feature = "lz4")) { Self::COMPRESSED } else { 0 };
+
+    /// Checks whether the records are prefixed with a header.
+    pub fn is_framed(&self) -> bool { self.flags() & !Self::SCHEMA != 0 }
+
+    pub fn flags(&self) -> u16 {
+        let mut flags = 0;
+        if self.chained {
+            flags |= Self::CHAINED;
+        }
+        if self.encrypted {
+            flags |= Self::ENCRYPTED;
+        }
+        if self.sealed_keys {
+            flags |= Self::SEALED_KEYS;
+        }
+        if self.shreddable {
+            flags |= Self::SHREDDABLE;
+        }
+        if self.compressed {
+            flags |= Self::COMPRESSED;
+        }
+        if self.schema.is_some() {
+            flags |= Self::SCHEMA;
+        }
+        if self.timestamped {
+            flags |= Self::TIMESTAMPED;
+        }
+        if self.flagged {
+            flags |= Self::FLAGGED;
+        }
+        flags
+    }
+
+    pub fn load<const MAGIC: u64, const VER: u16>(path: &Path) -> io::Result<Self> {
+        if !fs::exists(path)? {
+            return Ok(Self::default());
+        }
+        let mut file = BinFile::<MAGIC, VER>::open(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("format file '{}'", path.display())))?;
+        Self::read(&mut *file, path)
+    }
+
+    /// Reads the format from the content of the format file following its header.
+    pub fn read(file: &mut impl Read, path: &Path) -> io::Result<Self> {
+        let mut buf = [0u8; 2];
+        file.read_exact(&mut buf)?;
+        let flags = u16::from_le_bytes(buf);
+        if flags & !Self::KNOWN != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("format file '{}' contains unknown flags {flags:#06x}", path.display()),
+            ));
+        }
+        let mut nonce = [0u8; 24];
+        if flags & Self::SEALED_KEYS != 0 {
+            file.read_exact(&mut nonce)?;
+        }
+        let mut codec = Codec::Raw;
+        let mut dict = None;
+        if flags & Self::COMPRESSED != 0 {
+            let mut buf = [0u8; 1];
+            file.read_exact(&mut buf)?;
+            codec = Codec::try_from(buf[0]).map_err(|codec| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("format file '{}' specifies unknown codec {codec}", path.display()),
+                )
+            })?;
+            let mut buf = [0u8; 4];
+            file.read_exact(&mut buf)?;
+            let mut data = vec![0u8; u32::from_le_bytes(buf) as usize];
+            file.read_exact(&mut data)?;
+            dict = (!data.is_empty()).then(|| data.into());
+        }
+        let mut schema = None;
+        if flags & Self::SCHEMA != 0 {
+            let mut id = [0u8; 32];
+            file.read_exact(&mut id)?;
+            schema = Some(id);
+        }
+        Ok(Self {
+            chained: flags & Self::CHAINED != 0,
+            encrypted: flags & Self::ENCRYPTED != 0,
+            sealed_keys: flags & Self::SEALED_KEYS != 0,
+            shreddable: flags & Self::SHREDDABLE != 0,
+            nonce,
+            #[cfg(feature = "encryption")]
+            key: None,
+            compressed: flags & Self::COMPRESSED != 0,
+            codec,
+            level: 0,
+            dict,
+            schema,
+            timestamped: flags & Self::TIMESTAMPED != 0,
+            flagged: flags & Self::FLAGGED != 0,
+        })
+    }
+
+    pub fn save<const MAGIC: u64, const VER: u16>(&self, path: &Path) -> io::Result<()> {
+        if self.flags() == 0 {
+            return Ok(());
+        }
+        let mut file = BinFile::<MAGIC, VER>::create_new(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("format file '{}'", path.display())))?;
+        file.write_all(&self.flags().to_le_bytes())?;
+        if self.sealed_keys {
+            file.write_all(&self.nonce)?;
+        }
+        if self.compressed {
+            file.write_all(&[self.codec as u8])?;
+            let dict = self.dict.as_deref().unwrap_or_default();
+            file.write_all(&(dict.len() as u32).to_le_bytes())?;
+            file.write_all(dict)?;
+        }
+        if let Some(id) = &self.schema {
+            file.write_all(id)?;
+        }
+        Ok(())
+    }
+
+    /// Compresses the encoded value, if the log is compressed and the compression makes the
+    /// value smaller.
+    pub fn compress(&self, data: Vec<u8>) -> (Codec, Vec<u8>) {
+        let compressed: Option<Vec<u8>> = match self.codec {
+            Codec::Raw => None,
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => Some(
+                match &self.dict {
+                    Some(dict) => zstd::bulk::Compressor::with_dictionary(self.level, dict)
+                        .and_then(|mut compressor| compressor.compress(&data)),
+                    None => zstd::bulk::compress(&data, self.level),
+                }
+                .expect("unable to compress item"),
+            ),
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => Some(lz4_flex::compress_prepend_size(&data)),
+            #[allow(unreachable_patterns)]
+            _ => unreachable!("compression codec is not supported"),
+        };
+        match compressed {
+            Some(compressed) if compressed.len() < data.len() => (self.codec, compressed),
+            _ => (Codec::Raw, data),
+        }
+    }
+
+    /// Decompresses the record payload. Returns `None` if the payload is corrupted.
+    pub fn decompress(&self, codec: Codec, payload: Vec<u8>) -> Option<Vec<u8>> {
+        match codec {
+            Codec::Raw => Some(payload),
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => {
+                let dict = self.dict.as_deref().unwrap_or_default();
+                let mut decoder =
+                    zstd::stream::read::Decoder::with_dictionary(payload.as_slice(), dict).ok()?;
+                let mut data = Vec::new();
+                decoder.read_to_end(&mut data).ok()?;
+                Some(data)
+            }
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => lz4_flex::decompress_size_prepended(&payload).ok(),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    /// Computes the hash of the record, to which the next record in a hash-chained log commits.
+    pub fn link(
+        &self,
+        header: &RecordHeader,
+        hasher: HashFn,
+        key: &[u8],
+        payload: &[u8],
+    ) -> [u8; 32] {
+        let mut data = Vec::with_capacity(41 + key.len() + payload.len());
+        data.extend_from_slice(&header.prev);
+        data.extend_from_slice(key);
+        if self.compressed {
+            data.push(header.codec as u8);
+        }
+        if self.timestamped {
+            data.extend_from_slice(&header.time.to_le_bytes());
+        }
+        data.extend_from_slice(payload);
+        hasher(&data)
+    }
+
+    /// Encrypts the record payload, if the log is encrypted. If the log is shreddable, the
+    /// payload is encrypted with a new random data key, which is returned wrapped with the secret
+    /// key.
+    pub fn seal(&self, key: &[u8], data: Vec<u8>) -> (Vec<u8>, Option<[u8; WRAPPED_KEY_LEN]>) {
+        #[cfg(feature = "encryption")]
+        if self.shreddable {
+            let data_key = SecretKey::random();
+            let wrapped = self.secret().seal(key, &data_key.0);
+            let wrapped = wrapped.try_into().expect("wrapped key has a fixed length");
+            return (data_key.seal(key, &data), Some(wrapped));
+        }
+        #[cfg(feature = "encryption")]
+        if self.encrypted {
+            return (self.secret().seal(key, &data), None);
+        }
+        let _ = key;
+        (data, None)
+    }
+
+    /// Decrypts the record payload, if the log is encrypted, using the wrapped data key of the
+    /// record if the log is shreddable. Returns `None` if the payload or the data key can't be
+    /// authenticated.
+    pub fn unseal(
+        &self,
+        key: &[u8],
+        payload: Vec<u8>,
+        wrapped: Option<&[u8; WRAPPED_KEY_LEN]>,
+    ) -> Option<Vec<u8>> {
+        #[cfg(feature = "encryption")]
+        if self.encrypted {
+            let secret = match wrapped {
+                Some(wrapped) => SecretKey(self.secret().open(key, wrapped)?.try_into().ok()?),
+                None => *self.secret(),
+            };
+            return secret.open(key, &payload);
+        }
+        let _ = (key, wrapped);
+        Some(payload)
+    }
+
+    /// Encrypts or decrypts a key stored in the index file under the given number, if the index
+    /// keys are encrypted.
+    pub fn seal_key(&self, no: usize, key: &mut [u8]) {
+        #[cfg(feature = "encryption")]
+        if self.sealed_keys {
+            let offset = no as u64 * key.len() as u64;
+            self.secret().apply_keystream(&self.nonce, offset, key);
+        }
+        let _ = (no, key);
+    }
+
+    #[cfg(feature = "encryption")]
+    fn secret(&self) -> &SecretKey { self.key.as_ref().expect("encryption key is not provided") }
+
+    pub fn read_header(&self, reader: &mut impl Read) -> io::Result<RecordHeader> {
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf)?;
+        let len = u32::from_le_bytes(buf);
+        let mut flags = RecordFlags::default();
+        if self.flagged {
+            let mut buf = [0u8; 1];
+            reader.read_exact(&mut buf)?;
+            flags = RecordFlags(buf[0]);
+        }
+        let mut codec = Codec::Raw;
+        if self.compressed {
+            let mut buf = [0u8; 1];
+            reader.read_exact(&mut buf)?;
+            codec = Codec::try_from(buf[0]).map_err(|codec| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("unknown record codec {codec}"))
+            })?;
+        }
+        let mut time = 0;
+        if self.timestamped {
+            let mut buf = [0u8; 8];
+            reader.read_exact(&mut buf)?;
+            time = u64::from_le_bytes(buf);
+        }
+        let mut prev = [0u8; 32];
+        if self.chained {
+            reader.read_exact(&mut prev)?;
+        }
+        Ok(RecordHeader { len, flags, codec, time, prev })
+    }
+
+    pub fn write_header(&self, writer: &mut impl Write, header: &RecordHeader) -> io::Result<()> {
+        writer.write_all(&header.len.to_le_bytes())?;
+        if self.flagged {
+            writer.write_all(&[header.flags.0])?;
+        }
+        if self.compressed {
+            writer.write_all(&[header.codec as u8])?;
+        }
+        if self.timestamped {
+            writer.write_all(&header.time.to_le_bytes())?;
+        }
+        if self.chained {
+            writer.write_all(&header.prev)?;
+        }
+        Ok(())
+    }
+}
+
+/// Header of a record in the framed log format.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct RecordHeader {
+    /// Length of the record payload (the encoded value).
+    pub len: u32,
+    /// Flags of the record, if the log stores them. They directly follow the length, so they can
+    /// be updated in place.
+    pub flags: RecordFlags,
+    /// Compression codec of the payload, if the log is compressed.
+    pub codec: Codec,
+    /// Time the record was appended, in nanoseconds since the Unix epoch, if the log is
+    /// timestamped.
+    pub time: u64,
+    /// Hash of the previous record, if the log is hash-chained.
+    pub prev: [u8; 32],
+}
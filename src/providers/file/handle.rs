@@ -0,0 +1,29 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// Windows `FILE_FLAG_WRITE_THROUGH` flag.
+#[cfg(windows)]
+const FILE_FLAG_WRITE_THROUGH: u32 = 0x8000_0000;
+
+/// Opens the file for reading, allowing the other handles to keep writing to, and replacing the
+/// file at the same time.
+///
+/// This is the default on Unix, and the standard library opens the files on Windows with the
+/// share mode allowing it as well (`FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE`).
+pub(crate) fn open_shared(path: &Path) -> io::Result<File> { File::open(path) }
+
+/// Opens the file for appending with the writes going through the operating system caches down
+/// to the storage device (`O_DSYNC` on Unix, `FILE_FLAG_WRITE_THROUGH` on Windows), so that each
+/// write is durable once it returns.
+pub(crate) fn open_write_through(path: &Path) -> io::Result<File> {
+    let mut opts = OpenOptions::new();
+    opts.append(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::custom_flags(&mut opts, libc::O_DSYNC);
+    #[cfg(windows)]
+    std::os::windows::fs::OpenOptionsExt::custom_flags(&mut opts, FILE_FLAG_WRITE_THROUGH);
+    opts.open(path)
+}
@@ -1,17 +1,31 @@
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::{self, Read, Write};
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 use binfile::BinFile;
-use indexmap::IndexSet;
+use indexmap::IndexMap;
 
-use crate::AoraIndex;
+#[cfg(feature = "encryption")]
+use super::crypto::SecretKey;
+use super::observer::{AoraObserver, Observer};
+use super::{AoraError, telemetry};
+use crate::{AoraIndex, AoraKey};
 
-// For now, this is just an in-memory read BTree. In the next releases we need to change this.
+// For now, this is just an in-memory read BTree, sorted by the key bytes. In the next releases we
+// need to change this.
+/// File-based [`AoraIndex`] provider.
+///
+/// The index is ordered: the values under each key are stored in the index file in the order they
+/// were first pushed, and are read back in the same order when the index is opened, so
+/// [`AoraIndex::get`] and [`AoraIndex::get_ordered`] yield them as a sequence of events.
+///
+/// With the `encryption` feature, the index can be encrypted at rest with
+/// [`Self::create_encrypted`].
 #[derive(Debug)]
 pub struct FileAoraIndex<
     K,
@@ -21,27 +35,92 @@ pub struct FileAoraIndex<
     const KEY_LEN: usize = 32,
     const VAL_LEN: usize = 32,
 > where
-    K: From<[u8; KEY_LEN]> + Into<[u8; KEY_LEN]>,
-    V: From<[u8; VAL_LEN]> + Into<[u8; VAL_LEN]>,
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
 {
     path: PathBuf,
-    cache: HashMap<[u8; KEY_LEN], IndexSet<[u8; VAL_LEN]>>,
+    /// Values under each key with the number of times they were pushed.
+    cache: BTreeMap<[u8; KEY_LEN], IndexMap<[u8; VAL_LEN], u32>>,
+    /// Whether the repeated pushes of a value are counted, rather than ignored.
+    counted: bool,
+    /// Secret key the index file is encrypted with, if any.
+    #[cfg(feature = "encryption")]
+    key: Option<SecretKey>,
+    observer: Option<Observer>,
     _phantom: PhantomData<(K, V)>,
 }
 
 impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize, const VAL_LEN: usize>
     FileAoraIndex<K, V, MAGIC, VER, KEY_LEN, VAL_LEN>
 where
-    K: From<[u8; KEY_LEN]> + Into<[u8; KEY_LEN]>,
-    V: From<[u8; VAL_LEN]> + Into<[u8; VAL_LEN]>,
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
 {
-    fn prepare(path: impl AsRef<Path>, name: &str) -> PathBuf {
+    fn prepare(path: impl AsRef<Path>, name: &str, counted: bool) -> PathBuf {
         let path = path.as_ref();
-        path.join(name).with_extension("dat")
+        path.join(name).with_extension(if counted { "cdat" } else { "dat" })
     }
 
+    #[cfg(feature = "encryption")]
+    fn encrypted_path(path: impl AsRef<Path>, name: &str) -> PathBuf {
+        path.as_ref().join(name).with_extension("edat")
+    }
+
+    fn with_cache(
+        path: PathBuf,
+        cache: BTreeMap<[u8; KEY_LEN], IndexMap<[u8; VAL_LEN], u32>>,
+        counted: bool,
+    ) -> Self {
+        Self {
+            path,
+            cache,
+            counted,
+            #[cfg(feature = "encryption")]
+            key: None,
+            observer: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(path), err))]
     pub fn create_new(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
-        let path = Self::prepare(path, name);
+        Self::create_mode(path, name, false)
+    }
+
+    /// Creates a counted index, which keeps track of how many times each value was pushed under
+    /// a key, for the reference-counting indexes where the same relation is asserted by several
+    /// sources. The counts are reported by [`Self::value_count`], while the value iterators still
+    /// yield each value once.
+    ///
+    /// The counted index is stored in a `.cdat` file with the counts following the values, so it
+    /// can be opened only with [`Self::open_counted`].
+    pub fn create_counted(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        Self::create_mode(path, name, true)
+    }
+
+    /// Creates an index encrypted at rest with XChaCha20-Poly1305 using the provided secret key,
+    /// which must be provided when the index is opened with [`Self::open_encrypted`].
+    ///
+    /// Since the index file is re-written on each push, it is sealed as a whole into a `.edat`
+    /// file, which also records whether the index is counted (see [`Self::create_counted`]).
+    #[cfg(feature = "encryption")]
+    pub fn create_encrypted(
+        path: impl AsRef<Path>,
+        name: &str,
+        key: [u8; 32],
+        counted: bool,
+    ) -> io::Result<Self> {
+        let mut me = Self::create_at(Self::encrypted_path(path, name), counted)?;
+        me.key = Some(SecretKey(key));
+        me.save()?;
+        Ok(me)
+    }
+
+    fn create_mode(path: impl AsRef<Path>, name: &str, counted: bool) -> io::Result<Self> {
+        Self::create_at(Self::prepare(path, name, counted), counted)
+    }
+
+    fn create_at(path: PathBuf, counted: bool) -> io::Result<Self> {
         if fs::exists(&path)? {
             return Err(io::Error::new(
                 io::ErrorKind::AlreadyExists,
@@ -49,69 +128,286 @@ where
             ));
         }
         BinFile::<MAGIC, VER>::create_new(&path)?;
-        Ok(Self { cache: HashMap::new(), path, _phantom: PhantomData })
+        Ok(Self::with_cache(path, BTreeMap::new(), counted))
     }
 
     pub fn open_or_create(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
-        let path = Self::prepare(path, name);
-        if !fs::exists(&path)? { Self::create_new(path, name) } else { Self::open(path, name) }
+        let path = path.as_ref();
+        if !fs::exists(Self::prepare(path, name, false))? {
+            Self::create_new(path, name)
+        } else {
+            Self::open(path, name)
+        }
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(path), err))]
     pub fn open(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
-        let path = Self::prepare(path, name);
-        let mut cache = HashMap::new();
+        Self::open_mode(path, name, false)
+    }
 
-        if !fs::exists(&path)? {
+    /// Opens a counted index created with [`Self::create_counted`].
+    pub fn open_counted(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        Self::open_mode(path, name, true)
+    }
+
+    /// Opens an encrypted index created with [`Self::create_encrypted`].
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the key is wrong or the file is corrupted.
+    #[cfg(feature = "encryption")]
+    pub fn open_encrypted(path: impl AsRef<Path>, name: &str, key: [u8; 32]) -> io::Result<Self> {
+        let path = Self::encrypted_path(path, name);
+        let mut sealed = Vec::new();
+        Self::open_file(&path)?.read_to_end(&mut sealed)?;
+        let key = SecretKey(key);
+        let data = key.open(&MAGIC.to_le_bytes(), &sealed).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "index file '{}' can't be decrypted; either the key is wrong or the file is \
+                     corrupted",
+                    path.display()
+                ),
+            )
+        })?;
+        let (counted, mut data) = data.split_first().ok_or(io::ErrorKind::InvalidData)?;
+        let counted = *counted != 0;
+        let cache = Self::decode(&mut data, counted)?;
+        let mut me = Self::with_cache(path, cache, counted);
+        me.key = Some(key);
+        Ok(me)
+    }
+
+    fn open_mode(path: impl AsRef<Path>, name: &str, counted: bool) -> io::Result<Self> {
+        let path = Self::prepare(path, name, counted);
+        let mut file = Self::open_file(&path)?;
+        let cache = Self::decode(&mut *file, counted)?;
+        Ok(Self::with_cache(path, cache, counted))
+    }
+
+    fn open_file(path: &Path) -> io::Result<BinFile<MAGIC, VER>> {
+        if !fs::exists(path)? {
             return Err(io::Error::new(
                 io::ErrorKind::AlreadyExists,
                 format!("index file '{}' does not exist", path.display()),
             ));
         }
-        let mut file = BinFile::<MAGIC, VER>::open(&path)?;
+        BinFile::<MAGIC, VER>::open(path)
+    }
+
+    /// Reads the keys, each followed by the number of its values and the values (with their
+    /// counts, if the index is counted).
+    fn decode(
+        file: &mut impl Read,
+        counted: bool,
+    ) -> io::Result<BTreeMap<[u8; KEY_LEN], IndexMap<[u8; VAL_LEN], u32>>> {
+        let mut cache = BTreeMap::new();
         let mut key_buf = [0u8; KEY_LEN];
         let mut val_buf = [0u8; VAL_LEN];
+        let mut buf = [0u8; 4];
         while file.read_exact(&mut key_buf).is_ok() {
-            let mut values = IndexSet::new();
-            let mut len = [0u8; 4];
-            file.read_exact(&mut len)?;
-            let mut len = u32::from_le_bytes(len);
+            let mut values = IndexMap::new();
+            file.read_exact(&mut buf)?;
+            let mut len = u32::from_le_bytes(buf);
             while len > 0 {
                 file.read_exact(&mut val_buf)?;
-                let res = values.insert(val_buf);
-                debug_assert!(res, "duplicate id in index file");
+                let count = if counted {
+                    file.read_exact(&mut buf)?;
+                    u32::from_le_bytes(buf)
+                } else {
+                    1
+                };
+                let res = values.insert(val_buf, count);
+                debug_assert!(res.is_none(), "duplicate id in index file");
                 len -= 1;
             }
             cache.insert(key_buf, values);
         }
-        Ok(Self { path, cache, _phantom: PhantomData })
+        Ok(cache)
+    }
+
+    /// Writes the keys in the format read by [`Self::decode`].
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        for (key, values) in &self.cache {
+            writer.write_all(key)?;
+            let len = values.len() as u32;
+            writer.write_all(&len.to_le_bytes())?;
+            for (value, count) in values {
+                writer.write_all(value)?;
+                if self.counted {
+                    writer.write_all(&count.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the index data sealed with the secret key, if the index is encrypted.
+    fn sealed(&self) -> io::Result<Option<Vec<u8>>> {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.key {
+            let mut data = vec![self.counted as u8];
+            self.encode(&mut data)?;
+            return Ok(Some(key.seal(&MAGIC.to_le_bytes(), &data)));
+        }
+        Ok(None)
+    }
+
+    /// Sets the observer notified about the keys, under which new values are pushed.
+    pub fn set_observer(&mut self, observer: impl AoraObserver + Send + Sync + 'static) {
+        self.observer = Some(Observer::new(observer));
+    }
+
+    /// Checks whether the index counts the repeated pushes of the values.
+    pub fn is_counted(&self) -> bool { self.counted }
+
+    /// Returns how many times the value was pushed under the key, or zero if it is absent. For
+    /// an index which is not counted, the count of a present value is always one.
+    pub fn value_count(&self, key: K, val: V) -> usize {
+        self.cache
+            .get(&key.into())
+            .and_then(|values| values.get(&val.into()))
+            .map_or(0, |count| *count as usize)
+    }
+
+    /// Returns the values under the key with their counts.
+    fn values(&self, key: K) -> Option<&IndexMap<[u8; VAL_LEN], u32>> {
+        self.cache.get(&key.into())
+    }
+
+    /// Adds the value under the key, counting it if the index is counted.
+    fn add(&mut self, key: [u8; KEY_LEN], val: [u8; VAL_LEN]) {
+        let count = self.cache.entry(key).or_default().entry(val).or_insert(0);
+        if self.counted || *count == 0 {
+            *count = count.saturating_add(1);
+        }
+    }
+
+    fn notify(&self, key: &[u8; KEY_LEN]) {
+        if let Some(observer) = &self.observer {
+            observer.on_insert(key);
+        }
+    }
+
+    fn name(&self) -> &str {
+        self.path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or("<unnamed>")
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(table = self.name()), err))]
     pub fn save(&self) -> io::Result<()> {
+        let start = Instant::now();
         let mut index_file = BinFile::<MAGIC, VER>::create(&self.path)
             .map_err(|e| io::Error::new(e.kind(), format!("at path '{}'", self.path.display())))?;
 
-        for (key, values) in &self.cache {
-            index_file.write_all(key)?;
-            let len = values.len() as u32;
-            index_file.write_all(&len.to_le_bytes())?;
-            for value in values {
-                index_file.write_all(value)?;
-            }
+        match self.sealed()? {
+            Some(sealed) => index_file.write_all(&sealed)?,
+            None => self.encode(&mut *index_file)?,
         }
+        telemetry::written(self.name(), index_file.metadata()?.len());
+        telemetry::committed(self.name(), start);
+        Ok(())
+    }
+
+    /// Closes the index, syncing the index file to the storage device and reporting the
+    /// failures, which are lost when the index is just dropped.
+    pub fn close(self) -> Result<(), AoraError> {
+        self.sync()?;
         Ok(())
     }
+
+    /// Writes the index file, like [`Self::save`].
+    pub fn flush(&self) -> io::Result<()> { self.save() }
+
+    /// Syncs the index file to the storage device. The index is written with each push, so the
+    /// file is not re-written.
+    pub fn sync(&self) -> io::Result<()> {
+        BinFile::<MAGIC, VER>::open_rw(&self.path)
+            .map_err(|e| io::Error::new(e.kind(), format!("at path '{}'", self.path.display())))?
+            .sync_all()
+    }
+
+    /// Reports statistics on the index size and the distribution of values across the keys.
+    pub fn stats(&self) -> io::Result<IndexStats> {
+        let keys = self.cache.len();
+        let values = self.cache.values().map(IndexMap::len).sum::<usize>();
+        let max_values = self
+            .cache
+            .values()
+            .map(IndexMap::len)
+            .max()
+            .unwrap_or_default();
+        let mean_values = if keys == 0 { 0.0 } else { values as f64 / keys as f64 };
+        let disk_size = fs::metadata(&self.path)?.len();
+        Ok(IndexStats { keys, values, max_values, mean_values, disk_size })
+    }
+
+    pub fn to_dump(&self) -> FileAoraIndexDump<KEY_LEN, VAL_LEN> {
+        FileAoraIndexDump {
+            entries: self
+                .cache
+                .iter()
+                .map(|(key, values)| (*key, values.keys().copied().collect()))
+                .collect(),
+        }
+    }
+}
+
+/// Statistics on a [`FileAoraIndex`], returned by [`FileAoraIndex::stats`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexStats {
+    /// Number of keys in the index.
+    pub keys: usize,
+    /// Total number of values under all keys.
+    pub values: usize,
+    /// Maximal number of values under a single key.
+    pub max_values: usize,
+    /// Mean number of values per key.
+    pub mean_values: f64,
+    /// Size of the index file, in bytes.
+    pub disk_size: u64,
+}
+
+/// Structured dump of a [`FileAoraIndex`], returned by [`FileAoraIndex::to_dump`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct FileAoraIndexDump<const KEY_LEN: usize, const VAL_LEN: usize> {
+    /// Values under each of the keys, in the order they were pushed.
+    pub entries: BTreeMap<[u8; KEY_LEN], Vec<[u8; VAL_LEN]>>,
 }
 
 impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize, const VAL_LEN: usize>
     AoraIndex<K, V, KEY_LEN, VAL_LEN> for FileAoraIndex<K, V, MAGIC, VER, KEY_LEN, VAL_LEN>
 where
-    K: From<[u8; KEY_LEN]> + Into<[u8; KEY_LEN]>,
-    V: From<[u8; VAL_LEN]> + Into<[u8; VAL_LEN]>,
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
 {
     fn len(&self) -> usize { self.cache.len() }
 
     fn keys(&self) -> impl Iterator<Item = K> { self.cache.keys().copied().map(K::from) }
 
+    fn keys_with_prefix(&self, prefix: &[u8]) -> impl Iterator<Item = K> {
+        let mut start = [0u8; KEY_LEN];
+        let len = prefix.len().min(KEY_LEN);
+        start[..len].copy_from_slice(&prefix[..len]);
+        self.cache
+            .range(start..)
+            .map(|(key, _)| key)
+            .take_while(move |key| key.starts_with(prefix))
+            .copied()
+            .map(K::from)
+    }
+
+    fn filter_keys(&self, mut predicate: impl FnMut(&K, usize) -> bool) -> impl Iterator<Item = K> {
+        self.cache.iter().filter_map(move |(key, values)| {
+            let key = K::from(*key);
+            predicate(&key, values.len()).then_some(key)
+        })
+    }
+
     fn contains_key(&self, key: K) -> bool { self.cache.contains_key(&key.into()) }
 
     fn value_len(&self, key: K) -> usize {
@@ -122,14 +418,275 @@ where
     }
 
     fn get(&self, key: K) -> impl ExactSizeIterator<Item = V> {
-        match self.cache.get(&key.into()) {
-            Some(ids) => ids.clone().into_iter().map(V::from),
-            None => IndexSet::new().into_iter().map(V::from),
-        }
+        telemetry::got(self.name());
+        let ids = self
+            .cache
+            .get(&key.into())
+            .map(IndexMap::as_slice)
+            .unwrap_or_default();
+        ids.keys().copied().map(V::from)
+    }
+
+    /// Yields the values in the order they were first pushed under the key with the fewest
+    /// values.
+    fn intersect(&self, keys: &[K]) -> impl Iterator<Item = V>
+    where K: Copy {
+        let smallest = keys
+            .iter()
+            .map(|key| self.values(*key))
+            .min_by_key(|values| values.map_or(0, IndexMap::len));
+        smallest
+            .flatten()
+            .into_iter()
+            .flat_map(IndexMap::keys)
+            .filter(move |val| {
+                keys.iter()
+                    .all(|key| self.values(*key).is_some_and(|values| values.contains_key(*val)))
+            })
+            .copied()
+            .map(V::from)
+    }
+
+    /// Yields the values in the order of the keys, and then in the order they were first pushed
+    /// under the key.
+    fn union(&self, keys: &[K]) -> impl Iterator<Item = V>
+    where K: Copy {
+        keys.iter()
+            .enumerate()
+            .flat_map(move |(no, key)| {
+                self.values(*key)
+                    .into_iter()
+                    .flat_map(IndexMap::keys)
+                    .filter(move |val| {
+                        !keys[..no].iter().any(|key| {
+                            self.values(*key)
+                                .is_some_and(|values| values.contains_key(*val))
+                        })
+                    })
+            })
+            .copied()
+            .map(V::from)
+    }
+
+    fn is_ordered(&self) -> bool { true }
+
+    fn get_ordered(&self, key: K) -> Option<impl ExactSizeIterator<Item = V>> {
+        Some(self.get(key))
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (K, V)> {
+        self.cache
+            .iter()
+            .flat_map(|(key, vals)| vals.keys().map(move |val| (K::from(*key), V::from(*val))))
     }
 
     fn push(&mut self, key: K, val: V) {
-        self.cache.entry(key.into()).or_default().insert(val.into());
+        let key = key.into();
+        telemetry::inserted(self.name(), 1);
+        self.add(key, val.into());
+        self.save().expect("Cannot save index file");
+        self.notify(&key);
+    }
+
+    fn push_all(&mut self, key: K, vals: impl IntoIterator<Item = V>)
+    where K: Copy {
+        let vals = vals.into_iter().map(V::into).collect::<Vec<_>>();
+        telemetry::inserted(self.name(), vals.len() as u64);
+        let key = key.into();
+        for val in vals {
+            self.add(key, val);
+        }
+        self.save().expect("Cannot save index file");
+        self.notify(&key);
+    }
+
+    fn extend(&mut self, iter: impl IntoIterator<Item = (K, V)>) {
+        let mut keys = vec![];
+        for (key, val) in iter {
+            let key = key.into();
+            telemetry::inserted(self.name(), 1);
+            self.add(key, val.into());
+            keys.push(key);
+        }
         self.save().expect("Cannot save index file");
+        for key in keys {
+            self.notify(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::U64Be;
+
+    type Db = FileAoraIndex<U64Be, U64Be, { u64::from_be_bytes(*b"DUMBTEST") }, 1, 8, 8>;
+
+    #[test]
+    fn prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "prefix").unwrap();
+
+        db.push(0x0100_0000_0000_0002.into(), 1.into());
+        db.push(0x0100_0000_0000_0001.into(), 2.into());
+        db.push(0x0200_0000_0000_0001.into(), 3.into());
+        db.push(0x0001_0000_0000_0001.into(), 4.into());
+
+        let keys = db.keys_with_prefix(&[1]).map(|k| k.0).collect::<Vec<_>>();
+        assert_eq!(keys, vec![0x0100_0000_0000_0001, 0x0100_0000_0000_0002]);
+        assert_eq!(db.keys_with_prefix(&[2, 0]).count(), 1);
+        assert_eq!(db.keys_with_prefix(&[3]).count(), 0);
+        assert_eq!(db.keys_with_prefix(&[]).count(), 4);
+
+        let db = Db::open(dir.path(), "prefix").unwrap();
+        assert_eq!(db.keys_with_prefix(&[0, 1]).count(), 1);
+    }
+
+    #[test]
+    fn bulk() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "bulk").unwrap();
+
+        db.push_all(1.into(), (0..10).map(U64Be));
+        db.extend((0..10).map(|no| (U64Be(no % 3), U64Be(no))));
+        assert_eq!(db.len(), 3);
+        assert_eq!(db.value_len(1.into()), 10);
+        assert_eq!(db.value_len(2.into()), 3);
+
+        let db = Db::open(dir.path(), "bulk").unwrap();
+        assert_eq!(db.value_len(0.into()), 4);
+        assert_eq!(db.value_len(1.into()), 10);
+        assert_eq!(db.iter().count(), 17);
+        let stats = db.stats().unwrap();
+        assert_eq!(stats.keys, 3);
+        assert_eq!(stats.values, 17);
+        assert_eq!(stats.max_values, 10);
+        assert_eq!(stats.disk_size, 10 + 3 * (8 + 4) + 17 * 8);
+        assert_eq!(
+            db.iter()
+                .filter(|(k, _)| k.0 == 2)
+                .map(|(_, v)| v.0)
+                .sum::<u64>(),
+            15
+        );
+    }
+
+    #[test]
+    fn dump() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "dump").unwrap();
+        db.push(2.into(), 3.into());
+        db.push(1.into(), 2.into());
+        db.push(2.into(), 1.into());
+
+        let dump = db.to_dump();
+        assert_eq!(dump.entries.len(), 2);
+        assert_eq!(dump.entries[&1u64.to_be_bytes()], vec![2u64.to_be_bytes()]);
+        assert_eq!(dump.entries[&2u64.to_be_bytes()], vec![
+            3u64.to_be_bytes(),
+            1u64.to_be_bytes()
+        ]);
+        assert_eq!(Db::open(dir.path(), "dump").unwrap().to_dump(), dump);
+    }
+
+    #[test]
+    fn ordered() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_counted(dir.path(), "ordered").unwrap();
+        assert!(db.is_ordered());
+        db.push(1.into(), 5.into());
+        db.push_all(1.into(), [U64Be(2), U64Be(5), U64Be(9)]);
+        db.extend([(U64Be(1), U64Be(1)), (U64Be(1), U64Be(2))]);
+        let events = |db: &Db| {
+            db.get_ordered(1.into())
+                .unwrap()
+                .map(|v| v.0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(events(&db), vec![5, 2, 9, 1]);
+        assert_eq!(db.get_ordered(2.into()).unwrap().len(), 0);
+
+        let db = Db::open_counted(dir.path(), "ordered").unwrap();
+        assert_eq!(events(&db), vec![5, 2, 9, 1]);
+    }
+
+    #[test]
+    fn filtered_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "filtered").unwrap();
+        db.push_all(1.into(), (0..5).map(U64Be));
+        db.push_all(2.into(), (0..2).map(U64Be));
+        db.push_all(3.into(), (0..3).map(U64Be));
+
+        let keys = |iter: &mut dyn Iterator<Item = U64Be>| iter.map(|k| k.0).collect::<Vec<_>>();
+        assert_eq!(keys(&mut db.keys_with_min_values(3)), vec![1, 3]);
+        assert_eq!(keys(&mut db.keys_with_min_values(6)), Vec::<u64>::new());
+        assert_eq!(keys(&mut db.filter_keys(|_, len| len < 3)), vec![2]);
+        assert_eq!(keys(&mut db.filter_keys(|key, len| key.0 > 1 && len > 1)), vec![2, 3]);
+    }
+
+    #[test]
+    fn set_algebra() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "algebra").unwrap();
+        db.push_all(1.into(), [1, 2, 3, 4].map(U64Be));
+        db.push_all(2.into(), [4, 3, 5].map(U64Be));
+        db.push_all(3.into(), [3, 4, 6, 7].map(U64Be));
+
+        let values = |iter: &mut dyn Iterator<Item = U64Be>| iter.map(|v| v.0).collect::<Vec<_>>();
+        assert_eq!(values(&mut db.intersect(&[1.into(), 2.into(), 3.into()])), vec![4, 3]);
+        assert_eq!(values(&mut db.intersect(&[1.into(), 9.into()])), Vec::<u64>::new());
+        assert_eq!(values(&mut db.intersect(&[])), Vec::<u64>::new());
+        assert_eq!(values(&mut db.union(&[2.into(), 1.into(), 9.into(), 3.into()])), vec![
+            4, 3, 5, 1, 2, 6, 7
+        ]);
+        assert_eq!(values(&mut db.union(&[1.into(), 1.into()])), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_counted(dir.path(), "counted").unwrap();
+        db.push(1.into(), 2.into());
+        db.push(1.into(), 3.into());
+        db.push(1.into(), 2.into());
+        db.push_all(1.into(), [U64Be(2), U64Be(4)]);
+        assert_eq!(db.value_count(1.into(), 2.into()), 3);
+        assert_eq!(db.value_count(1.into(), 3.into()), 1);
+        assert_eq!(db.value_count(2.into(), 2.into()), 0);
+        assert_eq!(db.get(1.into()).map(|v| v.0).collect::<Vec<_>>(), vec![2, 3, 4]);
+
+        assert!(Db::open(dir.path(), "counted").is_err());
+        let db = Db::open_counted(dir.path(), "counted").unwrap();
+        assert!(db.is_counted());
+        assert_eq!(db.value_count(1.into(), 2.into()), 3);
+        assert_eq!(db.value_count(1.into(), 4.into()), 1);
+        assert_eq!(db.stats().unwrap().disk_size, 10 + (8 + 4) + 3 * (8 + 4));
+
+        let mut db = Db::create_new(dir.path(), "plain").unwrap();
+        db.push(1.into(), 2.into());
+        db.push(1.into(), 2.into());
+        assert_eq!(db.value_count(1.into(), 2.into()), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = [7u8; 32];
+        let mut db = Db::create_encrypted(dir.path(), "secret", key, true).unwrap();
+        assert_eq!(Db::open_encrypted(dir.path(), "secret", key).unwrap().len(), 0);
+
+        db.push(0x1122_3344_5566_7788.into(), 2.into());
+        db.push(0x1122_3344_5566_7788.into(), 2.into());
+        let file = fs::read(dir.path().join("secret.edat")).unwrap();
+        assert!(!file.windows(8).any(|w| w == 0x1122_3344_5566_7788u64.to_be_bytes()));
+
+        let db = Db::open_encrypted(dir.path(), "secret", key).unwrap();
+        assert!(db.is_counted());
+        assert_eq!(db.value_count(0x1122_3344_5566_7788.into(), 2.into()), 2);
+        let err = Db::open_encrypted(dir.path(), "secret", [8u8; 32]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(Db::open(dir.path(), "secret").is_err());
     }
 }
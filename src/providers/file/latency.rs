@@ -0,0 +1,155 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Number of the histogram buckets: four sub-buckets for each power of two of nanoseconds.
+const BUCKETS: usize = 252;
+
+/// Latency percentiles of a single kind of operation.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Percentiles {
+    /// Number of the measured operations.
+    pub count: u64,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+/// Latency percentiles of the operations of a provider, reported as a part of
+/// [`super::StorageStats`] once latency tracking is enabled.
+///
+/// The latencies are measured with a relative error of at most 25%.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencyStats {
+    pub get: Percentiles,
+    pub insert: Percentiles,
+    /// Latency of committing a transaction or flushing a write buffer.
+    pub commit: Percentiles,
+}
+
+/// Log-linear histogram of operation latencies, which can be updated from a shared reference.
+#[derive(Debug)]
+pub(crate) struct Histogram(Box<[AtomicU64]>);
+
+impl Default for Histogram {
+    fn default() -> Self { Self((0..BUCKETS).map(|_| AtomicU64::new(0)).collect()) }
+}
+
+impl Histogram {
+    fn bucket(nanos: u64) -> usize {
+        if nanos < 4 {
+            return nanos as usize;
+        }
+        let exp = 63 - nanos.leading_zeros();
+        let sub = (nanos >> (exp - 2)) & 3;
+        (exp as usize - 1) * 4 + sub as usize
+    }
+
+    /// Returns the largest value falling into the bucket.
+    fn upper_bound(bucket: usize) -> u64 {
+        if bucket < 4 {
+            return bucket as u64;
+        }
+        let exp = bucket / 4 + 1;
+        let sub = (bucket % 4) as u64;
+        ((4 + sub) << (exp - 2)).saturating_add((1 << (exp - 2)) - 1)
+    }
+
+    /// Records the latency of an operation which has started at `start`.
+    pub fn record(&self, start: Instant) {
+        let nanos = u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.0[Self::bucket(nanos)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Adds the measurements from another histogram.
+    pub fn merge(&self, other: &Self) {
+        for (bucket, other) in self.0.iter().zip(&other.0) {
+            bucket.fetch_add(other.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+    }
+
+    pub fn percentiles(&self) -> Percentiles {
+        let counts = self
+            .0
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        let count = counts.iter().sum::<u64>();
+        let quantile = |q: f64| {
+            let rank = ((count as f64 * q).ceil() as u64).max(1);
+            let mut seen = 0;
+            for (bucket, n) in counts.iter().enumerate() {
+                seen += n;
+                if seen >= rank {
+                    return Duration::from_nanos(Self::upper_bound(bucket));
+                }
+            }
+            Duration::ZERO
+        };
+        Percentiles {
+            count,
+            p50: quantile(0.5),
+            p95: quantile(0.95),
+            p99: quantile(0.99),
+        }
+    }
+}
+
+/// Latency histograms of the provider operations.
+#[derive(Debug, Default)]
+pub(crate) struct Latencies {
+    pub get: Histogram,
+    pub insert: Histogram,
+    pub commit: Histogram,
+}
+
+impl Latencies {
+    pub fn merge(&self, other: &Self) {
+        self.get.merge(&other.get);
+        self.insert.merge(&other.insert);
+        self.commit.merge(&other.commit);
+    }
+
+    pub fn stats(&self) -> LatencyStats {
+        LatencyStats {
+            get: self.get.percentiles(),
+            insert: self.insert.percentiles(),
+            commit: self.commit.percentiles(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets() {
+        for nanos in [0, 1, 3, 4, 7, 8, 100, 1_000_000, u64::MAX / 3, u64::MAX] {
+            let bucket = Histogram::bucket(nanos);
+            assert!(Histogram::upper_bound(bucket) >= nanos);
+            assert!(Histogram::upper_bound(bucket) / 5 * 4 <= nanos.max(1));
+            if bucket > 0 {
+                assert!(Histogram::upper_bound(bucket - 1) < nanos);
+            }
+        }
+        assert_eq!(Histogram::bucket(u64::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn percentiles() {
+        let histogram = Histogram::default();
+        for nanos in 1..=100u64 {
+            histogram.0[Histogram::bucket(nanos * 1000)].fetch_add(1, Ordering::Relaxed);
+        }
+        let stats = histogram.percentiles();
+        assert_eq!(stats.count, 100);
+        assert!((50_000..=62_500).contains(&(stats.p50.as_nanos() as u64)));
+        assert!((95_000..=118_750).contains(&(stats.p95.as_nanos() as u64)));
+        assert!(stats.p99 >= stats.p95);
+        assert_eq!(Histogram::default().percentiles(), Percentiles::default());
+    }
+}
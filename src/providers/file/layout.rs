@@ -0,0 +1,167 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use binfile::BinFile;
+
+use super::dynamic::{create_file, open_file};
+
+/// Kind of the table, determining the format of its log.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[repr(u8)]
+pub enum TableKind {
+    /// Append-only map, stored by [`super::FileAoraMap`].
+    AppendOnly = 0,
+    /// Append-update map with transactions, stored by [`super::FileAuraMap`].
+    AppendUpdate = 1,
+}
+
+/// Lengths of the keys and values of a table, persisted next to its log at the table creation, so
+/// the table can be read without compile-time knowledge of its types.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TableLayout {
+    pub kind: TableKind,
+    /// Length of the keys, in bytes.
+    pub key_len: usize,
+    /// Length of the values, in bytes, or `None` if the values are strict-encoded and have
+    /// variable length.
+    pub val_len: Option<usize>,
+}
+
+impl Display for TableLayout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            TableKind::AppendOnly => f.write_str("append-only")?,
+            TableKind::AppendUpdate => f.write_str("append-update")?,
+        }
+        write!(f, " table with {}-byte keys and ", self.key_len)?;
+        match self.val_len {
+            Some(len) => write!(f, "{len}-byte values"),
+            None => f.write_str("variable-length values"),
+        }
+    }
+}
+
+impl TableLayout {
+    pub(super) fn path(path: &Path, name: &str) -> PathBuf {
+        path.join(name).with_extension("layout")
+    }
+
+    fn to_bytes(self) -> [u8; 5] {
+        let mut data = [0u8; 5];
+        data[0] = self.kind as u8;
+        data[1..3].copy_from_slice(&(self.key_len as u16).to_le_bytes());
+        data[3..].copy_from_slice(&(self.val_len.unwrap_or_default() as u16).to_le_bytes());
+        data
+    }
+
+    fn from_bytes(data: [u8; 5], path: &Path) -> io::Result<Self> {
+        let kind = match data[0] {
+            0 => TableKind::AppendOnly,
+            1 => TableKind::AppendUpdate,
+            kind => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("layout file '{}' specifies unknown table kind {kind}", path.display()),
+                ));
+            }
+        };
+        let key_len = u16::from_le_bytes([data[1], data[2]]) as usize;
+        let val_len = u16::from_le_bytes([data[3], data[4]]) as usize;
+        Ok(Self { kind, key_len, val_len: (val_len > 0).then_some(val_len) })
+    }
+
+    pub(super) fn save<const MAGIC: u64, const VER: u16>(
+        self,
+        path: &Path,
+        name: &str,
+    ) -> io::Result<()> {
+        let path = Self::path(path, name);
+        let mut file = BinFile::<MAGIC, VER>::create_new(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("layout file '{}'", path.display())))?;
+        file.write_all(&self.to_bytes())
+    }
+
+    /// Saves the layout with the magic number and the version known only at runtime.
+    pub(super) fn save_dyn(self, path: &Path, name: &str, magic: u64, ver: u16) -> io::Result<()> {
+        let path = Self::path(path, name);
+        create_file(&path, magic, ver, "layout file")?.write_all(&self.to_bytes())
+    }
+
+    /// Reads the layout of the table with the given name, whose files have the given magic number
+    /// and version.
+    pub fn load(path: impl AsRef<Path>, name: &str, magic: u64, ver: u16) -> io::Result<Self> {
+        let path = Self::path(path.as_ref(), name);
+        let mut file = open_file(&path, magic, ver, false, "layout file")?;
+        let mut data = [0u8; 5];
+        file.read_exact(&mut data)?;
+        Self::from_bytes(data, &path)
+    }
+
+    /// Checks that the table with the given name was created with this layout, if the table has
+    /// the layout file.
+    pub(super) fn check<const MAGIC: u64, const VER: u16>(
+        self,
+        path: &Path,
+        name: &str,
+    ) -> io::Result<()> {
+        let path = Self::path(path, name);
+        if !fs::exists(&path)? {
+            return Ok(());
+        }
+        let mut file = BinFile::<MAGIC, VER>::open(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("layout file '{}'", path.display())))?;
+        let mut data = [0u8; 5];
+        file.read_exact(&mut data)?;
+        self.verify(Self::from_bytes(data, &path)?, name)
+    }
+
+    /// Checks the layout with the magic number and the version known only at runtime.
+    pub(super) fn check_dyn(self, path: &Path, name: &str, magic: u64, ver: u16) -> io::Result<()> {
+        if !fs::exists(Self::path(path, name))? {
+            return Ok(());
+        }
+        self.verify(Self::load(path, name, magic, ver)?, name)
+    }
+
+    fn verify(self, created: Self, name: &str) -> io::Result<()> {
+        if created != self {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("table '{name}' was created as {created}, but is opened as {self}"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::confinement::SmallVec;
+
+    use super::*;
+    use crate::file::FileAoraMap;
+
+    const MAGIC: u64 = u64::from_be_bytes(*b"DUMBTEST");
+
+    type Db = FileAoraMap<[u8; 8], SmallVec<u8>, MAGIC, 1, 8>;
+    type WideDb = FileAoraMap<[u8; 16], SmallVec<u8>, MAGIC, 1, 16>;
+
+    #[test]
+    fn mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        Db::create_new(dir.path(), "test").unwrap();
+        let layout = TableLayout::load(dir.path(), "test", MAGIC, 1).unwrap();
+        assert_eq!(
+            layout.to_string(),
+            "append-only table with 8-byte keys and variable-length values"
+        );
+
+        let err = WideDb::open(dir.path(), "test").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(Db::open(dir.path(), "test").is_ok());
+    }
+}
@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io;
+use std::path::Path;
+
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use super::{AoraError, FileAoraMap, LogOptions};
+use crate::{AoraLog, AoraMap, U64Be};
+
+/// Append-only log of values addressed by their sequence numbers, stored as a [`FileAoraMap`]
+/// keyed by the big-endian sequence numbers.
+///
+/// Since the sequence numbers follow the order of the appends, iteration from a sequence number
+/// starts directly at its index entry.
+#[derive(Debug)]
+pub struct FileAoraLog<V, const MAGIC: u64, const VER: u16 = 1> {
+    map: FileAoraMap<U64Be, V, MAGIC, VER, 8>,
+}
+
+impl<V, const MAGIC: u64, const VER: u16> FileAoraLog<V, MAGIC, VER> {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(path), err))]
+    pub fn create_new(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        FileAoraMap::create_new(path, name).map(|map| Self { map })
+    }
+
+    pub fn open_or_create(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        FileAoraMap::open_or_create(path, name).map(|map| Self { map })
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(path), err))]
+    pub fn open(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        FileAoraMap::open(path, name).map(|map| Self { map })
+    }
+
+    /// Creates a new log with the given options, see [`FileAoraMap::create_with`].
+    pub fn create_with(path: impl AsRef<Path>, name: &str, opts: LogOptions) -> io::Result<Self> {
+        FileAoraMap::create_with(path, name, opts).map(|map| Self { map })
+    }
+
+    /// Opens an existing log with the given runtime options, see [`FileAoraMap::open_with`].
+    pub fn open_with(path: impl AsRef<Path>, name: &str, opts: LogOptions) -> io::Result<Self> {
+        FileAoraMap::open_with(path, name, opts).map(|map| Self { map })
+    }
+
+    /// Closes the log, see [`FileAoraMap::close`].
+    pub fn close(self) -> Result<(), AoraError> { self.map.close() }
+
+    /// Flushes the buffered writes, see [`FileAoraMap::flush`].
+    pub fn flush(&mut self) -> io::Result<()> { self.map.flush() }
+
+    /// Syncs the log files to the storage device, see [`FileAoraMap::sync`].
+    pub fn sync(&mut self) -> io::Result<()> { self.map.sync() }
+
+    /// Returns a reference to the underlying map, keyed by the big-endian sequence numbers.
+    pub fn as_map(&self) -> &FileAoraMap<U64Be, V, MAGIC, VER, 8> { &self.map }
+
+    /// Returns a mutable reference to the underlying map, which allows configuring it.
+    ///
+    /// Inserting into the map directly with keys other than the next sequence number breaks the
+    /// log addressing.
+    pub fn as_map_mut(&mut self) -> &mut FileAoraMap<U64Be, V, MAGIC, VER, 8> { &mut self.map }
+}
+
+impl<V, const MAGIC: u64, const VER: u16> AoraLog<V> for FileAoraLog<V, MAGIC, VER>
+where V: Eq + StrictEncode + StrictDecode
+{
+    fn len(&self) -> u64 { self.map.len() as u64 }
+
+    fn append(&mut self, value: &V) -> u64 {
+        let seq = self.len();
+        self.map.insert(U64Be(seq), value);
+        seq
+    }
+
+    fn get(&self, seq: u64) -> Option<V> { self.map.get(U64Be(seq)) }
+
+    fn iter_from(&self, seq: u64) -> impl Iterator<Item = (u64, V)> {
+        let from = usize::try_from(seq).unwrap_or(usize::MAX);
+        self.map
+            .iter_range(from..usize::MAX, false)
+            .map(|(seq, value)| (seq.0, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::confinement::SmallVec;
+
+    use super::*;
+
+    type Log = FileAoraLog<SmallVec<u8>, { u64::from_be_bytes(*b"DUMBTEST") }>;
+
+    fn val(no: u64) -> SmallVec<u8> { SmallVec::from_checked(no.to_le_bytes().to_vec()) }
+
+    #[test]
+    fn sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = Log::create_new(dir.path(), "events").unwrap();
+        assert!(log.is_empty());
+        for no in 0..10 {
+            assert_eq!(log.append(&val(no * 10)), no);
+        }
+        // Equal values get different sequence numbers
+        assert_eq!(log.append(&val(0)), 10);
+        drop(log);
+
+        let log = Log::open(dir.path(), "events").unwrap();
+        assert_eq!(log.len(), 11);
+        assert_eq!(log.get(3), Some(val(30)));
+        assert_eq!(log.get(10), Some(val(0)));
+        assert_eq!(log.get(11), None);
+        assert_eq!(log.iter_from(8).collect::<Vec<_>>(), vec![
+            (8, val(80)),
+            (9, val(90)),
+            (10, val(0))
+        ]);
+        assert_eq!(log.iter_from(u64::MAX).count(), 0);
+        assert_eq!(log.iter().count(), 11);
+    }
+}
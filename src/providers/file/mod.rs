@@ -1,9 +1,73 @@
 // SPDX-License-Identifier: Apache-2.0
 
+mod advice;
 mod aomap;
 mod aumap;
+mod bloom;
+mod budget;
+mod check;
+#[cfg(feature = "encryption")]
+mod crypto;
+#[cfg(target_os = "linux")]
+mod direct;
+mod dynamic;
+mod error;
+mod format;
+mod handle;
 mod index;
+mod latency;
+mod layout;
+mod log;
+mod observer;
+mod pack;
+#[cfg(any(unix, windows))]
+mod pread;
+mod prealloc;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod raw;
+#[cfg(any(unix, windows))]
+mod reader;
+mod report;
+mod segment;
+mod sharded;
+mod sorted;
+mod sparse;
+mod spill;
+mod stats;
+mod table;
+mod telemetry;
+mod tomb;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+#[cfg(feature = "notify")]
+mod watch;
 
-pub use aomap::FileAoraMap;
-pub use aumap::{FileAuraMap, FileAuraMapDump};
-pub use index::FileAoraIndex;
+pub use aomap::{FileAoraMap, FileAoraMapDump, RecordDump};
+pub use aumap::{
+    DropPolicy, FileAuraFork, FileAuraMap, FileAuraMapDump, FileAuraSnapshot, PageSigner,
+    PageVerifier, Visibility,
+};
+pub use budget::MemoryBudget;
+pub use check::{CheckIssue, CheckReport, DuplicateKey, IndexAudit, IndexRepair};
+pub use dynamic::FileAoraMapDyn;
+pub use error::AoraError;
+pub use format::{LogOptions, RecordFlags};
+pub use index::{FileAoraIndex, FileAoraIndexDump, IndexStats};
+pub use latency::{LatencyStats, Percentiles};
+pub use layout::{TableKind, TableLayout};
+pub use log::FileAoraLog;
+pub use observer::AoraObserver;
+pub use pack::FileAoraPack;
+#[cfg(feature = "prometheus")]
+pub use prometheus::PrometheusReport;
+pub use raw::{FileAoraMapRaw, FileAuraMapRaw, RawPage};
+#[cfg(any(unix, windows))]
+pub use reader::Reader;
+pub use sharded::FileShardedMap;
+pub use sorted::{DEFAULT_MEMTABLE_LIMIT, FileSortedMap, SPARSE_INDEX_STEP};
+pub use stats::StorageStats;
+pub use telemetry::{
+    METRIC_BLOOM_NEGATIVES, METRIC_BYTES_WRITTEN, METRIC_CACHE_HITS, METRIC_CACHE_MISSES,
+    METRIC_COMMIT_SECONDS, METRIC_GETS, METRIC_INSERTS,
+};
@@ -0,0 +1,47 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{self, Debug, Formatter};
+use std::ops::{Deref, Range};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Callbacks invoked by the file providers on the changes of the stored data, allowing to
+/// invalidate caches or trigger replication without polling.
+///
+/// The keys are provided as the raw key bytes. All methods have empty default implementations.
+pub trait AoraObserver {
+    /// Called after a key is inserted (or, for the append-update maps, updated).
+    fn on_insert(&self, key: &[u8]) { let _ = key; }
+
+    /// Called after a transaction is committed, with the transaction number and its keys.
+    fn on_commit(&self, txno: u64, keys: &[&[u8]]) { let _ = (txno, keys); }
+
+    /// Called after the pending transaction is aborted.
+    fn on_abort(&self) {}
+
+    /// Called after the data are written to a file, with the byte ranges of the file which were
+    /// written, allowing incremental backup tools to copy just the changed regions.
+    ///
+    /// Reported by [`super::FileAuraMap`] after saving the committed pages, for the log and (if
+    /// the pages are signed) the signature files.
+    fn on_written(&self, path: &Path, ranges: &[Range<u64>]) { let _ = (path, ranges); }
+}
+
+#[derive(Clone)]
+pub(crate) struct Observer(Arc<dyn AoraObserver + Send + Sync>);
+
+impl Observer {
+    pub fn new(observer: impl AoraObserver + Send + Sync + 'static) -> Self {
+        Self(Arc::new(observer))
+    }
+}
+
+impl Deref for Observer {
+    type Target = dyn AoraObserver + Send + Sync;
+
+    fn deref(&self) -> &Self::Target { self.0.as_ref() }
+}
+
+impl Debug for Observer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str("Observer(..)") }
+}
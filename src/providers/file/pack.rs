@@ -0,0 +1,409 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use amplify::hex::ToHex;
+use binfile::BinFile;
+use indexmap::IndexMap;
+use strict_encoding::{StreamReader, StrictDecode, StrictEncode, StrictReader, StrictWriter};
+
+use super::AoraError;
+use super::aomap::AoraMapError;
+use crate::{AoraKey, AoraMap};
+
+/// Position of the pointer to the last index block, following the file header.
+const LAST_BLOCK_POS: u64 = 10;
+/// Position of the first block in the file.
+const FIRST_BLOCK_POS: u64 = LAST_BLOCK_POS + 8;
+/// Tag of the blocks containing a record.
+const RECORD_TAG: u8 = 0x01;
+/// Tag of the index blocks.
+const INDEX_TAG: u8 = 0x02;
+/// Number of the records after which an index block is written.
+const INDEX_BLOCK_LEN: usize = 1024;
+
+/// Append-only map stored in a single `.aora` file, containing both the records and the index.
+///
+/// Each record is framed with its key and length, and after every 1024 records (and on
+/// [`Self::sync`]) an index block with the keys and positions of the preceding records is
+/// appended. The blocks are chained, so opening the file reads only the index blocks and the
+/// records appended after the last of them. Since the records describe themselves, the index
+/// can't get out of sync with them, and an interrupted append leaves only an incomplete last
+/// record, which is discarded on open.
+///
+/// The file isn't compatible with the files of [`super::FileAoraMap`].
+#[derive(Debug)]
+pub struct FileAoraPack<K, V, const MAGIC: u64, const VER: u16 = 1, const KEY_LEN: usize = 32>
+where K: AoraKey<KEY_LEN>
+{
+    name: String,
+    file: RefCell<BinFile<MAGIC, VER>>,
+    index: IndexMap<[u8; KEY_LEN], u64>,
+    /// Index entries of the records appended after the last index block.
+    pending: Vec<u8>,
+    /// Position of the last index block, or zero if there are none.
+    last_block: u64,
+    /// End of the valid data in the file, where the next block is appended.
+    end: u64,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize>
+    FileAoraPack<K, V, MAGIC, VER, KEY_LEN>
+where K: AoraKey<KEY_LEN>
+{
+    const ENTRY_LEN: usize = KEY_LEN + 8;
+
+    fn file_path(path: &Path, name: &str) -> PathBuf { path.join(name).with_extension("aora") }
+
+    pub fn create_new(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file_path = Self::file_path(path, name);
+        if fs::exists(&file_path)? {
+            return Err(io::Error::other(AoraMapError::Exists {
+                name: name.to_string(),
+                path: path.display().to_string(),
+            }));
+        }
+        let mut file = BinFile::create_new(&file_path).map_err(|err| {
+            io::Error::new(err.kind(), format!("table file '{}'", file_path.display()))
+        })?;
+        file.write_all(&0u64.to_le_bytes())?;
+        Ok(Self {
+            name: name.to_string(),
+            file: RefCell::new(file),
+            index: IndexMap::new(),
+            pending: Vec::new(),
+            last_block: 0,
+            end: FIRST_BLOCK_POS,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Opens the table, loading the index from the index blocks and the records appended after
+    /// them. An incomplete record left by an interrupted append is truncated.
+    pub fn open(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file_path = Self::file_path(path, name);
+        if !fs::exists(&file_path)? {
+            return Err(io::Error::other(AoraMapError::NotExists {
+                name: name.to_string(),
+                path: path.display().to_string(),
+            }));
+        }
+        let mut file = BinFile::open_rw(&file_path).map_err(|err| {
+            io::Error::new(err.kind(), format!("table file '{}'", file_path.display()))
+        })?;
+        let len = file.seek(SeekFrom::End(0))?;
+        let corrupted = |pos: u64| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("table file '{}' is corrupted at {pos}", file_path.display()),
+            )
+        };
+
+        let mut buf = [0u8; 8];
+        file.seek(SeekFrom::Start(LAST_BLOCK_POS))?;
+        file.read_exact(&mut buf)?;
+        // The pointer is written after the block it points to, so an invalid one can be only
+        // caused by an interrupted write, in which case all the records are scanned
+        let mut last_block = u64::from_le_bytes(buf);
+        let (mut entries, mut pos) =
+            Self::read_blocks(&mut file, last_block).unwrap_or_else(|_| {
+                last_block = 0;
+                (Vec::new(), FIRST_BLOCK_POS)
+            });
+        let mut pending = Vec::new();
+
+        // The records appended after the last known index block are found by scanning them
+        file.seek(SeekFrom::Start(pos))?;
+        let mut head = [0u8; 1 + KEY_LEN + 4];
+        while pos < len {
+            file.read_exact(&mut head[..1])?;
+            let (key, block_len) = match head[0] {
+                RECORD_TAG if len - pos >= head.len() as u64 => {
+                    file.read_exact(&mut head[1..])?;
+                    let mut key = [0u8; KEY_LEN];
+                    key.copy_from_slice(&head[1..1 + KEY_LEN]);
+                    let data_len = &head[1 + KEY_LEN..];
+                    let data_len = u32::from_le_bytes(data_len.try_into().expect("fixed length"));
+                    (Some(key), head.len() as u64 + data_len as u64)
+                }
+                INDEX_TAG if len - pos >= 13 => {
+                    file.read_exact(&mut buf[..4])?;
+                    let count = u32::from_le_bytes(buf[..4].try_into().expect("fixed length"));
+                    (None, 13 + count as u64 * Self::ENTRY_LEN as u64)
+                }
+                RECORD_TAG | INDEX_TAG => break,
+                _ => return Err(corrupted(pos)),
+            };
+            if len - pos < block_len {
+                break;
+            }
+            match key {
+                Some(key) => {
+                    entries.push((key, pos));
+                    pending.extend_from_slice(&key);
+                    pending.extend_from_slice(&pos.to_le_bytes());
+                }
+                // Index block written after a stale pointer, covering the scanned records
+                None => {
+                    last_block = pos;
+                    pending.clear();
+                }
+            }
+            pos += block_len;
+            file.seek(SeekFrom::Start(pos))?;
+        }
+        if pos < len {
+            file.set_len(pos)?;
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            file: RefCell::new(file),
+            index: entries.into_iter().collect(),
+            pending,
+            last_block,
+            end: pos,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Reads the chain of the index blocks ending with the given one, returning the index
+    /// entries in the append order and the end of the last block.
+    fn read_blocks(
+        file: &mut BinFile<MAGIC, VER>,
+        last: u64,
+    ) -> io::Result<(Vec<([u8; KEY_LEN], u64)>, u64)> {
+        let len = file.metadata()?.len();
+        let mut blocks = Vec::new();
+        let mut end = FIRST_BLOCK_POS;
+        let mut block = last;
+        while block != 0 {
+            file.seek(SeekFrom::Start(block))?;
+            let mut head = [0u8; 13];
+            file.read_exact(&mut head)?;
+            let count = u32::from_le_bytes(head[1..5].try_into().expect("fixed length")) as usize;
+            let prev = u64::from_le_bytes(head[5..].try_into().expect("fixed length"));
+            if head[0] != INDEX_TAG
+                || (prev != 0 && prev >= block)
+                || block + 13 + (count * Self::ENTRY_LEN) as u64 > len
+            {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+            let mut data = vec![0u8; count * Self::ENTRY_LEN];
+            file.read_exact(&mut data)?;
+            if block == last {
+                end = file.stream_position()?;
+            }
+            blocks.push(data);
+            block = prev;
+        }
+        let entries = blocks
+            .iter()
+            .rev()
+            .flat_map(|data| data.chunks_exact(Self::ENTRY_LEN))
+            .map(|entry| {
+                let (key, pos) = entry.split_at(KEY_LEN);
+                let key = <[u8; KEY_LEN]>::try_from(key).expect("fixed length");
+                (key, u64::from_le_bytes(pos.try_into().expect("fixed length")))
+            })
+            .collect();
+        Ok((entries, end))
+    }
+
+    /// Appends the index block with the entries of the records appended after the previous one.
+    fn write_index_block(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let count = (self.pending.len() / Self::ENTRY_LEN) as u32;
+        let mut block = Vec::with_capacity(13 + self.pending.len());
+        block.push(INDEX_TAG);
+        block.extend_from_slice(&count.to_le_bytes());
+        block.extend_from_slice(&self.last_block.to_le_bytes());
+        block.extend_from_slice(&self.pending);
+        let file = self.file.get_mut();
+        file.seek(SeekFrom::Start(self.end))?;
+        file.write_all(&block)?;
+        file.seek(SeekFrom::Start(LAST_BLOCK_POS))?;
+        file.write_all(&self.end.to_le_bytes())?;
+        self.last_block = self.end;
+        self.end += block.len() as u64;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Writes the index block for the recently appended records and syncs the file to the
+    /// storage device.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.write_index_block()?;
+        self.file.get_mut().sync_all()
+    }
+
+    /// Syncs the table and closes it.
+    pub fn close(mut self) -> Result<(), AoraError> {
+        self.sync()?;
+        Ok(())
+    }
+
+    /// Retrieves value from the table, reporting I/O failures and undecodable values as errors.
+    pub fn try_get(&self, key: K) -> Result<Option<V>, AoraError>
+    where V: StrictDecode {
+        let key = key.into();
+        match self.index.get(&key) {
+            Some(&pos) => self.read(&key, pos).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn read(&self, key: &[u8; KEY_LEN], pos: u64) -> Result<V, AoraError>
+    where V: StrictDecode {
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(pos))?;
+        let mut head = [0u8; 1 + KEY_LEN + 4];
+        file.read_exact(&mut head)?;
+        if head[0] != RECORD_TAG || head[1..1 + KEY_LEN] != key[..] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "table '{}' doesn't have a record under the key {} at {pos}",
+                    self.name,
+                    key.to_hex()
+                ),
+            )
+            .into());
+        }
+        let len = u32::from_le_bytes(head[1 + KEY_LEN..].try_into().expect("fixed length"));
+        let mut data = vec![0u8; len as usize];
+        file.read_exact(&mut data)?;
+        let mut reader = StrictReader::with(StreamReader::in_memory::<{ usize::MAX }>(data));
+        V::strict_decode(&mut reader).map_err(|error| AoraError::Decode {
+            key: key.to_hex(),
+            pos,
+            error,
+        })
+    }
+}
+
+impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize> AoraMap<K, V, KEY_LEN>
+    for FileAoraPack<K, V, MAGIC, VER, KEY_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    V: Eq + StrictEncode + StrictDecode,
+{
+    fn len(&self) -> usize { self.index.len() }
+
+    fn contains_key(&self, key: K) -> bool { self.index.contains_key(&key.into()) }
+
+    fn get(&self, key: K) -> Option<V> {
+        self.try_get(key)
+            .unwrap_or_else(|err| panic!("unable to read item: {err}"))
+    }
+
+    fn insert(&mut self, key: K, value: &V) {
+        let key = key.into();
+        if self.index.contains_key(&key) {
+            let old = self.get(key.into());
+            if old.as_ref() != Some(value) {
+                panic!(
+                    "item under the given id is different from another item under the same id \
+                     already present in the log"
+                );
+            }
+            return;
+        }
+        let data = value
+            .strict_encode(StrictWriter::in_memory::<{ usize::MAX }>())
+            .expect("unable to encode item")
+            .unbox()
+            .unconfine();
+        let mut record = Vec::with_capacity(1 + KEY_LEN + 4 + data.len());
+        record.push(RECORD_TAG);
+        record.extend_from_slice(&key);
+        record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        record.extend_from_slice(&data);
+        let pos = self.end;
+        let file = self.file.get_mut();
+        file.seek(SeekFrom::Start(pos))
+            .expect("unable to seek to the end of the table");
+        file.write_all(&record).expect("unable to write to table");
+        self.end += record.len() as u64;
+        self.index.insert(key, pos);
+        self.pending.extend_from_slice(&key);
+        self.pending.extend_from_slice(&pos.to_le_bytes());
+        if self.pending.len() >= INDEX_BLOCK_LEN * Self::ENTRY_LEN {
+            self.write_index_block().expect("unable to write index block");
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (K, V)> {
+        self.index.iter().map(|(key, &pos)| {
+            let value = self
+                .read(key, pos)
+                .unwrap_or_else(|err| panic!("unable to read item: {err}"));
+            (K::from(*key), value)
+        })
+    }
+
+    fn iter_rev(&self) -> impl Iterator<Item = (K, V)> {
+        self.index.iter().rev().map(|(key, &pos)| {
+            let value = self
+                .read(key, pos)
+                .unwrap_or_else(|err| panic!("unable to read item: {err}"));
+            (K::from(*key), value)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::confinement::SmallVec;
+
+    use super::*;
+
+    type Db = FileAoraPack<[u8; 8], SmallVec<u8>, { u64::from_be_bytes(*b"DUMBTEST") }, 1, 8>;
+
+    fn val(no: u64) -> SmallVec<u8> { SmallVec::from_checked(no.to_le_bytes().to_vec()) }
+
+    #[test]
+    fn single_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "pack").unwrap();
+        for no in 0..2500u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        db.close().unwrap();
+        db = Db::open(dir.path(), "pack").unwrap();
+        assert_eq!(db.len(), 2500);
+        assert_eq!(db.get(1234u64.to_be_bytes()), Some(val(1234)));
+        for no in 2500..2510u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // Records appended after the last index block are recovered, and an incomplete one is
+        // discarded
+        let path = dir.path().join("pack.aora");
+        let len = fs::metadata(&path).unwrap().len();
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+        let mut db = Db::open(dir.path(), "pack").unwrap();
+        assert_eq!(db.len(), 2509);
+        assert!(db.iter().map(|(_, v)| v).eq((0..2509).map(val)));
+        db.insert(2509u64.to_be_bytes(), &val(2509));
+        drop(db);
+        let db = Db::open(dir.path(), "pack").unwrap();
+        assert_eq!(db.get(2509u64.to_be_bytes()), Some(val(2509)));
+    }
+}
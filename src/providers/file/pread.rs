@@ -0,0 +1,27 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::File;
+use std::io::{self, Read};
+
+/// Reader performing positioned reads from a shared file handle, which doesn't move the file
+/// cursor and thus can be used from multiple threads at the same time.
+#[derive(Debug)]
+pub(crate) struct PosReader<'file> {
+    file: &'file File,
+    pos: u64,
+}
+
+impl<'file> PosReader<'file> {
+    pub fn new(file: &'file File, pos: u64) -> Self { Self { file, pos } }
+}
+
+impl Read for PosReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(unix)]
+        let count = std::os::unix::fs::FileExt::read_at(self.file, buf, self.pos)?;
+        #[cfg(windows)]
+        let count = std::os::windows::fs::FileExt::seek_read(self.file, buf, self.pos)?;
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
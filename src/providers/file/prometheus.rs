@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{self, Display, Formatter};
+
+use super::StorageStats;
+
+/// Gauge reported for each table: metric name, help text and the accessor of the value.
+type Gauge = (&'static str, &'static str, fn(&StorageStats) -> u64);
+
+const GAUGES: [Gauge; 5] = [
+    ("aora_log_bytes", "Size of the record files, in bytes.", |s| s.log_size),
+    ("aora_idx_bytes", "Size of the index files, in bytes.", |s| s.idx_size),
+    ("aora_records", "Number of the records persisted on disk.", |s| s.records as u64),
+    ("aora_pages", "Number of the pages persisted or committed.", |s| s.pages as u64),
+    ("aora_pending_records", "Number of the records pending a commit or a flush.", |s| {
+        s.pending as u64
+    }),
+];
+
+/// Aggregates the storage usage of several tables into a report in the Prometheus text
+/// exposition format, which can be served from a metrics endpoint.
+///
+/// Each table is reported with a `table` label under the gauges `aora_log_bytes`,
+/// `aora_idx_bytes`, `aora_records`, `aora_pages` and `aora_pending_records`.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct PrometheusReport {
+    tables: Vec<(String, StorageStats)>,
+}
+
+impl PrometheusReport {
+    pub fn new() -> Self { Self::default() }
+
+    /// Adds the storage usage of a table, usually obtained from the `stats` method of a provider.
+    pub fn add_table(&mut self, name: impl Into<String>, stats: StorageStats) -> &mut Self {
+        self.tables.push((name.into(), stats));
+        self
+    }
+
+    /// Renders the report in the Prometheus text exposition format.
+    pub fn render(&self) -> String { self.to_string() }
+}
+
+impl Display for PrometheusReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (metric, help, value) in GAUGES {
+            writeln!(f, "# HELP {metric} {help}")?;
+            writeln!(f, "# TYPE {metric} gauge")?;
+            for (table, stats) in &self.tables {
+                let table = table
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n");
+                writeln!(f, "{metric}{{table=\"{table}\"}} {}", value(stats))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let stats = StorageStats {
+            log_size: 100,
+            idx_size: 26,
+            records: 1,
+            pages: 0,
+            pending: 2,
+            latency: None,
+        };
+        let report = PrometheusReport::new()
+            .add_table("blocks", stats)
+            .add_table("odd \"name\"", StorageStats::default())
+            .render();
+        assert!(report.starts_with(
+            "# HELP aora_log_bytes Size of the record files, in bytes.\n# TYPE aora_log_bytes \
+             gauge\naora_log_bytes{table=\"blocks\"} 100\naora_log_bytes{table=\"odd \
+             \\\"name\\\"\"} 0\n"
+        ));
+        assert!(report.contains("aora_pending_records{table=\"blocks\"} 2\n"));
+        assert_eq!(report.lines().count(), 5 * 4);
+    }
+}
@@ -0,0 +1,315 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use amplify::hex::ToHex;
+use indexmap::IndexMap;
+
+use super::aomap::read_record;
+use super::aumap::{CONTINUED, PAGE_FLAGS, TIMESTAMPED};
+use super::dynamic::{check_files, file_path, open_file};
+use super::format::LogFormat;
+use super::{AoraError, TableKind, TableLayout};
+
+/// Checks that the layout describes a table of the given kind.
+fn check_kind(layout: TableLayout, kind: TableKind) -> io::Result<()> {
+    if layout.kind != kind {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("table is {:?}, while {kind:?} was expected", layout.kind),
+        ));
+    }
+    Ok(())
+}
+
+/// Read-only variant of [`super::FileAoraMap`] with the key length taken from the table layout
+/// at runtime, returning the keys and the strict-encoded values as byte vectors.
+///
+/// Allows generic tooling to read any append-only table without compile-time knowledge of its
+/// types. The encrypted and segmented tables are not supported.
+#[derive(Debug)]
+pub struct FileAoraMapRaw {
+    layout: TableLayout,
+    log: RefCell<File>,
+    /// Positions of the records in the log.
+    index: IndexMap<Vec<u8>, u64>,
+    /// Sorted positions of all the records followed by the end of the log, delimiting the records
+    /// of the unframed logs.
+    bounds: Vec<u64>,
+    format: LogFormat,
+}
+
+impl FileAoraMapRaw {
+    /// Opens the table, reading its layout from the layout file created with the table.
+    pub fn open(path: impl AsRef<Path>, name: &str, magic: u64, ver: u16) -> io::Result<Self> {
+        let path = path.as_ref();
+        let layout = TableLayout::load(path, name, magic, ver)?;
+        Self::open_with_layout(path, name, magic, ver, layout)
+    }
+
+    /// Opens the table with the given layout, which is useful for the tables created before the
+    /// layout files were introduced.
+    pub fn open_with_layout(
+        path: impl AsRef<Path>,
+        name: &str,
+        magic: u64,
+        ver: u16,
+        layout: TableLayout,
+    ) -> io::Result<Self> {
+        check_kind(layout, TableKind::AppendOnly)?;
+        let (log, idx, format) = check_files(path.as_ref(), name, magic, ver)?;
+        let mut log = open_file(&log, magic, ver, false, "log file")?;
+        let mut idx = open_file(&idx, magic, ver, false, "index file")?;
+        let mut data = Vec::new();
+        idx.read_to_end(&mut data)?;
+        // A trailing incomplete entry is left by an interrupted append and is ignored
+        let index = data
+            .chunks_exact(layout.key_len + 8)
+            .map(|entry| {
+                let (key, pos) = entry.split_at(layout.key_len);
+                (key.to_vec(), u64::from_le_bytes(pos.try_into().expect("fixed length")))
+            })
+            .collect::<IndexMap<_, _>>();
+        let mut bounds = index.values().copied().collect::<Vec<_>>();
+        bounds.push(log.seek(SeekFrom::End(0))?);
+        bounds.sort_unstable();
+        bounds.dedup();
+        Ok(Self { layout, log: RefCell::new(log), index, bounds, format })
+    }
+
+    /// Returns the layout of the table.
+    pub fn layout(&self) -> TableLayout { self.layout }
+
+    /// Returns a number of the items in the table.
+    pub fn len(&self) -> usize { self.index.len() }
+
+    /// Checks whether the table is empty.
+    pub fn is_empty(&self) -> bool { self.index.is_empty() }
+
+    /// Checks whether a value under the key is present in the table.
+    pub fn contains_key(&self, key: &[u8]) -> bool { self.index.contains_key(key) }
+
+    /// Retrieves the strict-encoded value under the key.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, AoraError> {
+        match self.index.get(key) {
+            Some(&pos) => self.read(key, pos).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns an iterator over the keys and the strict-encoded values, in the order of the index
+    /// entries.
+    pub fn iter(&self) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>), AoraError>> + '_ {
+        self.index
+            .iter()
+            .map(|(key, &pos)| self.read(key, pos).map(|value| (key.clone(), value)))
+    }
+
+    fn read(&self, key: &[u8], pos: u64) -> Result<Vec<u8>, AoraError> {
+        let mut log = self.log.borrow_mut();
+        log.seek(SeekFrom::Start(pos))?;
+        if !self.format.is_framed() {
+            // The records follow each other, so the next one starts where this one ends
+            let end = self.bounds[self.bounds.partition_point(|bound| *bound <= pos)];
+            let mut value = vec![0u8; (end - pos) as usize];
+            log.read_exact(&mut value)?;
+            return Ok(value);
+        }
+        let (header, payload) = read_record(&self.format, &mut *log)?;
+        if header.flags.is_redacted() {
+            return Err(AoraError::Redacted { key: key.to_hex(), pos });
+        }
+        self.format
+            .decompress(header.codec, payload)
+            .ok_or_else(|| AoraError::Decompress { key: key.to_hex(), pos })
+    }
+}
+
+/// Page of a committed transaction with the keys and values as byte vectors.
+pub type RawPage = IndexMap<Vec<u8>, Vec<u8>>;
+
+/// Read-only variant of [`super::FileAuraMap`] with the key and value lengths taken from the
+/// table layout at runtime, returning the keys and values as byte vectors.
+///
+/// Allows generic tooling to read any append-update table without compile-time knowledge of its
+/// types.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct FileAuraMapRaw {
+    layout: TableLayout,
+    pages: Vec<RawPage>,
+    /// Most recent values of the keys.
+    state: RawPage,
+}
+
+impl FileAuraMapRaw {
+    /// Opens the table, reading its layout from the layout file created with the table.
+    pub fn open(path: impl AsRef<Path>, name: &str, magic: u64, ver: u16) -> io::Result<Self> {
+        let path = path.as_ref();
+        let layout = TableLayout::load(path, name, magic, ver)?;
+        Self::open_with_layout(path, name, magic, ver, layout)
+    }
+
+    /// Opens the table with the given layout, which is useful for the tables created before the
+    /// layout files were introduced.
+    pub fn open_with_layout(
+        path: impl AsRef<Path>,
+        name: &str,
+        magic: u64,
+        ver: u16,
+        layout: TableLayout,
+    ) -> io::Result<Self> {
+        check_kind(layout, TableKind::AppendUpdate)?;
+        let val_len = layout.val_len.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "append-update table must have value length",
+            )
+        })?;
+        let path = file_path(path.as_ref(), name, "log");
+        let mut file = open_file(&path, magic, ver, false, "append-update log file")?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        let corrupted = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("append-update log file '{}' is corrupted", path.display()),
+            )
+        };
+
+        let mut reader = data.as_slice();
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf).map_err(|_| corrupted())?;
+        let mut pages = Vec::new();
+        let mut state = RawPage::new();
+        for _ in 0..u64::from_le_bytes(buf) {
+            let mut page = RawPage::new();
+            // A large page may be written in several chunks
+            loop {
+                reader.read_exact(&mut buf).map_err(|_| corrupted())?;
+                let num_keys = u64::from_le_bytes(buf);
+                // The commit time of the page is not exposed
+                if num_keys & TIMESTAMPED != 0 {
+                    reader.read_exact(&mut buf).map_err(|_| corrupted())?;
+                }
+                let count = (num_keys & !PAGE_FLAGS) as usize;
+                if reader.len() < count.saturating_mul(layout.key_len + val_len) {
+                    return Err(corrupted());
+                }
+                page.reserve(count);
+                for _ in 0..count {
+                    let (key, rest) = reader.split_at(layout.key_len);
+                    let (value, rest) = rest.split_at(val_len);
+                    page.insert(key.to_vec(), value.to_vec());
+                    state.insert(key.to_vec(), value.to_vec());
+                    reader = rest;
+                }
+                if num_keys & CONTINUED == 0 {
+                    break;
+                }
+            }
+            pages.push(page);
+        }
+        if !reader.is_empty() {
+            return Err(corrupted());
+        }
+        Ok(Self { layout, pages, state })
+    }
+
+    /// Returns the layout of the table.
+    pub fn layout(&self) -> TableLayout { self.layout }
+
+    /// Returns a number of the committed transactions.
+    pub fn transactions(&self) -> u64 { self.pages.len() as u64 }
+
+    /// Returns the page of the committed transaction with the given number.
+    pub fn page(&self, txno: u64) -> Option<&RawPage> { self.pages.get(txno as usize) }
+
+    /// Returns a number of the distinct keys in the table.
+    pub fn len(&self) -> usize { self.state.len() }
+
+    /// Checks whether the table is empty.
+    pub fn is_empty(&self) -> bool { self.state.is_empty() }
+
+    /// Retrieves the most recent value under the key.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> { self.state.get(key).map(Vec::as_slice) }
+
+    /// Returns an iterator over the keys and their most recent values, in the order the keys were
+    /// first written.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.state
+            .iter()
+            .map(|(key, value)| (key.as_slice(), value.as_slice()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::confinement::SmallVec;
+
+    use super::*;
+    use crate::file::{FileAoraMap, FileAuraMap};
+    use crate::{AoraMap, AuraMap, TransactionalMap, U64Le};
+
+    const MAGIC: u64 = u64::from_be_bytes(*b"DUMBTEST");
+
+    fn val(no: u64) -> SmallVec<u8> { SmallVec::from_checked(no.to_le_bytes().to_vec()) }
+
+    #[test]
+    fn aora() {
+        type Db = FileAoraMap<[u8; 8], SmallVec<u8>, MAGIC, 1, 8>;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "test").unwrap();
+        for no in 0..5u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        drop(db);
+
+        let raw = FileAoraMapRaw::open(dir.path(), "test", MAGIC, 1).unwrap();
+        assert_eq!(
+            raw.layout(),
+            TableLayout { kind: TableKind::AppendOnly, key_len: 8, val_len: None }
+        );
+        assert_eq!(raw.len(), 5);
+        // Strict encoding prefixes the bytes with their 2-byte length
+        let encoded = |no: u64| [&[8u8, 0][..], &no.to_le_bytes()].concat();
+        assert_eq!(raw.get(&3u64.to_be_bytes()).unwrap(), Some(encoded(3)));
+        assert_eq!(raw.get(&5u64.to_be_bytes()).unwrap(), None);
+        assert_eq!(
+            raw.iter().map(Result::unwrap).collect::<Vec<_>>(),
+            (0..5u64)
+                .map(|no| (no.to_be_bytes().to_vec(), encoded(no)))
+                .collect::<Vec<_>>()
+        );
+        assert!(FileAuraMapRaw::open(dir.path(), "test", MAGIC, 1).is_err());
+    }
+
+    #[test]
+    fn aura() {
+        type Db = FileAuraMap<U64Le, U64Le, MAGIC, 1, 8, 8>;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "test").unwrap();
+        db.insert_or_update(1.into(), 10.into());
+        db.insert_or_update(2.into(), 20.into());
+        db.commit_transaction();
+        db.insert_or_update(1.into(), 11.into());
+        db.commit_transaction();
+        drop(db);
+
+        let raw = FileAuraMapRaw::open(dir.path(), "test", MAGIC, 1).unwrap();
+        assert_eq!(raw.layout().val_len, Some(8));
+        assert_eq!(raw.transactions(), 2);
+        assert_eq!(raw.len(), 2);
+        assert_eq!(raw.page(1).unwrap().len(), 1);
+        assert_eq!(raw.get(&1u64.to_le_bytes()), Some(&11u64.to_le_bytes()[..]));
+        assert_eq!(
+            raw.iter().map(|(key, _)| key.to_vec()).collect::<Vec<_>>(),
+            vec![1u64.to_le_bytes().to_vec(), 2u64.to_le_bytes().to_vec()]
+        );
+        assert!(FileAoraMapRaw::open(dir.path(), "test", MAGIC, 1).is_err());
+    }
+}
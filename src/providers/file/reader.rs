@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use amplify::hex::ToHex;
+use strict_encoding::{StrictDecode, StrictEncode, StrictWriter};
+
+use super::aomap::read_value;
+use super::format::{HashFn, LogFormat};
+use super::pread::PosReader;
+use super::segment::SharedLog;
+use super::sparse::FullIndex;
+use super::{AoraError, telemetry};
+use crate::AoraKey;
+
+/// Read-only handle of a [`super::FileAoraMap`], returned by [`super::FileAoraMap::reader`].
+///
+/// The handle serves the items present in the map at the moment the handle was created, using
+/// positioned reads, which don't interfere with the writer or the other readers. It is cheap to
+/// clone and can be sent to other threads, so a server can hand one to each request.
+pub struct Reader<K, V, const KEY_LEN: usize = 32> {
+    inner: Arc<Snapshot<KEY_LEN>>,
+    _phantom: PhantomData<fn() -> (K, V)>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Snapshot<const KEY_LEN: usize> {
+    pub name: String,
+    pub log: SharedLog,
+    pub index: FullIndex<KEY_LEN>,
+    /// Hash file and the hash function, if the map verifies the values on read.
+    pub sums: Option<(File, HashFn)>,
+    pub format: LogFormat,
+}
+
+impl<K, V, const KEY_LEN: usize> Clone for Reader<K, V, KEY_LEN> {
+    fn clone(&self) -> Self { Self { inner: self.inner.clone(), _phantom: PhantomData } }
+}
+
+impl<K, V, const KEY_LEN: usize> std::fmt::Debug for Reader<K, V, KEY_LEN> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Reader")
+            .field("name", &self.inner.name)
+            .field("len", &self.inner.index.len())
+            .finish()
+    }
+}
+
+impl<K, V, const KEY_LEN: usize> Reader<K, V, KEY_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    V: StrictEncode + StrictDecode,
+{
+    pub(crate) fn new(snapshot: Snapshot<KEY_LEN>) -> Self {
+        Self { inner: Arc::new(snapshot), _phantom: PhantomData }
+    }
+
+    /// Returns the number of the items visible to the reader.
+    pub fn len(&self) -> usize { self.inner.index.len() }
+
+    /// Checks whether the reader sees no items.
+    pub fn is_empty(&self) -> bool { self.inner.index.is_empty() }
+
+    /// Checks whether the key was present in the map when the reader was created.
+    pub fn contains_key(&self, key: K) -> bool { self.inner.index.contains_key(&key.into()) }
+
+    /// Retrieves value from the log, panicking on I/O failures, undecodable values and hash
+    /// mismatches. The redacted values are reported as absent.
+    pub fn get(&self, key: K) -> Option<V> {
+        match self.try_get(key) {
+            Ok(value) => value,
+            Err(AoraError::Redacted { .. }) => None,
+            Err(err) => panic!("unable to read item: {err}"),
+        }
+    }
+
+    /// Retrieves value from the log, reporting I/O failures, undecodable values and (if the log
+    /// stores value hashes) hash mismatches as errors.
+    pub fn try_get(&self, key: K) -> Result<Option<V>, AoraError> {
+        let key = key.into();
+        telemetry::got(&self.inner.name);
+        let Some((no, pos)) = self.inner.index.get_full(&key) else {
+            return Ok(None);
+        };
+        self.read(no, &key, pos).map(Some)
+    }
+
+    fn read(&self, no: usize, key: &[u8; KEY_LEN], pos: u64) -> Result<V, AoraError> {
+        let Snapshot { log, index, sums, format, .. } = &*self.inner;
+        let mut reader = BufReader::new(log.reader(pos));
+        let value: V = read_value(format, &mut reader, key, pos)?;
+        if let Some((sums, hasher)) = sums {
+            let mut expected = [0u8; 32];
+            let entry = index.entry_no(no);
+            PosReader::new(sums, 10 + entry as u64 * 32).read_exact(&mut expected)?;
+            let data = value
+                .strict_encode(StrictWriter::in_memory::<{ usize::MAX }>())
+                .expect("unable to encode item")
+                .unbox()
+                .unconfine();
+            if hasher(&data) != expected {
+                return Err(AoraError::HashMismatch { key: key.to_hex(), pos });
+            }
+        }
+        Ok(value)
+    }
+
+    /// Returns an iterator over the key and value pairs in the order they were appended to the
+    /// log.
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)> + '_ {
+        self.inner
+            .index
+            .iter()
+            .enumerate()
+            .filter_map(|(no, (key, pos))| match self.read(no, key, *pos) {
+                Ok(value) => Some((K::from(*key), value)),
+                Err(AoraError::Redacted { .. }) => None,
+                Err(err) => panic!("unable to read item: {err}"),
+            })
+    }
+
+    /// Returns an iterator over the key and value pairs in the reverse order.
+    pub fn iter_rev(&self) -> impl Iterator<Item = (K, V)> + '_ {
+        let len = self.len();
+        self.inner
+            .index
+            .iter()
+            .rev()
+            .enumerate()
+            .filter_map(move |(no, (key, pos))| match self.read(len - 1 - no, key, *pos) {
+                Ok(value) => Some((K::from(*key), value)),
+                Err(AoraError::Redacted { .. }) => None,
+                Err(err) => panic!("unable to read item: {err}"),
+            })
+    }
+}
@@ -0,0 +1,74 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{self, Display, Formatter};
+
+use amplify::hex::ToHex;
+
+/// Maximal number of the anomalies listed in a report; the rest are only counted.
+const MAX_ANOMALIES: usize = 16;
+
+/// Human-readable summary of a table in YAML, produced by the `debug_report` method of the
+/// providers, which users can attach to bug reports.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub(crate) struct DebugReport {
+    fields: Vec<(&'static str, String)>,
+    lists: Vec<(&'static str, Vec<String>)>,
+}
+
+impl DebugReport {
+    /// Starts the report with the information from the file header.
+    pub fn new<const MAGIC: u64, const VER: u16>(provider: &str, table: &str) -> Self {
+        let mut report = Self::default();
+        report
+            .field("provider", provider)
+            .field("table", format!("{table:?}"))
+            .field("magic", format!("{MAGIC:#018x}"))
+            .field("version", VER);
+        report
+    }
+
+    pub fn field(&mut self, name: &'static str, value: impl Display) -> &mut Self {
+        self.fields.push((name, value.to_string()));
+        self
+    }
+
+    /// Adds the key as a hex string, or as a null if there is no key.
+    pub fn key(&mut self, name: &'static str, key: Option<&[u8]>) -> &mut Self {
+        self.field(name, key.map_or_else(|| s!("~"), |key| key.to_hex()))
+    }
+
+    pub fn list(&mut self, name: &'static str, items: Vec<String>) -> &mut Self {
+        self.lists.push((name, items));
+        self
+    }
+
+    /// Adds the list of the anomalies, keeping at most [`MAX_ANOMALIES`] of them.
+    pub fn anomalies(&mut self, anomalies: impl IntoIterator<Item = String>) -> &mut Self {
+        let mut anomalies = anomalies.into_iter();
+        let mut items = anomalies.by_ref().take(MAX_ANOMALIES).collect::<Vec<_>>();
+        let rest = anomalies.count();
+        if rest > 0 {
+            items.push(format!("... and {rest} more"));
+        }
+        self.list("anomalies", items)
+    }
+}
+
+impl Display for DebugReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.fields {
+            writeln!(f, "{name}: {value}")?;
+        }
+        for (name, items) in &self.lists {
+            if items.is_empty() {
+                writeln!(f, "{name}: []")?;
+                continue;
+            }
+            writeln!(f, "{name}:")?;
+            for item in items {
+                writeln!(f, "  - {item:?}")?;
+            }
+        }
+        Ok(())
+    }
+}
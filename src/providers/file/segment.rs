@@ -0,0 +1,765 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "zstd")]
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use binfile::BinFile;
+
+use super::advice::{Access, advise};
+#[cfg(target_os = "linux")]
+use super::direct::DirectAppender;
+use super::handle::open_shared;
+use super::prealloc::preallocate;
+
+/// Length of the header of the files created with [`BinFile`].
+const HEADER_LEN: u64 = 10;
+
+/// Bit set in the segment number stored in the manifest for the compressed segments.
+const COMPRESSED_SEGMENT: u32 = 0x8000_0000;
+
+/// Length of the segment data compressed into a single frame of a compressed segment.
+#[cfg(feature = "zstd")]
+const FRAME_LEN: usize = 64 * 1024;
+
+/// Storage of the log records: either a single file, or a sequence of segment files, or a single
+/// file appended with the unbuffered writes.
+///
+/// All the variants use the same positions: the first record starts at the position 10, right after
+/// the file header, and each next record follows the previous one, so the positions stored in the
+/// index don't depend on the storage kind.
+#[derive(Debug)]
+pub(crate) enum LogFile<const MAGIC: u64, const VER: u16> {
+    Single(BinFile<MAGIC, VER>),
+    Segmented(SegmentedLog<MAGIC, VER>),
+    /// Single file read through the page cache and appended with [`DirectAppender`], which keeps
+    /// the last partially filled block until the log is flushed.
+    #[cfg(target_os = "linux")]
+    Direct(BinFile<MAGIC, VER>, DirectAppender),
+}
+
+impl<const MAGIC: u64, const VER: u16> From<BinFile<MAGIC, VER>> for LogFile<MAGIC, VER> {
+    fn from(file: BinFile<MAGIC, VER>) -> Self { Self::Single(file) }
+}
+
+impl<const MAGIC: u64, const VER: u16> LogFile<MAGIC, VER> {
+    /// Prepares the log for appending a new record, starting a new segment if the active one is
+    /// full.
+    pub fn prepare_append(&mut self) -> io::Result<()> {
+        match self {
+            Self::Single(_) => Ok(()),
+            Self::Segmented(log) => log.rotate_if_full(),
+            #[cfg(target_os = "linux")]
+            Self::Direct(..) => Ok(()),
+        }
+    }
+
+    /// Returns paths of the closed segments, which are never modified again (though they may be
+    /// replaced with their compressed versions).
+    pub fn closed_segments(&self) -> Vec<PathBuf> {
+        match self {
+            Self::Single(_) => vec![],
+            #[cfg(target_os = "linux")]
+            Self::Direct(..) => vec![],
+            Self::Segmented(log) => {
+                let count = log.segments.len().saturating_sub(1);
+                log.segments[..count]
+                    .iter()
+                    .map(|segment| segment.path.clone())
+                    .collect()
+            }
+        }
+    }
+
+    /// Compresses the closed segments of a segmented log which are not compressed yet, returning
+    /// the number of the compressed segments.
+    #[cfg(feature = "zstd")]
+    pub fn compress_segments(&mut self, level: i32) -> io::Result<usize> {
+        match self {
+            Self::Single(_) => Ok(0),
+            Self::Segmented(log) => log.compress_closed(level),
+            #[cfg(target_os = "linux")]
+            Self::Direct(..) => Ok(0),
+        }
+    }
+
+    /// Returns the total size of the log files, in bytes.
+    pub fn disk_size(&self) -> io::Result<u64> {
+        match self {
+            Self::Single(file) => Ok(file.metadata()?.len()),
+            Self::Segmented(log) => log
+                .segments
+                .iter()
+                .try_fold(0, |size, segment| Ok(size + fs::metadata(&segment.path)?.len())),
+            #[cfg(target_os = "linux")]
+            Self::Direct(_, direct) => Ok(direct.end()),
+        }
+    }
+
+    /// Syncs the data and metadata of the active log file (and the segment manifest) to the
+    /// storage device. The log must be flushed before.
+    pub fn sync_all(&self) -> io::Result<()> {
+        match self {
+            Self::Single(file) => file.sync_all(),
+            Self::Segmented(log) => {
+                log.active.sync_all()?;
+                log.manifest.sync_all()
+            }
+            #[cfg(target_os = "linux")]
+            Self::Direct(file, _) => file.sync_all(),
+        }
+    }
+
+    /// Preallocates disk space for `len` more bytes of the log. For a segmented log the space is
+    /// preallocated only up to the size limit of the active segment.
+    pub fn preallocate(&self, len: u64) -> io::Result<()> {
+        match self {
+            Self::Single(file) => preallocate(file, len),
+            Self::Segmented(log) => {
+                let last = log.segments.last().expect("segmented log has no segments");
+                let left = log.segment_size.saturating_sub(log.end - last.start);
+                preallocate(&log.active, len.min(left))
+            }
+            #[cfg(target_os = "linux")]
+            Self::Direct(file, _) => preallocate(file, len),
+        }
+    }
+
+    /// Advises the kernel about the expected pattern of the reads from the log.
+    pub fn advise(&mut self, access: Access) {
+        match self {
+            Self::Single(file) => advise(file, access),
+            Self::Segmented(log) => {
+                log.access = access;
+                if let Some((_, SegmentReader::Plain(file))) = &log.reader {
+                    advise(file, access);
+                }
+            }
+            #[cfg(target_os = "linux")]
+            Self::Direct(file, _) => advise(file, access),
+        }
+    }
+
+    /// Opens read-only handles for positioned reads from multiple threads, writing out the tail
+    /// buffered for the direct I/O first.
+    #[cfg(any(unix, windows))]
+    pub fn shared(&mut self) -> io::Result<SharedLog> {
+        let files = match self {
+            Self::Single(file) => vec![(0, SharedSegment::Plain(0, file.try_clone()?))],
+            Self::Segmented(log) => log
+                .segments
+                .iter()
+                .map(|segment| {
+                    Ok((segment.start, SegmentedLog::<MAGIC, VER>::open_shared_segment(segment)?))
+                })
+                .collect::<io::Result<_>>()?,
+            #[cfg(target_os = "linux")]
+            Self::Direct(file, direct) => {
+                direct.write_tail()?;
+                vec![(0, SharedSegment::Plain(0, file.try_clone()?))]
+            }
+        };
+        Ok(SharedLog { files })
+    }
+}
+
+impl<const MAGIC: u64, const VER: u16> Read for LogFile<MAGIC, VER> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Single(file) => file.read(buf),
+            Self::Segmented(log) => log.read(buf),
+            #[cfg(target_os = "linux")]
+            Self::Direct(file, direct) => {
+                direct.write_tail()?;
+                file.read(buf)
+            }
+        }
+    }
+}
+
+impl<const MAGIC: u64, const VER: u16> Write for LogFile<MAGIC, VER> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Single(file) => file.write(buf),
+            Self::Segmented(log) => log.write(buf),
+            #[cfg(target_os = "linux")]
+            Self::Direct(_, direct) => direct.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Single(file) => file.flush(),
+            Self::Segmented(log) => log.flush(),
+            #[cfg(target_os = "linux")]
+            Self::Direct(_, direct) => direct.flush(),
+        }
+    }
+}
+
+impl<const MAGIC: u64, const VER: u16> Seek for LogFile<MAGIC, VER> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::Single(file) => file.seek(pos),
+            Self::Segmented(log) => log.seek(pos),
+            // The file may not contain the buffered tail yet
+            #[cfg(target_os = "linux")]
+            Self::Direct(file, direct) => match pos {
+                SeekFrom::End(offset) => {
+                    let pos = direct.end().checked_add_signed(offset).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position")
+                    })?;
+                    file.seek(SeekFrom::Start(pos))
+                }
+                pos => file.seek(pos),
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Segment {
+    no: u32,
+    path: PathBuf,
+    /// Log position of the first byte in the segment.
+    start: u64,
+    /// Whether the segment is compressed with [`LogFile::compress_segments`].
+    compressed: bool,
+}
+
+/// Handle of the segment used for reading.
+#[derive(Debug)]
+enum SegmentReader {
+    Plain(File),
+    /// Compressed segment with the last decompressed frame.
+    #[cfg(feature = "zstd")]
+    Compressed(Frames, Option<(usize, Vec<u8>)>),
+}
+
+/// Log split into segment files (`name.0001.log`, `name.0002.log`, …) of a limited size, listed
+/// in a segment manifest (`name.segments`).
+///
+/// A new segment is started once the active one exceeds the size limit, so a record never spans
+/// several segments, and the closed segments are never modified again. A closed segment may be
+/// replaced with its compressed version (`name.0001.log.zst`), which is marked in the manifest.
+#[derive(Debug)]
+pub(crate) struct SegmentedLog<const MAGIC: u64, const VER: u16> {
+    dir: PathBuf,
+    name: String,
+    manifest: BinFile<MAGIC, VER>,
+    segment_size: u64,
+    segments: Vec<Segment>,
+    /// Position after the last byte in the log.
+    end: u64,
+    /// Current read/write position.
+    pos: u64,
+    /// Handle of the active (last) segment, used for appending.
+    active: BinFile<MAGIC, VER>,
+    /// Handle of the segment used for the last read, with its number.
+    reader: Option<(usize, SegmentReader)>,
+    /// Expected pattern of the reads, advised for each opened segment.
+    access: Access,
+}
+
+impl<const MAGIC: u64, const VER: u16> SegmentedLog<MAGIC, VER> {
+    pub fn manifest_path(dir: &Path, name: &str) -> PathBuf {
+        dir.join(name).with_extension("segments")
+    }
+
+    fn segment_path(dir: &Path, name: &str, no: u32) -> PathBuf {
+        dir.join(format!("{name}.{no:04}.log"))
+    }
+
+    fn compressed_path(dir: &Path, name: &str, no: u32) -> PathBuf {
+        dir.join(format!("{name}.{no:04}.log.zst"))
+    }
+
+    /// Path of the compressed segment while it is being written.
+    fn compressed_tmp_path(dir: &Path, name: &str, no: u32) -> PathBuf {
+        dir.join(format!("{name}.{no:04}.log.zst.tmp"))
+    }
+
+    pub fn create_new(dir: &Path, name: &str, segment_size: u64) -> io::Result<Self> {
+        let path = Self::manifest_path(dir, name);
+        let mut manifest = BinFile::<MAGIC, VER>::create_new(&path).map_err(|e| {
+            io::Error::new(e.kind(), format!("segment manifest '{}'", path.display()))
+        })?;
+        manifest.write_all(&segment_size.to_le_bytes())?;
+        let mut me = Self {
+            dir: dir.to_path_buf(),
+            name: name.to_string(),
+            manifest,
+            segment_size,
+            segments: vec![],
+            end: HEADER_LEN,
+            pos: HEADER_LEN,
+            active: Self::create_segment(dir, name, 1)?,
+            reader: None,
+            access: Access::default(),
+        };
+        me.add_segment(1)?;
+        Ok(me)
+    }
+
+    pub fn open(dir: &Path, name: &str) -> io::Result<Self> {
+        let path = Self::manifest_path(dir, name);
+        let mut manifest = BinFile::<MAGIC, VER>::open_rw(&path).map_err(|e| {
+            io::Error::new(e.kind(), format!("segment manifest '{}'", path.display()))
+        })?;
+        let mut buf = [0u8; 8];
+        manifest.read_exact(&mut buf)?;
+        let segment_size = u64::from_le_bytes(buf);
+
+        let mut segments = vec![];
+        let mut no_buf = [0u8; 4];
+        loop {
+            match manifest.read_exact(&mut no_buf) {
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                res => res?,
+            }
+            match manifest.read_exact(&mut buf) {
+                // Entry torn by an interrupted rotation
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    manifest.set_len(HEADER_LEN + 8 + segments.len() as u64 * 12)?;
+                    break;
+                }
+                res => res?,
+            }
+            let no = u32::from_le_bytes(no_buf);
+            let start = u64::from_le_bytes(buf);
+            let compressed = no & COMPRESSED_SEGMENT != 0;
+            let no = no & !COMPRESSED_SEGMENT;
+            Self::clean_compression(dir, name, no, compressed)?;
+            let path = match compressed {
+                false => Self::segment_path(dir, name, no),
+                true => Self::compressed_path(dir, name, no),
+            };
+            segments.push(Segment { no, path, start, compressed });
+        }
+        let Some(last) = segments.last() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("segment manifest '{}' is empty", path.display()),
+            ));
+        };
+        // Segment created by an interrupted rotation before it was recorded in the manifest, which
+        // would prevent the next rotation from creating it
+        let unlisted = Self::segment_path(dir, name, last.no + 1);
+        if fs::exists(&unlisted)? {
+            fs::remove_file(&unlisted)?;
+        }
+
+        let mut active = BinFile::<MAGIC, VER>::open_rw(&last.path).map_err(|e| {
+            io::Error::new(e.kind(), format!("log segment '{}'", last.path.display()))
+        })?;
+        let end = last.start + active.seek(SeekFrom::End(0))? - HEADER_LEN;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            name: name.to_string(),
+            manifest,
+            segment_size,
+            segments,
+            end,
+            pos: end,
+            active,
+            reader: None,
+            access: Access::default(),
+        })
+    }
+
+    /// Removes the files left by an interrupted compression of the segment: the temporary file,
+    /// the compressed file not recorded in the manifest yet, or the uncompressed file not removed
+    /// after the compressed one was recorded.
+    fn clean_compression(dir: &Path, name: &str, no: u32, compressed: bool) -> io::Result<()> {
+        let leftover = match compressed {
+            false => Self::compressed_path(dir, name, no),
+            true => Self::segment_path(dir, name, no),
+        };
+        for path in [Self::compressed_tmp_path(dir, name, no), leftover] {
+            if fs::exists(&path)? {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the segment for reading.
+    fn open_segment(segment: &Segment, access: Access) -> io::Result<SegmentReader> {
+        if segment.compressed {
+            #[cfg(feature = "zstd")]
+            return Ok(SegmentReader::Compressed(Self::open_frames(&segment.path)?, None));
+            #[cfg(not(feature = "zstd"))]
+            return Err(compression_unsupported(&segment.path));
+        }
+        let file = open_shared(&segment.path)?;
+        advise(&file, access);
+        Ok(SegmentReader::Plain(file))
+    }
+
+    /// Opens the segment for positioned reads from multiple threads.
+    #[cfg(any(unix, windows))]
+    fn open_shared_segment(segment: &Segment) -> io::Result<SharedSegment> {
+        if segment.compressed {
+            #[cfg(feature = "zstd")]
+            return Ok(SharedSegment::Compressed(Self::open_frames(&segment.path)?));
+            #[cfg(not(feature = "zstd"))]
+            return Err(compression_unsupported(&segment.path));
+        }
+        Ok(SharedSegment::Plain(HEADER_LEN, open_shared(&segment.path)?))
+    }
+
+    #[cfg(feature = "zstd")]
+    fn open_frames(path: &Path) -> io::Result<Frames> {
+        BinFile::<MAGIC, VER>::open(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("log segment '{}'", path.display())))?;
+        Frames::open(path)
+    }
+
+    /// Compresses the closed segments which are not compressed yet with zstd using the given
+    /// compression level, returning the number of the compressed segments.
+    ///
+    /// Each segment is compressed into a temporary file, which is renamed and then recorded in
+    /// the manifest; only then the uncompressed segment is removed. If the process is
+    /// interrupted, the leftover files are removed on the next open.
+    #[cfg(feature = "zstd")]
+    pub fn compress_closed(&mut self, level: i32) -> io::Result<usize> {
+        let mut count = 0;
+        for idx in 0..self.segments.len() - 1 {
+            if !self.segments[idx].compressed {
+                self.compress_segment(idx, level)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    #[cfg(feature = "zstd")]
+    fn compress_segment(&mut self, idx: usize, level: i32) -> io::Result<()> {
+        let segment = &self.segments[idx];
+        let len = self.segments[idx + 1].start - segment.start;
+        let tmp = Self::compressed_tmp_path(&self.dir, &self.name, segment.no);
+        let path = Self::compressed_path(&self.dir, &self.name, segment.no);
+
+        let mut src = BinFile::<MAGIC, VER>::open(&segment.path).map_err(|e| {
+            io::Error::new(e.kind(), format!("log segment '{}'", segment.path.display()))
+        })?;
+        src.seek(SeekFrom::Start(HEADER_LEN))?;
+        if fs::exists(&tmp)? {
+            fs::remove_file(&tmp)?;
+        }
+        let dst = BinFile::<MAGIC, VER>::create_new(&tmp)
+            .map_err(|e| io::Error::new(e.kind(), format!("log segment '{}'", tmp.display())))?;
+        let mut writer = io::BufWriter::new(dst);
+        Frames::write(&mut io::BufReader::new(&mut *src).take(len), &mut writer, level)?;
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()?;
+        fs::rename(&tmp, &path)?;
+
+        // The segment number is the first field of the manifest entry
+        self.manifest
+            .seek(SeekFrom::Start(HEADER_LEN + 8 + idx as u64 * 12))?;
+        self.manifest
+            .write_all(&(segment.no | COMPRESSED_SEGMENT).to_le_bytes())?;
+        self.manifest.sync_all()?;
+
+        if matches!(self.reader, Some((cached, _)) if cached == idx) {
+            self.reader = None;
+        }
+        let segment = &mut self.segments[idx];
+        segment.compressed = true;
+        fs::remove_file(mem::replace(&mut segment.path, path))
+    }
+
+    fn create_segment(dir: &Path, name: &str, no: u32) -> io::Result<BinFile<MAGIC, VER>> {
+        let path = Self::segment_path(dir, name, no);
+        BinFile::create_new(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("log segment '{}'", path.display())))
+    }
+
+    fn add_segment(&mut self, no: u32) -> io::Result<()> {
+        self.manifest.seek(SeekFrom::End(0))?;
+        self.manifest.write_all(&no.to_le_bytes())?;
+        self.manifest.write_all(&self.end.to_le_bytes())?;
+        self.segments.push(Segment {
+            no,
+            path: Self::segment_path(&self.dir, &self.name, no),
+            start: self.end,
+            compressed: false,
+        });
+        Ok(())
+    }
+
+    /// Closes the active segment if it is full, starting the next one. The new segment file is
+    /// created before it gets recorded in the manifest; if the rotation is interrupted in between,
+    /// the unlisted file (and a torn manifest entry) are removed on the next open.
+    fn rotate_if_full(&mut self) -> io::Result<()> {
+        let last = self.segments.last().expect("segmented log has no segments");
+        if self.end - last.start < self.segment_size {
+            return Ok(());
+        }
+        self.active.sync_all()?;
+        let no = last.no + 1;
+        self.active = Self::create_segment(&self.dir, &self.name, no)?;
+        self.add_segment(no)
+    }
+
+    /// Finds the segment containing the given position.
+    fn locate(&self, pos: u64) -> usize {
+        self.segments
+            .partition_point(|segment| segment.start <= pos)
+            .saturating_sub(1)
+    }
+}
+
+impl<const MAGIC: u64, const VER: u16> Read for SegmentedLog<MAGIC, VER> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.end || buf.is_empty() {
+            return Ok(0);
+        }
+        let no = self.locate(self.pos);
+        let segment = &self.segments[no];
+        let reader = match &mut self.reader {
+            Some((cached, reader)) if *cached == no => reader,
+            reader => {
+                &mut reader
+                    .insert((no, Self::open_segment(segment, self.access)?))
+                    .1
+            }
+        };
+        let limit = self
+            .segments
+            .get(no + 1)
+            .map_or(self.end, |next| next.start)
+            - self.pos;
+        let len = buf.len().min(limit as usize);
+        let count = match reader {
+            SegmentReader::Plain(file) => {
+                file.seek(SeekFrom::Start(self.pos - segment.start + HEADER_LEN))?;
+                file.read(&mut buf[..len])?
+            }
+            #[cfg(feature = "zstd")]
+            SegmentReader::Compressed(frames, frame) => {
+                frames.read_at(&mut buf[..len], self.pos - segment.start, frame)?
+            }
+        };
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl<const MAGIC: u64, const VER: u16> Write for SegmentedLog<MAGIC, VER> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.pos != self.end {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "segmented log can be written only at its end",
+            ));
+        }
+        let count = self.active.write(buf)?;
+        self.end += count as u64;
+        self.pos = self.end;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { self.active.flush() }
+}
+
+impl<const MAGIC: u64, const VER: u16> Seek for SegmentedLog<MAGIC, VER> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(offset) => self.end.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compression_unsupported(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("log segment '{}' is compressed, which requires zstd", path.display()),
+    )
+}
+
+/// Closed segment compressed with zstd.
+///
+/// The segment data are split into frames of [`FRAME_LEN`] bytes, compressed independently and
+/// followed by the offsets of the frames in the file and the number of the frames, so a read
+/// decompresses just the frame containing the requested position.
+#[derive(Debug)]
+#[cfg(feature = "zstd")]
+struct Frames {
+    file: File,
+    /// Offset of each frame in the file, followed by the offset of the frame table.
+    offsets: Vec<u64>,
+}
+
+#[cfg(feature = "zstd")]
+impl Frames {
+    fn open(path: &Path) -> io::Result<Self> {
+        let corrupted = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("compressed log segment '{}' is corrupted", path.display()),
+            )
+        };
+        let file = open_shared(path)?;
+        let file_len = file.metadata()?.len();
+        let mut buf = [0u8; 8];
+        read_exact_at(&file, &mut buf, file_len.checked_sub(8).ok_or_else(corrupted)?)?;
+        let count = u64::from_le_bytes(buf);
+        let table = count
+            .checked_mul(8)
+            .and_then(|len| (file_len - 8).checked_sub(len))
+            .filter(|table| *table >= HEADER_LEN)
+            .ok_or_else(corrupted)?;
+        let mut data = vec![0u8; (count * 8) as usize];
+        read_exact_at(&file, &mut data, table)?;
+        let mut offsets = data
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().expect("8-byte chunk")))
+            .collect::<Vec<_>>();
+        offsets.push(table);
+        if offsets.first() != Some(&HEADER_LEN) || offsets.windows(2).any(|w| w[0] > w[1]) {
+            return Err(corrupted());
+        }
+        Ok(Self { file, offsets })
+    }
+
+    /// Writes the data read from the source as compressed frames followed by the frame table.
+    fn write(src: &mut impl Read, dst: &mut impl Write, level: i32) -> io::Result<()> {
+        let mut offsets = vec![];
+        let mut offset = HEADER_LEN;
+        let mut data = Vec::with_capacity(FRAME_LEN);
+        loop {
+            data.clear();
+            src.by_ref().take(FRAME_LEN as u64).read_to_end(&mut data)?;
+            if data.is_empty() {
+                break;
+            }
+            let frame = zstd::bulk::compress(&data, level)?;
+            dst.write_all(&frame)?;
+            offsets.push(offset);
+            offset += frame.len() as u64;
+        }
+        for offset in &offsets {
+            dst.write_all(&offset.to_le_bytes())?;
+        }
+        dst.write_all(&(offsets.len() as u64).to_le_bytes())
+    }
+
+    /// Reads the segment data starting at the given offset, decompressing the frame containing
+    /// the offset unless it is the last decompressed frame kept in `cache`.
+    fn read_at(
+        &self,
+        buf: &mut [u8],
+        pos: u64,
+        cache: &mut Option<(usize, Vec<u8>)>,
+    ) -> io::Result<usize> {
+        let no = (pos / FRAME_LEN as u64) as usize;
+        if no + 1 >= self.offsets.len() {
+            return Ok(0);
+        }
+        let frame = match cache {
+            Some((cached, frame)) if *cached == no => frame,
+            cache => {
+                let mut data = vec![0u8; (self.offsets[no + 1] - self.offsets[no]) as usize];
+                read_exact_at(&self.file, &mut data, self.offsets[no])?;
+                &mut cache
+                    .insert((no, zstd::bulk::decompress(&data, FRAME_LEN)?))
+                    .1
+            }
+        };
+        let start = ((pos % FRAME_LEN as u64) as usize).min(frame.len());
+        let len = buf.len().min(frame.len() - start);
+        buf[..len].copy_from_slice(&frame[start..start + len]);
+        Ok(len)
+    }
+}
+
+#[cfg(all(feature = "zstd", any(unix, windows)))]
+fn read_exact_at(file: &File, buf: &mut [u8], pos: u64) -> io::Result<()> {
+    super::pread::PosReader::new(file, pos).read_exact(buf)
+}
+
+#[cfg(all(feature = "zstd", not(any(unix, windows))))]
+fn read_exact_at(mut file: &File, buf: &mut [u8], pos: u64) -> io::Result<()> {
+    file.seek(SeekFrom::Start(pos))?;
+    file.read_exact(buf)
+}
+
+/// Read-only handle of a log file for positioned reads.
+#[derive(Debug)]
+#[cfg(any(unix, windows))]
+enum SharedSegment {
+    /// Uncompressed file with the offset of the data in it.
+    Plain(u64, File),
+    #[cfg(feature = "zstd")]
+    Compressed(Frames),
+}
+
+/// Read-only handles of the log files, allowing positioned reads from multiple threads.
+#[derive(Debug)]
+#[cfg(any(unix, windows))]
+pub(crate) struct SharedLog {
+    /// Log position of the first byte of each file with the file handle.
+    files: Vec<(u64, SharedSegment)>,
+}
+
+#[cfg(any(unix, windows))]
+impl SharedLog {
+    /// Returns reader starting at the given log position.
+    pub fn reader(&self, pos: u64) -> LogReader<'_> {
+        let no = self
+            .files
+            .partition_point(|(start, _)| *start <= pos)
+            .saturating_sub(1);
+        let (start, segment) = &self.files[no];
+        match segment {
+            SharedSegment::Plain(offset, file) => {
+                LogReader::Plain(super::pread::PosReader::new(file, pos - start + offset))
+            }
+            #[cfg(feature = "zstd")]
+            SharedSegment::Compressed(frames) => {
+                LogReader::Compressed { frames, pos: pos - start, frame: None }
+            }
+        }
+    }
+}
+
+/// Reader of a log file performing positioned reads, returned by [`SharedLog::reader`].
+#[derive(Debug)]
+#[cfg(any(unix, windows))]
+pub(crate) enum LogReader<'log> {
+    Plain(super::pread::PosReader<'log>),
+    #[cfg(feature = "zstd")]
+    Compressed {
+        frames: &'log Frames,
+        pos: u64,
+        frame: Option<(usize, Vec<u8>)>,
+    },
+}
+
+#[cfg(any(unix, windows))]
+impl Read for LogReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(reader) => reader.read(buf),
+            #[cfg(feature = "zstd")]
+            Self::Compressed { frames, pos, frame } => {
+                let count = frames.read_at(buf, *pos, frame)?;
+                *pos += count as u64;
+                Ok(count)
+            }
+        }
+    }
+}
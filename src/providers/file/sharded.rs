@@ -0,0 +1,267 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io;
+use std::path::Path;
+
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use super::latency::Latencies;
+use super::observer::{AoraObserver, Observer};
+use super::{AoraError, FileAoraMap, StorageStats};
+use crate::{AoraKey, AoraMap};
+
+/// Append-only map which routes keys to several [`FileAoraMap`] shards by the key prefix.
+///
+/// Each shard has its own log and index files (`name-000.log`, `name-000.idx`, …), which keeps
+/// the per-file index size small and allows writing to different shards in parallel.
+///
+/// Keys are distributed by their first two bytes, so the shards hold continuous key ranges. Thus,
+/// the keys must be uniformly distributed (as hashes are) for the shards to be balanced.
+#[derive(Debug)]
+pub struct FileShardedMap<K, V, const MAGIC: u64, const VER: u16 = 1, const KEY_LEN: usize = 32>
+where K: AoraKey<KEY_LEN>
+{
+    shards: Vec<FileAoraMap<K, V, MAGIC, VER, KEY_LEN>>,
+}
+
+impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize>
+    FileShardedMap<K, V, MAGIC, VER, KEY_LEN>
+where K: AoraKey<KEY_LEN>
+{
+    fn shard_name(name: &str, no: usize) -> String { format!("{name}-{no:03}") }
+
+    /// Creates a new map with the given number of shards.
+    ///
+    /// # Panics
+    ///
+    /// If the number of shards is zero or exceeds 1000.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(path), err))]
+    pub fn create_new(path: impl AsRef<Path>, name: &str, shards: usize) -> io::Result<Self> {
+        assert!((1..=1000).contains(&shards), "invalid number of shards {shards}");
+        let path = path.as_ref();
+        let shards = (0..shards)
+            .map(|no| FileAoraMap::create_new(path, &Self::shard_name(name, no)))
+            .collect::<io::Result<_>>()?;
+        Ok(Self { shards })
+    }
+
+    /// Opens an existing map, detecting the number of its shards.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(path), err))]
+    pub fn open(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut shards = vec![];
+        while path
+            .join(Self::shard_name(name, shards.len()))
+            .with_extension("idx")
+            .exists()
+        {
+            shards.push(FileAoraMap::open(path, &Self::shard_name(name, shards.len()))?);
+        }
+        if shards.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("sharded AORA map '{name}' does not exist at '{}'", path.display()),
+            ));
+        }
+        Ok(Self { shards })
+    }
+
+    /// Returns number of the shards.
+    pub fn shard_count(&self) -> usize { self.shards.len() }
+
+    /// Returns the number of the shard holding the given key.
+    pub fn shard_for(&self, key: &[u8; KEY_LEN]) -> usize {
+        let prefix = match key.as_slice() {
+            [] => 0,
+            [first] => (*first as usize) << 8,
+            [first, second, ..] => ((*first as usize) << 8) | *second as usize,
+        };
+        (prefix * self.shards.len()) >> 16
+    }
+
+    /// Reports the storage usage summed over all the shards, with the latency percentiles
+    /// computed over the operations in all the shards.
+    pub fn stats(&self) -> io::Result<StorageStats> {
+        let mut stats = StorageStats::default();
+        for shard in &self.shards {
+            stats = stats + shard.stats()?;
+        }
+        let mut tracked = self
+            .shards
+            .iter()
+            .filter_map(FileAoraMap::latencies)
+            .peekable();
+        if tracked.peek().is_some() {
+            let latencies = Latencies::default();
+            tracked.for_each(|shard| latencies.merge(shard));
+            stats.latency = Some(latencies.stats());
+        }
+        Ok(stats)
+    }
+
+    /// Enables tracking of the operation latencies in all the shards, which are reported by
+    /// [`Self::stats`].
+    pub fn track_latencies(&mut self) {
+        self.shards
+            .iter_mut()
+            .for_each(FileAoraMap::track_latencies)
+    }
+
+    /// Sets the observer notified about each key inserted into any of the shards.
+    pub fn set_observer(&mut self, observer: impl AoraObserver + Send + Sync + 'static) {
+        let observer = Observer::new(observer);
+        for shard in &mut self.shards {
+            shard.set_shared_observer(observer.clone());
+        }
+    }
+
+    /// Closes all the shards (see [`FileAoraMap::close`]), reporting the first failure.
+    pub fn close(self) -> Result<(), AoraError> {
+        let mut res = Ok(());
+        for shard in self.shards {
+            let closed = shard.close();
+            if res.is_ok() {
+                res = closed;
+            }
+        }
+        res
+    }
+
+    /// Flushes the buffered writes of all the shards, see [`FileAoraMap::flush`].
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.shards.iter_mut().try_for_each(FileAoraMap::flush)
+    }
+
+    /// Syncs the files of all the shards to the storage device, see [`FileAoraMap::sync`].
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.shards.iter_mut().try_for_each(FileAoraMap::sync)
+    }
+
+    /// Returns references to the underlying shards.
+    pub fn shards(&self) -> &[FileAoraMap<K, V, MAGIC, VER, KEY_LEN>] { &self.shards }
+
+    /// Inserts items, writing to different shards in parallel.
+    #[cfg(feature = "rayon")]
+    pub fn par_insert(&mut self, items: impl IntoIterator<Item = (K, V)>)
+    where
+        K: Send,
+        V: Eq + StrictEncode + StrictDecode + Send,
+    {
+        use rayon::prelude::*;
+
+        let mut batches = (0..self.shards.len())
+            .map(|_| Vec::new())
+            .collect::<Vec<_>>();
+        for (key, value) in items {
+            let key = key.into();
+            batches[self.shard_for(&key)].push((key, value));
+        }
+        self.shards
+            .par_iter_mut()
+            .zip(batches)
+            .for_each(|(shard, batch)| {
+                for (key, value) in batch {
+                    shard.insert(key.into(), &value);
+                }
+            });
+    }
+}
+
+impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize> AoraMap<K, V, KEY_LEN>
+    for FileShardedMap<K, V, MAGIC, VER, KEY_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    V: Eq + StrictEncode + StrictDecode,
+{
+    fn len(&self) -> usize { self.shards.iter().map(FileAoraMap::len).sum() }
+
+    fn contains_key(&self, key: K) -> bool {
+        let key = key.into();
+        self.shards[self.shard_for(&key)].contains_key(key.into())
+    }
+
+    fn get(&self, key: K) -> Option<V> {
+        let key = key.into();
+        self.shards[self.shard_for(&key)].get(key.into())
+    }
+
+    fn insert(&mut self, key: K, value: &V) {
+        let key = key.into();
+        let no = self.shard_for(&key);
+        self.shards[no].insert(key.into(), value)
+    }
+
+    /// Iterates shard by shard, each in the order the items were appended to it.
+    fn iter(&self) -> impl Iterator<Item = (K, V)> { self.shards.iter().flat_map(AoraMap::iter) }
+
+    /// Iterates shard by shard from the last one, each in the reverse order the items were
+    /// appended to it.
+    fn iter_rev(&self) -> impl Iterator<Item = (K, V)> {
+        self.shards.iter().rev().flat_map(AoraMap::iter_rev)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::confinement::SmallVec;
+
+    use super::*;
+
+    type Db = FileShardedMap<[u8; 8], SmallVec<u8>, { u64::from_be_bytes(*b"DUMBTEST") }, 1, 8>;
+
+    fn key(no: u64) -> [u8; 8] { (no << 48).to_be_bytes() }
+    fn val(no: u64) -> SmallVec<u8> { SmallVec::from_checked(no.to_le_bytes().to_vec()) }
+
+    #[test]
+    fn shards() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "sharded", 4).unwrap();
+        assert_eq!(db.shard_for(&key(0)), 0);
+        assert_eq!(db.shard_for(&key(0x4000)), 1);
+        assert_eq!(db.shard_for(&key(0xFFFF)), 3);
+
+        for no in 0..16u64 {
+            db.insert(key(no * 0x1000), &val(no));
+        }
+        assert_eq!(db.len(), 16);
+        assert!(db.shards().iter().all(|shard| shard.len() == 4));
+        assert_eq!(db.get(key(0x5000)), Some(val(5)));
+        assert!(!db.contains_key(key(0x5001)));
+        assert!(db.iter().map(|(_, v)| v).eq((0..16).map(val)));
+        drop(db);
+
+        let db = Db::open(dir.path(), "sharded").unwrap();
+        assert_eq!(db.shard_count(), 4);
+        assert_eq!(db.iter_rev().next(), Some((key(0xF000), val(15))));
+        assert!(Db::open(dir.path(), "missing").is_err());
+    }
+
+    #[test]
+    fn latencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "latencies", 2).unwrap();
+        assert_eq!(db.stats().unwrap().latency, None);
+        db.track_latencies();
+        for no in 0..30u64 {
+            db.insert(key(no * 0x800), &val(no));
+        }
+        for no in 0..5u64 {
+            db.get(key(no));
+        }
+        let latency = db.stats().unwrap().latency.unwrap();
+        assert_eq!(latency.insert.count, 30);
+        assert_eq!(latency.get.count, 5);
+        assert_eq!(latency.commit.count, 0);
+        assert!(latency.insert.p50 <= latency.insert.p99);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn par_insert() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "par_insert", 3).unwrap();
+        db.par_insert((0..300u64).map(|no| (key(no * 0xD5), val(no))));
+        assert_eq!(db.len(), 300);
+        assert_eq!(db.get(key(150 * 0xD5)), Some(val(150)));
+    }
+}
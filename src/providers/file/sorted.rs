@@ -0,0 +1,781 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use binfile::BinFile;
+use strict_encoding::{StreamReader, StrictDecode, StrictEncode, StrictReader, StrictWriter};
+
+use super::budget::{Charge, ENTRY_OVERHEAD, MemoryBudget};
+use super::latency::Latencies;
+use super::observer::{AoraObserver, Observer};
+use super::{AoraError, StorageStats, telemetry};
+use crate::{AoraKey, AoraMap, U128BeTime};
+
+/// Each segment keeps every `SPARSE_INDEX_STEP`-th key in its in-memory sparse index.
+pub const SPARSE_INDEX_STEP: u64 = 64;
+
+/// Default size of the in-memory write buffer (in bytes of encoded values), which is flushed into
+/// a new sorted segment once exceeded.
+pub const DEFAULT_MEMTABLE_LIMIT: usize = 4 * 1024 * 1024;
+
+/// Length of the segment footer: sparse index offset and number of records.
+const FOOTER_LEN: u64 = 16;
+
+/// Immutable sorted run of records persisted in a single file, for which only a sparse index is
+/// kept in memory.
+#[derive(Debug)]
+struct Segment<const KEY_LEN: usize> {
+    path: PathBuf,
+    /// Offset at which the records end and the sparse index starts.
+    data_end: u64,
+    /// Number of the records in the segment.
+    count: u64,
+    /// Every [`SPARSE_INDEX_STEP`]-th key with the offset of its record.
+    sparse: Vec<([u8; KEY_LEN], u64)>,
+}
+
+impl<const KEY_LEN: usize> Segment<KEY_LEN> {
+    fn open<const MAGIC: u64, const VER: u16>(path: PathBuf) -> io::Result<Self> {
+        let mut file = BinFile::<MAGIC, VER>::open(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("at path '{}'", path.display())))?;
+        let file_len = file.metadata()?.len();
+        if file_len < 10 + FOOTER_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("sorted segment file '{}' is corrupted", path.display()),
+            ));
+        }
+
+        let mut buf = [0u8; 8];
+        file.seek(SeekFrom::Start(file_len - FOOTER_LEN))?;
+        file.read_exact(&mut buf)?;
+        let data_end = u64::from_le_bytes(buf);
+        file.read_exact(&mut buf)?;
+        let count = u64::from_le_bytes(buf);
+
+        file.seek(SeekFrom::Start(data_end))?;
+        let mut reader = BufReader::new(&mut *file);
+        let mut sparse = Vec::with_capacity(count.div_ceil(SPARSE_INDEX_STEP) as usize);
+        let mut key = [0u8; KEY_LEN];
+        for _ in 0..count.div_ceil(SPARSE_INDEX_STEP) {
+            reader.read_exact(&mut key)?;
+            reader.read_exact(&mut buf)?;
+            sparse.push((key, u64::from_le_bytes(buf)));
+        }
+
+        Ok(Self { path, data_end, count, sparse })
+    }
+
+    /// Writes records, which must be provided in the strictly increasing key order, into a new
+    /// segment file.
+    fn write<const MAGIC: u64, const VER: u16>(
+        path: PathBuf,
+        records: impl Iterator<Item = io::Result<([u8; KEY_LEN], Vec<u8>)>>,
+    ) -> io::Result<Self> {
+        let file = BinFile::<MAGIC, VER>::create_new(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("at path '{}'", path.display())))?;
+        let mut writer = io::BufWriter::new(file);
+        let mut pos = 10u64;
+        let mut count = 0u64;
+        let mut sparse = vec![];
+        for record in records {
+            let (key, val) = record?;
+            if count % SPARSE_INDEX_STEP == 0 {
+                sparse.push((key, pos));
+            }
+            writer.write_all(&key)?;
+            writer.write_all(&(val.len() as u32).to_le_bytes())?;
+            writer.write_all(&val)?;
+            pos += KEY_LEN as u64 + 4 + val.len() as u64;
+            count += 1;
+        }
+        let data_end = pos;
+        for (key, offset) in &sparse {
+            writer.write_all(key)?;
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        writer.write_all(&data_end.to_le_bytes())?;
+        writer.write_all(&count.to_le_bytes())?;
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()?;
+
+        Ok(Self { path, data_end, count, sparse })
+    }
+
+    /// Opens a cursor positioned at the start of the block which may contain the `start` key.
+    fn cursor(&self, start: Bound<&[u8; KEY_LEN]>) -> io::Result<SegmentCursor<KEY_LEN>> {
+        let block = match start {
+            Bound::Included(key) | Bound::Excluded(key) => self
+                .sparse
+                .partition_point(|(k, _)| k <= key)
+                .saturating_sub(1),
+            Bound::Unbounded => 0,
+        };
+        let pos = self
+            .sparse
+            .get(block)
+            .map(|(_, pos)| *pos)
+            .unwrap_or(self.data_end);
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(pos))?;
+        Ok(SegmentCursor { reader: BufReader::new(file), pos, end: self.data_end })
+    }
+
+    /// Reads all records of a block starting at the given sparse index entry.
+    fn read_block(&self, block: usize) -> io::Result<Vec<([u8; KEY_LEN], Vec<u8>)>> {
+        let Some((_, pos)) = self.sparse.get(block) else {
+            return Ok(vec![]);
+        };
+        let end = self
+            .sparse
+            .get(block + 1)
+            .map(|(_, pos)| *pos)
+            .unwrap_or(self.data_end);
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(*pos))?;
+        let mut cursor = SegmentCursor { reader: BufReader::new(file), pos: *pos, end };
+        let mut records = Vec::with_capacity(SPARSE_INDEX_STEP as usize);
+        while let Some(record) = cursor.next_record()? {
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    fn get(&self, key: &[u8; KEY_LEN]) -> io::Result<Option<Vec<u8>>> {
+        if self.sparse.first().map_or(true, |(first, _)| key < first) {
+            return Ok(None);
+        }
+        let mut cursor = self.cursor(Bound::Included(key))?;
+        while let Some((k, val)) = cursor.next_record()? {
+            if &k == key {
+                return Ok(Some(val));
+            }
+            if &k > key {
+                break;
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Sequential reader over the records of a single segment.
+struct SegmentCursor<const KEY_LEN: usize> {
+    reader: BufReader<File>,
+    pos: u64,
+    end: u64,
+}
+
+impl<const KEY_LEN: usize> SegmentCursor<KEY_LEN> {
+    fn next_record(&mut self) -> io::Result<Option<([u8; KEY_LEN], Vec<u8>)>> {
+        if self.pos >= self.end {
+            return Ok(None);
+        }
+        let mut key = [0u8; KEY_LEN];
+        self.reader.read_exact(&mut key)?;
+        let mut len = [0u8; 4];
+        self.reader.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len);
+        let mut val = vec![0u8; len as usize];
+        self.reader.read_exact(&mut val)?;
+        self.pos += KEY_LEN as u64 + 4 + len as u64;
+        Ok(Some((key, val)))
+    }
+}
+
+/// Append-only map for the datasets which are too large to keep their index in memory.
+///
+/// New items are buffered in a sorted in-memory write buffer (memtable), which is flushed as an
+/// immutable sorted run (segment) once it grows above a limit. Each segment keeps just a sparse
+/// index in memory; lookups binary-search it and scan a single block from the disk. Segments can be
+/// merged into one with [`Self::merge`].
+///
+/// NB: This is blocking
+#[derive(Debug)]
+pub struct FileSortedMap<K, V, const MAGIC: u64, const VER: u16 = 1, const KEY_LEN: usize = 32>
+where K: AoraKey<KEY_LEN>
+{
+    path: PathBuf,
+    name: String,
+    /// Segments ordered from the oldest to the newest.
+    segments: Vec<Segment<KEY_LEN>>,
+    next_segment: u32,
+    memtable: BTreeMap<[u8; KEY_LEN], Vec<u8>>,
+    memtable_size: usize,
+    memtable_limit: usize,
+    /// Memory charged for the write buffer, if a memory budget is set.
+    memory: Option<Charge>,
+    observer: Option<Observer>,
+    latencies: Option<Latencies>,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize>
+    FileSortedMap<K, V, MAGIC, VER, KEY_LEN>
+where K: AoraKey<KEY_LEN>
+{
+    fn segment_path(&self, no: u32) -> PathBuf {
+        self.path.join(format!("{}.{no:04}.sst", self.name))
+    }
+
+    fn scan(path: &Path, name: &str) -> io::Result<Vec<(u32, PathBuf)>> {
+        let prefix = format!("{name}.");
+        let mut found = vec![];
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(no) = file_name
+                .to_str()
+                .and_then(|s| s.strip_prefix(&prefix))
+                .and_then(|s| s.strip_suffix(".sst"))
+                .and_then(|s| s.parse::<u32>().ok())
+            else {
+                continue;
+            };
+            found.push((no, entry.path()));
+        }
+        found.sort();
+        Ok(found)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(path), err))]
+    pub fn create_new(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        let path = path.as_ref();
+        if !Self::scan(path, name)?.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("sorted segments '{name}' already exist at '{}'", path.display()),
+            ));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            name: name.to_string(),
+            segments: vec![],
+            next_segment: 0,
+            memtable: BTreeMap::new(),
+            memtable_size: 0,
+            memtable_limit: DEFAULT_MEMTABLE_LIMIT,
+            memory: None,
+            observer: None,
+            latencies: None,
+            _phantom: PhantomData,
+        })
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(path), err))]
+    pub fn open(path: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        let path = path.as_ref();
+        let found = Self::scan(path, name)?;
+        let next_segment = found.last().map(|(no, _)| no + 1).unwrap_or_default();
+        let segments = found
+            .into_iter()
+            .map(|(_, path)| Segment::open::<MAGIC, VER>(path))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            name: name.to_string(),
+            segments,
+            next_segment,
+            memtable: BTreeMap::new(),
+            memtable_size: 0,
+            memtable_limit: DEFAULT_MEMTABLE_LIMIT,
+            memory: None,
+            observer: None,
+            latencies: None,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Sets the size of the in-memory write buffer (in bytes of encoded values), after which it is
+    /// flushed into a new segment.
+    pub fn set_memtable_limit(&mut self, limit: usize) { self.memtable_limit = limit; }
+
+    /// Charges the memory used by the write buffer to the budget. Once the budget is exceeded,
+    /// the write buffer is flushed into a new segment, even if it is below the memtable limit.
+    pub fn set_memory_budget(&mut self, budget: MemoryBudget) {
+        let mut charge = Charge::new(budget);
+        charge.set(self.memtable_memory());
+        self.memory = Some(charge);
+    }
+
+    /// Returns the estimated size of the write buffer in memory, in bytes.
+    fn memtable_memory(&self) -> usize {
+        self.memtable_size + self.memtable.len() * (KEY_LEN + ENTRY_OVERHEAD)
+    }
+
+    /// Reports the size of the segment files, the number of the records in them and the number
+    /// of the records in the in-memory write buffer.
+    ///
+    /// Records overwritten in the newer segments are counted once per segment until the segments
+    /// are merged.
+    pub fn stats(&self) -> io::Result<StorageStats> {
+        let mut log_size = 0;
+        for segment in &self.segments {
+            log_size += fs::metadata(&segment.path)?.len();
+        }
+        Ok(StorageStats {
+            log_size,
+            idx_size: 0,
+            records: self.segments.iter().map(|s| s.count as usize).sum(),
+            pages: 0,
+            pending: self.memtable.len(),
+            latency: self.latencies.as_ref().map(Latencies::stats),
+        })
+    }
+
+    /// Enables tracking of the operation latencies, which are reported by [`Self::stats`].
+    pub fn track_latencies(&mut self) { self.latencies = Some(Latencies::default()); }
+
+    /// Sets the observer notified about each inserted key.
+    pub fn set_observer(&mut self, observer: impl AoraObserver + Send + Sync + 'static) {
+        self.observer = Some(Observer::new(observer));
+    }
+
+    /// Closes the map, flushing the write buffer into a new segment and reporting the failures,
+    /// which are lost when the map is just dropped. The segments are synced to the storage
+    /// device once written.
+    pub fn close(mut self) -> Result<(), AoraError> {
+        let res = self.flush();
+        if res.is_err() {
+            // The failure is reported, so dropping the map must not retry the flush and panic
+            self.memtable.clear();
+        }
+        Ok(res?)
+    }
+
+    /// Flushes the write buffer into a new segment like [`Self::flush`]; since the segments are
+    /// synced to the storage device once written, this makes all the inserted items durable.
+    pub fn sync(&mut self) -> io::Result<()> { self.flush() }
+
+    /// Returns number of the sorted segments persisted on disk.
+    pub fn segment_count(&self) -> usize { self.segments.len() }
+
+    /// Writes the in-memory write buffer as a new sorted segment. Does nothing if the buffer is
+    /// empty.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(table = %self.name), err))]
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.memtable.is_empty() {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let path = self.segment_path(self.next_segment);
+        let records = self.memtable.iter().map(|(k, v)| Ok((*k, v.clone())));
+        let segment = Segment::write::<MAGIC, VER>(path, records)?;
+        telemetry::written(&self.name, fs::metadata(&segment.path)?.len());
+        telemetry::committed(&self.name, start);
+        if let Some(latencies) = &self.latencies {
+            latencies.commit.record(start);
+        }
+        self.segments.push(segment);
+        self.next_segment += 1;
+        self.memtable.clear();
+        self.memtable_size = 0;
+        if let Some(charge) = &mut self.memory {
+            charge.set(0);
+        }
+        Ok(())
+    }
+
+    /// Merges all segments (and the write buffer) into a single sorted segment, streaming the
+    /// records so that the memory use does not depend on the size of the data.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(table = %self.name), err))]
+    pub fn merge(&mut self) -> io::Result<()> {
+        self.flush()?;
+        if self.segments.len() <= 1 {
+            return Ok(());
+        }
+        let path = self.segment_path(self.next_segment);
+        let merged = {
+            let records = MergeIter::new(
+                &self.segments,
+                &self.memtable,
+                (Bound::Unbounded, Bound::Unbounded),
+            )?;
+            Segment::write::<MAGIC, VER>(path, records)?
+        };
+        for segment in self.segments.drain(..) {
+            fs::remove_file(&segment.path)?;
+        }
+        self.segments.push(merged);
+        self.next_segment += 1;
+        Ok(())
+    }
+
+    fn get_raw(&self, key: &[u8; KEY_LEN]) -> io::Result<Option<Vec<u8>>> {
+        if let Some(val) = self.memtable.get(key) {
+            telemetry::cache(&self.name, true);
+            return Ok(Some(val.clone()));
+        }
+        telemetry::cache(&self.name, false);
+        for segment in self.segments.iter().rev() {
+            if let Some(val) = segment.get(key)? {
+                return Ok(Some(val));
+            }
+        }
+        Ok(None)
+    }
+
+    fn decode(val: Vec<u8>) -> V
+    where V: StrictDecode {
+        let mut reader = StrictReader::with(StreamReader::in_memory::<{ usize::MAX }>(val));
+        V::strict_decode(&mut reader).expect("unable to read item")
+    }
+
+    /// Iterates over the items with keys within the given range, in the order of the key bytes.
+    ///
+    /// Only a single block per segment is kept in memory during the iteration.
+    pub fn range(&self, range: impl RangeBounds<K>) -> impl Iterator<Item = (K, V)> + '_
+    where
+        K: Copy,
+        V: StrictDecode,
+    {
+        let bounds =
+            (range.start_bound().map(|k| (*k).into()), range.end_bound().map(|k| (*k).into()));
+        MergeIter::new(&self.segments, &self.memtable, bounds)
+            .expect("unable to open sorted segments")
+            .map(|res| {
+                let (key, val) = res.expect("unable to read sorted segment");
+                (K::from(key), Self::decode(val))
+            })
+    }
+}
+
+impl<V, const MAGIC: u64, const VER: u16> FileSortedMap<U128BeTime, V, MAGIC, VER, 16> {
+    /// Iterates over the items with the key timestamps within the given range, in the
+    /// chronological order.
+    pub fn time_range(
+        &self,
+        range: impl RangeBounds<u64>,
+    ) -> impl Iterator<Item = (U128BeTime, V)> + '_
+    where
+        V: StrictDecode,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(t) => Bound::Included(U128BeTime::first_at(*t)),
+            Bound::Excluded(t) => Bound::Excluded(U128BeTime::last_at(*t)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(t) => Bound::Included(U128BeTime::last_at(*t)),
+            Bound::Excluded(t) => Bound::Excluded(U128BeTime::first_at(*t)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        self.range((start, end))
+    }
+}
+
+impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize> AoraMap<K, V, KEY_LEN>
+    for FileSortedMap<K, V, MAGIC, VER, KEY_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    V: Eq + StrictEncode + StrictDecode,
+{
+    fn len(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|s| s.count as usize)
+            .sum::<usize>()
+            + self.memtable.len()
+    }
+
+    fn contains_key(&self, key: K) -> bool {
+        self.get_raw(&key.into())
+            .expect("unable to read sorted segment")
+            .is_some()
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all, fields(table = %self.name)))]
+    fn get(&self, key: K) -> Option<V> {
+        telemetry::got(&self.name);
+        let start = Instant::now();
+        let val = self
+            .get_raw(&key.into())
+            .expect("unable to read sorted segment");
+        if let Some(latencies) = &self.latencies {
+            latencies.get.record(start);
+        }
+        Some(Self::decode(val?))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all, fields(table = %self.name)))]
+    fn insert(&mut self, key: K, value: &V) {
+        let start = Instant::now();
+        let key = key.into();
+        let val = value
+            .strict_encode(StrictWriter::in_memory::<{ usize::MAX }>())
+            .expect("unable to encode item")
+            .unbox()
+            .unconfine();
+        if let Some(old) = self.get_raw(&key).expect("unable to read sorted segment") {
+            if old != val {
+                panic!(
+                    "item under the given id is different from another item under the same id \
+                     already present in the log"
+                );
+            }
+            return;
+        }
+        telemetry::inserted(&self.name, 1);
+        self.memtable_size += val.len();
+        self.memtable.insert(key, val);
+        if let Some(latencies) = &self.latencies {
+            latencies.insert.record(start);
+        }
+        if let Some(observer) = &self.observer {
+            observer.on_insert(&key);
+        }
+        let memory = self.memtable_memory();
+        if let Some(charge) = &mut self.memory {
+            charge.set(memory);
+        }
+        let over_budget = self
+            .memory
+            .as_ref()
+            .is_some_and(|charge| charge.budget().is_exceeded());
+        if self.memtable_size > self.memtable_limit || over_budget {
+            self.flush().expect("unable to write sorted segment");
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (K, V)> {
+        MergeIter::new(&self.segments, &self.memtable, (Bound::Unbounded, Bound::Unbounded))
+            .expect("unable to open sorted segments")
+            .map(|res| {
+                let (key, val) = res.expect("unable to read sorted segment");
+                (K::from(key), Self::decode(val))
+            })
+    }
+
+    /// Since the sorted map doesn't keep the order of the insertion within a segment, the items
+    /// are returned segment by segment from the newest to the oldest, each in the descending key
+    /// order. Only a single block is kept in memory during the iteration.
+    fn iter_rev(&self) -> impl Iterator<Item = (K, V)> {
+        let memtable = self.memtable.iter().rev().map(|(k, v)| (*k, v.clone()));
+        let segments = self.segments.iter().rev().flat_map(|segment| {
+            (0..segment.sparse.len()).rev().flat_map(move |block| {
+                segment
+                    .read_block(block)
+                    .expect("unable to read sorted segment")
+                    .into_iter()
+                    .rev()
+            })
+        });
+        memtable
+            .chain(segments)
+            .map(|(key, val)| (K::from(key), Self::decode(val)))
+    }
+}
+
+impl<K, V, const MAGIC: u64, const VER: u16, const KEY_LEN: usize> Drop
+    for FileSortedMap<K, V, MAGIC, VER, KEY_LEN>
+where K: AoraKey<KEY_LEN>
+{
+    fn drop(&mut self) { self.flush().expect("unable to write sorted segment"); }
+}
+
+/// K-way merge over the sorted segments and the write buffer, restricted to a key range.
+struct MergeIter<'a, const KEY_LEN: usize> {
+    cursors: Vec<SegmentCursor<KEY_LEN>>,
+    heads: Vec<Option<([u8; KEY_LEN], Vec<u8>)>>,
+    memtable: std::collections::btree_map::Range<'a, [u8; KEY_LEN], Vec<u8>>,
+    memtable_head: Option<([u8; KEY_LEN], Vec<u8>)>,
+    bounds: (Bound<[u8; KEY_LEN]>, Bound<[u8; KEY_LEN]>),
+}
+
+impl<'a, const KEY_LEN: usize> MergeIter<'a, KEY_LEN> {
+    fn new(
+        segments: &[Segment<KEY_LEN>],
+        memtable: &'a BTreeMap<[u8; KEY_LEN], Vec<u8>>,
+        bounds: (Bound<[u8; KEY_LEN]>, Bound<[u8; KEY_LEN]>),
+    ) -> io::Result<Self> {
+        let mut cursors = Vec::with_capacity(segments.len());
+        let mut heads = Vec::with_capacity(segments.len());
+        for segment in segments {
+            let mut cursor = segment.cursor(bounds.0.as_ref())?;
+            heads.push(Self::seek_start(&mut cursor, &bounds.0)?);
+            cursors.push(cursor);
+        }
+        let mut memtable = memtable.range(bounds);
+        let memtable_head = memtable.next().map(|(k, v)| (*k, v.clone()));
+        Ok(Self { cursors, heads, memtable, memtable_head, bounds })
+    }
+
+    fn seek_start(
+        cursor: &mut SegmentCursor<KEY_LEN>,
+        start: &Bound<[u8; KEY_LEN]>,
+    ) -> io::Result<Option<([u8; KEY_LEN], Vec<u8>)>> {
+        while let Some((key, val)) = cursor.next_record()? {
+            let after_start = match start {
+                Bound::Included(start) => &key >= start,
+                Bound::Excluded(start) => &key > start,
+                Bound::Unbounded => true,
+            };
+            if after_start {
+                return Ok(Some((key, val)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn before_end(&self, key: &[u8; KEY_LEN]) -> bool {
+        match &self.bounds.1 {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        }
+    }
+}
+
+impl<const KEY_LEN: usize> Iterator for MergeIter<'_, KEY_LEN> {
+    type Item = io::Result<([u8; KEY_LEN], Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let min = self
+            .heads
+            .iter()
+            .chain([&self.memtable_head])
+            .filter_map(|head| head.as_ref().map(|(key, _)| *key))
+            .min()?;
+        if !self.before_end(&min) {
+            return None;
+        }
+
+        // The same key may be present in several segments only with the same value, so we keep a
+        // single copy and advance all sources positioned at it.
+        let mut found = None;
+        for (head, cursor) in self.heads.iter_mut().zip(&mut self.cursors) {
+            if head.as_ref().is_some_and(|(key, _)| *key == min) {
+                found = head.take();
+                *head = match cursor.next_record() {
+                    Ok(next) => next,
+                    Err(err) => return Some(Err(err)),
+                };
+            }
+        }
+        if self
+            .memtable_head
+            .as_ref()
+            .is_some_and(|(key, _)| *key == min)
+        {
+            found = self.memtable_head.take();
+            self.memtable_head = self.memtable.next().map(|(k, v)| (*k, v.clone()));
+        }
+        found.map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::confinement::SmallVec;
+
+    use super::*;
+
+    type Db = FileSortedMap<[u8; 8], SmallVec<u8>, { u64::from_be_bytes(*b"DUMBTEST") }, 1, 8>;
+
+    fn val(no: u64) -> SmallVec<u8> { SmallVec::from_checked(no.to_le_bytes().to_vec()) }
+
+    #[test]
+    fn segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "segments").unwrap();
+        db.set_memtable_limit(8 * 100);
+
+        // Insert in reverse order to check the sorting
+        for no in (0..1000u64).rev() {
+            db.insert((no * 2).to_be_bytes(), &val(no));
+        }
+        assert_eq!(db.segment_count(), 12);
+        assert_eq!(db.len(), 1000);
+        assert_eq!(db.get(20u64.to_be_bytes()), Some(val(10)));
+        assert_eq!(db.get(21u64.to_be_bytes()), None);
+        assert!(db.contains_key(1998u64.to_be_bytes()));
+        assert!(!db.contains_key(2000u64.to_be_bytes()));
+
+        let range = db
+            .range(100u64.to_be_bytes()..110u64.to_be_bytes())
+            .map(|(k, _)| u64::from_be_bytes(k))
+            .collect::<Vec<_>>();
+        assert_eq!(range, vec![100, 102, 104, 106, 108]);
+        assert_eq!(db.iter().count(), 1000);
+        let latest = db.iter_rev().take(3).map(|(k, _)| u64::from_be_bytes(k));
+        assert_eq!(latest.collect::<Vec<_>>(), vec![54, 52, 50]);
+        assert_eq!(db.iter_rev().count(), 1000);
+        drop(db);
+
+        let mut db = Db::open(dir.path(), "segments").unwrap();
+        assert_eq!(db.segment_count(), 13);
+        assert_eq!(db.len(), 1000);
+        db.merge().unwrap();
+        assert_eq!(db.segment_count(), 1);
+        assert_eq!(db.len(), 1000);
+        assert_eq!(db.get(1998u64.to_be_bytes()), Some(val(999)));
+        let keys = db
+            .iter()
+            .map(|(k, _)| u64::from_be_bytes(k))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, (0..1000u64).map(|no| no * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn memory_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Db::create_new(dir.path(), "budget").unwrap();
+        // Each value takes 10 bytes, plus the key and the entry overhead
+        let budget = MemoryBudget::new(50 * (10 + 8 + ENTRY_OVERHEAD));
+        db.set_memory_budget(budget.clone());
+        for no in 0..200u64 {
+            db.insert(no.to_be_bytes(), &val(no));
+        }
+        assert_eq!(db.segment_count(), 3);
+        assert_eq!(budget.used(), 47 * (10 + 8 + ENTRY_OVERHEAD));
+        assert_eq!(db.get(100u64.to_be_bytes()), Some(val(100)));
+
+        drop(db);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn time_range() {
+        type Events =
+            FileSortedMap<U128BeTime, SmallVec<u8>, { u64::from_be_bytes(*b"DUMBTEST") }, 1, 16>;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = Events::create_new(dir.path(), "events").unwrap();
+        db.set_memtable_limit(8 * 10);
+        for no in (0..100u64).rev() {
+            // Two events per timestamp, distinguished by the nonce
+            db.insert(U128BeTime::new(no / 2 * 10, no % 2), &val(no));
+        }
+        let times = |range| {
+            db.time_range(range)
+                .map(|(k, v)| (k.timestamp, v))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(times((Bound::Included(100), Bound::Excluded(120))), vec![
+            (100, val(20)),
+            (100, val(21)),
+            (110, val(22)),
+            (110, val(23))
+        ]);
+        assert_eq!(times((Bound::Excluded(100), Bound::Included(110))), vec![
+            (110, val(22)),
+            (110, val(23))
+        ]);
+        assert_eq!(times((Bound::Included(101), Bound::Excluded(110))), vec![]);
+        assert_eq!(db.time_range(..).count(), 100);
+        assert_eq!(db.time_range(480..).count(), 4);
+
+        let key = U128BeTime::new(0x0102, 0x0304);
+        assert_eq!(U128BeTime::from(<[u8; 16]>::from(key)), key);
+        assert!(
+            <[u8; 16]>::from(U128BeTime::last_at(1)) < <[u8; 16]>::from(U128BeTime::first_at(2))
+        );
+    }
+}
@@ -0,0 +1,706 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use binfile::BinFile;
+use indexmap::IndexMap;
+
+use super::advice::{Access, advise};
+use super::budget::ENTRY_OVERHEAD;
+use super::handle::open_shared;
+
+/// Length of the header of the files created with [`BinFile`].
+const HEADER_LEN: u64 = 10;
+
+/// Number of the keys appended to the full index, reaching which they are frozen into a run
+/// shared with the reader handles.
+const TAIL_LEN: usize = 4096;
+
+/// Number of the entries sorted in memory at once when building a sorted index file for a large
+/// number of keys. Each sorted run is spilled into a temporary file, and the runs are merged.
+pub(crate) const SORT_RUN_LEN: usize = 1 << 20;
+
+/// In-memory index of the log keys: either a full map of all keys, or a sparse index backed by a
+/// sorted index file.
+#[derive(Debug)]
+pub(crate) enum KeyIndex<const MAGIC: u64, const VER: u16, const KEY_LEN: usize> {
+    Full(FullIndex<KEY_LEN>),
+    Sparse(SparseIndex<MAGIC, VER, KEY_LEN>),
+}
+
+impl<const MAGIC: u64, const VER: u16, const KEY_LEN: usize> Default
+    for KeyIndex<MAGIC, VER, KEY_LEN>
+{
+    fn default() -> Self { Self::Full(default!()) }
+}
+
+impl<const MAGIC: u64, const VER: u16, const KEY_LEN: usize> KeyIndex<MAGIC, VER, KEY_LEN> {
+    /// Returns the number of the keys in the index.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Full(index) => index.len(),
+            Self::Sparse(index) => index.count,
+        }
+    }
+
+    /// Returns the number of the entries of the `.idx` file, which exceeds the number of the keys
+    /// if some keys got duplicate entries.
+    pub fn entries(&self) -> usize {
+        match self {
+            Self::Full(index) => index.entries(),
+            Self::Sparse(index) => index.count,
+        }
+    }
+
+    /// Returns the number of the `.idx` file entry holding the log position of the key with the
+    /// given number in the append order. The value hashes and the data keys are stored per entry.
+    pub fn entry_no(&self, no: usize) -> usize {
+        match self {
+            Self::Full(index) => index.entry_no(no),
+            // The sparse index numbers the keys by their entries
+            Self::Sparse(_) => no,
+        }
+    }
+
+    /// Returns the number of the key in the append order and its log position.
+    pub fn get_full(&self, key: &[u8; KEY_LEN]) -> io::Result<Option<(usize, u64)>> {
+        match self {
+            Self::Full(index) => Ok(index.get_full(key)),
+            Self::Sparse(index) => index.get(key),
+        }
+    }
+
+    /// Returns the key with the given number in the append order and its log position.
+    pub fn get_index(&self, no: usize) -> io::Result<Option<([u8; KEY_LEN], u64)>> {
+        match self {
+            Self::Full(index) => Ok(index.get_index(no)),
+            Self::Sparse(index) => index.get_index(no),
+        }
+    }
+
+    pub fn contains_key(&self, key: &[u8; KEY_LEN]) -> io::Result<bool> {
+        self.get_full(key).map(|entry| entry.is_some())
+    }
+
+    /// Returns the last appended key with its log position.
+    pub fn last(&self) -> io::Result<Option<([u8; KEY_LEN], u64)>> {
+        match self.len() {
+            0 => Ok(None),
+            len => self.get_index(len - 1),
+        }
+    }
+
+    /// Returns the size of the sorted index file (if any), in bytes.
+    pub fn disk_size(&self) -> io::Result<u64> {
+        match self {
+            Self::Full(_) => Ok(0),
+            Self::Sparse(index) => Ok(index.sorted.metadata()?.len()),
+        }
+    }
+
+    /// Returns the estimated size of the index in memory, in bytes.
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Self::Full(index) => index.len() * (KEY_LEN + 8 + ENTRY_OVERHEAD),
+            Self::Sparse(index) => {
+                index.sparse.len() * (KEY_LEN + 8)
+                    + index.tail.len() * (KEY_LEN + 16 + ENTRY_OVERHEAD)
+            }
+        }
+    }
+
+    /// Reserves capacity for at least `additional` more keys in the full index. The sparse index
+    /// doesn't keep all the keys in memory, so nothing is reserved for it.
+    pub fn reserve(&mut self, additional: usize) {
+        if let Self::Full(index) = self {
+            index.reserve(additional);
+        }
+    }
+
+    pub fn insert(&mut self, key: [u8; KEY_LEN], pos: u64) -> io::Result<()> {
+        match self {
+            Self::Full(index) => {
+                index.insert(key, pos);
+                Ok(())
+            }
+            Self::Sparse(index) => index.insert(key, pos),
+        }
+    }
+}
+
+/// Full in-memory map of the keys in the append order, which can be cheaply cloned into a
+/// snapshot for the reader handles.
+///
+/// The keys are kept in runs of consecutive keys, which are shared by the snapshots and never
+/// modified while shared, followed by a tail of at most [`TAIL_LEN`] recently appended keys,
+/// which is copied into each snapshot. Once the tail is full, it is frozen into a new run, and
+/// the runs are merged as in a binary counter, so there are at most logarithmically many of them
+/// and each key is copied a logarithmic number of times.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FullIndex<const KEY_LEN: usize> {
+    /// Runs of the keys with the number of the first key of each run.
+    runs: Vec<(usize, Arc<IndexMap<[u8; KEY_LEN], u64>>)>,
+    tail: IndexMap<[u8; KEY_LEN], u64>,
+    /// Total number of the keys.
+    len: usize,
+    /// Number of the keys preceding each duplicate entry of the `.idx` file, which shifts the
+    /// entry numbers of the keys appended after it.
+    shifts: Vec<usize>,
+    /// Numbers of the latest `.idx` file entries of the keys which got duplicate entries.
+    moved: BTreeMap<usize, usize>,
+}
+
+impl<const KEY_LEN: usize> From<IndexMap<[u8; KEY_LEN], u64>> for FullIndex<KEY_LEN> {
+    fn from(map: IndexMap<[u8; KEY_LEN], u64>) -> Self {
+        let len = map.len();
+        let runs = if map.is_empty() { vec![] } else { vec![(0, Arc::new(map))] };
+        Self { runs, tail: IndexMap::new(), len, shifts: vec![], moved: BTreeMap::new() }
+    }
+}
+
+impl<const KEY_LEN: usize> FullIndex<KEY_LEN> {
+    /// Builds the index from the entries of the `.idx` file in their order. A key with several
+    /// entries gets the log position of the last one, but keeps the place of the first one.
+    pub fn load(entries: impl IntoIterator<Item = ([u8; KEY_LEN], u64)>) -> Self {
+        let mut map = IndexMap::new();
+        let mut shifts = vec![];
+        let mut moved = BTreeMap::new();
+        for (entry, (key, pos)) in entries.into_iter().enumerate() {
+            if let (no, Some(_)) = map.insert_full(key, pos) {
+                shifts.push(map.len());
+                moved.insert(no, entry);
+            }
+        }
+        Self { shifts, moved, ..Self::from(map) }
+    }
+
+    pub fn len(&self) -> usize { self.len }
+
+    /// Returns the number of the entries of the `.idx` file, including the duplicate ones.
+    pub fn entries(&self) -> usize { self.len + self.shifts.len() }
+
+    /// Returns the number of the `.idx` file entry holding the log position of the key with the
+    /// given number in the append order.
+    pub fn entry_no(&self, no: usize) -> usize {
+        match self.moved.get(&no) {
+            Some(entry) => *entry,
+            None => no + self.shifts.partition_point(|shift| *shift <= no),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Returns the number of the key in the append order and its log position.
+    pub fn get_full(&self, key: &[u8; KEY_LEN]) -> Option<(usize, u64)> {
+        if let Some((no, _, pos)) = self.tail.get_full(key) {
+            return Some((self.len - self.tail.len() + no, *pos));
+        }
+        self.runs
+            .iter()
+            .find_map(|(start, run)| run.get_full(key).map(|(no, _, pos)| (start + no, *pos)))
+    }
+
+    pub fn contains_key(&self, key: &[u8; KEY_LEN]) -> bool { self.get_full(key).is_some() }
+
+    /// Returns the key with the given number in the append order and its log position.
+    pub fn get_index(&self, no: usize) -> Option<([u8; KEY_LEN], u64)> {
+        let tail_start = self.len - self.tail.len();
+        if no >= tail_start {
+            return self
+                .tail
+                .get_index(no - tail_start)
+                .map(|(key, pos)| (*key, *pos));
+        }
+        let run = self.runs.partition_point(|(start, _)| *start <= no) - 1;
+        let (start, run) = &self.runs[run];
+        run.get_index(no - start).map(|(key, pos)| (*key, *pos))
+    }
+
+    /// Returns an iterator over the keys and their log positions in the append order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&[u8; KEY_LEN], &u64)> + '_ {
+        self.runs
+            .iter()
+            .flat_map(|(_, run)| run.iter())
+            .chain(self.tail.iter())
+    }
+
+    /// Returns the number of the keys the index can hold before reallocating its tail.
+    #[cfg(test)]
+    pub fn capacity(&self) -> usize { self.len + self.tail.capacity() - self.tail.len() }
+
+    /// Reserves capacity for at least `additional` more keys in the tail.
+    pub fn reserve(&mut self, additional: usize) { self.tail.reserve(additional); }
+
+    /// Appends the key. The key already present in the index gets the new log position, but
+    /// keeps its place in the append order, which copies the run containing it if the run is
+    /// shared with a snapshot.
+    pub fn insert(&mut self, key: [u8; KEY_LEN], pos: u64) {
+        let entry = self.entries();
+        for (start, run) in &mut self.runs {
+            if let Some(no) = run.get_index_of(&key) {
+                Arc::make_mut(run).insert(key, pos);
+                self.shifts.push(self.len);
+                self.moved.insert(*start + no, entry);
+                return;
+            }
+        }
+        let tail_start = self.len - self.tail.len();
+        match self.tail.insert_full(key, pos) {
+            (no, Some(_)) => {
+                self.shifts.push(self.len);
+                self.moved.insert(tail_start + no, entry);
+            }
+            (_, None) => self.len += 1,
+        }
+        if self.tail.len() >= TAIL_LEN {
+            self.freeze();
+        }
+    }
+
+    /// Moves the tail into a new run, merging the runs which are not larger than the next ones.
+    fn freeze(&mut self) {
+        let tail = mem::replace(&mut self.tail, IndexMap::with_capacity(TAIL_LEN));
+        self.runs.push((self.len - tail.len(), Arc::new(tail)));
+        while let [.., (_, prev), (_, last)] = &self.runs[..] {
+            if prev.len() > last.len() {
+                break;
+            }
+            let (_, last) = self.runs.pop().expect("at least two runs");
+            let (_, prev) = self.runs.last_mut().expect("at least two runs");
+            // Extends the run in place unless a snapshot still shares it
+            Arc::make_mut(prev).extend(last.iter().map(|(key, pos)| (*key, *pos)));
+        }
+    }
+}
+
+/// Sparse index keeping in memory only every `step`-th key of a sorted index file (`.sidx`),
+/// together with the keys appended since the sorted file was last rewritten.
+///
+/// The sorted file holds the first appended keys with their numbers and log positions, ordered
+/// by the key. Once the number of the recently appended keys exceeds the size of the sparse
+/// index, they are merged into the sorted file. Since the entries after the ones covered by the
+/// sorted file are re-read from the `.idx` file on open, the sorted file never gets out of sync
+/// with the log.
+#[derive(Debug)]
+pub(crate) struct SparseIndex<const MAGIC: u64, const VER: u16, const KEY_LEN: usize> {
+    /// Read handle of the `.idx` file with the entries in the append order.
+    idx: File,
+    /// Total number of the keys.
+    count: usize,
+    path: PathBuf,
+    sorted: BinFile<MAGIC, VER>,
+    /// Number of the keys in the sorted file.
+    sorted_count: usize,
+    step: usize,
+    /// Every `step`-th key of the sorted file with its number in the sorted file.
+    sparse: Vec<([u8; KEY_LEN], usize)>,
+    /// Keys not yet merged into the sorted file with their numbers and log positions.
+    tail: BTreeMap<[u8; KEY_LEN], (usize, u64)>,
+}
+
+impl<const MAGIC: u64, const VER: u16, const KEY_LEN: usize> SparseIndex<MAGIC, VER, KEY_LEN> {
+    const IDX_ENTRY: u64 = KEY_LEN as u64 + 8;
+    const SORTED_ENTRY: u64 = KEY_LEN as u64 + 16;
+
+    /// Opens the sorted index file at `path` (creating it if absent), catching up with the
+    /// entries of the `idx` file appended after the sorted file was written.
+    pub fn open(idx: &Path, path: &Path, step: u64) -> io::Result<Self> {
+        let step = step.max(1) as usize;
+        // Run files of a bulk load interrupted by a crash
+        for no in 0.. {
+            let run = Self::run_path(path, no);
+            if !fs::exists(&run)? {
+                break;
+            }
+            fs::remove_file(&run)?;
+        }
+        if !fs::exists(path)? {
+            fs::rename(Self::write_sorted(path, [].into_iter())?, path)?;
+        }
+        let idx = open_shared(idx)
+            .map_err(|e| io::Error::new(e.kind(), format!("index file '{}'", idx.display())))?;
+        let count = ((idx.metadata()?.len().saturating_sub(HEADER_LEN)) / Self::IDX_ENTRY) as usize;
+        let (sorted, sorted_count, sparse) = Self::open_sorted(path, step)?;
+        if sorted_count > count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("sorted index file '{}' doesn't match the index", path.display()),
+            ));
+        }
+        let mut me = Self {
+            idx,
+            count: sorted_count,
+            path: path.to_path_buf(),
+            sorted,
+            sorted_count,
+            step,
+            sparse,
+            tail: BTreeMap::new(),
+        };
+        if count - sorted_count > step.max(me.sparse.len()) {
+            me.bulk_load(count, SORT_RUN_LEN)?;
+        } else {
+            for no in sorted_count..count {
+                let (key, pos) = me.read_idx(no)?;
+                me.insert(key, pos)?;
+            }
+        }
+        advise(&me.idx, Access::Random);
+        advise(&me.sorted, Access::Random);
+        Ok(me)
+    }
+
+    /// Path of the temporary file of the sorted run with the given number, spilled by
+    /// [`Self::bulk_load`].
+    fn run_path(path: &Path, no: usize) -> PathBuf { path.with_extension(format!("sidx.run{no}")) }
+
+    /// Opens the sorted index file, loading its sparse index.
+    #[allow(clippy::type_complexity)]
+    fn open_sorted(
+        path: &Path,
+        step: usize,
+    ) -> io::Result<(BinFile<MAGIC, VER>, usize, Vec<([u8; KEY_LEN], usize)>)> {
+        let mut sorted = BinFile::<MAGIC, VER>::open(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("sorted index file '{}'", path.display()))
+        })?;
+        let len = sorted.metadata()?.len() - HEADER_LEN;
+        if len % Self::SORTED_ENTRY != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("sorted index file '{}' is corrupted", path.display()),
+            ));
+        }
+        let sorted_count = (len / Self::SORTED_ENTRY) as usize;
+        let mut sparse = Vec::with_capacity(sorted_count.div_ceil(step));
+        for no in (0..sorted_count).step_by(step) {
+            let mut key = [0u8; KEY_LEN];
+            sorted.seek(SeekFrom::Start(HEADER_LEN + no as u64 * Self::SORTED_ENTRY))?;
+            sorted.read_exact(&mut key)?;
+            sparse.push((key, no));
+        }
+        Ok((sorted, sorted_count, sparse))
+    }
+
+    /// Writes the sorted entries into a temporary file next to the sorted index file, returning
+    /// its path.
+    fn write_sorted(
+        path: &Path,
+        entries: impl Iterator<Item = io::Result<([u8; KEY_LEN], (usize, u64))>>,
+    ) -> io::Result<PathBuf> {
+        let tmp = path.with_extension("sidx.tmp");
+        let file = BinFile::<MAGIC, VER>::create(&tmp).map_err(|e| {
+            io::Error::new(e.kind(), format!("sorted index file '{}'", tmp.display()))
+        })?;
+        let mut writer = BufWriter::new(file);
+        for entry in entries {
+            let (key, (no, pos)) = entry?;
+            writer.write_all(&key)?;
+            writer.write_all(&(no as u64).to_le_bytes())?;
+            writer.write_all(&pos.to_le_bytes())?;
+        }
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        Ok(tmp)
+    }
+
+    /// Replaces the sorted index file with the temporary one, and re-opens it.
+    ///
+    /// The sorted file must not be open elsewhere, since on Windows an open file can't be
+    /// replaced.
+    fn replace_sorted(&mut self, tmp: &Path) -> io::Result<()> {
+        // Closes the handle of the sorted file
+        self.sorted = BinFile::open(tmp)?;
+        fs::rename(tmp, &self.path)?;
+        let (sorted, sorted_count, sparse) = Self::open_sorted(&self.path, self.step)?;
+        self.sorted = sorted;
+        self.sorted_count = sorted_count;
+        self.sparse = sparse;
+        advise(&self.sorted, Access::Random);
+        Ok(())
+    }
+
+    fn read_idx(&self, no: usize) -> io::Result<([u8; KEY_LEN], u64)> {
+        let mut idx = &self.idx;
+        let mut key = [0u8; KEY_LEN];
+        let mut pos = [0u8; 8];
+        idx.seek(SeekFrom::Start(HEADER_LEN + no as u64 * Self::IDX_ENTRY))?;
+        idx.read_exact(&mut key)?;
+        idx.read_exact(&mut pos)?;
+        Ok((key, u64::from_le_bytes(pos)))
+    }
+
+    fn read_sorted(reader: &mut impl Read) -> io::Result<([u8; KEY_LEN], (usize, u64))> {
+        let mut key = [0u8; KEY_LEN];
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut key)?;
+        reader.read_exact(&mut buf)?;
+        let no = u64::from_le_bytes(buf) as usize;
+        reader.read_exact(&mut buf)?;
+        Ok((key, (no, u64::from_le_bytes(buf))))
+    }
+
+    pub fn get(&self, key: &[u8; KEY_LEN]) -> io::Result<Option<(usize, u64)>> {
+        if let Some(entry) = self.tail.get(key) {
+            return Ok(Some(*entry));
+        }
+        let block = self.sparse.partition_point(|(first, _)| first <= key);
+        let Some((_, from)) = block.checked_sub(1).map(|block| self.sparse[block]) else {
+            return Ok(None);
+        };
+        let mut sorted = &*self.sorted;
+        sorted.seek(SeekFrom::Start(HEADER_LEN + from as u64 * Self::SORTED_ENTRY))?;
+        let mut reader = BufReader::new(sorted);
+        for _ in from..(from + self.step).min(self.sorted_count) {
+            let (found, entry) = Self::read_sorted(&mut reader)?;
+            if &found == key {
+                return Ok(Some(entry));
+            }
+            if &found > key {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn get_index(&self, no: usize) -> io::Result<Option<([u8; KEY_LEN], u64)>> {
+        if no >= self.count {
+            return Ok(None);
+        }
+        self.read_idx(no).map(Some)
+    }
+
+    /// Adds a key, which must be already written to the `.idx` file under the next number.
+    pub fn insert(&mut self, key: [u8; KEY_LEN], pos: u64) -> io::Result<()> {
+        self.tail.insert(key, (self.count, pos));
+        self.count += 1;
+        if self.tail.len() > self.step.max(self.sparse.len()) {
+            self.merge()?;
+        }
+        Ok(())
+    }
+
+    /// Adds the `.idx` entries up to the number `count` to the sorted index file using an
+    /// external merge sort: the entries are sorted in runs of `run_len`, which are spilled into
+    /// temporary files, and then merged together with the existing sorted file. Thus, the memory
+    /// use doesn't depend on the number of the entries. The run files left by a crash are removed
+    /// when the index is opened.
+    ///
+    /// Must be called when there are no keys pending the merge into the sorted file.
+    fn bulk_load(&mut self, count: usize, run_len: usize) -> io::Result<()> {
+        debug_assert!(self.tail.is_empty());
+        let mut runs = vec![];
+        let mut sorted = open_shared(&self.path)?;
+        advise(&sorted, Access::Sequential);
+        advise(&self.idx, Access::Sequential);
+        sorted.seek(SeekFrom::Start(HEADER_LEN))?;
+        runs.push((BufReader::new(sorted), self.sorted_count));
+
+        let mut idx = &self.idx;
+        idx.seek(SeekFrom::Start(HEADER_LEN + self.count as u64 * Self::IDX_ENTRY))?;
+        let mut reader = BufReader::new(idx);
+        let mut paths = vec![];
+        let res = (|| {
+            for from in (self.count..count).step_by(run_len.max(1)) {
+                let len = run_len.min(count - from);
+                let mut run = Vec::with_capacity(len);
+                for no in from..from + len {
+                    let mut key = [0u8; KEY_LEN];
+                    let mut pos = [0u8; 8];
+                    reader.read_exact(&mut key)?;
+                    reader.read_exact(&mut pos)?;
+                    run.push((key, (no, u64::from_le_bytes(pos))));
+                }
+                run.sort_unstable_by_key(|(key, _)| *key);
+
+                let path = Self::run_path(&self.path, paths.len());
+                let mut file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&path)?;
+                paths.push(path);
+                let mut writer = BufWriter::new(&mut file);
+                for (key, (no, pos)) in run {
+                    writer.write_all(&key)?;
+                    writer.write_all(&(no as u64).to_le_bytes())?;
+                    writer.write_all(&pos.to_le_bytes())?;
+                }
+                writer.flush()?;
+                drop(writer);
+                file.rewind()?;
+                runs.push((BufReader::new(file), len));
+            }
+
+            let mut heads = BinaryHeap::new();
+            let next = |runs: &mut Vec<(BufReader<File>, usize)>, no: usize| {
+                let (reader, left) = &mut runs[no];
+                if *left == 0 {
+                    return Ok(None);
+                }
+                *left -= 1;
+                Self::read_sorted(reader).map(Some)
+            };
+            for no in 0..runs.len() {
+                if let Some(entry) = next(&mut runs, no)? {
+                    heads.push(Reverse((entry, no)));
+                }
+            }
+            let merged = core::iter::from_fn(|| {
+                let Reverse((entry, no)) = heads.pop()?;
+                match next(&mut runs, no) {
+                    Ok(Some(head)) => heads.push(Reverse((head, no))),
+                    Ok(None) => {}
+                    Err(err) => return Some(Err(err)),
+                }
+                Some(Ok(entry))
+            });
+            Self::write_sorted(&self.path, merged)
+        })();
+        drop(runs);
+        for path in paths {
+            let _ = fs::remove_file(path);
+        }
+        self.replace_sorted(&res?)?;
+        self.count = count;
+        Ok(())
+    }
+
+    /// Merges the recently appended keys into the sorted index file.
+    fn merge(&mut self) -> io::Result<()> {
+        let mut sorted = &*self.sorted;
+        sorted.seek(SeekFrom::Start(HEADER_LEN))?;
+        let mut reader = BufReader::new(sorted);
+        let mut old = (0..self.sorted_count)
+            .map(|_| Self::read_sorted(&mut reader))
+            .peekable();
+        let mut new = self
+            .tail
+            .iter()
+            .map(|(key, entry)| (*key, *entry))
+            .peekable();
+        let merged = core::iter::from_fn(|| match (old.peek(), new.peek()) {
+            (Some(Ok((a, _))), Some((b, _))) if b < a => new.next().map(Ok),
+            (Some(_), _) => old.next(),
+            (None, _) => new.next().map(Ok),
+        });
+        let tmp = Self::write_sorted(&self.path, merged)?;
+        drop(reader);
+        self.replace_sorted(&tmp)?;
+        self.tail.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAGIC: u64 = u64::from_be_bytes(*b"DUMBTEST");
+
+    #[test]
+    fn full_index_snapshots() {
+        let key = |no: usize| (no as u64).to_be_bytes();
+        let mut index = FullIndex::<8>::default();
+        for no in 0..3 * TAIL_LEN + 10 {
+            index.insert(key(no), no as u64 * 10);
+        }
+        let snapshot = index.clone();
+        for no in 3 * TAIL_LEN + 10..5 * TAIL_LEN {
+            index.insert(key(no), no as u64 * 10);
+        }
+        // The runs are merged as in a binary counter, copying the runs shared with the snapshot
+        let runs = |index: &FullIndex<8>| {
+            index
+                .runs
+                .iter()
+                .map(|(start, run)| (*start, run.len()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(runs(&index), vec![(0, 4 * TAIL_LEN), (4 * TAIL_LEN, TAIL_LEN)]);
+        assert_eq!(runs(&snapshot), vec![(0, 2 * TAIL_LEN), (2 * TAIL_LEN, TAIL_LEN)]);
+        // The appends to the tail don't touch the runs shared with a snapshot
+        let other = index.clone();
+        index.insert(key(5 * TAIL_LEN), 0);
+        assert!(Arc::ptr_eq(&other.runs[0].1, &index.runs[0].1));
+        assert_eq!(snapshot.len(), 3 * TAIL_LEN + 10);
+        assert_eq!(snapshot.get_full(&key(3 * TAIL_LEN + 20)), None);
+        assert_eq!(
+            index.get_full(&key(3 * TAIL_LEN + 20)),
+            Some((3 * TAIL_LEN + 20, 3 * TAIL_LEN as u64 * 10 + 200))
+        );
+        assert_eq!(
+            index.get_index(TAIL_LEN + 1),
+            Some((key(TAIL_LEN + 1), TAIL_LEN as u64 * 10 + 10))
+        );
+        assert!(index.iter().map(|(k, _)| *k).eq((0..=5 * TAIL_LEN).map(key)));
+        assert!(
+            snapshot
+                .iter()
+                .rev()
+                .map(|(k, _)| *k)
+                .eq((0..3 * TAIL_LEN + 10).rev().map(key))
+        );
+
+        // Updating a key of a shared run copies just that run
+        index.insert(key(5), 1);
+        assert_eq!(index.get_full(&key(5)), Some((5, 1)));
+        assert_eq!(snapshot.get_full(&key(5)), Some((5, 50)));
+        assert_eq!(index.len(), 5 * TAIL_LEN + 1);
+    }
+
+    #[test]
+    fn full_index_entries() {
+        let key = |no: usize| (no as u64).to_be_bytes();
+        // The keys 1 and 0 get duplicate entries
+        let entries = [0, 1, 2, 1, 3, 0, 4].map(|no| (key(no), no as u64));
+        let mut index = FullIndex::<8>::default();
+        for (key, pos) in entries {
+            index.insert(key, pos);
+        }
+        for index in [index, FullIndex::load(entries)] {
+            assert_eq!((index.len(), index.entries()), (5, 7));
+            let entry_nos = (0..5).map(|no| index.entry_no(no)).collect::<Vec<_>>();
+            assert_eq!(entry_nos, vec![5, 3, 2, 4, 6]);
+        }
+    }
+
+    #[test]
+    fn external_sort() {
+        let dir = tempfile::tempdir().unwrap();
+        let idx = dir.path().join("test.idx");
+        let path = dir.path().join("test.sidx");
+        let key = |no: u64| (no * 7919 % 1000).to_be_bytes();
+        let mut file = BinFile::<MAGIC, 1>::create_new(&idx).unwrap();
+        let mut index = SparseIndex::<MAGIC, 1, 8>::open(&idx, &path, 16).unwrap();
+        for no in 0..1000u64 {
+            file.write_all(&key(no)).unwrap();
+            file.write_all(&(no * 10).to_le_bytes()).unwrap();
+        }
+
+        index.bulk_load(1000, 64).unwrap();
+        assert_eq!(index.sorted_count, 1000);
+        assert!(index.tail.is_empty());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+        assert!(
+            (0..1000u64).all(|no| index.get(&key(no)).unwrap() == Some((no as usize, no * 10)))
+        );
+        assert_eq!(index.get(&1000u64.to_be_bytes()).unwrap(), None);
+        drop(index);
+
+        // Leftovers of an interrupted bulk load
+        fs::write(dir.path().join("test.sidx.run0"), b"run").unwrap();
+        fs::write(dir.path().join("test.sidx.run1"), b"run").unwrap();
+        let index = SparseIndex::<MAGIC, 1, 8>::open(&idx, &path, 16).unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+        assert_eq!(index.get(&key(7)).unwrap(), Some((7, 70)));
+    }
+}
@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+
+use indexmap::IndexMap;
+
+/// Number of the entries read from the spill file at once.
+const CHUNK_ENTRIES: u64 = 4096;
+
+/// Temporary file with the entries of a pending transaction which didn't fit into memory, in the
+/// order they were spilled. The same key may be present several times, the last entry holding
+/// its latest value.
+///
+/// The file is removed when dropped.
+#[derive(Debug)]
+pub(crate) struct Spill<const KEY_LEN: usize, const VAL_LEN: usize> {
+    path: PathBuf,
+    file: RefCell<File>,
+    /// Number of the entries in the file.
+    len: u64,
+}
+
+impl<const KEY_LEN: usize, const VAL_LEN: usize> Spill<KEY_LEN, VAL_LEN> {
+    const ENTRY_LEN: usize = KEY_LEN + VAL_LEN;
+
+    /// Creates the spill file, replacing the one left by a previous process.
+    pub fn create(path: PathBuf) -> io::Result<Self> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("spill file '{}'", path.display())))?;
+        Ok(Self { path, file: RefCell::new(file), len: 0 })
+    }
+
+    /// Returns the number of the entries in the file, including the repeated keys.
+    pub fn len(&self) -> u64 { self.len }
+
+    /// Appends the entries to the file with a single write.
+    pub fn append(&mut self, entries: &IndexMap<[u8; KEY_LEN], [u8; VAL_LEN]>) -> io::Result<()> {
+        let mut data = Vec::with_capacity(entries.len() * Self::ENTRY_LEN);
+        for (key, value) in entries {
+            data.extend_from_slice(key);
+            data.extend_from_slice(value);
+        }
+        let file = self.file.get_mut();
+        file.seek(SeekFrom::Start(self.len * Self::ENTRY_LEN as u64))?;
+        file.write_all(&data)?;
+        self.len += entries.len() as u64;
+        Ok(())
+    }
+
+    /// Reads the entries with the numbers in the given range.
+    fn read(&self, range: Range<u64>) -> io::Result<Vec<u8>> {
+        let mut data = vec![0u8; (range.end - range.start) as usize * Self::ENTRY_LEN];
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(range.start * Self::ENTRY_LEN as u64))?;
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// Returns the latest value spilled under the key.
+    pub fn get(&self, key: &[u8; KEY_LEN]) -> io::Result<Option<[u8; VAL_LEN]>> {
+        for entry in self.entries(true) {
+            let (k, value) = entry?;
+            if k == *key {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns an iterator over the entries in the order they were spilled, or in the reverse
+    /// order, reading the file in chunks.
+    pub fn entries(&self, rev: bool) -> SpillIter<'_, KEY_LEN, VAL_LEN> {
+        SpillIter { spill: self, range: 0..self.len, rev, chunk: Vec::new(), pos: 0 }
+    }
+}
+
+impl<const KEY_LEN: usize, const VAL_LEN: usize> Drop for Spill<KEY_LEN, VAL_LEN> {
+    fn drop(&mut self) { let _ = fs::remove_file(&self.path); }
+}
+
+/// Iterator over the entries of a [`Spill`].
+pub(crate) struct SpillIter<'spill, const KEY_LEN: usize, const VAL_LEN: usize> {
+    spill: &'spill Spill<KEY_LEN, VAL_LEN>,
+    /// Numbers of the entries which are not read yet.
+    range: Range<u64>,
+    rev: bool,
+    chunk: Vec<u8>,
+    /// Position of the next entry in the chunk.
+    pos: usize,
+}
+
+impl<const KEY_LEN: usize, const VAL_LEN: usize> Iterator for SpillIter<'_, KEY_LEN, VAL_LEN> {
+    type Item = io::Result<([u8; KEY_LEN], [u8; VAL_LEN])>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos == self.chunk.len() {
+            if self.range.is_empty() {
+                return None;
+            }
+            let count = CHUNK_ENTRIES.min(self.range.end - self.range.start);
+            let range = if self.rev {
+                self.range.end -= count;
+                self.range.end..self.range.end + count
+            } else {
+                self.range.start += count;
+                self.range.start - count..self.range.start
+            };
+            match self.spill.read(range) {
+                Ok(chunk) => self.chunk = chunk,
+                Err(err) => {
+                    self.range = 0..0;
+                    return Some(Err(err));
+                }
+            }
+            self.pos = 0;
+        }
+        let entry_len = KEY_LEN + VAL_LEN;
+        let start = match self.rev {
+            false => self.pos,
+            true => self.chunk.len() - self.pos - entry_len,
+        };
+        self.pos += entry_len;
+        let entry = &self.chunk[start..start + entry_len];
+        let mut key = [0u8; KEY_LEN];
+        let mut value = [0u8; VAL_LEN];
+        key.copy_from_slice(&entry[..KEY_LEN]);
+        value.copy_from_slice(&entry[KEY_LEN..]);
+        Some(Ok((key, value)))
+    }
+}
@@ -0,0 +1,42 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::ops::Add;
+
+use super::LatencyStats;
+
+/// Storage usage of a file provider, returned by the `stats` method of the providers.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StorageStats {
+    /// Size of the files holding the records (log, pages or sorted segments), in bytes.
+    pub log_size: u64,
+    /// Size of the index files, including the auxiliary ones (value hashes, Bloom filter, sorted
+    /// index), in bytes.
+    pub idx_size: u64,
+    /// Number of the records persisted on disk.
+    pub records: usize,
+    /// Number of the pages persisted or committed; zero for the providers without pages.
+    pub pages: usize,
+    /// Number of the records pending in an uncommitted transaction or in an unflushed write
+    /// buffer.
+    pub pending: usize,
+    /// Latency percentiles of the operations, if the latency tracking is enabled.
+    pub latency: Option<LatencyStats>,
+}
+
+/// Sums the storage usage of two providers. The latency percentiles can't be summed, so they are
+/// dropped.
+impl Add for StorageStats {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            log_size: self.log_size + rhs.log_size,
+            idx_size: self.idx_size + rhs.idx_size,
+            records: self.records + rhs.records,
+            pages: self.pages + rhs.pages,
+            pending: self.pending + rhs.pending,
+            latency: None,
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: Apache-2.0
+
+/// Declares a table stored by one of the file providers, expanding into the type alias of the
+/// provider with its magic number and version, and a marker type with the table name and the
+/// helpers creating and opening the table.
+///
+/// The kind of the table selects the provider:
+/// - `AppendOnly(K, V, KEY_LEN)` for [`FileAoraMap`](crate::file::FileAoraMap);
+/// - `Pack(K, V, KEY_LEN)` for [`FileAoraPack`](crate::file::FileAoraPack);
+/// - `AppendUpdate(K, V, KEY_LEN, VAL_LEN)` for [`FileAuraMap`](crate::file::FileAuraMap);
+/// - `Index(K, V, KEY_LEN, VAL_LEN)` for [`FileAoraIndex`](crate::file::FileAoraIndex);
+/// - `Log(V)` for [`FileAoraLog`](crate::file::FileAoraLog).
+///
+/// The magic number is given as an 8-byte string; the version defaults to 1.
+///
+/// ```ignore
+/// aora_table! {
+///     /// Blobs of the node.
+///     pub table Blobs("blobs", b"BLOBSTOR") as BlobsDb: AppendOnly(BlobId, Blob, 32);
+///
+///     /// Balances of the accounts, in the second version of the format.
+///     pub table Balances("balances", b"BALANCES", 2) as BalancesDb:
+///         AppendUpdate(AccountId, Amount, 32, 8);
+/// }
+///
+/// let blobs: BlobsDb = Blobs::open(dir)?;
+/// ```
+#[macro_export]
+macro_rules! aora_table {
+    (@ver) => { 1 };
+    (@ver $ver:literal) => { $ver };
+
+    (@provider $marker:ident AppendOnly($key:ty, $val:ty, $key_len:tt)) => {
+        $crate::file::FileAoraMap<$key, $val, { $marker::MAGIC }, { $marker::VER }, $key_len>
+    };
+    (@provider $marker:ident Pack($key:ty, $val:ty, $key_len:tt)) => {
+        $crate::file::FileAoraPack<$key, $val, { $marker::MAGIC }, { $marker::VER }, $key_len>
+    };
+    (@provider $marker:ident AppendUpdate($key:ty, $val:ty, $key_len:tt, $val_len:tt)) => {
+        $crate::file::FileAuraMap<
+            $key,
+            $val,
+            { $marker::MAGIC },
+            { $marker::VER },
+            $key_len,
+            $val_len,
+        >
+    };
+    (@provider $marker:ident Index($key:ty, $val:ty, $key_len:tt, $val_len:tt)) => {
+        $crate::file::FileAoraIndex<
+            $key,
+            $val,
+            { $marker::MAGIC },
+            { $marker::VER },
+            $key_len,
+            $val_len,
+        >
+    };
+    (@provider $marker:ident Log($val:ty)) => {
+        $crate::file::FileAoraLog<$val, { $marker::MAGIC }, { $marker::VER }>
+    };
+
+    ($(
+        $(#[$attr:meta])*
+        $vis:vis table $marker:ident($name:literal, $magic:literal $(, $ver:literal)?)
+            as $alias:ident: $kind:ident($($param:tt)*);
+    )+) => {$(
+        $(#[$attr])*
+        $vis type $alias = $crate::aora_table!(@provider $marker $kind($($param)*));
+
+        #[doc = concat!(
+            "Marker of the `", $name, "` table stored as [`", stringify!($alias), "`]."
+        )]
+        #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+        $vis struct $marker;
+
+        impl $marker {
+            /// Name of the table files.
+            pub const NAME: &'static str = $name;
+            /// Magic number of the table files.
+            pub const MAGIC: u64 = u64::from_be_bytes(*$magic);
+            /// Version of the table files.
+            pub const VER: u16 = $crate::aora_table!(@ver $($ver)?);
+
+            /// Creates the table in the given directory.
+            pub fn create_new(path: impl AsRef<::std::path::Path>) -> ::std::io::Result<$alias> {
+                <$alias>::create_new(path, Self::NAME)
+            }
+
+            /// Opens the table existing in the given directory.
+            pub fn open(path: impl AsRef<::std::path::Path>) -> ::std::io::Result<$alias> {
+                <$alias>::open(path, Self::NAME)
+            }
+        }
+    )+};
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::confinement::SmallVec;
+
+    use crate::{AoraLog, AoraMap, AuraMap, TransactionalMap, U64Le};
+
+    aora_table! {
+        table Blobs("blobs", b"DUMBTEST") as BlobsDb: AppendOnly([u8; 8], SmallVec<u8>, 8);
+        table Balances("balances", b"DUMBTEST", 3) as BalancesDb: AppendUpdate(U64Le, U64Le, 8, 8);
+        table Events("events", b"DUMBTEST") as EventsDb: Log(SmallVec<u8>);
+    }
+
+    fn val(no: u64) -> SmallVec<u8> { SmallVec::from_checked(no.to_le_bytes().to_vec()) }
+
+    #[test]
+    fn tables() {
+        assert_eq!(Blobs::NAME, "blobs");
+        assert_eq!(Blobs::MAGIC, u64::from_be_bytes(*b"DUMBTEST"));
+        assert_eq!(Blobs::VER, 1);
+        assert_eq!(Balances::VER, 3);
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut blobs: BlobsDb = Blobs::create_new(dir.path()).unwrap();
+        blobs.insert(1u64.to_be_bytes(), &val(1));
+        let mut balances: BalancesDb = Balances::create_new(dir.path()).unwrap();
+        balances.insert_or_update(1.into(), 10.into());
+        balances.commit_transaction();
+        let mut events: EventsDb = Events::create_new(dir.path()).unwrap();
+        events.append(&val(2));
+        drop((blobs, balances, events));
+
+        assert!(Blobs::create_new(dir.path()).is_err());
+        assert_eq!(Blobs::open(dir.path()).unwrap().get(1u64.to_be_bytes()), Some(val(1)));
+        assert_eq!(Balances::open(dir.path()).unwrap().get_expect(1.into()).0, 10);
+        assert_eq!(Events::open(dir.path()).unwrap().get(0), Some(val(2)));
+    }
+}
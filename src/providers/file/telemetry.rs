@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Metrics emitted by the file providers via the [`metrics`](https://docs.rs/metrics) facade when
+//! the `metrics` feature is enabled. Each metric is labelled with the `table` name. Without the
+//! feature, the functions here are no-ops.
+//!
+//! With the `tracing` feature, the number of the written bytes is also reported as a trace event
+//! within the span of the operation.
+
+use std::time::Instant;
+
+/// Counter of the inserted records.
+pub const METRIC_INSERTS: &str = "aora_inserts_total";
+/// Counter of the value lookups.
+pub const METRIC_GETS: &str = "aora_gets_total";
+/// Counter of the bytes written to the data and index files.
+pub const METRIC_BYTES_WRITTEN: &str = "aora_bytes_written_total";
+/// Counter of the lookups answered from a record cache (or a memtable) without reading the files.
+pub const METRIC_CACHE_HITS: &str = "aora_cache_hits_total";
+/// Counter of the lookups which missed a record cache (or a memtable) and had to read the files.
+/// The lookups of the providers without a cache are not counted.
+pub const METRIC_CACHE_MISSES: &str = "aora_cache_misses_total";
+/// Counter of the lookups of absent keys answered by a Bloom filter without reading the index.
+pub const METRIC_BLOOM_NEGATIVES: &str = "aora_bloom_negatives_total";
+/// Histogram of the time (in seconds) taken to persist a transaction or a write buffer.
+pub const METRIC_COMMIT_SECONDS: &str = "aora_commit_seconds";
+
+pub(crate) fn inserted(table: &str, count: u64) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(METRIC_INSERTS, "table" => table.to_owned()).increment(count);
+    let _ = (table, count);
+}
+
+pub(crate) fn got(table: &str) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(METRIC_GETS, "table" => table.to_owned()).increment(1);
+    let _ = table;
+}
+
+pub(crate) fn written(table: &str, bytes: u64) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(table, bytes, "bytes written");
+    #[cfg(feature = "metrics")]
+    metrics::counter!(METRIC_BYTES_WRITTEN, "table" => table.to_owned()).increment(bytes);
+    let _ = (table, bytes);
+}
+
+pub(crate) fn cache(table: &str, hit: bool) {
+    #[cfg(feature = "metrics")]
+    {
+        let name = if hit { METRIC_CACHE_HITS } else { METRIC_CACHE_MISSES };
+        metrics::counter!(name, "table" => table.to_owned()).increment(1);
+    }
+    let _ = (table, hit);
+}
+
+pub(crate) fn bloom_negative(table: &str) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(METRIC_BLOOM_NEGATIVES, "table" => table.to_owned()).increment(1);
+    let _ = table;
+}
+
+/// Records the latency of a commit which has started at `start`.
+pub(crate) fn committed(table: &str, start: Instant) {
+    #[cfg(feature = "metrics")]
+    metrics::histogram!(METRIC_COMMIT_SECONDS, "table" => table.to_owned())
+        .record(start.elapsed().as_secs_f64());
+    let _ = (table, start);
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use amplify::confinement::SmallVec;
+    use metrics::{
+        Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+    };
+
+    use super::*;
+    use crate::AoraMap;
+    use crate::file::FileAoraMap;
+
+    #[derive(Default)]
+    struct TestRecorder(Mutex<HashMap<String, Arc<AtomicU64>>>);
+
+    impl TestRecorder {
+        fn get(&self, name: &str) -> u64 {
+            self.0
+                .lock()
+                .unwrap()
+                .get(name)
+                .map_or(0, |counter| counter.load(Ordering::Relaxed))
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            assert_eq!(key.labels().next().unwrap().value(), "metrics");
+            let mut counters = self.0.lock().unwrap();
+            Counter::from_arc(counters.entry(key.name().to_owned()).or_default().clone())
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge { Gauge::noop() }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram { Histogram::noop() }
+    }
+
+    #[test]
+    fn counters() {
+        type Db = FileAoraMap<[u8; 8], SmallVec<u8>, { u64::from_be_bytes(*b"DUMBTEST") }, 1, 8>;
+
+        let recorder = TestRecorder::default();
+        let dir = tempfile::tempdir().unwrap();
+        metrics::with_local_recorder(&recorder, || {
+            let mut db = Db::create_new(dir.path(), "metrics").unwrap();
+            for no in 0..10u64 {
+                db.insert(no.to_be_bytes(), &SmallVec::from_checked(vec![no as u8]));
+            }
+            assert!(db.get(5u64.to_be_bytes()).is_some());
+        });
+        assert_eq!(recorder.get(METRIC_INSERTS), 10);
+        assert_eq!(recorder.get(METRIC_GETS), 1);
+        // The map has no record cache
+        assert_eq!(recorder.get(METRIC_CACHE_MISSES), 0);
+        assert_eq!(recorder.get(METRIC_BYTES_WRITTEN), 10 * (3 + 16));
+    }
+}
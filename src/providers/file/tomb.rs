@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashSet;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use binfile::BinFile;
+
+/// Length of a tombstone: the number of the superseded record in the append order.
+const ENTRY_LEN: u64 = 8;
+
+/// Tombstones of the superseded log records, appended to a file next to the index.
+///
+/// The records are identified by their numbers in the append order rather than by their keys, so
+/// the file doesn't reveal the keys of the logs with encrypted index keys.
+#[derive(Debug)]
+pub(crate) struct Tombstones<const MAGIC: u64, const VER: u16> {
+    file: BinFile<MAGIC, VER>,
+    records: HashSet<u64>,
+    /// Number of the complete tombstones read from or written to the file.
+    len: u64,
+}
+
+impl<const MAGIC: u64, const VER: u16> Tombstones<MAGIC, VER> {
+    pub fn create_new(path: &Path) -> io::Result<Self> {
+        let file = BinFile::<MAGIC, VER>::create_new(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("tombstone file '{}'", path.display())))?;
+        Ok(Self { file, records: HashSet::new(), len: 0 })
+    }
+
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = BinFile::<MAGIC, VER>::open_rw(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("tombstone file '{}'", path.display())))?;
+        let mut me = Self { file, records: HashSet::new(), len: 0 };
+        me.reload()?;
+        Ok(me)
+    }
+
+    /// Returns the number of the superseded records.
+    pub fn len(&self) -> usize { self.records.len() }
+
+    /// Checks whether the record with the given number is superseded.
+    pub fn contains(&self, no: usize) -> bool { self.records.contains(&(no as u64)) }
+
+    /// Appends the tombstone of the record with the given number, returning `false` if the record
+    /// is already superseded.
+    pub fn insert(&mut self, no: usize) -> io::Result<bool> {
+        if self.contains(no) {
+            return Ok(false);
+        }
+        self.file
+            .seek(SeekFrom::Start(10 + self.len * ENTRY_LEN))?;
+        self.file.write_all(&(no as u64).to_le_bytes())?;
+        self.records.insert(no as u64);
+        self.len += 1;
+        Ok(true)
+    }
+
+    /// Reads the tombstones appended by other processes. A trailing incomplete tombstone is left
+    /// by an interrupted append and is ignored.
+    pub fn reload(&mut self) -> io::Result<()> {
+        let start = 10 + self.len * ENTRY_LEN;
+        let end = self.file.seek(SeekFrom::End(0))?;
+        let count = end.saturating_sub(start) / ENTRY_LEN;
+        let mut data = vec![0u8; (count * ENTRY_LEN) as usize];
+        self.file.seek(SeekFrom::Start(start))?;
+        self.file.read_exact(&mut data)?;
+        for entry in data.chunks_exact(ENTRY_LEN as usize) {
+            let mut no = [0u8; 8];
+            no.copy_from_slice(entry);
+            self.records.insert(u64::from_le_bytes(no));
+        }
+        self.len += count;
+        Ok(())
+    }
+
+    /// Replaces all the tombstones with the ones of the records with the given numbers, as when
+    /// the records are renumbered by an index rewrite.
+    pub fn replace(&mut self, nos: &[usize]) -> io::Result<()> {
+        let mut data = Vec::with_capacity(nos.len() * ENTRY_LEN as usize);
+        for no in nos {
+            data.extend_from_slice(&(*no as u64).to_le_bytes());
+        }
+        self.file.seek(SeekFrom::Start(10))?;
+        self.file.write_all(&data)?;
+        self.file.set_len(10 + data.len() as u64)?;
+        self.file.sync_data()?;
+        self.records = nos.iter().map(|no| *no as u64).collect();
+        self.len = nos.len() as u64;
+        Ok(())
+    }
+
+    /// Flushes the appended tombstones to the operating system.
+    pub fn flush(&mut self) -> io::Result<()> { self.file.flush() }
+
+    /// Syncs the tombstone file to the storage device.
+    pub fn sync(&self) -> io::Result<()> { self.file.sync_all() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload() {
+        const MAGIC: u64 = u64::from_be_bytes(*b"DUMBTEST");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.tomb");
+        let mut tombs = Tombstones::<MAGIC, 1>::create_new(&path).unwrap();
+        assert!(tombs.insert(3).unwrap());
+        assert!(!tombs.insert(3).unwrap());
+        tombs.flush().unwrap();
+
+        let mut other = Tombstones::<MAGIC, 1>::open(&path).unwrap();
+        assert!(other.contains(3));
+        assert!(tombs.insert(7).unwrap());
+        tombs.flush().unwrap();
+        assert!(!other.contains(7));
+        other.reload().unwrap();
+        assert!(other.contains(7));
+        assert_eq!(other.len(), 2);
+
+        other.replace(&[1]).unwrap();
+        let tombs = Tombstones::<MAGIC, 1>::open(&path).unwrap();
+        assert!(tombs.contains(1) && !tombs.contains(3) && !tombs.contains(7));
+    }
+}
@@ -0,0 +1,170 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt::{self, Debug, Formatter};
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+
+use io_uring::{IoUring, opcode, types};
+
+/// Positioned read or write request, advanced on partial completions.
+struct Request {
+    fd: types::Fd,
+    offset: u64,
+    buf: *mut u8,
+    len: usize,
+    write: bool,
+}
+
+/// The io_uring instance performing batches of positioned reads and writes with a single system
+/// call per batch, instead of a seek and a read or write call per buffer.
+pub(crate) struct Ring(RefCell<IoUring>);
+
+impl Debug for Ring {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str("Ring(..)") }
+}
+
+impl Ring {
+    /// Sets up the ring with the submission queue of the given depth.
+    pub fn new(entries: u32) -> io::Result<Self> { Ok(Self(RefCell::new(IoUring::new(entries)?))) }
+
+    /// Fills each of the buffers with the file data starting at the paired offset.
+    ///
+    /// Fails with [`io::ErrorKind::UnexpectedEof`] if any of the buffers can't be filled.
+    pub fn read_at(&self, reads: &mut [(&File, u64, &mut [u8])]) -> io::Result<()> {
+        let requests = reads
+            .iter_mut()
+            .map(|(file, offset, buf)| Request {
+                fd: types::Fd(file.as_raw_fd()),
+                offset: *offset,
+                buf: buf.as_mut_ptr(),
+                len: buf.len(),
+                write: false,
+            })
+            .collect();
+        self.run(requests)
+    }
+
+    /// Writes each of the buffers to the paired file at the paired offset.
+    pub fn write_at(&self, writes: &[(&File, u64, &[u8])]) -> io::Result<()> {
+        let requests = writes
+            .iter()
+            .map(|(file, offset, buf)| Request {
+                fd: types::Fd(file.as_raw_fd()),
+                offset: *offset,
+                buf: buf.as_ptr().cast_mut(),
+                len: buf.len(),
+                write: true,
+            })
+            .collect();
+        self.run(requests)
+    }
+
+    fn run(&self, mut requests: Vec<Request>) -> io::Result<()> {
+        let mut ring = self.0.borrow_mut();
+        let mut queue = (0..requests.len())
+            .filter(|no| requests[*no].len > 0)
+            .collect::<VecDeque<_>>();
+        let mut in_flight = 0usize;
+        let mut error = None;
+        while in_flight > 0 || (error.is_none() && !queue.is_empty()) {
+            while let (None, Some(&no)) = (&error, queue.front()) {
+                let req = &requests[no];
+                let len = req.len.min(u32::MAX as usize) as u32;
+                let entry = if req.write {
+                    opcode::Write::new(req.fd, req.buf, len)
+                        .offset(req.offset)
+                        .build()
+                } else {
+                    opcode::Read::new(req.fd, req.buf, len)
+                        .offset(req.offset)
+                        .build()
+                };
+                // SAFETY: the buffers are borrowed by the caller until this method returns, and it
+                // doesn't return before all the submitted requests are completed.
+                if unsafe { ring.submission().push(&entry.user_data(no as u64)) }.is_err() {
+                    break;
+                }
+                queue.pop_front();
+                in_flight += 1;
+            }
+            match ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                // Returning here would free the buffers still used by the kernel
+                Err(err) => panic!("unable to submit io_uring requests: {err}"),
+            }
+            let completed = ring
+                .completion()
+                .map(|cqe| (cqe.user_data() as usize, cqe.result()))
+                .collect::<Vec<_>>();
+            for (no, res) in completed {
+                in_flight -= 1;
+                let req = &mut requests[no];
+                match res {
+                    res if res < 0 => {
+                        error.get_or_insert(io::Error::from_raw_os_error(-res));
+                    }
+                    0 if req.write => {
+                        error.get_or_insert(io::ErrorKind::WriteZero.into());
+                    }
+                    0 => {
+                        error.get_or_insert(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    count => {
+                        let count = count as usize;
+                        req.offset += count as u64;
+                        // SAFETY: the kernel never reports more bytes than the requested length.
+                        req.buf = unsafe { req.buf.add(count) };
+                        req.len -= count;
+                        if req.len > 0 {
+                            queue.push_back(no);
+                        }
+                    }
+                }
+            }
+        }
+        error.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Seek, SeekFrom, Write};
+
+    use super::*;
+
+    #[test]
+    fn batches() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&[0xFF; 4]).unwrap();
+        let ring = Ring::new(2).unwrap();
+
+        let data = (0..5u8).map(|no| vec![no; 3000]).collect::<Vec<_>>();
+        let writes = data
+            .iter()
+            .enumerate()
+            .map(|(no, buf)| (&file, 4 + no as u64 * 3000, buf.as_slice()))
+            .collect::<Vec<_>>();
+        ring.write_at(&writes).unwrap();
+        assert_eq!(file.seek(SeekFrom::End(0)).unwrap(), 4 + 5 * 3000);
+
+        let mut bufs = vec![vec![0u8; 3000]; 5];
+        let mut reads = bufs
+            .iter_mut()
+            .enumerate()
+            .rev()
+            .map(|(no, buf)| (&file, 4 + no as u64 * 3000, buf.as_mut_slice()))
+            .collect::<Vec<_>>();
+        ring.read_at(&mut reads).unwrap();
+        assert_eq!(bufs, data);
+
+        let mut buf = [0u8; 8];
+        let err = ring
+            .read_at(&mut [(&file, 4 + 5 * 3000 - 4, &mut buf[..])])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,65 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Filesystem watcher of the files of a table, flagging their changes made by other processes.
+///
+/// The directories containing the files are watched rather than the files themselves, so the
+/// files replaced by renaming (as when the transactions are archived) keep being watched.
+#[derive(Debug)]
+pub(crate) struct ChangeWatcher {
+    _watcher: RecommendedWatcher,
+    changed: Arc<AtomicBool>,
+}
+
+impl ChangeWatcher {
+    pub fn new(files: &[&Path]) -> io::Result<Self> {
+        let names = files
+            .iter()
+            .filter_map(|path| path.file_name())
+            .map(OsString::from)
+            .collect::<Vec<_>>();
+        let changed = Arc::new(AtomicBool::new(false));
+        let flag = changed.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let changed = match event {
+                Ok(event) => {
+                    (event.kind.is_modify() || event.kind.is_create())
+                        && event.paths.iter().any(|path| {
+                            path.file_name()
+                                .is_some_and(|name| names.iter().any(|n| n == name))
+                        })
+                }
+                // It is safer to refresh the table than to miss a change
+                Err(_) => true,
+            };
+            if changed {
+                flag.store(true, Ordering::Release);
+            }
+        })
+        .map_err(io::Error::other)?;
+        let mut dirs = files
+            .iter()
+            .map(|file| match file.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            })
+            .collect::<Vec<_>>();
+        dirs.dedup();
+        for dir in dirs {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(io::Error::other)?;
+        }
+        Ok(Self { _watcher: watcher, changed })
+    }
+
+    /// Checks whether the files were changed since the previous call.
+    pub fn take_changes(&self) -> bool { self.changed.swap(false, Ordering::AcqRel) }
+}
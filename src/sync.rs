@@ -0,0 +1,422 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use core::fmt::Display;
+use core::marker::PhantomData;
+use core::ops::Range;
+use std::string::{String, ToString};
+use std::sync::{Mutex, MutexGuard};
+use std::vec::Vec;
+
+use crate::{AoraKey, AoraMap, AuraMap, AuraMapError, TransactionalMap};
+
+/// Append-only map provider guarded by a [`Mutex`], which can be shared between threads (for
+/// instance, in an `Arc`) and accessed with `&self` methods, including [`Self::insert`].
+///
+/// The wrapper is `Sync` if the provider is `Send`, so it can share the providers which mutate
+/// their internal state on reads (like the file-based `FileAoraMap`, which seeks its file
+/// handles). Since such providers can't be read concurrently, the reads and the inserts take the
+/// same lock, one at a time.
+///
+/// The iterators collect the items under the lock, taking memory proportional to the size of the
+/// map; to iterate without collecting the items, lock the provider with [`Self::lock`].
+#[derive(Debug, Default)]
+pub struct SyncAoraMap<K, V, P, const KEY_LEN: usize = 32> {
+    provider: Mutex<P>,
+    _phantom: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V, P, const KEY_LEN: usize> SyncAoraMap<K, V, P, KEY_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    P: AoraMap<K, V, KEY_LEN>,
+{
+    /// Wraps the provider.
+    pub fn new(provider: P) -> Self {
+        Self { provider: Mutex::new(provider), _phantom: PhantomData }
+    }
+
+    /// Locks the provider, blocking until the concurrent reads and writes are complete.
+    ///
+    /// # Panics
+    ///
+    /// If the lock was poisoned by a panic during another access.
+    pub fn lock(&self) -> MutexGuard<'_, P> {
+        self.provider
+            .lock()
+            .expect("unable to lock the map poisoned by a panic")
+    }
+
+    /// Releases the provider.
+    pub fn into_inner(self) -> P {
+        self.provider
+            .into_inner()
+            .expect("unable to lock the map poisoned by a panic")
+    }
+
+    /// Returns a number of the items in the log.
+    pub fn len(&self) -> usize { self.lock().len() }
+
+    /// Checks whether the log is empty.
+    pub fn is_empty(&self) -> bool { self.lock().is_empty() }
+
+    /// Checks whether a given value is present in the log.
+    pub fn contains_key(&self, key: K) -> bool { self.lock().contains_key(key) }
+
+    /// Retrieves value from the log.
+    pub fn get(&self, key: K) -> Option<V> { self.lock().get(key) }
+
+    /// Retrieves value from the log.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the item under the provided key is not present.
+    pub fn get_expect(&self, key: K) -> V { self.lock().get_expect(key) }
+
+    /// Inserts (appends) an item to the append-only log, see [`AoraMap::insert`].
+    pub fn insert(&self, key: K, item: &V) { self.lock().insert(key, item) }
+
+    /// Inserts (appends) all items from an iterator to the append-only log under a single lock,
+    /// see [`AoraMap::extend`].
+    pub fn extend<'a>(&self, iter: impl IntoIterator<Item = (K, &'a V)>)
+    where V: 'a {
+        self.lock().extend(iter)
+    }
+
+    /// Returns the key and value pairs present in the log, collected under the lock.
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)> {
+        self.lock().iter().collect::<Vec<_>>().into_iter()
+    }
+
+    /// Returns the key and value pairs present in the log, collected under the lock, with the
+    /// most recently appended items first.
+    pub fn iter_rev(&self) -> impl Iterator<Item = (K, V)> {
+        self.lock().iter_rev().collect::<Vec<_>>().into_iter()
+    }
+}
+
+impl<K, V, P, const KEY_LEN: usize> AoraMap<K, V, KEY_LEN> for SyncAoraMap<K, V, P, KEY_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    P: AoraMap<K, V, KEY_LEN>,
+{
+    fn len(&self) -> usize { SyncAoraMap::len(self) }
+
+    fn contains_key(&self, key: K) -> bool { SyncAoraMap::contains_key(self, key) }
+
+    fn get(&self, key: K) -> Option<V> { SyncAoraMap::get(self, key) }
+
+    fn insert(&mut self, key: K, item: &V) {
+        self.provider
+            .get_mut()
+            .expect("unable to lock the map poisoned by a panic")
+            .insert(key, item)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (K, V)> { SyncAoraMap::iter(self) }
+
+    fn iter_rev(&self) -> impl Iterator<Item = (K, V)> { SyncAoraMap::iter_rev(self) }
+}
+
+/// Append-update map provider guarded by a [`Mutex`], which can be shared between threads (for
+/// instance, in an `Arc`) and accessed with `&self` methods, including the updates and the
+/// transaction control.
+///
+/// The wrapper is `Sync` if the provider is `Send`; the reads and the updates take the same lock,
+/// one at a time. The iterators collect the items under the lock, taking memory proportional to
+/// the size of the map; to iterate without collecting the items, lock the provider with
+/// [`Self::lock`].
+#[derive(Debug, Default)]
+pub struct SyncAuraMap<K, V, P, const KEY_LEN: usize = 32, const VAL_LEN: usize = 32> {
+    provider: Mutex<P>,
+    _phantom: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V, P, const KEY_LEN: usize, const VAL_LEN: usize> SyncAuraMap<K, V, P, KEY_LEN, VAL_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
+    P: AuraMap<K, V, KEY_LEN, VAL_LEN>,
+{
+    /// Wraps the provider.
+    pub fn new(provider: P) -> Self {
+        Self { provider: Mutex::new(provider), _phantom: PhantomData }
+    }
+
+    /// Locks the provider, blocking until the concurrent reads and writes are complete.
+    ///
+    /// # Panics
+    ///
+    /// If the lock was poisoned by a panic during another access.
+    pub fn lock(&self) -> MutexGuard<'_, P> {
+        self.provider
+            .lock()
+            .expect("unable to lock the map poisoned by a panic")
+    }
+
+    /// Releases the provider.
+    pub fn into_inner(self) -> P {
+        self.provider
+            .into_inner()
+            .expect("unable to lock the map poisoned by a panic")
+    }
+
+    /// Returns human-readable table identifier.
+    pub fn display(&self) -> String { self.lock().display().to_string() }
+
+    /// Returns all known keys, collected under the lock.
+    pub fn keys(&self) -> impl Iterator<Item = K> {
+        self.lock().keys().collect::<Vec<_>>().into_iter()
+    }
+
+    /// Returns all known keys, collected under the lock, with the most recently inserted or
+    /// updated keys first.
+    pub fn keys_rev(&self) -> impl Iterator<Item = K> {
+        self.lock().keys_rev().collect::<Vec<_>>().into_iter()
+    }
+
+    /// Returns all known keys with their latest values, collected under the lock.
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)> {
+        self.lock().iter().collect::<Vec<_>>().into_iter()
+    }
+
+    /// Returns the latest values of all known keys, collected under the lock.
+    pub fn values(&self) -> impl Iterator<Item = V> {
+        self.lock().values().collect::<Vec<_>>().into_iter()
+    }
+
+    /// Returns the number of the known keys.
+    pub fn len(&self) -> usize { self.lock().len() }
+
+    /// Checks whether the map has no keys.
+    pub fn is_empty(&self) -> bool { self.lock().is_empty() }
+
+    /// Checks whether a given value is present in the log.
+    pub fn contains_key(&self, key: K) -> bool { self.lock().contains_key(key) }
+
+    /// Retrieves value from the log.
+    pub fn get(&self, key: K) -> Option<V> { self.lock().get(key) }
+
+    /// Retrieves value from the log.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the item under the provided key is not present.
+    pub fn get_expect(&self, key: K) -> V { self.lock().get_expect(key) }
+
+    /// Inserts item if the key is not yet present, see [`AuraMap::insert_only`].
+    pub fn insert_only(&self, key: K, val: V)
+    where K: Copy {
+        self.lock().insert_only(key, val)
+    }
+
+    /// Inserts item if the key is not yet present, see [`AuraMap::try_insert_only`].
+    pub fn try_insert_only(&self, key: K, val: V) -> Result<(), AuraMapError<KEY_LEN, VAL_LEN>>
+    where K: Copy {
+        self.lock().try_insert_only(key, val)
+    }
+
+    /// Inserts an item or updates its value.
+    pub fn insert_or_update(&self, key: K, val: V) { self.lock().insert_or_update(key, val) }
+
+    /// Updates the value for a given key, see [`AuraMap::update_only`].
+    pub fn update_only(&self, key: K, val: V)
+    where K: Copy {
+        self.lock().update_only(key, val)
+    }
+
+    /// Updates the value for a given key, see [`AuraMap::try_update_only`].
+    pub fn try_update_only(&self, key: K, val: V) -> Result<(), AuraMapError<KEY_LEN, VAL_LEN>>
+    where K: Copy {
+        self.lock().try_update_only(key, val)
+    }
+}
+
+impl<K, V, P, const KEY_LEN: usize, const VAL_LEN: usize> SyncAuraMap<K, V, P, KEY_LEN, VAL_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
+    P: AuraMap<K, V, KEY_LEN, VAL_LEN> + TransactionalMap<K>,
+{
+    /// Commits the pending transaction, see [`TransactionalMap::commit_transaction`].
+    pub fn commit_transaction(&self) -> Option<u64> { self.lock().commit_transaction() }
+
+    /// Aborts the pending transaction.
+    pub fn abort_transaction(&self) { self.lock().abort_transaction() }
+
+    /// Returns keys added to the log as a part of a specific transaction number, collected under
+    /// the lock.
+    ///
+    /// # Panics
+    ///
+    /// If the transaction number is not known.
+    pub fn transaction_keys(&self, txno: u64) -> impl ExactSizeIterator<Item = K> {
+        self.lock()
+            .transaction_keys(txno)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Returns keys added to the log as a part of the transactions with the numbers in the range,
+    /// collected under the lock.
+    ///
+    /// # Panics
+    ///
+    /// If the range includes unknown transaction numbers.
+    pub fn keys_in_range(&self, range: Range<u64>) -> impl Iterator<Item = K> {
+        self.lock()
+            .keys_in_range(range)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Returns number of the committed transactions.
+    pub fn transaction_count(&self) -> u64 { self.lock().transaction_count() }
+
+    /// Returns number of the writes in the pending transaction.
+    pub fn pending_len(&self) -> usize { self.lock().pending_len() }
+
+    /// Checks whether there is a pending transaction with some writes.
+    pub fn has_pending(&self) -> bool { self.lock().has_pending() }
+
+    /// Returns keys written by the pending transaction, collected under the lock.
+    pub fn pending_keys(&self) -> impl Iterator<Item = K> {
+        self.lock().pending_keys().collect::<Vec<_>>().into_iter()
+    }
+}
+
+impl<K, V, P, const KEY_LEN: usize, const VAL_LEN: usize> AuraMap<K, V, KEY_LEN, VAL_LEN>
+    for SyncAuraMap<K, V, P, KEY_LEN, VAL_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
+    P: AuraMap<K, V, KEY_LEN, VAL_LEN>,
+{
+    fn display(&self) -> impl Display { SyncAuraMap::display(self) }
+
+    fn keys(&self) -> impl Iterator<Item = K> { SyncAuraMap::keys(self) }
+
+    fn keys_rev(&self) -> impl Iterator<Item = K> { SyncAuraMap::keys_rev(self) }
+
+    fn iter(&self) -> impl Iterator<Item = (K, V)> { SyncAuraMap::iter(self) }
+
+    fn values(&self) -> impl Iterator<Item = V> { SyncAuraMap::values(self) }
+
+    fn len(&self) -> usize { SyncAuraMap::len(self) }
+
+    fn is_empty(&self) -> bool { SyncAuraMap::is_empty(self) }
+
+    fn contains_key(&self, key: K) -> bool { SyncAuraMap::contains_key(self, key) }
+
+    fn get(&self, key: K) -> Option<V> { SyncAuraMap::get(self, key) }
+
+    fn insert_or_update(&mut self, key: K, val: V) {
+        self.provider
+            .get_mut()
+            .expect("unable to lock the map poisoned by a panic")
+            .insert_or_update(key, val)
+    }
+}
+
+impl<K, V, P, const KEY_LEN: usize, const VAL_LEN: usize> TransactionalMap<K>
+    for SyncAuraMap<K, V, P, KEY_LEN, VAL_LEN>
+where
+    K: AoraKey<KEY_LEN>,
+    V: AoraKey<VAL_LEN>,
+    P: AuraMap<K, V, KEY_LEN, VAL_LEN> + TransactionalMap<K>,
+{
+    fn commit_transaction(&mut self) -> Option<u64> { SyncAuraMap::commit_transaction(self) }
+
+    fn abort_transaction(&mut self) { SyncAuraMap::abort_transaction(self) }
+
+    fn transaction_keys(&self, txno: u64) -> impl ExactSizeIterator<Item = K> {
+        SyncAuraMap::transaction_keys(self, txno)
+    }
+
+    fn keys_in_range(&self, range: Range<u64>) -> impl Iterator<Item = K> {
+        SyncAuraMap::keys_in_range(self, range)
+    }
+
+    fn transaction_count(&self) -> u64 { SyncAuraMap::transaction_count(self) }
+
+    fn pending_len(&self) -> usize { SyncAuraMap::pending_len(self) }
+
+    fn has_pending(&self) -> bool { SyncAuraMap::has_pending(self) }
+
+    fn pending_keys(&self) -> impl Iterator<Item = K> { SyncAuraMap::pending_keys(self) }
+}
+
+#[cfg(all(test, feature = "file-strict"))]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use amplify::confinement::SmallVec;
+
+    use super::*;
+    use crate::U64Le;
+    use crate::file::{FileAoraMap, FileAuraMap};
+
+    const MAGIC: u64 = u64::from_be_bytes(*b"DUMBTEST");
+
+    #[test]
+    fn aora() {
+        type Db = FileAoraMap<[u8; 8], SmallVec<u8>, MAGIC, 1, 8>;
+        let val = |no: u64| SmallVec::from_checked(no.to_le_bytes().to_vec());
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(SyncAoraMap::new(Db::create_new(dir.path(), "sync").unwrap()));
+        // The provider reads through its file handles, so the threads share it under the lock
+        let threads = (0..2u64)
+            .map(|thread| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    for no in (0..10u64).filter(|no| no % 2 == thread) {
+                        db.insert(no.to_be_bytes(), &val(no));
+                        assert_eq!(db.get(no.to_be_bytes()), Some(val(no)));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        db.extend([(10u64.to_be_bytes(), &val(10))]);
+        assert_eq!(db.len(), 11);
+        assert_eq!(db.get(5u64.to_be_bytes()), Some(val(5)));
+        assert_eq!(db.iter_rev().next(), Some((10u64.to_be_bytes(), val(10))));
+        let db = Arc::into_inner(db).unwrap().into_inner();
+        let mut values = db.iter().map(|(_, v)| v).collect::<Vec<_>>();
+        values.sort_by_key(|v| u64::from_le_bytes(v.as_slice().try_into().unwrap()));
+        assert!(values.into_iter().eq((0..11).map(val)));
+    }
+
+    #[test]
+    fn aura() {
+        type Db = FileAuraMap<U64Le, U64Le, MAGIC, 1, 8, 8>;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(SyncAuraMap::new(Db::create_new(dir.path(), "sync").unwrap()));
+        let threads = (0..4u64)
+            .map(|thread| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    for no in 0..25 {
+                        let key = U64Le(thread * 25 + no);
+                        db.insert_only(key, U64Le(no));
+                        assert_eq!(db.get(key), Some(U64Le(no)));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(db.commit_transaction(), Some(0));
+        db.update_only(U64Le(0), U64Le(100));
+        db.abort_transaction();
+        assert_eq!(db.keys().count(), 100);
+        assert_eq!(db.transaction_keys(0).len(), 100);
+        assert_eq!(db.get_expect(U64Le(0)), U64Le(0));
+        assert_eq!(db.transaction_count(), 1);
+    }
+}
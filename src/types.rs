@@ -1,9 +1,75 @@
 // SPDX-License-Identifier: Apache-2.0
 
+use core::cmp::Ordering;
+use core::fmt::{self, Debug, Display, Formatter};
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::num::ParseIntError;
+#[cfg(any(feature = "chrono", feature = "time"))]
+use core::num::TryFromIntError;
+use core::str::FromStr;
+
+use amplify::hex::{self, FromHex, ToHex};
+
+use crate::KeyHex;
+
+/// Little-endian 16-bit unsigned integer.
+#[derive(Wrapper, WrapperMut, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From)]
+#[wrapper(Deref, Display, FromStr, Octal, LowerHex, UpperHex, Add, Sub, Mul, Div, Rem, BitOps)]
+#[wrapper_mut(DerefMut, AddAssign, SubAssign, MulAssign, DivAssign, RemAssign, BitAssign)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct U16Le(pub u16);
+impl From<U16Le> for [u8; 2] {
+    fn from(value: U16Le) -> Self { value.0.to_le_bytes() }
+}
+impl From<[u8; 2]> for U16Le {
+    fn from(value: [u8; 2]) -> Self { Self(u16::from_le_bytes(value)) }
+}
+
+/// Big-endian 16-bit unsigned integer.
+#[derive(Wrapper, WrapperMut, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From)]
+#[wrapper(Deref, Display, FromStr, Octal, LowerHex, UpperHex, Add, Sub, Mul, Div, Rem, BitOps)]
+#[wrapper_mut(DerefMut, AddAssign, SubAssign, MulAssign, DivAssign, RemAssign, BitAssign)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct U16Be(pub u16);
+impl From<U16Be> for [u8; 2] {
+    fn from(value: U16Be) -> Self { value.0.to_be_bytes() }
+}
+impl From<[u8; 2]> for U16Be {
+    fn from(value: [u8; 2]) -> Self { Self(u16::from_be_bytes(value)) }
+}
+
+/// Little-endian 32-bit unsigned integer.
+#[derive(Wrapper, WrapperMut, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From)]
+#[wrapper(Deref, Display, FromStr, Octal, LowerHex, UpperHex, Add, Sub, Mul, Div, Rem, BitOps)]
+#[wrapper_mut(DerefMut, AddAssign, SubAssign, MulAssign, DivAssign, RemAssign, BitAssign)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct U32Le(pub u32);
+impl From<U32Le> for [u8; 4] {
+    fn from(value: U32Le) -> Self { value.0.to_le_bytes() }
+}
+impl From<[u8; 4]> for U32Le {
+    fn from(value: [u8; 4]) -> Self { Self(u32::from_le_bytes(value)) }
+}
+
+/// Big-endian 32-bit unsigned integer.
+#[derive(Wrapper, WrapperMut, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From)]
+#[wrapper(Deref, Display, FromStr, Octal, LowerHex, UpperHex, Add, Sub, Mul, Div, Rem, BitOps)]
+#[wrapper_mut(DerefMut, AddAssign, SubAssign, MulAssign, DivAssign, RemAssign, BitAssign)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct U32Be(pub u32);
+impl From<U32Be> for [u8; 4] {
+    fn from(value: U32Be) -> Self { value.0.to_be_bytes() }
+}
+impl From<[u8; 4]> for U32Be {
+    fn from(value: [u8; 4]) -> Self { Self(u32::from_be_bytes(value)) }
+}
+
 /// Little-endian 64-bit unsigned integer.
 #[derive(Wrapper, WrapperMut, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From)]
 #[wrapper(Deref, Display, FromStr, Octal, LowerHex, UpperHex, Add, Sub, Mul, Div, Rem, BitOps)]
 #[wrapper_mut(DerefMut, AddAssign, SubAssign, MulAssign, DivAssign, RemAssign, BitAssign)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
 pub struct U64Le(pub u64);
 impl From<U64Le> for [u8; 8] {
     fn from(value: U64Le) -> Self { value.0.to_le_bytes() }
@@ -16,6 +82,7 @@ impl From<[u8; 8]> for U64Le {
 #[derive(Wrapper, WrapperMut, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From)]
 #[wrapper(Deref, Display, FromStr, Octal, LowerHex, UpperHex, Add, Sub, Mul, Div, Rem, BitOps)]
 #[wrapper_mut(DerefMut, AddAssign, SubAssign, MulAssign, DivAssign, RemAssign, BitAssign)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
 pub struct U64Be(pub u64);
 impl From<U64Be> for [u8; 8] {
     fn from(value: U64Be) -> Self { value.0.to_be_bytes() }
@@ -23,3 +90,364 @@ impl From<U64Be> for [u8; 8] {
 impl From<[u8; 8]> for U64Be {
     fn from(value: [u8; 8]) -> Self { Self(u64::from_be_bytes(value)) }
 }
+
+/// Little-endian 128-bit unsigned integer.
+#[derive(Wrapper, WrapperMut, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From)]
+#[wrapper(Deref, Display, FromStr, Octal, LowerHex, UpperHex, Add, Sub, Mul, Div, Rem, BitOps)]
+#[wrapper_mut(DerefMut, AddAssign, SubAssign, MulAssign, DivAssign, RemAssign, BitAssign)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct U128Le(pub u128);
+impl From<U128Le> for [u8; 16] {
+    fn from(value: U128Le) -> Self { value.0.to_le_bytes() }
+}
+impl From<[u8; 16]> for U128Le {
+    fn from(value: [u8; 16]) -> Self { Self(u128::from_le_bytes(value)) }
+}
+
+/// Big-endian 128-bit unsigned integer.
+#[derive(Wrapper, WrapperMut, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From)]
+#[wrapper(Deref, Display, FromStr, Octal, LowerHex, UpperHex, Add, Sub, Mul, Div, Rem, BitOps)]
+#[wrapper_mut(DerefMut, AddAssign, SubAssign, MulAssign, DivAssign, RemAssign, BitAssign)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct U128Be(pub u128);
+impl From<U128Be> for [u8; 16] {
+    fn from(value: U128Be) -> Self { value.0.to_be_bytes() }
+}
+impl From<[u8; 16]> for U128Be {
+    fn from(value: [u8; 16]) -> Self { Self(u128::from_be_bytes(value)) }
+}
+
+/// 128-bit big-endian time-ordered key: a 64-bit timestamp followed by a 64-bit nonce, which
+/// distinguishes the events happening at the same time.
+///
+/// Since both parts are big-endian, the order of the key bytes matches the chronological order,
+/// allowing sorted providers to serve time range queries.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct U128BeTime {
+    /// Timestamp; [`U128BeTime::now`] uses nanoseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Nonce, ordering the keys sharing the same timestamp.
+    pub nonce: u64,
+}
+
+impl U128BeTime {
+    pub const fn new(timestamp: u64, nonce: u64) -> Self { Self { timestamp, nonce } }
+
+    /// Constructs the key with the current system time as the number of nanoseconds since the Unix
+    /// epoch.
+    ///
+    /// # Panics
+    ///
+    /// If the system clock is set before the Unix epoch.
+    #[cfg(feature = "std")]
+    pub fn now(nonce: u64) -> Self {
+        let elapsed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("unable to get time since the Unix epoch");
+        Self::new(elapsed.as_nanos() as u64, nonce)
+    }
+
+    /// Returns the smallest key with the given timestamp.
+    pub const fn first_at(timestamp: u64) -> Self { Self::new(timestamp, 0) }
+
+    /// Returns the largest key with the given timestamp.
+    pub const fn last_at(timestamp: u64) -> Self { Self::new(timestamp, u64::MAX) }
+}
+
+impl From<U128BeTime> for u128 {
+    fn from(value: U128BeTime) -> Self { ((value.timestamp as u128) << 64) | value.nonce as u128 }
+}
+impl From<u128> for U128BeTime {
+    fn from(value: u128) -> Self { Self::new((value >> 64) as u64, value as u64) }
+}
+impl From<U128BeTime> for [u8; 16] {
+    fn from(value: U128BeTime) -> Self { u128::from(value).to_be_bytes() }
+}
+impl From<[u8; 16]> for U128BeTime {
+    fn from(value: [u8; 16]) -> Self { Self::from(u128::from_be_bytes(value)) }
+}
+
+/// Time as the number of seconds since the Unix epoch, stored big-endian, so the order of the
+/// bytes matches the chronological order.
+#[derive(Wrapper, WrapperMut, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[derive(From)]
+#[wrapper(Deref, Display, FromStr, Add, Sub)]
+#[wrapper_mut(DerefMut, AddAssign, SubAssign)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct UnixTimeSecs(pub u64);
+impl From<UnixTimeSecs> for [u8; 8] {
+    fn from(value: UnixTimeSecs) -> Self { value.0.to_be_bytes() }
+}
+impl From<[u8; 8]> for UnixTimeSecs {
+    fn from(value: [u8; 8]) -> Self { Self(u64::from_be_bytes(value)) }
+}
+
+impl UnixTimeSecs {
+    /// Returns the current system time.
+    ///
+    /// # Panics
+    ///
+    /// If the system clock is set before the Unix epoch.
+    #[cfg(feature = "std")]
+    pub fn now() -> Self {
+        let elapsed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("unable to get time since the Unix epoch");
+        Self(elapsed.as_secs())
+    }
+
+    /// Converts the time into a [`chrono::DateTime`], returning `None` if it is out of the
+    /// range supported by `chrono`.
+    #[cfg(feature = "chrono")]
+    pub fn to_chrono(self) -> Option<chrono::DateTime<chrono::Utc>> {
+        chrono::DateTime::from_timestamp(i64::try_from(self.0).ok()?, 0)
+    }
+
+    /// Converts the time into a [`time::OffsetDateTime`], returning `None` if it is out of the
+    /// range supported by `time`.
+    #[cfg(feature = "time")]
+    pub fn to_offset_date_time(self) -> Option<time::OffsetDateTime> {
+        time::OffsetDateTime::from_unix_timestamp(i64::try_from(self.0).ok()?).ok()
+    }
+}
+
+/// Time as the number of milliseconds since the Unix epoch, stored big-endian, so the order of
+/// the bytes matches the chronological order.
+#[derive(Wrapper, WrapperMut, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[derive(From)]
+#[wrapper(Deref, Display, FromStr, Add, Sub)]
+#[wrapper_mut(DerefMut, AddAssign, SubAssign)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct UnixTimeMillis(pub u64);
+impl From<UnixTimeMillis> for [u8; 8] {
+    fn from(value: UnixTimeMillis) -> Self { value.0.to_be_bytes() }
+}
+impl From<[u8; 8]> for UnixTimeMillis {
+    fn from(value: [u8; 8]) -> Self { Self(u64::from_be_bytes(value)) }
+}
+impl From<UnixTimeSecs> for UnixTimeMillis {
+    fn from(value: UnixTimeSecs) -> Self { Self(value.0.saturating_mul(1000)) }
+}
+
+impl UnixTimeMillis {
+    /// Returns the current system time.
+    ///
+    /// # Panics
+    ///
+    /// If the system clock is set before the Unix epoch.
+    #[cfg(feature = "std")]
+    pub fn now() -> Self {
+        let elapsed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("unable to get time since the Unix epoch");
+        Self(elapsed.as_millis() as u64)
+    }
+
+    /// Returns the time truncated to whole seconds.
+    pub const fn to_secs(self) -> UnixTimeSecs { UnixTimeSecs(self.0 / 1000) }
+
+    /// Converts the time into a [`chrono::DateTime`], returning `None` if it is out of the
+    /// range supported by `chrono`.
+    #[cfg(feature = "chrono")]
+    pub fn to_chrono(self) -> Option<chrono::DateTime<chrono::Utc>> {
+        chrono::DateTime::from_timestamp_millis(i64::try_from(self.0).ok()?)
+    }
+
+    /// Converts the time into a [`time::OffsetDateTime`], returning `None` if it is out of the
+    /// range supported by `time`.
+    #[cfg(feature = "time")]
+    pub fn to_offset_date_time(self) -> Option<time::OffsetDateTime> {
+        time::OffsetDateTime::from_unix_timestamp_nanos(self.0 as i128 * 1_000_000).ok()
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<chrono::DateTime<chrono::Utc>> for UnixTimeSecs {
+    type Error = TryFromIntError;
+
+    fn try_from(value: chrono::DateTime<chrono::Utc>) -> Result<Self, Self::Error> {
+        u64::try_from(value.timestamp()).map(Self)
+    }
+}
+#[cfg(feature = "chrono")]
+impl TryFrom<chrono::DateTime<chrono::Utc>> for UnixTimeMillis {
+    type Error = TryFromIntError;
+
+    fn try_from(value: chrono::DateTime<chrono::Utc>) -> Result<Self, Self::Error> {
+        u64::try_from(value.timestamp_millis()).map(Self)
+    }
+}
+
+#[cfg(feature = "time")]
+impl TryFrom<time::OffsetDateTime> for UnixTimeSecs {
+    type Error = TryFromIntError;
+
+    fn try_from(value: time::OffsetDateTime) -> Result<Self, Self::Error> {
+        u64::try_from(value.unix_timestamp()).map(Self)
+    }
+}
+#[cfg(feature = "time")]
+impl TryFrom<time::OffsetDateTime> for UnixTimeMillis {
+    type Error = TryFromIntError;
+
+    fn try_from(value: time::OffsetDateTime) -> Result<Self, Self::Error> {
+        u64::try_from(value.unix_timestamp_nanos() / 1_000_000).map(Self)
+    }
+}
+
+/// Implements a fixed-size byte array newtype, displayed and parsed as a hex string.
+macro_rules! bytes_newtype {
+    ($name:ident, $len:literal, $doc:literal) => {
+        #[doc = $doc]
+        #[derive(Wrapper, WrapperMut, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+        #[derive(From)]
+        #[wrapper(Deref)]
+        #[wrapper_mut(DerefMut)]
+        pub struct $name(pub [u8; $len]);
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] { &self.0 }
+        }
+
+        impl From<$name> for [u8; $len] {
+            fn from(value: $name) -> Self { value.0 }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0.to_hex())
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = hex::Error;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> { <[u8; $len]>::from_hex(s).map(Self) }
+        }
+    };
+}
+
+bytes_newtype!(Bytes20, 20, "20-byte array, like a RIPEMD-160 hash, displayed in hex.");
+bytes_newtype!(Bytes32, 32, "32-byte array, like a SHA-256 hash, displayed in hex.");
+bytes_newtype!(Bytes64, 64, "64-byte array, like a Schnorr signature, displayed in hex.");
+
+/// UUID usable as a 16-byte key or value, wrapping [`uuid::Uuid`], which can't implement the
+/// array conversions itself.
+#[cfg(feature = "uuid")]
+#[derive(Wrapper, WrapperMut, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From)]
+#[wrapper(Deref, Display, FromStr, LowerHex, UpperHex)]
+#[wrapper_mut(DerefMut)]
+pub struct UuidKey(pub uuid::Uuid);
+#[cfg(feature = "uuid")]
+impl From<UuidKey> for [u8; 16] {
+    fn from(value: UuidKey) -> Self { value.0.into_bytes() }
+}
+#[cfg(feature = "uuid")]
+impl From<[u8; 16]> for UuidKey {
+    fn from(value: [u8; 16]) -> Self { Self(uuid::Uuid::from_bytes(value)) }
+}
+#[cfg(feature = "uuid")]
+impl From<UuidKey> for uuid::Uuid {
+    fn from(value: UuidKey) -> Self { value.0 }
+}
+
+/// Key of a type which can be constructed only from some of the byte arrays, implementing
+/// `TryFrom<[u8; LEN]>` instead of `From<[u8; LEN]>`, which allows using it with the providers.
+///
+/// The wrapper keeps the byte representation read from the storage as is; it is validated by
+/// [`Checked::check`], which reports invalid keys as errors instead of panicking, since such keys
+/// can appear only in corrupted data.
+pub struct Checked<K, const LEN: usize>([u8; LEN], PhantomData<fn() -> K>);
+
+impl<K, const LEN: usize> Checked<K, LEN> {
+    /// Wraps a valid key.
+    pub fn new(key: K) -> Self
+    where K: Into<[u8; LEN]> {
+        Self(key.into(), PhantomData)
+    }
+
+    /// Returns the byte representation of the key.
+    pub const fn as_bytes(&self) -> &[u8; LEN] { &self.0 }
+
+    /// Validates the key.
+    ///
+    /// # Errors
+    ///
+    /// If the byte representation doesn't correspond to a valid key.
+    pub fn check(self) -> Result<K, InvalidKey<LEN>>
+    where K: TryFrom<[u8; LEN]> {
+        K::try_from(self.0).map_err(|_| InvalidKey(self.0))
+    }
+}
+
+impl<K, const LEN: usize> Copy for Checked<K, LEN> {}
+impl<K, const LEN: usize> Clone for Checked<K, LEN> {
+    fn clone(&self) -> Self { *self }
+}
+impl<K, const LEN: usize> PartialEq for Checked<K, LEN> {
+    fn eq(&self, other: &Self) -> bool { self.0 == other.0 }
+}
+impl<K, const LEN: usize> Eq for Checked<K, LEN> {}
+impl<K, const LEN: usize> PartialOrd for Checked<K, LEN> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+impl<K, const LEN: usize> Ord for Checked<K, LEN> {
+    fn cmp(&self, other: &Self) -> Ordering { self.0.cmp(&other.0) }
+}
+impl<K, const LEN: usize> Hash for Checked<K, LEN> {
+    fn hash<H: Hasher>(&self, state: &mut H) { self.0.hash(state) }
+}
+impl<K, const LEN: usize> Debug for Checked<K, LEN> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Checked").field(&self.0).finish()
+    }
+}
+
+impl<K, const LEN: usize> From<Checked<K, LEN>> for [u8; LEN] {
+    fn from(value: Checked<K, LEN>) -> Self { value.0 }
+}
+impl<K, const LEN: usize> From<[u8; LEN]> for Checked<K, LEN> {
+    fn from(value: [u8; LEN]) -> Self { Self(value, PhantomData) }
+}
+
+/// Error of [`Checked::check`] reporting the byte representation of an invalid key.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct InvalidKey<const LEN: usize>(pub [u8; LEN]);
+
+impl<const LEN: usize> Display for InvalidKey<LEN> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid key {}", KeyHex(self.0))
+    }
+}
+
+impl<const LEN: usize> core::error::Error for InvalidKey<LEN> {}
+
+/// Opaque position in an append-only log, used to resume iteration in pages across multiple calls
+/// (or processes).
+///
+/// The cursor can be persisted as an 8-byte array or as a hex string.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct AoraCursor(pub(crate) u64);
+
+impl AoraCursor {
+    /// Cursor pointing to the beginning of the log.
+    pub const START: Self = Self(0);
+}
+
+impl From<AoraCursor> for [u8; 8] {
+    fn from(value: AoraCursor) -> Self { value.0.to_be_bytes() }
+}
+impl From<[u8; 8]> for AoraCursor {
+    fn from(value: [u8; 8]) -> Self { Self(u64::from_be_bytes(value)) }
+}
+
+impl Display for AoraCursor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { write!(f, "{:016x}", self.0) }
+}
+
+impl FromStr for AoraCursor {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> { u64::from_str_radix(s, 16).map(Self) }
+}